@@ -0,0 +1,292 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Appended to agent system prompts when action extraction is enabled, so
+/// agents know how to propose follow-up work.
+pub const ACTIONS_INSTRUCTION: &str =
+    "If you recommend concrete follow-up actions, list them at the end \
+of your answer in a fenced code block tagged `actions` containing a JSON array of objects with \
+\"kind\" (e.g. \"restart_service\", \"open_ticket\"), \"target\", \"reason\", and optionally \
+\"command\" (a shell command an operator could run). Actions are queued for human review and \
+are never executed automatically.";
+
+/// An action as proposed by an agent inside an ```actions block.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProposedAction {
+    pub kind: String,
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Executed,
+    Failed,
+}
+
+/// A proposed action tracked in the queue across runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedAction {
+    /// Stable ID derived from agent, kind and target, so the same proposal
+    /// made in later runs updates the existing entry instead of duplicating it.
+    pub id: String,
+    pub agent: String,
+    #[serde(flatten)]
+    pub action: ProposedAction,
+    pub status: ActionStatus,
+    pub first_run_id: String,
+    pub last_run_id: String,
+    pub times_proposed: u32,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+}
+
+/// Extract actions from every ```actions fenced block in an agent's output.
+/// A block may hold a JSON array or a single object; malformed blocks are skipped.
+pub fn extract(output: &str) -> Vec<ProposedAction> {
+    let mut actions = Vec::new();
+    let mut rest = output;
+    while let Some(start) = rest.find("```actions") {
+        let body_start = start + "```actions".len();
+        let Some(len) = rest[body_start..].find("```") else {
+            break;
+        };
+        let body = rest[body_start..body_start + len].trim();
+        match serde_json::from_str::<Vec<ProposedAction>>(body) {
+            Ok(mut items) => actions.append(&mut items),
+            Err(_) => match serde_json::from_str::<ProposedAction>(body) {
+                Ok(item) => actions.push(item),
+                Err(e) => warn!("Ignoring malformed actions block: {}", e),
+            },
+        }
+        rest = &rest[body_start + len + 3..];
+    }
+    actions
+}
+
+pub fn action_id(agent: &str, action: &ProposedAction) -> String {
+    let key = format!(
+        "{}|{}|{}",
+        agent,
+        action.kind,
+        action.target.as_deref().unwrap_or("")
+    );
+    let hash = digest::digest(&digest::SHA256, key.as_bytes());
+    hash.as_ref()[..6]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The persisted action queue (a JSON file under the outputs directory).
+pub struct ActionQueue {
+    path: PathBuf,
+    pub actions: Vec<QueuedAction>,
+}
+
+impl ActionQueue {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let actions = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read action queue {}", path.display()))?;
+            serde_json::from_str(&content).context("Failed to parse action queue")?
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, actions })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(&self.actions)?;
+        fs::write(&self.path, json).context("Failed to write action queue")
+    }
+
+    /// Add or refresh proposals from a run. Returns the IDs touched.
+    /// Previously rejected or executed actions keep their disposition and
+    /// the action as reviewed; a re-proposed failed action goes back to
+    /// pending for another review, and so does an approved one whose action
+    /// changed, so nothing runs that wasn't approved as it stands.
+    pub fn record(
+        &mut self,
+        run_id: &str,
+        agent: &str,
+        proposed: &[ProposedAction],
+    ) -> Vec<String> {
+        let now = Utc::now();
+        let mut ids = Vec::new();
+        for action in proposed {
+            let id = action_id(agent, action);
+            match self.actions.iter_mut().find(|a| a.id == id) {
+                Some(existing) => {
+                    existing.last_run_id = run_id.to_string();
+                    existing.times_proposed += 1;
+                    existing.updated_at = now;
+                    match existing.status {
+                        ActionStatus::Pending => existing.action = action.clone(),
+                        ActionStatus::Failed => {
+                            existing.action = action.clone();
+                            existing.status = ActionStatus::Pending;
+                        }
+                        ActionStatus::Approved if existing.action != *action => {
+                            existing.action = action.clone();
+                            existing.status = ActionStatus::Pending;
+                        }
+                        _ => {}
+                    }
+                }
+                None => self.actions.push(QueuedAction {
+                    id: id.clone(),
+                    agent: agent.to_string(),
+                    action: action.clone(),
+                    status: ActionStatus::Pending,
+                    first_run_id: run_id.to_string(),
+                    last_run_id: run_id.to_string(),
+                    times_proposed: 1,
+                    updated_at: now,
+                    outcome: None,
+                }),
+            }
+            ids.push(id);
+        }
+        ids
+    }
+
+    pub fn set_status(&mut self, id: &str, status: ActionStatus) -> Result<()> {
+        let action = self
+            .actions
+            .iter_mut()
+            .find(|a| a.id == id)
+            .with_context(|| format!("No action with id '{}'", id))?;
+        action.status = status;
+        action.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Run the command of every approved action. Only approved actions are
+    /// eligible, so nothing executes without a human having signed off.
+    pub async fn execute_approved(&mut self) -> Result<usize> {
+        let mut executed = 0;
+        for action in self
+            .actions
+            .iter_mut()
+            .filter(|a| a.status == ActionStatus::Approved)
+        {
+            let Some(command) = action.action.command.clone() else {
+                warn!(
+                    "Action {} is approved but has no command; skipping",
+                    action.id
+                );
+                continue;
+            };
+            info!("Executing action {}: {}", action.id, command);
+            let output = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .output()
+                .await
+                .with_context(|| format!("Failed to spawn command for action {}", action.id))?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            action.status = if output.status.success() {
+                ActionStatus::Executed
+            } else {
+                ActionStatus::Failed
+            };
+            action.outcome = Some(
+                format!("{}\n{}{}", output.status, stdout, stderr)
+                    .trim()
+                    .to_string(),
+            );
+            action.updated_at = Utc::now();
+            executed += 1;
+        }
+        Ok(executed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = r#"Disk is nearly full.
+
+```actions
+[{"kind": "restart_service", "target": "nginx", "reason": "worker leak"},
+ {"kind": "open_ticket", "target": "storage", "command": "echo ticket"}]
+```
+"#;
+
+    #[test]
+    fn test_extract_actions_block() {
+        let actions = extract(OUTPUT);
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].kind, "restart_service");
+        assert_eq!(actions[1].command.as_deref(), Some("echo ticket"));
+        assert!(extract("no actions here").is_empty());
+    }
+
+    #[test]
+    fn test_record_dedupes_across_runs() {
+        let path = std::env::temp_dir().join("orchestra-actions-test-dedupe.json");
+        let mut queue = ActionQueue {
+            path,
+            actions: Vec::new(),
+        };
+        let proposed = extract(OUTPUT);
+        queue.record("run-1", "monitor", &proposed);
+        queue
+            .set_status(&queue.actions[0].id.clone(), ActionStatus::Rejected)
+            .unwrap();
+        queue.record("run-2", "monitor", &proposed);
+
+        assert_eq!(queue.actions.len(), 2);
+        assert_eq!(queue.actions[0].times_proposed, 2);
+        assert_eq!(queue.actions[0].last_run_id, "run-2");
+        // Rejection sticks across runs
+        assert_eq!(queue.actions[0].status, ActionStatus::Rejected);
+    }
+
+    #[test]
+    fn test_changed_proposal_needs_approval_again() {
+        let path = std::env::temp_dir().join("orchestra-actions-test-reapprove.json");
+        let mut queue = ActionQueue {
+            path,
+            actions: Vec::new(),
+        };
+        let mut proposed = extract(OUTPUT);
+        queue.record("run-1", "monitor", &proposed);
+        let id = queue.actions[1].id.clone();
+        queue.set_status(&id, ActionStatus::Approved).unwrap();
+
+        // The same proposal again keeps its approval
+        queue.record("run-2", "monitor", &proposed);
+        assert_eq!(queue.actions[1].status, ActionStatus::Approved);
+
+        proposed[1].command = Some("rm -rf /var/lib/app".to_string());
+        queue.record("run-3", "monitor", &proposed);
+        assert_eq!(queue.actions[1].id, id);
+        assert_eq!(queue.actions[1].status, ActionStatus::Pending);
+        assert_eq!(
+            queue.actions[1].action.command.as_deref(),
+            Some("rm -rf /var/lib/app")
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::Utc;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{span, Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Name of the span the orchestrator runs each agent in. It carries `agent`
+/// and `run_id` fields.
+pub const AGENT_SPAN: &str = "agent";
+
+/// Tracing layer that copies every event emitted inside an agent's span to
+/// `<dir>/<run_id>/<agent>.log`, so one agent's story (prompt, client
+/// messages, captured CLI stderr, errors) can be read without the other
+/// agents' lines interleaved.
+pub struct AgentLogLayer {
+    dir: PathBuf,
+}
+
+impl AgentLogLayer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `<dir>/<run_id>/<agent>.log`, with anything but `[A-Za-z0-9_.-]`
+    /// replaced in either name.
+    pub fn path(dir: &Path, run_id: &str, agent: &str) -> PathBuf {
+        dir.join(safe_name(run_id))
+            .join(format!("{}.log", safe_name(agent)))
+    }
+}
+
+/// `name` with anything but `[A-Za-z0-9_.-]` replaced, for use in a path.
+pub fn safe_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Open log file, stored in the agent span's extensions.
+struct AgentLogFile(Mutex<File>);
+
+/// Collects the `agent` and `run_id` fields of a new agent span.
+#[derive(Default)]
+struct SpanFields {
+    agent: Option<String>,
+    run_id: Option<String>,
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "agent" => self.agent = Some(value.to_string()),
+            "run_id" => self.run_id = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, format!("{:?}", value).trim_matches('"'));
+    }
+}
+
+/// Renders an event as `message key=value ...`.
+#[derive(Default)]
+struct EventText(String);
+
+impl Visit for EventText {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.insert_str(0, value);
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{:?}", value));
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl<S> Layer<S> for AgentLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != AGENT_SPAN {
+            return;
+        }
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+        let (Some(agent), Some(run_id)) = (fields.agent, fields.run_id) else {
+            return;
+        };
+        let path = Self::path(&self.dir, &run_id, &agent);
+        let file = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&path));
+        match (file, ctx.span(id)) {
+            (Ok(file), Some(span)) => {
+                span.extensions_mut().insert(AgentLogFile(Mutex::new(file)));
+            }
+            // Can't log through tracing here without recursing into this layer
+            (Err(e), _) => eprintln!("Failed to open agent log {}: {}", path.display(), e),
+            _ => {}
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        for span in scope {
+            let extensions = span.extensions();
+            let Some(AgentLogFile(file)) = extensions.get::<AgentLogFile>() else {
+                continue;
+            };
+            let mut text = EventText::default();
+            event.record(&mut text);
+            let meta = event.metadata();
+            let line = format!(
+                "{} {:>5} {}: {}\n",
+                Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+                meta.level(),
+                meta.target(),
+                text.0
+            );
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            let _ = file.write_all(line.as_bytes());
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    async fn test_events_in_agent_span_go_to_its_file() {
+        let dir = std::env::temp_dir().join(format!("orchestra-agent-log-{}", std::process::id()));
+        let subscriber = tracing_subscriber::registry().with(AgentLogLayer::new(&dir));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        tracing::info!("outside any agent");
+        async {
+            tracing::debug!(bytes = 12, "claude stderr:\nrate limited");
+        }
+        .instrument(tracing::info_span!(
+            AGENT_SPAN,
+            agent = "monitor",
+            run_id = "auto-1"
+        ))
+        .await;
+        tracing::info_span!(AGENT_SPAN, agent = "alerts/x", run_id = "auto-1")
+            .in_scope(|| tracing::warn!("slow"));
+
+        let monitor = fs::read_to_string(dir.join("auto-1/monitor.log")).unwrap();
+        assert_eq!(monitor.lines().count(), 2);
+        assert!(monitor.contains("DEBUG agent_orchestra::agent_log::tests: claude stderr:"));
+        assert!(monitor.contains("rate limited bytes=12"));
+        assert!(!monitor.contains("outside"));
+        let alerts = fs::read_to_string(dir.join("auto-1/alerts_x.log")).unwrap();
+        assert!(alerts.contains(" WARN ") && alerts.contains("slow"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,15 @@
+use crate::approval::Approval;
+use crate::client::{ClientOptions, Usage};
+use crate::config::{AgentHooks, BudgetConfig, ContextConfig};
+use crate::context::SamplingRecord;
+use crate::teams::TeammateOutput;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Clone)]
+/// One agent's work for a run. Serializable so a distributed run can hand
+/// it to a worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentTask {
     pub name: String,
     pub prompt: String,
@@ -10,6 +18,26 @@ pub struct AgentTask {
     pub client_mode: Option<String>,
     /// System prompt giving this agent its role/identity.
     pub system_prompt: Option<String>,
+    /// Sampling options (max_tokens, temperature, stop sequences).
+    pub options: ClientOptions,
+    /// How injected logs were sampled for this agent.
+    pub sampling: Vec<SamplingRecord>,
+    /// Sources captured just before the agent runs.
+    pub context: Option<ContextConfig>,
+    /// Logs and context captured for the agent. They go in front of the
+    /// prompt only once its `results.*` placeholders are filled in, so
+    /// nothing in them is ever read as a placeholder.
+    pub injected: String,
+    /// Hold the output for approval before later agents start.
+    pub requires_approval: bool,
+    /// Higher starts first.
+    pub priority: i32,
+    /// The agent's own pre/post hook commands.
+    pub hooks: AgentHooks,
+    /// JSON Schema the reply must match.
+    pub output_schema: Option<Value>,
+    /// Cap on the prompt's estimated size.
+    pub budget: BudgetConfig,
 }
 
 impl AgentTask {
@@ -20,9 +48,30 @@ impl AgentTask {
             timeout_seconds,
             client_mode: None,
             system_prompt: None,
+            options: ClientOptions::default(),
+            sampling: Vec::new(),
+            context: None,
+            injected: String::new(),
+            requires_approval: false,
+            priority: 0,
+            hooks: AgentHooks::default(),
+            output_schema: None,
+            budget: BudgetConfig::default(),
         }
     }
 
+    /// Put the injected logs and context in front of the prompt.
+    pub fn inject(&mut self) {
+        if !self.injected.is_empty() {
+            self.prompt = format!("{}{}", std::mem::take(&mut self.injected), self.prompt);
+        }
+    }
+
+    /// The prompt as it will be sent, injected material included.
+    pub fn full_prompt(&self) -> String {
+        format!("{}{}", self.injected, self.prompt)
+    }
+
     pub fn with_client_mode(mut self, mode: Option<String>) -> Self {
         self.client_mode = mode;
         self
@@ -32,6 +81,11 @@ impl AgentTask {
         self.system_prompt = prompt;
         self
     }
+
+    pub fn with_options(mut self, options: ClientOptions) -> Self {
+        self.options = options;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,7 +97,44 @@ pub struct AgentResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
     pub client_mode: String,
+    /// Model that actually answered (after any fallbacks), when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
     pub timestamp: DateTime<Utc>,
+    /// When the agent's call started; `timestamp` is when it finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    /// Wall-clock time the agent's call took, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Backend calls it took to answer, counting model and CLI fallbacks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+    /// Token counts and cost, when the backend reports them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// Claude Code session that produced the output, for `claude --resume`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Team this result belongs to, for an Agent Teams teammate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    /// The teammate's role in its team definition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// A team lead's teammates until [`flatten_teams`] lists them alongside it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teammates: Vec<AgentResult>,
+    /// The decision on the output, for an agent with `requires_approval`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<Approval>,
+    /// The reply parsed as JSON, for an agent with an `output_schema`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_output: Option<Value>,
 }
 
 impl AgentResult {
@@ -54,7 +145,20 @@ impl AgentResult {
             output: Some(output),
             error: None,
             client_mode,
+            model: None,
             timestamp: Utc::now(),
+            started_at: None,
+            duration_ms: None,
+            attempts: None,
+            input_tokens: None,
+            output_tokens: None,
+            cost_usd: None,
+            session_id: None,
+            team: None,
+            role: None,
+            teammates: Vec::new(),
+            approval: None,
+            structured_output: None,
         }
     }
 
@@ -65,7 +169,102 @@ impl AgentResult {
             output: None,
             error: Some(error),
             client_mode,
+            model: None,
             timestamp: Utc::now(),
+            started_at: None,
+            duration_ms: None,
+            attempts: None,
+            input_tokens: None,
+            output_tokens: None,
+            cost_usd: None,
+            session_id: None,
+            team: None,
+            role: None,
+            teammates: Vec::new(),
+            approval: None,
+            structured_output: None,
+        }
+    }
+
+    /// An agent stopped before it finished, by a shutdown signal or by
+    /// `fail_fast`; `reason` says which.
+    pub fn cancelled(agent: String, reason: String, client_mode: String) -> Self {
+        Self {
+            status: "cancelled".to_string(),
+            ..Self::failed(agent, reason, client_mode)
         }
     }
+
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_duration(mut self, elapsed: std::time::Duration) -> Self {
+        self.duration_ms = Some(elapsed.as_millis() as u64);
+        self
+    }
+
+    pub fn with_started_at(mut self, started_at: DateTime<Utc>) -> Self {
+        self.started_at = Some(started_at);
+        self
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = Some(attempts);
+        self
+    }
+
+    pub fn with_usage(mut self, usage: Usage) -> Self {
+        self.input_tokens = usage.input_tokens;
+        self.output_tokens = usage.output_tokens;
+        self.cost_usd = usage.cost_usd;
+        self
+    }
+
+    pub fn with_structured_output(mut self, value: Option<Value>) -> Self {
+        self.structured_output = value;
+        self
+    }
+
+    pub fn with_session_id(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Attach a result per teammate of the team this agent led. A teammate
+    /// that left no output counts as failed.
+    pub fn with_teammates(mut self, teammates: Vec<TeammateOutput>) -> Self {
+        self.teammates = teammates
+            .into_iter()
+            .map(|t| {
+                let result = match t.output {
+                    Some(output) => AgentResult::success(t.name, output, self.client_mode.clone()),
+                    None => AgentResult::failed(
+                        t.name,
+                        format!("No output from teammate (task {})", t.status),
+                        self.client_mode.clone(),
+                    ),
+                };
+                AgentResult {
+                    team: Some(self.agent.clone()),
+                    role: Some(t.role.trim().to_string()),
+                    ..result
+                }
+            })
+            .collect();
+        self
+    }
+}
+
+/// List each team lead's teammates right after it, so every teammate is a
+/// result of its own.
+pub fn flatten_teams(results: Vec<AgentResult>) -> Vec<AgentResult> {
+    results
+        .into_iter()
+        .flat_map(|mut result| {
+            let teammates = std::mem::take(&mut result.teammates);
+            std::iter::once(result).chain(teammates)
+        })
+        .collect()
 }
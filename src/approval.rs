@@ -0,0 +1,367 @@
+//! Approval gates: the output of an agent with `requires_approval` is held
+//! until a person approves it, and nothing after it in the run starts before
+//! then. A rejected output cancels the rest of the run.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::agent_log::safe_name;
+use crate::agents::AgentResult;
+use crate::config::ApprovalsConfig;
+use crate::encryption::{self, Cipher};
+use crate::progress::LiveProgress;
+
+/// The decision on a gated result; recorded on the result.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Approval {
+    pub approved: bool,
+    pub decided_at: DateTime<Utc>,
+}
+
+impl Approval {
+    fn new(approved: bool) -> Self {
+        Self {
+            approved,
+            decided_at: Utc::now(),
+        }
+    }
+}
+
+/// Decides whether a gated agent's output may feed the rest of the run.
+#[async_trait]
+pub trait Approver: Send + Sync {
+    /// Wait until someone approves or rejects `result` of run `run_id`.
+    async fn decide(&self, run_id: &str, result: &AgentResult) -> Result<Approval>;
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+/// A gated result as stored in the approvals directory. Under
+/// `outputs.encryption` the output is sealed and kept hex-encoded; read it
+/// with [`ApprovalRequest::output`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    pub run_id: String,
+    pub agent: String,
+    pub output: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sealed: bool,
+    pub status: ApprovalStatus,
+    pub requested_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_at: Option<DateTime<Utc>>,
+}
+
+impl ApprovalRequest {
+    /// The output in plain text, opened with `cipher` if it was sealed.
+    pub fn output(&self, cipher: Option<&Cipher>) -> Result<String> {
+        if !self.sealed {
+            return Ok(self.output.clone());
+        }
+        let cipher = cipher.with_context(|| {
+            format!(
+                "The output of {} is encrypted; set outputs.encryption to read it",
+                self.agent
+            )
+        })?;
+        let sealed = encryption::decode_hex(&self.output)
+            .with_context(|| format!("Malformed sealed output for {}", self.agent))?;
+        let plain = cipher.open(&sealed)?;
+        String::from_utf8(plain).with_context(|| format!("Output of {} isn't UTF-8", self.agent))
+    }
+}
+
+/// Approval requests kept as `<dir>/<run_id>/<agent>.json`. A request is
+/// decided by `approvals approve|reject`, or by any tool, such as the
+/// dashboard, that rewrites its `status`.
+pub struct ApprovalQueue {
+    dir: PathBuf,
+}
+
+impl ApprovalQueue {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path(&self, run_id: &str, agent: &str) -> PathBuf {
+        self.dir
+            .join(safe_name(run_id))
+            .join(format!("{}.json", safe_name(agent)))
+    }
+
+    pub fn load(&self, run_id: &str, agent: &str) -> Result<ApprovalRequest> {
+        read(&self.path(run_id, agent))
+    }
+
+    /// Every request still waiting for a decision, oldest first.
+    pub fn pending(&self) -> Result<Vec<ApprovalRequest>> {
+        let mut pending = Vec::new();
+        let Ok(runs) = fs::read_dir(&self.dir) else {
+            return Ok(pending);
+        };
+        for run in runs.flatten() {
+            let Ok(files) = fs::read_dir(run.path()) else {
+                continue;
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                if path.extension().is_some_and(|e| e == "json") {
+                    match read(&path) {
+                        Ok(request) if request.status == ApprovalStatus::Pending => {
+                            pending.push(request)
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("{:#}", e),
+                    }
+                }
+            }
+        }
+        pending.sort_by_key(|r| r.requested_at);
+        Ok(pending)
+    }
+
+    /// Approve or reject a pending request.
+    pub fn decide(&self, run_id: &str, agent: &str, approved: bool) -> Result<()> {
+        let mut request = self.load(run_id, agent)?;
+        if request.status != ApprovalStatus::Pending {
+            anyhow::bail!(
+                "{} of run {} was already {:?}",
+                agent,
+                run_id,
+                request.status
+            );
+        }
+        request.status = if approved {
+            ApprovalStatus::Approved
+        } else {
+            ApprovalStatus::Rejected
+        };
+        request.decided_at = Some(Utc::now());
+        self.save(&request)
+    }
+
+    /// Write to a temporary file and rename it, so a poller never reads a
+    /// half-written request.
+    fn save(&self, request: &ApprovalRequest) -> Result<()> {
+        let path = self.path(&request.run_id, &request.agent);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(request)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+fn read(path: &Path) -> Result<ApprovalRequest> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("No approval request at {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Files a request in an [`ApprovalQueue`] and polls it until it's decided.
+pub struct FileApprover {
+    queue: ApprovalQueue,
+    poll: Duration,
+    cipher: Option<Arc<Cipher>>,
+}
+
+impl FileApprover {
+    pub fn new(config: &ApprovalsConfig) -> Self {
+        Self {
+            queue: ApprovalQueue::new(&config.dir),
+            poll: Duration::from_secs(config.poll_seconds.max(1)),
+            cipher: None,
+        }
+    }
+
+    /// Seal the outputs it files, as `outputs.encryption` does for results.
+    pub fn with_cipher(mut self, cipher: Option<Arc<Cipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+}
+
+#[async_trait]
+impl Approver for FileApprover {
+    async fn decide(&self, run_id: &str, result: &AgentResult) -> Result<Approval> {
+        let output = result.output.clone().unwrap_or_default();
+        let output = match self.cipher {
+            Some(ref cipher) => crate::remote::hex(&cipher.seal(output.as_bytes())?),
+            None => output,
+        };
+        let request = ApprovalRequest {
+            run_id: run_id.to_string(),
+            agent: result.agent.clone(),
+            output,
+            sealed: self.cipher.is_some(),
+            status: ApprovalStatus::Pending,
+            requested_at: Utc::now(),
+            decided_at: None,
+        };
+        self.queue.save(&request)?;
+        warn!(
+            "{} is waiting for approval: run `agent-orchestra approvals approve {} {}` (or reject)",
+            result.agent, run_id, result.agent
+        );
+        loop {
+            tokio::time::sleep(self.poll).await;
+            match self.queue.load(run_id, &result.agent)?.status {
+                ApprovalStatus::Pending => continue,
+                ApprovalStatus::Approved => return Ok(Approval::new(true)),
+                ApprovalStatus::Rejected => return Ok(Approval::new(false)),
+            }
+        }
+    }
+}
+
+/// Shows the output on the terminal and asks there, pausing the live
+/// progress view while it does.
+#[derive(Default)]
+pub struct TerminalApprover {
+    progress: Option<Arc<LiveProgress>>,
+}
+
+impl TerminalApprover {
+    pub fn new(progress: Option<Arc<LiveProgress>>) -> Self {
+        Self { progress }
+    }
+}
+
+#[async_trait]
+impl Approver for TerminalApprover {
+    async fn decide(&self, run_id: &str, result: &AgentResult) -> Result<Approval> {
+        let header = format!(
+            "\n── {} ({}) is waiting for approval ──\n{}\n",
+            result.agent,
+            run_id,
+            result.output.as_deref().unwrap_or("").trim_end()
+        );
+        let progress = self.progress.clone();
+        let approved = tokio::task::spawn_blocking(move || {
+            let ask = || -> Result<bool> {
+                let mut stderr = io::stderr();
+                write!(stderr, "{}\nApprove and continue? [y/N] ", header)?;
+                stderr.flush()?;
+                let mut answer = String::new();
+                io::stdin().lock().read_line(&mut answer)?;
+                Ok(is_yes(&answer))
+            };
+            match progress {
+                Some(progress) => progress.suspend(ask),
+                None => ask(),
+            }
+        })
+        .await??;
+        Ok(Approval::new(approved))
+    }
+}
+
+/// `y` or `yes`, in any case; anything else, including no answer, rejects.
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_approver_waits_for_decision() {
+        let dir = std::env::temp_dir().join(format!("orchestra-approvals-{}", std::process::id()));
+        let config = ApprovalsConfig {
+            dir: dir.display().to_string(),
+            poll_seconds: 1,
+        };
+        let approver = FileApprover::new(&config);
+        let result = AgentResult::success(
+            "mapper[logs/a.log]".to_string(),
+            "Restart the API".to_string(),
+            "mock".to_string(),
+        );
+        let waiting = approver.decide("auto-1", &result);
+        let deciding = async {
+            let queue = ApprovalQueue::new(&dir);
+            loop {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                if let Ok(pending) = queue.pending() {
+                    if let [request] = pending.as_slice() {
+                        assert_eq!(request.output(None).unwrap(), "Restart the API");
+                        queue.decide("auto-1", &request.agent, false).unwrap();
+                        return;
+                    }
+                }
+            }
+        };
+        let (approval, ()) = tokio::join!(waiting, deciding);
+        assert!(!approval.unwrap().approved);
+
+        let queue = ApprovalQueue::new(&dir);
+        assert!(queue.pending().unwrap().is_empty());
+        assert!(queue
+            .path("auto-1", &result.agent)
+            .ends_with("auto-1/mapper_logs_a.log_.json"));
+        assert!(queue.decide("auto-1", &result.agent, true).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sealed_request_needs_the_key() {
+        let dir = std::env::temp_dir().join(format!("orchestra-sealed-{}", std::process::id()));
+        let config = ApprovalsConfig {
+            dir: dir.display().to_string(),
+            poll_seconds: 1,
+        };
+        let cipher = Arc::new(Cipher::new(&[7; 32]));
+        let approver = FileApprover::new(&config).with_cipher(Some(cipher.clone()));
+        let result = AgentResult::success(
+            "planner".to_string(),
+            "Rotate the keys".to_string(),
+            "mock".to_string(),
+        );
+        let queue = ApprovalQueue::new(&dir);
+        let waiting = approver.decide("auto-2", &result);
+        let deciding = async {
+            loop {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                if let Ok(request) = queue.load("auto-2", "planner") {
+                    queue.decide("auto-2", "planner", true).unwrap();
+                    return request;
+                }
+            }
+        };
+        let (approval, request) = tokio::join!(waiting, deciding);
+        assert!(approval.unwrap().approved);
+
+        let file = fs::read_to_string(queue.path("auto-2", "planner")).unwrap();
+        assert!(!file.contains("Rotate the keys"));
+        assert!(request.sealed);
+        assert!(request.output(None).is_err());
+        assert_eq!(request.output(Some(&cipher)).unwrap(), "Rotate the keys");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" Yes "));
+        assert!(!is_yes("no"));
+        assert!(!is_yes(""));
+    }
+}
@@ -0,0 +1,288 @@
+//! `agent-orchestra bench`: the same prompts sent to several client modes
+//! and models, each call timed, so choosing between `api`, `claude-code`
+//! and `hybrid` for an agent can rest on numbers. The report lists latency
+//! percentiles, failure rate, tokens and cost per target, overall and per
+//! prompt, and keeps every call it was worked out from.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// One timed call.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Sample {
+    pub prompt: String,
+    /// `mode` or `mode:model`.
+    pub target: String,
+    /// 1-based round this call was part of.
+    pub round: u32,
+    pub latency_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Model that answered, if the backend says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Reported by the backend, else estimated from the text.
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Reported by the backend (the CLI's is at API prices), else worked
+    /// out from the tokens; nothing for CLI modes that don't report one.
+    pub cost_usd: f64,
+}
+
+impl Sample {
+    pub fn ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// What a target's calls add up to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TargetStats {
+    pub target: String,
+    pub calls: usize,
+    pub failures: usize,
+    /// Latency percentiles of the calls that succeeded.
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl TargetStats {
+    fn of<'a>(target: &str, samples: impl Iterator<Item = &'a Sample>) -> Self {
+        let samples: Vec<&Sample> = samples.collect();
+        let mut latencies: Vec<u64> = samples
+            .iter()
+            .filter(|s| s.ok())
+            .map(|s| s.latency_ms)
+            .collect();
+        latencies.sort_unstable();
+        Self {
+            target: target.to_string(),
+            calls: samples.len(),
+            failures: samples.iter().filter(|s| !s.ok()).count(),
+            p50_ms: percentile(&latencies, 50.0),
+            p90_ms: percentile(&latencies, 90.0),
+            p99_ms: percentile(&latencies, 99.0),
+            input_tokens: samples.iter().map(|s| s.input_tokens).sum(),
+            output_tokens: samples.iter().map(|s| s.output_tokens).sum(),
+            cost_usd: samples.iter().map(|s| s.cost_usd).sum(),
+        }
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        if self.calls == 0 {
+            return 0.0;
+        }
+        self.failures as f64 / self.calls as f64
+    }
+
+    pub fn cost_per_call(&self) -> f64 {
+        if self.calls == 0 {
+            return 0.0;
+        }
+        self.cost_usd / self.calls as f64
+    }
+}
+
+/// Nearest-rank percentile of `sorted`.
+pub fn percentile(sorted: &[u64], p: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// One prompt's calls, per target.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PromptStats {
+    pub prompt: String,
+    pub targets: Vec<TargetStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub run_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub rounds: u32,
+    pub targets: Vec<TargetStats>,
+    pub prompts: Vec<PromptStats>,
+    pub samples: Vec<Sample>,
+}
+
+impl BenchReport {
+    /// Add up `samples` per target, keeping `targets` and `prompts` in the
+    /// order given.
+    pub fn new(
+        run_id: &str,
+        timestamp: DateTime<Utc>,
+        rounds: u32,
+        targets: &[String],
+        prompts: &[String],
+        samples: Vec<Sample>,
+    ) -> Self {
+        let stats = |prompt: Option<&str>| -> Vec<TargetStats> {
+            targets
+                .iter()
+                .map(|target| {
+                    TargetStats::of(
+                        target,
+                        samples.iter().filter(|s| {
+                            &s.target == target && prompt.is_none_or(|p| s.prompt == p)
+                        }),
+                    )
+                })
+                .collect()
+        };
+        Self {
+            run_id: run_id.to_string(),
+            timestamp,
+            rounds,
+            targets: stats(None),
+            prompts: prompts
+                .iter()
+                .map(|p| PromptStats {
+                    prompt: p.clone(),
+                    targets: stats(Some(p)),
+                })
+                .collect(),
+            samples,
+        }
+    }
+
+    /// The comparison as a Markdown document.
+    pub fn markdown(&self) -> String {
+        let mut out = format!(
+            "# Benchmark {}\n\n{}, {} prompt(s) x {} round(s) per target.\n\n",
+            self.run_id,
+            self.timestamp.format("%Y-%m-%d %H:%M UTC"),
+            self.prompts.len(),
+            self.rounds
+        );
+        out.push_str(
+            "| target | calls | failed | p50 | p90 | p99 | in tokens | out tokens | cost | per call |\n\
+             |---|---:|---:|---:|---:|---:|---:|---:|---:|---:|\n",
+        );
+        for t in &self.targets {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {:.0}% | {} | {} | {} | {} | {} | ${:.4} | ${:.4} |",
+                t.target,
+                t.calls,
+                t.failure_rate() * 100.0,
+                seconds(t.p50_ms),
+                seconds(t.p90_ms),
+                seconds(t.p99_ms),
+                t.input_tokens,
+                t.output_tokens,
+                t.cost_usd,
+                t.cost_per_call()
+            );
+        }
+        for PromptStats { prompt, targets } in &self.prompts {
+            let _ = write!(
+                out,
+                "\n## {}\n\n| target | failed | p50 | p90 | out tokens | cost |\n\
+                 |---|---:|---:|---:|---:|---:|\n",
+                prompt
+            );
+            for t in targets {
+                let _ = writeln!(
+                    out,
+                    "| {} | {}/{} | {} | {} | {} | ${:.4} |",
+                    t.target,
+                    t.failures,
+                    t.calls,
+                    seconds(t.p50_ms),
+                    seconds(t.p90_ms),
+                    t.output_tokens,
+                    t.cost_usd
+                );
+            }
+        }
+        let failures: Vec<&Sample> = self.samples.iter().filter(|s| !s.ok()).collect();
+        if !failures.is_empty() {
+            out.push_str("\n## Failures\n\n");
+            for s in failures {
+                let _ = writeln!(
+                    out,
+                    "- {} / {} (round {}): {}",
+                    s.target,
+                    s.prompt,
+                    s.round,
+                    s.error.as_deref().unwrap_or_default()
+                );
+            }
+        }
+        out
+    }
+}
+
+fn seconds(ms: Option<u64>) -> String {
+    match ms {
+        Some(ms) => format!("{:.1}s", ms as f64 / 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(target: &str, prompt: &str, latency_ms: u64, error: Option<&str>) -> Sample {
+        Sample {
+            prompt: prompt.to_string(),
+            target: target.to_string(),
+            round: 1,
+            latency_ms,
+            error: error.map(str::to_string),
+            model: None,
+            input_tokens: 100,
+            output_tokens: 50,
+            cost_usd: if target == "api" { 0.001 } else { 0.0 },
+        }
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let sorted: Vec<u64> = (1..=10).collect();
+        assert_eq!(percentile(&sorted, 50.0), Some(5));
+        assert_eq!(percentile(&sorted, 90.0), Some(9));
+        assert_eq!(percentile(&sorted, 99.0), Some(10));
+        assert_eq!(percentile(&[7], 0.0), Some(7));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_report_compares_targets() {
+        let targets = vec!["api".to_string(), "claude-code".to_string()];
+        let prompts = vec!["triage".to_string(), "summary".to_string()];
+        let samples = vec![
+            sample("api", "triage", 1200, None),
+            sample("api", "summary", 800, None),
+            sample("claude-code", "triage", 9000, None),
+            sample("claude-code", "summary", 0, Some("timed out after 180s")),
+        ];
+        let report = BenchReport::new("01J", Utc::now(), 1, &targets, &prompts, samples);
+
+        let api = &report.targets[0];
+        assert_eq!((api.calls, api.failures), (2, 0));
+        assert_eq!((api.p50_ms, api.p99_ms), (Some(800), Some(1200)));
+        assert!((api.cost_per_call() - 0.001).abs() < 1e-12);
+        let cli = &report.targets[1];
+        assert_eq!(cli.failure_rate(), 0.5);
+        // Failed calls don't count toward latency
+        assert_eq!(cli.p90_ms, Some(9000));
+        assert_eq!(report.prompts[1].targets[1].failures, 1);
+
+        let markdown = report.markdown();
+        assert!(markdown
+            .contains("| api | 2 | 0% | 0.8s | 1.2s | 1.2s | 200 | 100 | $0.0020 | $0.0010 |"));
+        assert!(markdown.contains("## summary"));
+        assert!(markdown.contains("- claude-code / summary (round 1): timed out after 180s"));
+    }
+}
@@ -0,0 +1,178 @@
+//! Circuit breaker for the Messages API, shared by every hybrid agent in the
+//! process. Once the API has failed `failure_threshold` times in a row the
+//! circuit opens and hybrid agents use their CLI fallback without trying the
+//! API. After `cooldown_seconds` the next request probes the API; success
+//! closes the circuit, failure opens it for another cool-down.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::client::ApiStatusError;
+use crate::config::CircuitBreakerConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// One request is trying the API. If it never reports back (its agent
+    /// timed out), another may probe after a cool-down.
+    Probing {
+        since: Instant,
+    },
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: Mutex<State>,
+}
+
+static SHARED: CircuitBreaker = CircuitBreaker::new();
+
+/// The breaker every [`HybridClient`](crate::client::HybridClient) shares.
+pub fn shared() -> &'static CircuitBreaker {
+    &SHARED
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreaker {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a request may try the API now. When the cool-down is over,
+    /// the first caller gets `true` and becomes the probe.
+    pub fn allow(&self, config: &CircuitBreakerConfig, now: Instant) -> bool {
+        let cooldown = Duration::from_secs(config.cooldown_seconds);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if now < until => false,
+            State::Probing { since } if now < since + cooldown => false,
+            State::Open { .. } | State::Probing { .. } => {
+                info!("API circuit half-open; probing the API");
+                *state = State::Probing { since: now };
+                true
+            }
+        }
+    }
+
+    /// Record how an API request went. Only `outage` failures count; any
+    /// other result shows the API is up.
+    pub fn record(&self, config: &CircuitBreakerConfig, outage: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let open = State::Open {
+            until: now + Duration::from_secs(config.cooldown_seconds),
+        };
+        *state = match (*state, outage) {
+            (State::Closed { .. }, false) => State::Closed { failures: 0 },
+            (State::Open { .. } | State::Probing { .. }, false) => {
+                info!("API recovered; circuit closed");
+                State::Closed { failures: 0 }
+            }
+            (State::Closed { failures }, true) if failures + 1 < config.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (State::Closed { failures }, true) => {
+                warn!(
+                    "API failed {} times in a row; hybrid agents will use the CLI for {}s",
+                    failures + 1,
+                    config.cooldown_seconds
+                );
+                open
+            }
+            (State::Probing { .. }, true) => {
+                warn!(
+                    "API still failing; hybrid agents will use the CLI for another {}s",
+                    config.cooldown_seconds
+                );
+                open
+            }
+            // A request that started before the circuit opened
+            (State::Open { .. }, true) => *state,
+        };
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(
+            *self.state.lock().unwrap_or_else(|e| e.into_inner()),
+            State::Closed { .. }
+        )
+    }
+}
+
+/// Whether an API error suggests the API itself is unavailable: a server
+/// error, overload or rate limit, or no response at all. Other client errors
+/// (a bad request, an unknown model) don't trip the breaker.
+pub fn is_outage(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<ApiStatusError>() {
+        Some(e) => e.status >= 500 || matches!(e.status, 408 | 429),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: 2,
+            cooldown_seconds: 60,
+        }
+    }
+
+    #[test]
+    fn test_breaker_opens_cools_down_and_probes() {
+        let (config, breaker, t0) = (config(), CircuitBreaker::new(), Instant::now());
+        breaker.record(&config, true, t0);
+        breaker.record(&config, false, t0);
+        breaker.record(&config, true, t0);
+        assert!(breaker.allow(&config, t0), "failures must be consecutive");
+        breaker.record(&config, true, t0);
+        assert!(!breaker.allow(&config, t0 + Duration::from_secs(59)));
+
+        // One probe after the cool-down; it fails, so the circuit reopens
+        let t1 = t0 + Duration::from_secs(60);
+        assert!(breaker.allow(&config, t1));
+        assert!(!breaker.allow(&config, t1));
+        breaker.record(&config, true, t1);
+        assert!(!breaker.allow(&config, t1 + Duration::from_secs(30)));
+
+        let t2 = t1 + Duration::from_secs(60);
+        assert!(breaker.allow(&config, t2));
+        breaker.record(&config, false, t2);
+        assert!(!breaker.is_open());
+        assert!(breaker.allow(&config, t2));
+    }
+
+    #[test]
+    fn test_only_outages_trip_the_breaker() {
+        let status = |status| {
+            anyhow::Error::new(ApiStatusError {
+                status,
+                body: String::new(),
+            })
+            .context("Failed to send message to Claude")
+        };
+        assert!(is_outage(&status(529)));
+        assert!(is_outage(&status(429)));
+        assert!(!is_outage(&status(400)));
+        assert!(!is_outage(&status(404)));
+        assert!(is_outage(&anyhow::anyhow!("connection refused")));
+    }
+}
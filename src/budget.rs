@@ -0,0 +1,128 @@
+//! Input budgets: an agent's prompt is estimated in tokens just before it is
+//! sent, and one over the agent's `budget.max_input_tokens` is cut in the
+//! middle, where injected logs and context usually are, rather than failing
+//! against the model's context limit partway through a run. The start of the
+//! prompt and, more generously, its end (where the instructions are) stay.
+
+use crate::config::BudgetConfig;
+use crate::cost;
+
+/// What the cut middle is replaced with, at most.
+const MARKER_BYTES: usize = 128;
+
+/// Bytes of prompt the budget leaves once `reserved` bytes (the system
+/// prompt and anything appended later) are accounted for.
+pub fn prompt_bytes(budget: &BudgetConfig, reserved: usize) -> usize {
+    let bytes = budget.max_input_tokens as f64 * cost::CHARS_PER_TOKEN;
+    (bytes as usize).saturating_sub(reserved)
+}
+
+/// Split `text` into a head and tail that together fit `max` bytes, and the
+/// middle between them; `None` if it already fits. The head gets a quarter.
+pub fn split(text: &str, max: usize) -> Option<(&str, &str, &str)> {
+    if text.len() <= max {
+        return None;
+    }
+    let keep = max.saturating_sub(MARKER_BYTES);
+    let mut head = keep / 4;
+    while !text.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = text.len() - (keep - keep / 4);
+    while !text.is_char_boundary(tail) {
+        tail += 1;
+    }
+    Some((&text[..head], &text[head..tail], &text[tail..]))
+}
+
+/// `text` with its middle cut to fit `max` bytes.
+pub fn head_tail(text: &str, max: usize) -> String {
+    match split(text, max) {
+        Some((head, middle, tail)) => format!(
+            "{}\n[... {} bytes cut to fit the input budget ...]\n{}",
+            head,
+            middle.len(),
+            tail
+        ),
+        None => text.to_string(),
+    }
+}
+
+/// `text` in pieces of at most `max` bytes, broken at line ends where a
+/// line allows it.
+pub fn chunks(text: &str, max: usize) -> Vec<&str> {
+    let max = max.max(MARKER_BYTES);
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let end = match rest[..end].rfind('\n') {
+            Some(newline) if newline > 0 => newline + 1,
+            _ => end,
+        };
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// The prompt asking the summarizer to condense one piece of a cut middle.
+pub fn summary_prompt(piece: &str) -> String {
+    format!(
+        "The material below was cut from another agent's prompt because it \
+         is too long. Summarize it for that agent: keep errors, warnings, \
+         numbers, names and anything unusual, and drop repetition.\n\n{}",
+        piece
+    )
+}
+
+/// `head` and `tail` around `summary` of the `cut` bytes between them.
+pub fn with_summary(head: &str, cut: usize, summary: &str, tail: &str) -> String {
+    format!(
+        "{}\n[... {} bytes summarized to fit the input budget:]\n{}\n[end of summary]\n{}",
+        head,
+        cut,
+        summary.trim(),
+        tail
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_head_tail_keeps_both_ends() {
+        let budget = BudgetConfig {
+            max_input_tokens: 300,
+            ..BudgetConfig::default()
+        };
+        let max = prompt_bytes(&budget, 200);
+        assert_eq!(max, 1000);
+        let text = format!("HEAD{}TAIL", "é".repeat(2000));
+        let cut = head_tail(&text, max);
+        assert!(cut.len() <= max, "{}", cut.len());
+        assert!(cut.starts_with("HEAD") && cut.ends_with("TAIL"));
+        assert!(cut.contains("bytes cut to fit the input budget"));
+        assert_eq!(head_tail("short", max), "short");
+
+        let (head, middle, tail) = split(&text, max).unwrap();
+        assert_eq!(format!("{}{}{}", head, middle, tail), text);
+        assert!(tail.len() > head.len() * 2);
+    }
+
+    #[test]
+    fn test_chunks_break_at_lines() {
+        let text = "line\n".repeat(100);
+        let pieces = chunks(&text, 200);
+        assert!(pieces.iter().all(|p| p.len() <= 200 && p.ends_with('\n')));
+        assert_eq!(pieces.concat(), text);
+        assert_eq!(chunks("", 200), Vec::<&str>::new());
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::agents::AgentResult;
+use crate::encryption::{self, Cipher};
+
+/// Progress of a run that hasn't finished yet, saved as each agent completes
+/// so `--resume <run-id>` can skip agents that already succeeded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub run_id: String,
+    pub mode: String,
+    pub timestamp: DateTime<Utc>,
+    pub results: Vec<AgentResult>,
+}
+
+impl Checkpoint {
+    pub fn new(run_id: &str, mode: &str, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            mode: mode.to_string(),
+            timestamp,
+            results: Vec::new(),
+        }
+    }
+
+    /// `<output_dir>/checkpoints/<run_id>.json`
+    pub fn path(output_dir: &Path, run_id: &str) -> PathBuf {
+        output_dir
+            .join("checkpoints")
+            .join(format!("{}.json", run_id))
+    }
+
+    /// The checkpoint of `run_id`, opened with `cipher` if it was written
+    /// under `outputs.encryption`.
+    pub fn load(output_dir: &Path, run_id: &str, cipher: Option<&Cipher>) -> Result<Self> {
+        let path = Self::path(output_dir, run_id);
+        let sealed = encryption::sealed_path(&path);
+        let content = if sealed.exists() {
+            let cipher = cipher.with_context(|| {
+                format!(
+                    "{} is encrypted; set outputs.encryption to resume it",
+                    sealed.display()
+                )
+            })?;
+            cipher.open(&fs::read(&sealed)?)?
+        } else {
+            fs::read(&path).with_context(|| {
+                format!("No checkpoint for run {} at {}", run_id, path.display())
+            })?
+        };
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The saved result for `agent` if it succeeded. Failed agents run again.
+    pub fn completed(&self, agent: &str) -> Option<&AgentResult> {
+        self.results
+            .iter()
+            .find(|r| r.agent == agent && r.status == "success")
+    }
+
+    fn record(&mut self, result: &AgentResult) {
+        self.results.retain(|r| r.agent != result.agent);
+        self.results.push(result.clone());
+    }
+}
+
+/// Saves a [`Checkpoint`] after every recorded result. Shared between
+/// parallel agent tasks, so writes are serialized behind a mutex.
+pub struct CheckpointWriter {
+    path: PathBuf,
+    state: Mutex<Checkpoint>,
+    /// Seals the file, which holds agent outputs, under `outputs.encryption`.
+    cipher: Option<Arc<Cipher>>,
+}
+
+impl CheckpointWriter {
+    pub fn new(output_dir: &Path, checkpoint: Checkpoint, cipher: Option<Arc<Cipher>>) -> Self {
+        let path = Checkpoint::path(output_dir, &checkpoint.run_id);
+        Self {
+            path: match cipher {
+                Some(_) => encryption::sealed_path(&path),
+                None => path,
+            },
+            state: Mutex::new(checkpoint),
+            cipher,
+        }
+    }
+
+    /// Add `result` and rewrite the file. Failures are logged, not fatal:
+    /// losing a checkpoint only costs re-running agents on resume.
+    pub fn record(&self, result: &AgentResult) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.record(result);
+        if let Err(e) = self.save(&state) {
+            warn!("Failed to write checkpoint: {:#}", e);
+        }
+    }
+
+    /// Write to a temporary file and rename it, so a crash mid-write never
+    /// leaves a truncated checkpoint behind.
+    fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let json = serde_json::to_vec_pretty(checkpoint)?;
+        let contents = match self.cipher {
+            Some(ref cipher) => cipher.seal(&json)?,
+            None => json,
+        };
+        fs::write(&tmp, contents).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Delete the checkpoint once the run has written its results.
+    pub fn remove(&self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = std::env::temp_dir().join(format!("orchestra-checkpoint-{}", std::process::id()));
+        let writer =
+            CheckpointWriter::new(&dir, Checkpoint::new("auto-1", "auto", Utc::now()), None);
+        let failed = AgentResult::failed("monitor".into(), "boom".into(), "api".into());
+        writer.record(&failed);
+        writer.record(&AgentResult::success(
+            "analyzer".into(),
+            "done".into(),
+            "api".into(),
+        ));
+
+        let loaded = Checkpoint::load(&dir, "auto-1", None).unwrap();
+        assert_eq!(loaded.mode, "auto");
+        assert_eq!(loaded.results.len(), 2);
+        assert!(loaded.completed("monitor").is_none());
+        assert_eq!(
+            loaded.completed("analyzer").unwrap().output.as_deref(),
+            Some("done")
+        );
+
+        writer.remove();
+        assert!(Checkpoint::load(&dir, "auto-1", None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_checkpoint_needs_the_key() {
+        let dir =
+            std::env::temp_dir().join(format!("orchestra-checkpoint-enc-{}", std::process::id()));
+        let cipher = Arc::new(Cipher::new(&[7; 32]));
+        let writer = CheckpointWriter::new(
+            &dir,
+            Checkpoint::new("auto-1", "auto", Utc::now()),
+            Some(Arc::clone(&cipher)),
+        );
+        writer.record(&AgentResult::success(
+            "analyzer".into(),
+            "the secret plan".into(),
+            "api".into(),
+        ));
+
+        let sealed = fs::read(dir.join("checkpoints/auto-1.json.enc")).unwrap();
+        assert!(encryption::is_sealed(&sealed));
+        assert!(!dir.join("checkpoints/auto-1.json").exists());
+        let err = Checkpoint::load(&dir, "auto-1", None).unwrap_err();
+        assert!(err.to_string().contains("is encrypted"));
+        let loaded = Checkpoint::load(&dir, "auto-1", Some(&cipher)).unwrap();
+        assert_eq!(
+            loaded.completed("analyzer").unwrap().output.as_deref(),
+            Some("the secret plan")
+        );
+
+        writer.remove();
+        assert!(Checkpoint::load(&dir, "auto-1", Some(&cipher)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,552 @@
+use anyhow::{Context, Result};
+
+use agent_orchestra::config::BenchTarget;
+use agent_orchestra::graph::GraphFormat;
+use agent_orchestra::replay::Recording;
+
+/// Command-line arguments. Runtime behaviour is still mostly driven by env vars
+/// (`ORCHESTRATOR_MODE`, `CLIENT_MODE`); flags cover per-invocation inputs.
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    pub command: Command,
+    /// Template variables from `--var key=value` (repeatable).
+    pub vars: Vec<(String, String)>,
+    /// Row limit for listing commands (`--limit N`).
+    pub limit: Option<usize>,
+    /// Only consider the last N days (`--days N`).
+    pub days: Option<u32>,
+    /// Restrict a command to one agent (`--agent NAME`).
+    pub agent: Option<String>,
+    /// Template variable that receives everything piped on stdin (`--stdin-var name`).
+    pub stdin_var: Option<String>,
+    /// Orchestration mode (`--mode`), overriding `ORCHESTRATOR_MODE`.
+    pub mode: Option<String>,
+    /// Print the resolved plan and cost estimate without calling any backend.
+    pub dry_run: bool,
+    /// Emit the plan as a graph instead of text (`--graph dot|mermaid`).
+    pub graph: Option<GraphFormat>,
+    /// Continue an interrupted run from its checkpoint (`--resume RUN_ID`).
+    pub resume: Option<String>,
+    /// Save backend replies (`--record DIR`) or answer from them (`--replay DIR`).
+    pub recording: Option<Recording>,
+    /// Don't draw the live per-agent view even on a terminal (`--no-progress`).
+    pub no_progress: bool,
+    /// Rounds for `bench` (`--runs N`), overriding `bench.runs`.
+    pub runs: Option<u32>,
+    /// Paths whose changes start a run (`--watch PATH`, repeatable).
+    pub watch: Vec<String>,
+    /// Targets for `bench` (`--target mode[:model]`, repeatable), replacing
+    /// `bench.targets`.
+    pub targets: Vec<BenchTarget>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Command {
+    /// Run the orchestration (the default when no subcommand is given).
+    #[default]
+    Run,
+    /// Inspect and act on the queue of agent-proposed actions.
+    Actions(ActionsCommand),
+    /// Decide on gated agent outputs that runs are waiting on.
+    Approvals(ApprovalsCommand),
+    /// Backfill the history store from old `results-*.json`/`summary-*.txt`
+    /// files in the given directory (default: the outputs directory).
+    Import(Option<String>),
+    /// Query the history store.
+    History(HistoryCommand),
+    /// Summarize estimated costs from the history store.
+    Costs,
+    /// Agent utilities.
+    Agents(AgentsCommand),
+    /// Show what a run would do; same as `run --dry-run` unless `--graph` is given.
+    Plan,
+    /// Check a config file (default: config/orchestra.yml) and report problems.
+    Validate(Option<String>),
+    /// Run on the `orchestra.schedule` interval, reloading the config between cycles.
+    Daemon,
+    /// Re-execute the failed agents of an earlier `results-*.json` file.
+    Rerun(String),
+    /// Compare agent outputs of two results files (default: the two latest
+    /// runs of the same mode).
+    Diff(Option<(String, String)>),
+    /// Run under a full-screen dashboard with per-agent cancel and rerun.
+    Tui,
+    /// Take agents from the `distributed` queue and run them.
+    Worker,
+    /// Print an output file written with `outputs.encryption`.
+    Decrypt(String),
+    /// Time the `bench` prompts on each client mode and model and compare them.
+    Bench,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryCommand {
+    /// List past runs, newest first.
+    List,
+    /// Success rate and latency per agent, overall and per day; optionally for one agent.
+    Stats(Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentsCommand {
+    /// Run the configured self-tests, for one agent or all of them.
+    Test(Option<String>),
+}
+
+/// A pending approval is named by its run ID and agent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApprovalsCommand {
+    List,
+    Show(String, String),
+    Approve(String, String),
+    Reject(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionsCommand {
+    List,
+    Approve(String),
+    Reject(String),
+    /// Execute every approved action that has a command.
+    Execute,
+}
+
+const USAGE: &str = "Usage:
+  agent-orchestra [run] [--mode MODE] [--var key=value]... [--stdin-var name] [--dry-run]
+                        [--record DIR | --replay DIR] [--resume RUN_ID] [--no-progress]
+                        [--watch PATH]...
+  agent-orchestra rerun <results-file> [--var key=value]... [--record DIR | --replay DIR]
+                        [--no-progress]
+  agent-orchestra tui [--mode MODE] [--var key=value]... [--record DIR | --replay DIR]
+  agent-orchestra diff [old-results new-results] [--agent NAME]
+  agent-orchestra decrypt <file.enc>
+  agent-orchestra daemon [--mode MODE] [--var key=value]...
+  agent-orchestra worker [--record DIR | --replay DIR]
+  agent-orchestra plan [--mode MODE] [--var key=value]... [--graph dot|mermaid]
+  agent-orchestra actions list
+  agent-orchestra actions approve|reject <id>
+  agent-orchestra actions execute
+  agent-orchestra approvals list
+  agent-orchestra approvals show|approve|reject <run-id> <agent>
+  agent-orchestra import [dir]
+  agent-orchestra history [--limit N]
+  agent-orchestra history stats [agent] [--days N]
+  agent-orchestra costs
+  agent-orchestra agents test [name] [--record DIR | --replay DIR]
+  agent-orchestra bench [--runs N] [--target mode[:model]]... [--record DIR | --replay DIR]
+  agent-orchestra validate [config-file]";
+
+impl Args {
+    pub fn from_env() -> Result<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I, S>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().map(Into::into).peekable();
+
+        match args.peek().map(String::as_str) {
+            Some("run") => {
+                args.next();
+            }
+            Some("actions") => {
+                args.next();
+                parsed.command = Command::Actions(parse_actions(&mut args)?);
+            }
+            Some("approvals") => {
+                args.next();
+                parsed.command = Command::Approvals(parse_approvals(&mut args)?);
+            }
+            Some("import") => {
+                args.next();
+                let dir = args.next_if(|a| !a.starts_with('-'));
+                parsed.command = Command::Import(dir);
+            }
+            Some("history") => {
+                args.next();
+                parsed.command = Command::History(match args.peek().map(String::as_str) {
+                    Some("stats") => {
+                        args.next();
+                        HistoryCommand::Stats(args.next_if(|a| !a.starts_with('-')))
+                    }
+                    _ => HistoryCommand::List,
+                });
+            }
+            Some("costs") => {
+                args.next();
+                parsed.command = Command::Costs;
+            }
+            Some("validate") => {
+                args.next();
+                let path = args.next_if(|a| !a.starts_with('-'));
+                parsed.command = Command::Validate(path);
+            }
+            Some("daemon") => {
+                args.next();
+                parsed.command = Command::Daemon;
+            }
+            Some("worker") => {
+                args.next();
+                parsed.command = Command::Worker;
+            }
+            Some("rerun") => {
+                args.next();
+                let path = args
+                    .next_if(|a| !a.starts_with('-'))
+                    .with_context(|| format!("rerun requires a results file\n{}", USAGE))?;
+                parsed.command = Command::Rerun(path);
+            }
+            Some("decrypt") => {
+                args.next();
+                let path = args
+                    .next_if(|a| !a.starts_with('-'))
+                    .with_context(|| format!("decrypt requires a file\n{}", USAGE))?;
+                parsed.command = Command::Decrypt(path);
+            }
+            Some("diff") => {
+                args.next();
+                let files = match args.next_if(|a| !a.starts_with('-')) {
+                    Some(old) => {
+                        let new = args.next_if(|a| !a.starts_with('-')).with_context(|| {
+                            format!("diff takes two results files, or none\n{}", USAGE)
+                        })?;
+                        Some((old, new))
+                    }
+                    None => None,
+                };
+                parsed.command = Command::Diff(files);
+            }
+            Some("plan") => {
+                args.next();
+                parsed.command = Command::Plan;
+            }
+            Some("tui") => {
+                args.next();
+                parsed.command = Command::Tui;
+            }
+            Some("bench") => {
+                args.next();
+                parsed.command = Command::Bench;
+            }
+            Some("agents") => {
+                args.next();
+                match args.next().as_deref() {
+                    Some("test") => {
+                        let name = args.next_if(|a| !a.starts_with('-'));
+                        parsed.command = Command::Agents(AgentsCommand::Test(name));
+                    }
+                    other => anyhow::bail!(
+                        "Unknown agents subcommand '{}'\n{}",
+                        other.unwrap_or(""),
+                        USAGE
+                    ),
+                }
+            }
+            _ => {}
+        }
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((f, v)) if f.starts_with("--") => (f.to_string(), Some(v.to_string())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| -> Result<String> {
+                match inline.clone() {
+                    Some(v) => Ok(v),
+                    None => args
+                        .next()
+                        .with_context(|| format!("{} requires a value\n{}", name, USAGE)),
+                }
+            };
+
+            match flag.as_str() {
+                "--var" => {
+                    let pair = value("--var")?;
+                    let (key, val) = pair
+                        .split_once('=')
+                        .with_context(|| format!("--var expects key=value, got '{}'", pair))?;
+                    parsed.vars.push((key.trim().to_string(), val.to_string()));
+                }
+                "--stdin-var" => {
+                    let name = value("--stdin-var")?;
+                    if name.trim().is_empty() {
+                        anyhow::bail!("--stdin-var requires a variable name\n{}", USAGE);
+                    }
+                    parsed.stdin_var = Some(name.trim().to_string());
+                }
+                "--mode" => parsed.mode = Some(value("--mode")?),
+                "--dry-run" => parsed.dry_run = true,
+                "--no-progress" => parsed.no_progress = true,
+                "--resume" => parsed.resume = Some(value("--resume")?),
+                "--agent" => parsed.agent = Some(value("--agent")?),
+                "--graph" => parsed.graph = Some(value("--graph")?.parse()?),
+                "--watch" => parsed.watch.push(value("--watch")?),
+                "--target" => parsed.targets.push(value("--target")?.parse()?),
+                "--record" | "--replay" => {
+                    if parsed.recording.is_some() {
+                        anyhow::bail!("--record and --replay can only be given once\n{}", USAGE);
+                    }
+                    let dir = value(&flag)?.into();
+                    parsed.recording = Some(if flag == "--record" {
+                        Recording::Record(dir)
+                    } else {
+                        Recording::Replay(dir)
+                    });
+                }
+                "--days" => {
+                    let n = value("--days")?;
+                    parsed.days = Some(
+                        n.parse()
+                            .with_context(|| format!("--days expects a number, got '{}'", n))?,
+                    );
+                }
+                "--runs" => {
+                    let n = value("--runs")?;
+                    parsed.runs = Some(n.parse().ok().filter(|&n| n > 0).with_context(|| {
+                        format!("--runs expects a positive number, got '{}'", n)
+                    })?);
+                }
+                "--limit" => {
+                    let n = value("--limit")?;
+                    parsed.limit = Some(
+                        n.parse()
+                            .with_context(|| format!("--limit expects a number, got '{}'", n))?,
+                    );
+                }
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                other => anyhow::bail!("Unknown argument '{}'\n{}", other, USAGE),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_actions(args: &mut impl Iterator<Item = String>) -> Result<ActionsCommand> {
+    let sub = args.next().unwrap_or_else(|| "list".to_string());
+    let mut id = |verb: &str| -> Result<String> {
+        args.next()
+            .with_context(|| format!("actions {} requires an action id\n{}", verb, USAGE))
+    };
+    match sub.as_str() {
+        "list" => Ok(ActionsCommand::List),
+        "approve" => Ok(ActionsCommand::Approve(id("approve")?)),
+        "reject" => Ok(ActionsCommand::Reject(id("reject")?)),
+        "execute" => Ok(ActionsCommand::Execute),
+        other => anyhow::bail!("Unknown actions subcommand '{}'\n{}", other, USAGE),
+    }
+}
+
+fn parse_approvals(args: &mut impl Iterator<Item = String>) -> Result<ApprovalsCommand> {
+    let sub = args.next().unwrap_or_else(|| "list".to_string());
+    let mut target = |verb: &str| -> Result<(String, String)> {
+        let mut next = || {
+            args.next().with_context(|| {
+                format!(
+                    "approvals {} requires a run ID and an agent\n{}",
+                    verb, USAGE
+                )
+            })
+        };
+        Ok((next()?, next()?))
+    };
+    match sub.as_str() {
+        "list" => Ok(ApprovalsCommand::List),
+        "show" => target("show").map(|(run, agent)| ApprovalsCommand::Show(run, agent)),
+        "approve" => target("approve").map(|(run, agent)| ApprovalsCommand::Approve(run, agent)),
+        "reject" => target("reject").map(|(run, agent)| ApprovalsCommand::Reject(run, agent)),
+        other => anyhow::bail!("Unknown approvals subcommand '{}'\n{}", other, USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vars() {
+        let args = Args::parse(["--var", "env=prod", "--var=region=us-east=1"]).unwrap();
+        assert_eq!(args.command, Command::Run);
+        assert_eq!(
+            args.vars,
+            vec![
+                ("env".to_string(), "prod".to_string()),
+                ("region".to_string(), "us-east=1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_run_inputs() {
+        let args = Args::parse(["run", "--mode", "monitoring", "--stdin-var=events"]).unwrap();
+        assert_eq!(args.command, Command::Run);
+        assert!(!args.dry_run);
+        assert!(Args::parse(["--dry-run"]).unwrap().dry_run);
+        assert_eq!(args.mode.as_deref(), Some("monitoring"));
+        assert_eq!(args.stdin_var.as_deref(), Some("events"));
+        assert!(Args::parse(["--stdin-var", ""]).is_err());
+        let args = Args::parse(["--resume", "01KF3M2Q8V6W9XJ5T0R7C4N1BD"]).unwrap();
+        assert_eq!(args.resume.as_deref(), Some("01KF3M2Q8V6W9XJ5T0R7C4N1BD"));
+        assert!(!args.no_progress);
+        assert!(Args::parse(["--no-progress"]).unwrap().no_progress);
+        let args = Args::parse(["--watch", "/var/log/app", "--watch=deploy.done"]).unwrap();
+        assert_eq!(args.watch, vec!["/var/log/app", "deploy.done"]);
+    }
+
+    #[test]
+    fn test_parse_record_replay() {
+        let args = Args::parse(["--record", "fixtures/run"]).unwrap();
+        assert_eq!(
+            args.recording,
+            Some(Recording::Record("fixtures/run".into()))
+        );
+        let args = Args::parse(["agents", "test", "--replay=fixtures/tests"]).unwrap();
+        assert_eq!(
+            args.recording,
+            Some(Recording::Replay("fixtures/tests".into()))
+        );
+        assert!(Args::parse(["--record", "a", "--replay", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_input() {
+        assert!(Args::parse(["--var", "novalue"]).is_err());
+        assert!(Args::parse(["--var"]).is_err());
+        assert!(Args::parse(["--bogus"]).is_err());
+        assert!(Args::parse(["actions", "approve"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_actions_subcommands() {
+        let args = Args::parse(["actions", "approve", "abc123"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::Actions(ActionsCommand::Approve("abc123".to_string()))
+        );
+        let args = Args::parse(["actions"]).unwrap();
+        assert_eq!(args.command, Command::Actions(ActionsCommand::List));
+    }
+
+    #[test]
+    fn test_parse_approvals_subcommands() {
+        let args = Args::parse([
+            "approvals",
+            "reject",
+            "01KF3M2Q8V6W9XJ5T0R7C4N1BD",
+            "monitor",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.command,
+            Command::Approvals(ApprovalsCommand::Reject(
+                "01KF3M2Q8V6W9XJ5T0R7C4N1BD".to_string(),
+                "monitor".to_string()
+            ))
+        );
+        let args = Args::parse(["approvals"]).unwrap();
+        assert_eq!(args.command, Command::Approvals(ApprovalsCommand::List));
+        assert!(Args::parse(["approvals", "approve", "01KF3M2Q8V6W9XJ5T0R7C4N1BD"]).is_err());
+        assert!(Args::parse(["approvals", "skip"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_plan_graph() {
+        let args = Args::parse(["plan", "--mode", "research", "--graph", "mermaid"]).unwrap();
+        assert_eq!(args.command, Command::Plan);
+        assert_eq!(args.graph, Some(GraphFormat::Mermaid));
+        assert!(Args::parse(["plan", "--graph", "png"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_agents_test() {
+        let args = Args::parse(["agents", "test", "monitor"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::Agents(AgentsCommand::Test(Some("monitor".to_string())))
+        );
+        let args = Args::parse(["agents", "test"]).unwrap();
+        assert_eq!(args.command, Command::Agents(AgentsCommand::Test(None)));
+        assert!(Args::parse(["agents"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_diff() {
+        let args = Args::parse(["diff", "a.json", "b.json", "--agent", "monitor"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::Diff(Some(("a.json".to_string(), "b.json".to_string())))
+        );
+        assert_eq!(args.agent.as_deref(), Some("monitor"));
+        assert_eq!(Args::parse(["diff"]).unwrap().command, Command::Diff(None));
+        assert!(Args::parse(["diff", "a.json"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rerun() {
+        let args =
+            Args::parse(["rerun", "outputs/results-01KF3M2Q8V6W9XJ5T0R7C4N1BD.json"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::Rerun("outputs/results-01KF3M2Q8V6W9XJ5T0R7C4N1BD.json".to_string())
+        );
+        assert!(Args::parse(["rerun"]).is_err());
+        let args = Args::parse([
+            "decrypt",
+            "outputs/summary-01KF3M2Q8V6W9XJ5T0R7C4N1BD.txt.enc",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.command,
+            Command::Decrypt("outputs/summary-01KF3M2Q8V6W9XJ5T0R7C4N1BD.txt.enc".to_string())
+        );
+        assert!(Args::parse(["decrypt"]).is_err());
+        let args = Args::parse(["tui", "--mode", "monitoring"]).unwrap();
+        assert_eq!(args.command, Command::Tui);
+        assert_eq!(args.mode.as_deref(), Some("monitoring"));
+        assert_eq!(Args::parse(["worker"]).unwrap().command, Command::Worker);
+    }
+
+    #[test]
+    fn test_parse_bench() {
+        let args = Args::parse([
+            "bench",
+            "--runs",
+            "5",
+            "--target",
+            "api:claude-haiku-4-5",
+            "--target=claude-code",
+        ])
+        .unwrap();
+        assert_eq!(args.command, Command::Bench);
+        assert_eq!(args.runs, Some(5));
+        let targets: Vec<String> = args.targets.iter().map(BenchTarget::label).collect();
+        assert_eq!(targets, vec!["api:claude-haiku-4-5", "claude-code"]);
+        assert!(Args::parse(["bench", "--runs", "0"]).is_err());
+        assert!(Args::parse(["bench", "--target", "api:"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_history_commands() {
+        let args = Args::parse(["import", "old-outputs"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::Import(Some("old-outputs".to_string()))
+        );
+        let args = Args::parse(["history", "--limit", "5"]).unwrap();
+        assert_eq!(args.command, Command::History(HistoryCommand::List));
+        assert_eq!(args.limit, Some(5));
+        let args = Args::parse(["history", "stats", "monitor", "--days", "7"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::History(HistoryCommand::Stats(Some("monitor".to_string())))
+        );
+        assert_eq!(args.days, Some(7));
+        assert!(Args::parse(["history", "--limit", "many"]).is_err());
+    }
+}
@@ -2,12 +2,33 @@ use anyhow::{Context, Result};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
 use std::fmt;
-use tracing::{error, info, warn};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::breaker;
+use crate::config::{
+    CircuitBreakerConfig, DockerConfig, EgressPolicy, ExecConfig, HealthConfig, McpServer,
+    SshConfig,
+};
+use crate::health;
+use crate::keys::{ApiKey, KeyPool};
+use crate::secrets;
+use crate::teams::{TeamSession, TeammateOutput};
+use crate::template::{self, TemplateVars};
+use tracing::{debug, error, info, warn};
 
 const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1/messages";
+const ANTHROPIC_BATCHES_URL: &str = "https://api.anthropic.com/v1/messages/batches";
 const ANTHROPIC_VERSION: &str = "2023-06-01";
-const DEFAULT_MODEL: &str = "claude-sonnet-4-5-20250929";
+/// Beta that lets a Messages API request name remote MCP servers.
+const MCP_CONNECTOR_BETA: &str = "mcp-client-2025-04-04";
+pub const DEFAULT_MODEL: &str = "claude-sonnet-4-5-20250929";
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
 
 /// The supported client modes.
 #[derive(Debug, Clone, PartialEq)]
@@ -16,6 +37,10 @@ pub enum ClientMode {
     ClaudeCode,
     Hybrid,
     AgentTeams,
+    Mock,
+    Exec,
+    Ssh,
+    Docker,
 }
 
 impl fmt::Display for ClientMode {
@@ -25,30 +50,160 @@ impl fmt::Display for ClientMode {
             ClientMode::ClaudeCode => write!(f, "claude-code"),
             ClientMode::Hybrid => write!(f, "hybrid"),
             ClientMode::AgentTeams => write!(f, "agent-teams"),
+            ClientMode::Mock => write!(f, "mock"),
+            ClientMode::Exec => write!(f, "exec"),
+            ClientMode::Ssh => write!(f, "ssh"),
+            ClientMode::Docker => write!(f, "docker"),
         }
     }
 }
 
-impl ClientMode {
-    pub fn from_str(s: &str) -> Result<Self> {
+impl FromStr for ClientMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s {
             "api" => Ok(ClientMode::Api),
             "claude-code" => Ok(ClientMode::ClaudeCode),
             "hybrid" => Ok(ClientMode::Hybrid),
             "agent-teams" => Ok(ClientMode::AgentTeams),
+            "mock" => Ok(ClientMode::Mock),
+            "exec" => Ok(ClientMode::Exec),
+            "ssh" => Ok(ClientMode::Ssh),
+            "docker" => Ok(ClientMode::Docker),
             other => anyhow::bail!(
-                "Invalid CLIENT_MODE '{}'. Must be 'api', 'claude-code', 'hybrid', or 'agent-teams'.",
+                "Invalid CLIENT_MODE '{}'. Must be 'api', 'claude-code', 'hybrid', 'agent-teams', 'mock', 'exec', 'ssh', or 'docker'.",
                 other
             ),
         }
     }
 }
 
+/// Per-agent request options forwarded to the backend.
+/// Fields left as `None`/empty fall back to the backend's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientOptions {
+    /// Model ID; passed to the API as `model` and to the CLI as `--model`.
+    pub model: Option<String>,
+    /// Models to try, in order, when `model` is overloaded or unavailable.
+    /// The CLI only supports a single `--fallback-model`, so it gets the first.
+    pub fallback_models: Vec<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub stop_sequences: Vec<String>,
+    /// Skip the primary backend and go straight to the fallback (hybrid only).
+    /// Set when the provider status page reports an outage.
+    pub prefer_fallback: bool,
+    /// Skip the primary backend while it keeps failing (hybrid only).
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Network egress allow-list applied to CLI-based agents' tools.
+    pub egress: Option<EgressPolicy>,
+    /// Directory the CLI runs in; the orchestrator's own if `None`.
+    pub working_dir: Option<PathBuf>,
+    /// Extra environment for the CLI process.
+    pub env: BTreeMap<String, String>,
+    /// Tools the CLI may use and how it asks for permission.
+    pub tools: ToolPolicy,
+    /// Earlier CLI conversation to pick up (CLI client only).
+    pub continuation: Continuation,
+    /// Team the Agent Teams client leads, from `teams.definitions`.
+    pub team: Option<TeamSession>,
+    /// Canned reply returned by the mock client.
+    pub mock_response: Option<String>,
+    /// Command run by the exec client.
+    pub exec: Option<ExecConfig>,
+    /// Host the ssh client runs `claude` on.
+    pub ssh: Option<SshConfig>,
+    /// Image the docker client runs `claude` in.
+    pub docker: Option<DockerConfig>,
+    /// Watchdog for the agent's process, with `features.health_monitoring`.
+    pub health: Option<HealthConfig>,
+}
+
+/// Claude Code tool permissions for CLI-based agents, passed as
+/// `--allowedTools`, `--disallowedTools` and `--permission-mode`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolPolicy {
+    /// Permission rules such as `Read` or `Bash(git log:*)`.
+    pub allowed_tools: Vec<String>,
+    pub disallowed_tools: Vec<String>,
+    /// `default`, `acceptEdits`, `plan` or `bypassPermissions`.
+    pub permission_mode: Option<String>,
+    /// MCP servers passed with `--mcp-config`; their tools are allowed.
+    pub mcp_servers: BTreeMap<String, McpServer>,
+}
+
+/// Where a CLI conversation starts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Continuation {
+    #[default]
+    Fresh,
+    /// `--resume <session-id>`.
+    Resume(String),
+    /// `--continue`: the most recent conversation in the working directory.
+    Latest,
+}
+
+/// A backend response along with what is known about how it was produced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentResponse {
+    pub text: String,
+    /// Model that actually answered, if the backend reports it.
+    pub model: Option<String>,
+    /// Backend calls it took, counting model and CLI fallbacks.
+    pub attempts: u32,
+    /// What each teammate produced, for an Agent Teams session with a team.
+    pub teammates: Vec<TeammateOutput>,
+    /// Tokens and cost, as far as the backend reports them.
+    pub usage: Usage,
+    /// Claude Code session that produced the reply (CLI backends only).
+    pub session_id: Option<String>,
+}
+
+/// Token usage and cost reported by a backend. Fields it doesn't report
+/// stay `None` rather than being estimated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    /// What the CLI says the session would cost at API prices.
+    pub cost_usd: Option<f64>,
+}
+
+impl Usage {
+    /// Add another call's usage; a field stays `None` only if neither
+    /// call reported it.
+    pub fn add(&mut self, other: &Usage) {
+        fn sum<T: std::ops::Add<Output = T>>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            }
+        }
+        self.input_tokens = sum(self.input_tokens, other.input_tokens);
+        self.output_tokens = sum(self.output_tokens, other.output_tokens);
+        self.cost_usd = sum(self.cost_usd, other.cost_usd);
+    }
+}
+
 /// Trait for sending prompts to a Claude backend.
 #[async_trait]
 pub trait AgentClient: Send + Sync {
     /// Send a prompt with an optional system prompt.
     async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String>;
+
+    /// Like `send_message`, but also reports response metadata.
+    /// Backends that know nothing beyond the text can rely on this default.
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let text = self.send_message(prompt, system_prompt).await?;
+        Ok(AgentResponse {
+            text,
+            attempts: 1,
+            ..Default::default()
+        })
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -62,6 +217,23 @@ struct MessageRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     system: Option<String>,
     messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    mcp_servers: Vec<ApiMcpServer>,
+}
+
+/// A remote MCP server the API connects to on the agent's behalf.
+#[derive(Debug, Serialize)]
+struct ApiMcpServer {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    url: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authorization_token: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -79,81 +251,458 @@ struct MessageResponse {
     model: String,
     #[allow(dead_code)]
     role: String,
+    #[serde(default)]
+    usage: Option<ApiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+/// A non-2xx response from the Messages API.
+#[derive(Debug)]
+pub struct ApiStatusError {
+    pub status: u16,
+    pub body: String,
+}
+
+impl fmt::Display for ApiStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "API request failed with status {}: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for ApiStatusError {}
+
+impl ApiStatusError {
+    /// Errors where a different model may succeed: unknown model (404),
+    /// server errors, and overload (529).
+    pub fn is_model_specific(&self) -> bool {
+        matches!(self.status, 404 | 500 | 502 | 503 | 529)
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct ContentBlock {
     #[serde(rename = "type")]
-    #[allow(dead_code)]
     content_type: String,
-    text: String,
+    /// Only `text` blocks have text; MCP tool calls and results don't.
+    #[serde(default)]
+    text: Option<String>,
 }
 
 pub struct ApiClient {
     client: Client,
-    api_key: String,
+    key: ApiKey,
     model: String,
+    fallback_models: Vec<String>,
+    options: ClientOptions,
 }
 
 impl ApiClient {
     pub fn new(api_key: String) -> Self {
+        Self::with_keys(&KeyPool::single(api_key))
+    }
+
+    /// Uses the next key from `keys`, and others from it if that one is
+    /// rejected or throttled.
+    pub fn with_keys(keys: &Arc<KeyPool>) -> Self {
         Self {
             client: Client::new(),
-            api_key,
+            key: keys.pick(),
             model: DEFAULT_MODEL.to_string(),
+            fallback_models: Vec::new(),
+            options: ClientOptions::default(),
         }
     }
 
-    #[allow(dead_code)]
     pub fn with_model(mut self, model: &str) -> Self {
         self.model = model.to_string();
         self
     }
-}
 
-#[async_trait]
-impl AgentClient for ApiClient {
-    async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
-        let request = MessageRequest {
-            model: self.model.clone(),
-            max_tokens: 4096,
+    /// Apply per-agent options, including the model chain when one is set.
+    pub fn with_options(mut self, options: ClientOptions) -> Self {
+        if let Some(ref model) = options.model {
+            self = self.with_model(model);
+        }
+        self.fallback_models = options.fallback_models.clone();
+        for (name, server) in &options.tools.mcp_servers {
+            if server.url.is_none() {
+                warn!(
+                    "MCP server {} has no url; API agents can only use remote servers",
+                    name
+                );
+            }
+        }
+        self.options = options;
+        self
+    }
+
+    fn build_request(
+        &self,
+        model: &str,
+        prompt: &str,
+        system_prompt: Option<&str>,
+    ) -> MessageRequest {
+        MessageRequest {
+            model: model.to_string(),
+            max_tokens: self.options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
             system: system_prompt.map(|s| s.to_string()),
             messages: vec![Message {
                 role: "user".to_string(),
                 content: prompt.to_string(),
             }],
-        };
+            temperature: self.options.temperature,
+            stop_sequences: self.options.stop_sequences.clone(),
+            mcp_servers: self
+                .options
+                .tools
+                .mcp_servers
+                .iter()
+                .filter_map(|(name, server)| {
+                    Some(ApiMcpServer {
+                        kind: "url",
+                        url: server.url.clone()?,
+                        name: name.clone(),
+                        authorization_token: server
+                            .token_env
+                            .as_ref()
+                            .and_then(|var| secrets::var(var)),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Send to `model`, moving to another key from the pool if the API
+    /// rejects or throttles this one.
+    async fn send_to_model(
+        &self,
+        model: &str,
+        prompt: &str,
+        system_prompt: Option<&str>,
+    ) -> Result<AgentResponse> {
+        let mut key = self.key.clone();
+        let mut tried = Vec::new();
+        loop {
+            let err = match self.send_with_key(&key, model, prompt, system_prompt).await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            let Some(status) = err.downcast_ref::<ApiStatusError>().map(|e| e.status) else {
+                return Err(err);
+            };
+            tried.push(key.index());
+            let next = match key.report(status) {
+                true => key.pool().replacement(&tried),
+                false => None,
+            };
+            let Some(next) = next else {
+                return Err(err);
+            };
+            warn!(
+                "API returned {} for {}; retrying with {}",
+                status,
+                key.label(),
+                next.label()
+            );
+            key = next;
+        }
+    }
 
-        let response = self
+    async fn send_with_key(
+        &self,
+        key: &ApiKey,
+        model: &str,
+        prompt: &str,
+        system_prompt: Option<&str>,
+    ) -> Result<AgentResponse> {
+        let request = self.build_request(model, prompt, system_prompt);
+
+        let mut builder = self
             .client
             .post(ANTHROPIC_API_URL)
-            .header("x-api-key", &self.api_key)
+            .header("x-api-key", key.secret())
             .header("anthropic-version", ANTHROPIC_VERSION)
-            .header("content-type", "application/json")
+            .header("content-type", "application/json");
+        if !request.mcp_servers.is_empty() {
+            builder = builder.header("anthropic-beta", MCP_CONNECTOR_BETA);
+        }
+        let response = builder
             .json(&request)
             .send()
             .await
             .context("Failed to send request to Anthropic API")?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            anyhow::bail!("API request failed with status {}: {}", status, error_text);
+            let status = response.status().as_u16();
+            let body = response.text().await.unwrap_or_default();
+            return Err(ApiStatusError { status, body }.into());
         }
 
         let message_response: MessageResponse = response
             .json()
             .await
             .context("Failed to parse API response")?;
+        Ok(message_response.into_response())
+    }
+
+    /// This client's request for `prompt`, to send in a message batch under
+    /// `custom_id`. Batches use the first model of the chain only.
+    pub fn batch_request(
+        &self,
+        custom_id: &str,
+        prompt: &str,
+        system_prompt: Option<&str>,
+    ) -> BatchRequest {
+        BatchRequest {
+            custom_id: custom_id.to_string(),
+            params: self.build_request(&self.model, prompt, system_prompt),
+        }
+    }
+}
 
-        let text = message_response
+impl MessageResponse {
+    fn into_response(self) -> AgentResponse {
+        // With MCP tools the reply is text blocks around the tool calls
+        let text = self
             .content
-            .first()
-            .map(|block| block.text.clone())
+            .iter()
+            .filter(|block| block.content_type == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let usage = self
+            .usage
+            .map(|u| Usage {
+                input_tokens: Some(u.input_tokens),
+                output_tokens: Some(u.output_tokens),
+                cost_usd: None,
+            })
             .unwrap_or_default();
+        AgentResponse {
+            text,
+            model: Some(self.model),
+            attempts: 1,
+            usage,
+            session_id: None,
+            ..Default::default()
+        }
+    }
+}
+
+#[async_trait]
+impl AgentClient for ApiClient {
+    async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
+
+    /// Walk the model chain, moving on only for errors another model might avoid.
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let chain: Vec<&str> = std::iter::once(self.model.as_str())
+            .chain(self.fallback_models.iter().map(String::as_str))
+            .collect();
+
+        let mut last_err = None;
+        for (i, model) in chain.iter().enumerate() {
+            match self.send_to_model(model, prompt, system_prompt).await {
+                Ok(response) => {
+                    return Ok(AgentResponse {
+                        attempts: i as u32 + 1,
+                        ..response
+                    })
+                }
+                Err(e) => {
+                    let retryable = e
+                        .downcast_ref::<ApiStatusError>()
+                        .is_some_and(ApiStatusError::is_model_specific);
+                    if !retryable || i + 1 == chain.len() {
+                        return Err(e);
+                    }
+                    warn!(
+                        "Model {} failed ({:#}), falling back to {}",
+                        model,
+                        e,
+                        chain[i + 1]
+                    );
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("No models configured")))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Message Batches (paid, half price) — API requests answered within 24 hours
+// ---------------------------------------------------------------------------
+
+/// How often a submitted batch is checked on.
+pub const BATCH_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// One request of a message batch; `custom_id` matches it to its result.
+#[derive(Debug, Serialize)]
+pub struct BatchRequest {
+    custom_id: String,
+    params: MessageRequest,
+}
+
+/// A message batch as the API reports it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchStatus {
+    pub id: String,
+    /// `in_progress`, `canceling` or `ended`.
+    pub processing_status: String,
+    pub request_counts: BatchCounts,
+    /// Where the results can be read once the batch has ended.
+    #[serde(default)]
+    pub results_url: Option<String>,
+}
+
+impl BatchStatus {
+    pub fn ended(&self) -> bool {
+        self.processing_status == "ended"
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct BatchCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// Submits, polls and reads message batches with one key of the pool.
+pub struct BatchClient {
+    client: Client,
+    key: ApiKey,
+}
+
+impl BatchClient {
+    pub fn with_keys(keys: &Arc<KeyPool>) -> Self {
+        Self {
+            client: Client::new(),
+            key: keys.pick(),
+        }
+    }
+
+    pub async fn submit(&self, requests: &[BatchRequest]) -> Result<BatchStatus> {
+        let mut builder = self.client.post(ANTHROPIC_BATCHES_URL);
+        if requests.iter().any(|r| !r.params.mcp_servers.is_empty()) {
+            builder = builder.header("anthropic-beta", MCP_CONNECTOR_BETA);
+        }
+        let body = serde_json::json!({ "requests": requests });
+        self.send(builder.json(&body))
+            .await?
+            .json()
+            .await
+            .context("Failed to parse batch")
+    }
+
+    pub async fn status(&self, id: &str) -> Result<BatchStatus> {
+        let url = format!("{}/{}", ANTHROPIC_BATCHES_URL, id);
+        self.send(self.client.get(url))
+            .await?
+            .json()
+            .await
+            .context("Failed to parse batch")
+    }
+
+    /// Ask the API to stop `id`; requests already answered keep their results.
+    pub async fn cancel(&self, id: &str) -> Result<BatchStatus> {
+        let url = format!("{}/{}/cancel", ANTHROPIC_BATCHES_URL, id);
+        self.send(self.client.post(url))
+            .await?
+            .json()
+            .await
+            .context("Failed to parse batch")
+    }
 
-        Ok(text)
+    /// The reply or error for each request of an ended batch, by `custom_id`.
+    pub async fn results(
+        &self,
+        batch: &BatchStatus,
+    ) -> Result<HashMap<String, Result<AgentResponse, String>>> {
+        let url = batch
+            .results_url
+            .as_deref()
+            .with_context(|| format!("Batch {} has no results yet", batch.id))?;
+        let body = self.send(self.client.get(url)).await?.text().await?;
+        Ok(parse_batch_results(&body))
     }
+
+    async fn send(&self, builder: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = builder
+            .header("x-api-key", self.key.secret())
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .send()
+            .await
+            .context("Failed to send request to Anthropic API")?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            self.key.report(status);
+            let body = response.text().await.unwrap_or_default();
+            return Err(ApiStatusError { status, body }.into());
+        }
+        Ok(response)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchResultLine {
+    custom_id: String,
+    result: BatchResult,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum BatchResult {
+    Succeeded { message: MessageResponse },
+    Errored { error: serde_json::Value },
+    Canceled,
+    Expired,
+}
+
+/// Results JSONL, one line per request.
+fn parse_batch_results(jsonl: &str) -> HashMap<String, Result<AgentResponse, String>> {
+    let mut results = HashMap::new();
+    for line in jsonl.lines().filter(|l| !l.trim().is_empty()) {
+        let line: BatchResultLine = match serde_json::from_str(line) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Skipping a malformed batch result: {}", e);
+                continue;
+            }
+        };
+        let result = match line.result {
+            BatchResult::Succeeded { message } => Ok(message.into_response()),
+            BatchResult::Errored { error } => {
+                // `{"type": "error", "error": {"type": ..., "message": ...}}`
+                let detail = &error["error"];
+                Err(format!(
+                    "Batch request failed: {}: {}",
+                    detail["type"].as_str().unwrap_or("error"),
+                    detail["message"].as_str().unwrap_or_default()
+                ))
+            }
+            BatchResult::Canceled => Err("Batch request was cancelled".to_string()),
+            BatchResult::Expired => {
+                Err("Batch request expired before it was processed".to_string())
+            }
+        };
+        results.insert(line.custom_id, result);
+    }
+    results
 }
 
 // ---------------------------------------------------------------------------
@@ -162,52 +711,954 @@ impl AgentClient for ApiClient {
 
 pub struct CliClient {
     cli_path: String,
+    model: Option<String>,
+    fallback_model: Option<String>,
+    egress: Option<EgressPolicy>,
+    working_dir: Option<PathBuf>,
+    env: BTreeMap<String, String>,
+    tools: ToolPolicy,
+    continuation: Continuation,
+    health: Option<HealthConfig>,
+}
+
+impl Default for CliClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CliClient {
+    /// Uses the `claude` found by [`locate_claude_cli`], or plain `claude`
+    /// if there is none, so a missing CLI only fails when it's called.
     pub fn new() -> Self {
-        let cli_path = std::env::var("CLAUDE_CLI_PATH").unwrap_or_else(|_| {
-            for path in ["/usr/local/bin/claude", "/home/claude/.local/bin/claude"] {
-                if std::path::Path::new(path).exists() {
-                    return path.to_string();
+        Self::with_cli_path(default_cli_path())
+    }
+
+    /// Like [`new`](Self::new), but fails up front if there's no CLI.
+    pub fn discover() -> Result<Self> {
+        Ok(Self::with_cli_path(locate_claude_cli()?))
+    }
+
+    fn with_cli_path(cli_path: impl AsRef<Path>) -> Self {
+        Self {
+            cli_path: cli_path.as_ref().to_string_lossy().into_owned(),
+            model: None,
+            fallback_model: None,
+            egress: None,
+            working_dir: None,
+            env: BTreeMap::new(),
+            tools: ToolPolicy::default(),
+            continuation: Continuation::Fresh,
+            health: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+
+    pub fn with_options(mut self, options: &ClientOptions) -> Self {
+        if let Some(ref model) = options.model {
+            self.model = Some(model.clone());
+        }
+        self.fallback_model = options.fallback_models.first().cloned();
+        self.egress = options.egress.clone();
+        self.working_dir = options.working_dir.clone();
+        self.env = options.env.clone();
+        self.tools = options.tools.clone();
+        self.continuation = options.continuation.clone();
+        self.health = options.health.clone();
+        self
+    }
+}
+
+/// Find the `claude` CLI: `CLAUDE_CLI_PATH` if set (a bare name is looked
+/// up on `PATH`), else the first `claude` on `PATH`, else a usual install
+/// location. On Windows the npm `.cmd` shim counts too. The error lists
+/// every path tried.
+pub fn locate_claude_cli() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    find_cli(
+        std::env::var_os("CLAUDE_CLI_PATH"),
+        std::env::var_os("PATH"),
+        common_cli_locations(home.as_deref()),
+    )
+}
+
+fn default_cli_path() -> PathBuf {
+    locate_claude_cli().unwrap_or_else(|_| PathBuf::from("claude"))
+}
+
+/// File names `claude` is installed under on this platform.
+fn cli_names() -> &'static [&'static str] {
+    if cfg!(windows) {
+        &["claude.exe", "claude.cmd"]
+    } else {
+        &["claude"]
+    }
+}
+
+/// Install locations outside `PATH` worth checking; some live under `home`.
+fn common_cli_locations(home: Option<&Path>) -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+    if cfg!(windows) {
+        if let Some(appdata) = std::env::var_os("APPDATA") {
+            locations.push(PathBuf::from(appdata).join("npm").join("claude.cmd"));
+        }
+        if let Some(home) = home {
+            locations.push(home.join(".local").join("bin").join("claude.exe"));
+        }
+        return locations;
+    }
+    if let Some(home) = home {
+        locations.push(home.join(".claude/local/claude"));
+        locations.push(home.join(".local/bin/claude"));
+        locations.push(home.join(".npm-global/bin/claude"));
+    }
+    for path in [
+        "/usr/local/bin/claude",
+        "/opt/homebrew/bin/claude",
+        "/home/claude/.local/bin/claude",
+    ] {
+        locations.push(PathBuf::from(path));
+    }
+    locations
+}
+
+fn find_cli(
+    explicit: Option<OsString>,
+    path_var: Option<OsString>,
+    locations: Vec<PathBuf>,
+) -> Result<PathBuf> {
+    let mut tried = Vec::new();
+    let mut check = |candidate: PathBuf| {
+        let found = is_executable(&candidate);
+        tried.push(candidate.clone());
+        found.then_some(candidate)
+    };
+    let on_path = |names: &[&str]| -> Vec<PathBuf> {
+        path_var
+            .iter()
+            .flat_map(std::env::split_paths)
+            .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+            .collect()
+    };
+
+    if let Some(explicit) = explicit.filter(|p| !p.is_empty()) {
+        let explicit = PathBuf::from(explicit);
+        let bare = explicit.components().count() == 1;
+        let candidates = match explicit.to_str() {
+            // `claude` on Windows means `claude.exe` or `claude.cmd`
+            Some(name) if bare && cfg!(windows) && explicit.extension().is_none() => {
+                on_path(&[&format!("{}.exe", name), &format!("{}.cmd", name)])
+            }
+            Some(name) if bare => on_path(&[name]),
+            _ => vec![explicit.clone()],
+        };
+        if let Some(found) = candidates.into_iter().find_map(&mut check) {
+            return Ok(found);
+        }
+        anyhow::bail!(
+            "CLAUDE_CLI_PATH={} is not an executable. Looked in:\n  {}",
+            explicit.display(),
+            render_paths(&tried)
+        );
+    }
+
+    let candidates = on_path(cli_names()).into_iter().chain(locations);
+    for candidate in candidates {
+        if let Some(found) = check(candidate) {
+            return Ok(found);
+        }
+    }
+    anyhow::bail!(
+        "claude CLI not found. Install Claude Code or set CLAUDE_CLI_PATH. Looked in:\n  {}",
+        render_paths(&tried)
+    )
+}
+
+fn render_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n  ")
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.is_file() && meta.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        meta.is_file()
+    }
+}
+
+/// Pass an agent's tool policy to `claude`, together with what its egress
+/// policy requires: WebFetch restricted to the allowed domains, WebSearch
+/// denied (it can reach arbitrary hosts) and traffic routed through the
+/// egress proxy if one is set. The agent's MCP servers are passed with
+/// `--mcp-config`, and their tools allowed.
+fn apply_tools(
+    cmd: &mut tokio::process::Command,
+    tools: &ToolPolicy,
+    egress: Option<&EgressPolicy>,
+) {
+    let mut allowed = tools.allowed_tools.clone();
+    let mut disallowed = tools.disallowed_tools.clone();
+    if let Some(policy) = egress {
+        allowed.extend(policy.cli_allowed_tools());
+        disallowed.push("WebSearch".to_string());
+        for (key, value) in policy.proxy_env() {
+            cmd.env(key, value);
+        }
+    }
+    allowed.extend(
+        tools
+            .mcp_servers
+            .keys()
+            .map(|name| format!("mcp__{}", name)),
+    );
+    // One argument per rule: rules like `Bash(npm run test:*)` contain spaces.
+    if !allowed.is_empty() {
+        cmd.arg("--allowedTools").args(&allowed);
+    }
+    if !disallowed.is_empty() {
+        cmd.arg("--disallowedTools").args(&disallowed);
+    }
+    if let Some(ref mode) = tools.permission_mode {
+        cmd.arg("--permission-mode").arg(mode);
+    }
+    if !tools.mcp_servers.is_empty() {
+        cmd.arg("--mcp-config").arg(mcp_config(&tools.mcp_servers));
+        for (name, server) in &tools.mcp_servers {
+            for (key, value) in &server.env {
+                cmd.env(mcp_env_var(name, key), value);
+            }
+        }
+        // Tokens from `secrets:` aren't in the environment the CLI inherits
+        for var in tools
+            .mcp_servers
+            .values()
+            .filter_map(|s| s.token_env.as_ref())
+        {
+            if let Some(token) = secrets::var(var) {
+                cmd.env(var, token);
+            }
+        }
+    }
+}
+
+/// The `--mcp-config` JSON for `servers`. A bearer token stays a `${VAR}`
+/// reference that the CLI expands, so it never shows on the command line,
+/// and so do a stdio server's `env` values, each under the variable
+/// [`mcp_env_var`] names.
+fn mcp_config(servers: &BTreeMap<String, McpServer>) -> String {
+    let servers: serde_json::Map<String, serde_json::Value> = servers
+        .iter()
+        .map(|(name, server)| {
+            let config = match &server.url {
+                Some(url) => {
+                    let mut config = serde_json::json!({ "type": "http", "url": url });
+                    if let Some(var) = &server.token_env {
+                        config["headers"] =
+                            serde_json::json!({ "Authorization": format!("Bearer ${{{}}}", var) });
+                    }
+                    config
+                }
+                None => {
+                    let env: BTreeMap<&String, String> = server
+                        .env
+                        .keys()
+                        .map(|key| (key, format!("${{{}}}", mcp_env_var(name, key))))
+                        .collect();
+                    serde_json::json!({
+                        "command": server.command,
+                        "args": server.args,
+                        "env": env,
+                    })
                 }
+            };
+            (name.clone(), config)
+        })
+        .collect();
+    serde_json::json!({ "mcpServers": servers }).to_string()
+}
+
+/// The variable that carries `key` of MCP server `server`'s `env` to
+/// `claude`, e.g. `ORCHESTRA_MCP_GITHUB_TOKEN` for `github`'s `TOKEN`.
+/// Prefixed so it can't override anything `claude` itself reads.
+fn mcp_env_var(server: &str, key: &str) -> String {
+    format!("ORCHESTRA_MCP_{}_{}", server, key)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
             }
-            "claude".to_string()
+        })
+        .collect()
+}
+
+/// Whether the `claude` at `cli_path` accepts `flag`, judged from its
+/// `--help` text. Asked once per path and flag; a CLI that can't answer
+/// counts as lacking it.
+async fn cli_supports(cli_path: &str, flag: &'static str) -> bool {
+    let mut cmd = tokio::process::Command::new(cli_path);
+    cmd.arg("--help");
+    help_lists(cli_path, cmd, flag).await
+}
+
+/// Whether the help text `help` prints lists `flag`. `claude` names the CLI
+/// it comes from, for the cache and the log; `help` only runs the first time.
+async fn help_lists(claude: &str, help: tokio::process::Command, flag: &'static str) -> bool {
+    type Support = std::sync::Mutex<HashMap<(String, &'static str), bool>>;
+    static SUPPORT: std::sync::OnceLock<Support> = std::sync::OnceLock::new();
+    let support = SUPPORT.get_or_init(Default::default);
+    let key = (claude.to_string(), flag);
+    if let Some(&known) = support.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return known;
+    }
+
+    // Long enough for an ssh connection or a container to start
+    let help = tokio::time::timeout(std::time::Duration::from_secs(30), run_claude(help)).await;
+    let supported = match help {
+        Ok(Ok(output)) => String::from_utf8_lossy(&output.stdout).contains(flag),
+        _ => false,
+    };
+    if !supported {
+        debug!("{} doesn't list {}", claude, flag);
+    }
+    support
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, supported);
+    supported
+}
+
+/// Give `claude` the system prompt through `--append-system-prompt`. CLIs
+/// too old for that flag get it prefixed to the prompt as `[<label>: ...]`.
+/// Returns the prompt to pass with `-p`.
+async fn apply_system_prompt(
+    cmd: &mut tokio::process::Command,
+    cli_path: &str,
+    prompt: &str,
+    system_prompt: Option<&str>,
+    label: &str,
+) -> String {
+    match system_prompt {
+        Some(sys) if cli_supports(cli_path, "--append-system-prompt").await => {
+            cmd.arg("--append-system-prompt").arg(sys);
+            prompt.to_string()
+        }
+        Some(sys) => prefix_system_prompt(prompt, sys, label),
+        None => prompt.to_string(),
+    }
+}
+
+/// The prompt with the system prompt in front, for a CLI without
+/// `--append-system-prompt`.
+fn prefix_system_prompt(prompt: &str, system_prompt: &str, label: &str) -> String {
+    format!("[{}: {}]\n\n{}", label, system_prompt, prompt)
+}
+
+/// Run `claude`, or an exec agent's command, in the agent's `working_dir`
+/// with its extra `env`. The orchestrator's API key is withheld so the CLI
+/// uses its own login, unless `env` sets one explicitly.
+fn apply_process_env(
+    cmd: &mut tokio::process::Command,
+    working_dir: Option<&Path>,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    cmd.env_remove("ANTHROPIC_API_KEY");
+    if let Some(dir) = working_dir {
+        if !dir.is_dir() {
+            anyhow::bail!("working_dir {} is not a directory", dir.display());
+        }
+        cmd.current_dir(dir);
+    }
+    cmd.envs(env);
+    Ok(())
+}
+
+/// Run a `claude` invocation to completion. It gets its own process group,
+/// and if the future is dropped (the agent timed out or the run was
+/// cancelled) the whole group is killed, so tools claude started don't
+/// outlive it.
+async fn run_claude(cmd: tokio::process::Command) -> std::io::Result<std::process::Output> {
+    run_process(cmd, None, None).await
+}
+
+/// Like [`run_claude`], for any command; `stdin`, if given, is written to
+/// the process while it runs. With `health`, the process is watched by
+/// [`health::watch`] and killed if it stalls.
+async fn run_process(
+    cmd: tokio::process::Command,
+    stdin: Option<String>,
+    health: Option<&HealthConfig>,
+) -> std::io::Result<std::process::Output> {
+    stream_process(cmd, stdin, health, None).await
+}
+
+/// Like [`run_process`], also handing each line of stdout to `on_line` as
+/// the process writes it.
+async fn stream_process(
+    mut cmd: tokio::process::Command,
+    stdin: Option<String>,
+    health: Option<&HealthConfig>,
+    on_line: Option<health::OnLine<'_>>,
+) -> std::io::Result<std::process::Output> {
+    use tokio::io::AsyncWriteExt;
+
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let mut child = cmd
+        .stdin(match stdin {
+            Some(_) => std::process::Stdio::piped(),
+            None => std::process::Stdio::null(),
+        })
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut guard = ProcessGroupGuard(child.id());
+    let pipe = child.stdin.take();
+    // A process that exits without reading all of stdin is not an error
+    let write = async move {
+        if let (Some(mut pipe), Some(input)) = (pipe, stdin) {
+            let _ = pipe.write_all(input.as_bytes()).await;
+        }
+    };
+    let wait = async move {
+        match (health, on_line) {
+            (Some(health), _) => health::watch(child, health, on_line).await,
+            (None, Some(on_line)) => health::collect(child, on_line).await,
+            (None, None) => child.wait_with_output().await,
+        }
+    };
+    let ((), output) = tokio::join!(write, wait);
+    // A stalled process's group is killed along with it
+    if output.is_ok() {
+        guard.0 = None;
+    }
+    output
+}
+
+/// Kills the process group led by the given PID when dropped, unless cleared.
+struct ProcessGroupGuard(Option<u32>);
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.0 {
+            kill_process_group(pid);
+        }
+    }
+}
+
+/// SIGKILL every process in the group led by `pid`.
+pub(crate) fn kill_process_group(pid: u32) {
+    #[cfg(unix)]
+    // SAFETY: kill(2) has no memory-safety preconditions; a negative PID
+    // addresses the process group created for the child.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// The object `claude -p --output-format json` prints when the session ends.
+#[derive(Debug, Deserialize)]
+struct CliResult {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    subtype: Option<String>,
+    #[serde(default)]
+    is_error: bool,
+    #[serde(default)]
+    result: String,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    duration_ms: Option<u64>,
+    #[serde(default)]
+    num_turns: Option<u32>,
+    /// `cost_usd` in older CLI releases.
+    #[serde(default, alias = "cost_usd")]
+    total_cost_usd: Option<f64>,
+    #[serde(default)]
+    usage: Option<CliUsage>,
+    #[serde(default, rename = "modelUsage")]
+    model_usage: std::collections::BTreeMap<String, CliModelUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CliUsage {
+    #[serde(default)]
+    input_tokens: Option<u64>,
+    #[serde(default)]
+    output_tokens: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CliModelUsage {
+    #[serde(default, rename = "outputTokens")]
+    output_tokens: u64,
+}
+
+/// Turn what `claude -p --output-format json` (or `stream-json`) printed
+/// into a response. Output that isn't a result object (a wrapper script,
+/// an older CLI) is taken as the reply text, as before.
+fn parse_cli_output(stdout: &[u8]) -> Result<AgentResponse> {
+    // With `stream-json` the result object is the last of one event per line
+    let last_line = || {
+        stdout
+            .split(|b| *b == b'\n')
+            .rfind(|line| !line.trim_ascii().is_empty())
+    };
+    let parsed = serde_json::from_slice::<CliResult>(stdout)
+        .ok()
+        .or_else(|| serde_json::from_slice(last_line()?).ok())
+        .filter(|r| r.kind == "result");
+    let Some(result) = parsed else {
+        debug!("claude output isn't a JSON result; using it as plain text");
+        return Ok(AgentResponse {
+            text: String::from_utf8_lossy(stdout).to_string(),
+            attempts: 1,
+            ..Default::default()
         });
-        Self { cli_path }
+    };
+    if result.is_error {
+        anyhow::bail!(
+            "claude CLI reported {}: {}",
+            result.subtype.as_deref().unwrap_or("an error"),
+            result.result.trim()
+        );
+    }
+    debug!(
+        session_id = result.session_id.as_deref().unwrap_or("-"),
+        turns = result.num_turns.unwrap_or(0),
+        duration_ms = result.duration_ms.unwrap_or(0),
+        "claude session finished"
+    );
+    // Claude Code may use a small model for housekeeping; the answer comes
+    // from the one that wrote the most
+    let model = result
+        .model_usage
+        .iter()
+        .max_by_key(|(_, usage)| usage.output_tokens)
+        .map(|(model, _)| model.clone());
+    let usage = result.usage.unwrap_or(CliUsage {
+        input_tokens: None,
+        output_tokens: None,
+    });
+    Ok(AgentResponse {
+        text: result.result,
+        model,
+        attempts: 1,
+        usage: Usage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cost_usd: result.total_cost_usd,
+        },
+        session_id: result.session_id,
+        ..Default::default()
+    })
+}
+
+/// Log what a `claude` process wrote to stderr. At debug level it stays out
+/// of the global log but lands in the agent's own log file.
+fn log_stderr(stderr: &[u8]) {
+    let stderr = String::from_utf8_lossy(stderr);
+    if !stderr.trim().is_empty() {
+        debug!("claude stderr:\n{}", stderr.trim_end());
     }
 }
 
 #[async_trait]
 impl AgentClient for CliClient {
     async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
-        // If a system prompt is provided, prepend it as context
-        let full_prompt = match system_prompt {
-            Some(sys) => format!("[CONTEXT: {}]\n\n{}", sys, prompt),
-            None => prompt.to_string(),
-        };
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
 
-        let output = tokio::process::Command::new(&self.cli_path)
-            .arg("-p")
-            .arg(&full_prompt)
-            .env_remove("ANTHROPIC_API_KEY")
-            .output()
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let mut cmd = tokio::process::Command::new(&self.cli_path);
+        let prompt =
+            apply_system_prompt(&mut cmd, &self.cli_path, prompt, system_prompt, "CONTEXT").await;
+        cmd.arg("-p").arg(&prompt);
+        self.apply_options(&mut cmd, false);
+        apply_process_env(&mut cmd, self.working_dir.as_deref(), &self.env)?;
+        let output = run_process(cmd, None, self.health.as_ref())
             .await
             .context("Failed to execute claude CLI")?;
+        log_stderr(&output.stderr);
+        cli_response(&output)
+    }
+}
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let detail = if !stderr.is_empty() { &stderr } else { &stdout };
+impl CliClient {
+    /// The flags after `-p`: JSON output, the model chain, the conversation
+    /// to pick up and the tool policy. With `stream`, and always under the
+    /// watchdog, the output is streamed, so a working session keeps
+    /// producing some.
+    fn apply_options(&self, cmd: &mut tokio::process::Command, stream: bool) {
+        if stream || self.health.is_some() {
+            cmd.args(["--output-format", "stream-json", "--verbose"]);
+        } else {
+            cmd.args(["--output-format", "json"]);
+        }
+        if let Some(ref model) = self.model {
+            cmd.arg("--model").arg(model);
+        }
+        if let Some(ref fallback) = self.fallback_model {
+            cmd.arg("--fallback-model").arg(fallback);
+        }
+        match self.continuation {
+            Continuation::Fresh => {}
+            Continuation::Resume(ref session) => {
+                cmd.arg("--resume").arg(session);
+            }
+            Continuation::Latest => {
+                cmd.arg("--continue");
+            }
+        }
+        apply_tools(cmd, &self.tools, self.egress.as_ref());
+    }
+
+    /// `claude -p` with the agent's flags, for running on another host or in
+    /// a container: the program and arguments, and the environment it needs
+    /// there (the egress proxy and the agent's `env`).
+    fn invocation(&self, stream: bool) -> (Vec<String>, Vec<(String, String)>) {
+        let mut claude = tokio::process::Command::new(&self.cli_path);
+        claude.arg("-p");
+        self.apply_options(&mut claude, stream);
+        let claude = claude.as_std();
+        let argv = std::iter::once(claude.get_program())
+            .chain(claude.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let mut env: Vec<(String, String)> = claude
+            .get_envs()
+            .filter_map(|(key, value)| {
+                Some((
+                    key.to_string_lossy().into_owned(),
+                    value?.to_string_lossy().into_owned(),
+                ))
+            })
+            .collect();
+        env.extend(self.env.clone());
+        (argv, env)
+    }
+}
+
+/// The reply of a finished `claude -p --output-format json`, or why it failed.
+fn cli_response(output: &std::process::Output) -> Result<AgentResponse> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stderr.trim().is_empty() {
+            // With --output-format json the reason is in the result object
+            if let Err(e) = parse_cli_output(&output.stdout) {
+                return Err(e.context(format!("claude CLI exited with {}", output.status)));
+            }
+        }
+        let detail = if !stderr.is_empty() { &stderr } else { &stdout };
+        anyhow::bail!(
+            "claude CLI exited with {}: {}",
+            output.status,
+            detail.trim()
+        );
+    }
+
+    parse_cli_output(&output.stdout)
+}
+
+// ---------------------------------------------------------------------------
+// SSH client — runs `claude -p` on another host
+// ---------------------------------------------------------------------------
+
+pub struct SshClient {
+    ssh: SshConfig,
+    ssh_path: String,
+    /// The remote `claude` and its per-agent settings.
+    cli: CliClient,
+}
+
+impl SshClient {
+    pub fn new(ssh: SshConfig) -> Self {
+        Self {
+            cli: CliClient::with_cli_path(&ssh.claude_path),
+            ssh,
+            ssh_path: "ssh".to_string(),
+        }
+    }
+
+    pub fn with_options(mut self, options: &ClientOptions) -> Self {
+        self.cli = self.cli.with_options(options);
+        self
+    }
+
+    /// `ssh` arguments up to and including the destination. `BatchMode`
+    /// makes a missing key fail instead of prompting for a password, and
+    /// `--` keeps a destination starting with `-` from being read as an
+    /// option.
+    pub(crate) fn ssh_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "-T".to_string(),
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+        ];
+        for option in &self.ssh.options {
+            args.extend(["-o".to_string(), option.clone()]);
+        }
+        if let Some(port) = self.ssh.port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        if let Some(ref key) = self.ssh.key {
+            args.extend(["-i".to_string(), key.clone()]);
+        }
+        args.push("--".to_string());
+        args.push(match self.ssh.user {
+            Some(ref user) => format!("{}@{}", user, self.ssh.host),
+            None => self.ssh.host.clone(),
+        });
+        args
+    }
+
+    /// The shell command run on the host: `claude -p` with the agent's flags
+    /// and `system_prompt`, in its `working_dir`. The output is streamed.
+    /// The second string goes to stdin ahead of the prompt: it exports the
+    /// agent's `env`, which the command reads off stdin before starting
+    /// `claude`, so values stay out of both hosts' process lists.
+    fn remote_command(&self, system_prompt: Option<&str>) -> (String, String) {
+        let (mut argv, env) = self.cli.invocation(true);
+        if let Some(sys) = system_prompt {
+            argv.extend(["--append-system-prompt".to_string(), sys.to_string()]);
+        }
+        let mut steps = Vec::new();
+        let exports: String = env
+            .iter()
+            .map(|(key, value)| format!("export {}\n", shell_quote(&format!("{}={}", key, value))))
+            .collect();
+        if !exports.is_empty() {
+            // dd reads a byte at a time, leaving the prompt for claude
+            steps.push(format!(
+                "eval \"$(dd bs=1 count={} 2>/dev/null)\"",
+                exports.len()
+            ));
+        }
+        if let Some(ref dir) = self.cli.working_dir {
+            steps.push(format!("cd {}", shell_quote(&dir.to_string_lossy())));
+        }
+        let claude = argv
+            .iter()
+            .map(|word| shell_quote(word))
+            .collect::<Vec<_>>()
+            .join(" ");
+        steps.push(format!("exec {}", claude));
+        (steps.join(" && "), exports)
+    }
+
+    /// Whether the host's `claude` takes `--append-system-prompt`, asked
+    /// once per host and CLI path.
+    async fn supports_system_prompt(&self) -> bool {
+        let destination = self.ssh_args().pop().unwrap_or_default();
+        let mut help = tokio::process::Command::new(&self.ssh_path);
+        help.args(self.ssh_args())
+            .arg(format!("{} --help", shell_quote(&self.cli.cli_path)));
+        help_lists(
+            &format!("{}:{}", destination, self.cli.cli_path),
+            help,
+            "--append-system-prompt",
+        )
+        .await
+    }
+}
+
+/// Quote `word` for a POSIX shell.
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+#[async_trait]
+impl AgentClient for SshClient {
+    async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
+
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let (prompt, system_prompt) = match system_prompt {
+            Some(sys) if self.supports_system_prompt().await => (prompt.to_string(), Some(sys)),
+            Some(sys) => (prefix_system_prompt(prompt, sys, "CONTEXT"), None),
+            None => (prompt.to_string(), None),
+        };
+        let host = &self.ssh.host;
+        let (command, exports) = self.remote_command(system_prompt);
+        let mut cmd = tokio::process::Command::new(&self.ssh_path);
+        cmd.args(self.ssh_args()).arg(command);
+        let on_line = |line: &[u8]| debug!("{}: {}", host, String::from_utf8_lossy(line));
+        let stdin = format!("{}{}", exports, prompt);
+        let output = stream_process(cmd, Some(stdin), self.cli.health.as_ref(), Some(&on_line))
+            .await
+            .context("Failed to execute ssh")?;
+        log_stderr(&output.stderr);
+        // ssh itself exits with 255 when it can't connect or log in
+        if output.status.code() == Some(255) {
+            anyhow::bail!(
+                "ssh to {} failed: {}",
+                host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        cli_response(&output).with_context(|| format!("on {}", host))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Docker client — runs `claude -p` in a fresh container
+// ---------------------------------------------------------------------------
+
+/// Where an agent's `working_dir` is mounted in its container.
+const CONTAINER_WORKSPACE: &str = "/workspace";
+
+pub struct DockerClient {
+    docker: DockerConfig,
+    docker_path: String,
+    /// The container's `claude` and the agent's settings.
+    cli: CliClient,
+}
+
+impl DockerClient {
+    pub fn new(docker: DockerConfig) -> Self {
+        Self {
+            cli: CliClient::with_cli_path(&docker.claude_path),
+            docker,
+            docker_path: "docker".to_string(),
+        }
+    }
+
+    pub fn with_options(mut self, options: &ClientOptions) -> Self {
+        self.cli = self.cli.with_options(options);
+        self
+    }
+
+    /// The `docker run` arguments for container `name`. Environment
+    /// variables are named here and given values on the docker process, so
+    /// they don't show up in the host's process list.
+    fn run_args(&self, name: &str, env: &[(String, String)]) -> Vec<String> {
+        let mut args: Vec<String> = ["run", "--rm", "-i", "--name", name]
+            .map(String::from)
+            .into();
+        if let Some(ref memory) = self.docker.memory {
+            args.extend(["--memory".to_string(), memory.clone()]);
+        }
+        if let Some(cpus) = self.docker.cpus {
+            args.extend(["--cpus".to_string(), cpus.to_string()]);
+        }
+        if let Some(ref network) = self.docker.network {
+            args.extend(["--network".to_string(), network.clone()]);
+        }
+        if let Some(ref dir) = self.cli.working_dir {
+            let mode = if self.docker.read_only { ":ro" } else { "" };
+            args.extend([
+                "-v".to_string(),
+                format!("{}:{}{}", dir.display(), CONTAINER_WORKSPACE, mode),
+                "-w".to_string(),
+                CONTAINER_WORKSPACE.to_string(),
+            ]);
+        }
+        for (key, _) in env {
+            args.extend(["-e".to_string(), key.clone()]);
+        }
+        args.extend(self.docker.args.iter().cloned());
+        args.push(self.docker.image.clone());
+        args
+    }
+
+    /// Whether the image's `claude` takes `--append-system-prompt`, asked
+    /// once per image and CLI path.
+    async fn supports_system_prompt(&self) -> bool {
+        let mut help = tokio::process::Command::new(&self.docker_path);
+        help.args(self.run_args(&container_name(), &[]))
+            .args([&self.cli.cli_path, "--help"]);
+        help_lists(
+            &format!("{}:{}", self.docker.image, self.cli.cli_path),
+            help,
+            "--append-system-prompt",
+        )
+        .await
+    }
+}
+
+/// A container name no other call is using.
+fn container_name() -> String {
+    static NEXT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let n = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    format!("orchestra-{}-{}", std::process::id(), n)
+}
+
+/// Removes the container when dropped, unless cleared. Killing `docker run`
+/// alone would leave it running.
+struct ContainerGuard<'a>(Option<(&'a str, &'a str)>);
+
+impl Drop for ContainerGuard<'_> {
+    fn drop(&mut self) {
+        if let Some((docker, name)) = self.0 {
+            let _ = std::process::Command::new(docker)
+                .args(["rm", "-f", name])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn();
+        }
+    }
+}
+
+#[async_trait]
+impl AgentClient for DockerClient {
+    async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
+
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let (mut argv, env) = self.cli.invocation(false);
+        let prompt = match system_prompt {
+            Some(sys) if self.supports_system_prompt().await => {
+                argv.extend(["--append-system-prompt".to_string(), sys.to_string()]);
+                prompt.to_string()
+            }
+            Some(sys) => prefix_system_prompt(prompt, sys, "CONTEXT"),
+            None => prompt.to_string(),
+        };
+        let name = container_name();
+        let mut cmd = tokio::process::Command::new(&self.docker_path);
+        cmd.args(self.run_args(&name, &env)).args(argv).envs(env);
+        let mut guard = ContainerGuard(Some((&self.docker_path, &name)));
+        let output = run_process(cmd, Some(prompt), self.cli.health.as_ref())
+            .await
+            .context("Failed to execute docker")?;
+        guard.0 = None;
+        log_stderr(&output.stderr);
+        // docker run exits with 125 when the container couldn't be started
+        if output.status.code() == Some(125) {
             anyhow::bail!(
-                "claude CLI exited with {}: {}",
-                output.status,
-                detail.trim()
+                "docker couldn't start {}: {}",
+                self.docker.image,
+                String::from_utf8_lossy(&output.stderr).trim()
             );
         }
-
-        let text = String::from_utf8_lossy(&output.stdout).to_string();
-        Ok(text)
+        cli_response(&output).with_context(|| format!("in {}", self.docker.image))
     }
 }
 
@@ -218,19 +1669,36 @@ impl AgentClient for CliClient {
 pub struct HybridClient {
     api: ApiClient,
     cli: CliClient,
+    prefer_fallback: bool,
+    circuit_breaker: Option<CircuitBreakerConfig>,
 }
 
 impl HybridClient {
     pub fn new(api_key: String) -> Self {
+        Self::with_keys(&KeyPool::single(api_key))
+    }
+
+    pub fn with_keys(keys: &Arc<KeyPool>) -> Self {
         Self {
-            api: ApiClient::new(api_key),
+            api: ApiClient::with_keys(keys),
             cli: CliClient::new(),
+            prefer_fallback: false,
+            circuit_breaker: None,
         }
     }
 
     #[allow(dead_code)]
     pub fn with_model(mut self, model: &str) -> Self {
         self.api = self.api.with_model(model);
+        self.cli = self.cli.with_model(model);
+        self
+    }
+
+    pub fn with_options(mut self, options: ClientOptions) -> Self {
+        self.prefer_fallback = options.prefer_fallback;
+        self.circuit_breaker = options.circuit_breaker.clone();
+        self.cli = self.cli.with_options(&options);
+        self.api = self.api.with_options(options);
         self
     }
 }
@@ -238,18 +1706,45 @@ impl HybridClient {
 #[async_trait]
 impl AgentClient for HybridClient {
     async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
+
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        if self.prefer_fallback {
+            info!("Hybrid: provider outage reported, using CLI directly");
+            return self.cli.send(prompt, system_prompt).await;
+        }
+        let breaker = breaker::shared();
+        if let Some(config) = &self.circuit_breaker {
+            if !breaker.allow(config, Instant::now()) {
+                info!("Hybrid: API circuit open, using CLI directly");
+                return self.cli.send(prompt, system_prompt).await;
+            }
+        }
+
         // Try API first
-        match self.api.send_message(prompt, system_prompt).await {
+        let result = self.api.send(prompt, system_prompt).await;
+        if let Some(config) = &self.circuit_breaker {
+            let outage = result.as_ref().is_err_and(breaker::is_outage);
+            breaker.record(config, outage, Instant::now());
+        }
+        match result {
             Ok(response) => {
                 info!("Hybrid: API succeeded");
                 Ok(response)
             }
             Err(api_err) => {
                 warn!("Hybrid: API failed ({:#}), falling back to CLI", api_err);
-                self.cli
-                    .send_message(prompt, system_prompt)
+                let response = self
+                    .cli
+                    .send(prompt, system_prompt)
                     .await
-                    .context("Hybrid: both API and CLI failed")
+                    .context("Hybrid: both API and CLI failed")?;
+                // The API's own attempts aren't known once it failed; count it as one
+                Ok(AgentResponse {
+                    attempts: response.attempts + 1,
+                    ..response
+                })
             }
         }
     }
@@ -261,40 +1756,109 @@ impl AgentClient for HybridClient {
 
 pub struct TeamsClient {
     cli_path: String,
+    model: Option<String>,
+    fallback_model: Option<String>,
+    egress: Option<EgressPolicy>,
+    working_dir: Option<PathBuf>,
+    env: BTreeMap<String, String>,
+    tools: ToolPolicy,
+    team: Option<TeamSession>,
+}
+
+impl Default for TeamsClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TeamsClient {
+    /// See [`CliClient::new`].
     pub fn new() -> Self {
-        // Prefer the local `claude` on PATH, fall back to known paths
-        let cli_path = std::env::var("CLAUDE_CLI_PATH").unwrap_or_else(|_| {
-            if std::path::Path::new("/usr/local/bin/claude").exists() {
-                "/usr/local/bin/claude".to_string()
-            } else {
-                "claude".to_string()
-            }
-        });
-        Self { cli_path }
+        Self::with_cli_path(default_cli_path())
+    }
+
+    /// See [`CliClient::discover`].
+    pub fn discover() -> Result<Self> {
+        Ok(Self::with_cli_path(locate_claude_cli()?))
+    }
+
+    fn with_cli_path(cli_path: impl AsRef<Path>) -> Self {
+        Self {
+            cli_path: cli_path.as_ref().to_string_lossy().into_owned(),
+            model: None,
+            fallback_model: None,
+            egress: None,
+            working_dir: None,
+            env: BTreeMap::new(),
+            tools: ToolPolicy::default(),
+            team: None,
+        }
+    }
+
+    pub fn with_options(mut self, options: &ClientOptions) -> Self {
+        if let Some(ref model) = options.model {
+            self.model = Some(model.clone());
+        }
+        self.fallback_model = options.fallback_models.first().cloned();
+        self.egress = options.egress.clone();
+        self.working_dir = options.working_dir.clone();
+        self.env = options.env.clone();
+        self.tools = options.tools.clone();
+        self.team = options.team.clone();
+        self
     }
 }
 
 #[async_trait]
 impl AgentClient for TeamsClient {
     async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
-        let full_prompt = match system_prompt {
-            Some(sys) => format!("[TEAM CONTEXT: {}]\n\n{}", sys, prompt),
-            None => prompt.to_string(),
-        };
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
 
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
         info!("TeamsClient: launching claude with Agent Teams enabled");
 
-        let output = tokio::process::Command::new(&self.cli_path)
-            .arg("-p")
-            .arg(&full_prompt)
-            .env("CLAUDE_CODE_EXPERIMENTAL_AGENT_TEAMS", "1")
-            .env_remove("ANTHROPIC_API_KEY")
-            .output()
+        let lead_prompt;
+        let prompt = match self.team {
+            Some(ref team) => {
+                team.write_tasks()?;
+                info!(
+                    "TeamsClient: team {} with {} teammates in {}",
+                    team.team,
+                    team.definition.teammates.len(),
+                    team.dir.display()
+                );
+                lead_prompt = team.lead_prompt(prompt);
+                &lead_prompt
+            }
+            None => prompt,
+        };
+        let mut cmd = tokio::process::Command::new(&self.cli_path);
+        let prompt = apply_system_prompt(
+            &mut cmd,
+            &self.cli_path,
+            prompt,
+            system_prompt,
+            "TEAM CONTEXT",
+        )
+        .await;
+        cmd.arg("-p").arg(&prompt);
+        if let Some(ref model) = self.model {
+            cmd.arg("--model").arg(model);
+        }
+        if let Some(ref fallback) = self.fallback_model {
+            cmd.arg("--fallback-model").arg(fallback);
+        }
+        if let Some(ref team) = self.team {
+            cmd.arg("--add-dir").arg(&team.dir);
+        }
+        apply_tools(&mut cmd, &self.tools, self.egress.as_ref());
+        cmd.env("CLAUDE_CODE_EXPERIMENTAL_AGENT_TEAMS", "1");
+        apply_process_env(&mut cmd, self.working_dir.as_deref(), &self.env)?;
+        let output = run_claude(cmd)
             .await
             .context("Failed to execute claude CLI with Agent Teams")?;
+        log_stderr(&output.stderr);
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -313,7 +1877,165 @@ impl AgentClient for TeamsClient {
             "TeamsClient: session completed ({} bytes output)",
             text.len()
         );
-        Ok(text)
+        Ok(AgentResponse {
+            text,
+            attempts: 1,
+            teammates: self
+                .team
+                .as_ref()
+                .map(TeamSession::collect)
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Mock client (free) — canned responses, no CLI or network
+// ---------------------------------------------------------------------------
+
+pub const MOCK_MODEL: &str = "mock";
+
+#[derive(Default)]
+pub struct MockClient {
+    response: Option<String>,
+}
+
+impl MockClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reply with `response` instead of echoing the prompt.
+    pub fn with_response(mut self, response: impl Into<String>) -> Self {
+        self.response = Some(response.into());
+        self
+    }
+
+    pub fn with_options(mut self, options: &ClientOptions) -> Self {
+        self.response = options.mock_response.clone();
+        self
+    }
+}
+
+#[async_trait]
+impl AgentClient for MockClient {
+    async fn send_message(&self, prompt: &str, _system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.response.clone().unwrap_or_else(|| {
+            let first_line = prompt.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+            let preview: String = first_line.trim().chars().take(80).collect();
+            format!("[mock] {}", preview)
+        }))
+    }
+
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        Ok(AgentResponse {
+            text: self.send_message(prompt, system_prompt).await?,
+            model: Some(MOCK_MODEL.to_string()),
+            attempts: 1,
+            ..Default::default()
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Exec client — any command that reads a prompt and prints the answer
+// ---------------------------------------------------------------------------
+
+pub struct ExecClient {
+    command: Vec<String>,
+    model: Option<String>,
+    egress: Option<EgressPolicy>,
+    working_dir: Option<PathBuf>,
+    env: BTreeMap<String, String>,
+    health: Option<HealthConfig>,
+}
+
+impl ExecClient {
+    pub fn new(command: Vec<String>) -> Self {
+        Self {
+            command,
+            model: None,
+            egress: None,
+            working_dir: None,
+            env: BTreeMap::new(),
+            health: None,
+        }
+    }
+
+    pub fn with_options(mut self, options: &ClientOptions) -> Self {
+        self.model = options.model.clone();
+        self.egress = options.egress.clone();
+        self.working_dir = options.working_dir.clone();
+        self.env = options.env.clone();
+        self.health = options.health.clone();
+        self
+    }
+
+    /// Whether any argument reads variable `name`.
+    fn uses(&self, name: &str) -> bool {
+        self.command.iter().any(|arg| template::reads(arg, name))
+    }
+
+    /// The command line with its placeholders filled in, and the input for
+    /// stdin when the prompt isn't passed as an argument. A system prompt
+    /// with no placeholder of its own is prefixed to the prompt.
+    fn invocation(
+        &self,
+        prompt: &str,
+        system_prompt: Option<&str>,
+    ) -> (Vec<String>, Option<String>) {
+        let prompt = match system_prompt {
+            Some(sys) if !self.uses("system_prompt") => format!("[CONTEXT: {}]\n\n{}", sys, prompt),
+            _ => prompt.to_string(),
+        };
+        let mut vars = TemplateVars::new();
+        vars.insert("prompt", prompt.as_str());
+        vars.insert("system_prompt", system_prompt.unwrap_or_default());
+        vars.insert("model", self.model.clone().unwrap_or_default());
+        let args = self.command.iter().map(|arg| vars.render(arg).0).collect();
+        (args, (!self.uses("prompt")).then_some(prompt))
+    }
+}
+
+#[async_trait]
+impl AgentClient for ExecClient {
+    async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
+
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let (args, stdin) = self.invocation(prompt, system_prompt);
+        let (program, args) = args.split_first().context("exec.command is empty")?;
+        let mut cmd = tokio::process::Command::new(program);
+        cmd.args(args);
+        if let Some(policy) = &self.egress {
+            cmd.envs(policy.proxy_env());
+        }
+        apply_process_env(&mut cmd, self.working_dir.as_deref(), &self.env)?;
+        let output = run_process(cmd, stdin, self.health.as_ref())
+            .await
+            .with_context(|| format!("Failed to execute {}", program))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.trim().is_empty() {
+            debug!("{} stderr:\n{}", program, stderr.trim_end());
+        }
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} exited with {}: {}",
+                program,
+                output.status,
+                stderr.trim()
+            );
+        }
+        Ok(AgentResponse {
+            text: String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string(),
+            model: self.model.clone().filter(|_| self.uses("model")),
+            attempts: 1,
+            ..Default::default()
+        })
     }
 }
 
@@ -321,18 +2043,65 @@ impl AgentClient for TeamsClient {
 // Factory
 // ---------------------------------------------------------------------------
 
-pub fn create_client(mode: &ClientMode, api_key: Option<String>) -> Result<Box<dyn AgentClient>> {
+pub fn create_client(
+    mode: &ClientMode,
+    api_keys: Option<&Arc<KeyPool>>,
+) -> Result<Box<dyn AgentClient>> {
+    create_client_with_options(mode, api_keys, &ClientOptions::default())
+}
+
+/// Like [`create_client`], but applies per-agent options. The model chain is
+/// honoured by every client; sampling options only apply to API-backed clients
+/// since the CLI has no equivalent flags.
+pub fn create_client_with_options(
+    mode: &ClientMode,
+    api_keys: Option<&Arc<KeyPool>>,
+    options: &ClientOptions,
+) -> Result<Box<dyn AgentClient>> {
+    if let Some(egress) = &options.egress {
+        if !matches!(mode, ClientMode::Api | ClientMode::Mock | ClientMode::Exec) {
+            egress.check_cli()?;
+        }
+    }
     match mode {
         ClientMode::Api => {
-            let key = api_key.context("ANTHROPIC_API_KEY is required when CLIENT_MODE=api")?;
-            Ok(Box::new(ApiClient::new(key)))
+            let keys = api_keys.context("ANTHROPIC_API_KEY is required when CLIENT_MODE=api")?;
+            Ok(Box::new(
+                ApiClient::with_keys(keys).with_options(options.clone()),
+            ))
         }
-        ClientMode::ClaudeCode => Ok(Box::new(CliClient::new())),
+        ClientMode::ClaudeCode => Ok(Box::new(CliClient::discover()?.with_options(options))),
         ClientMode::Hybrid => {
-            let key = api_key.context("ANTHROPIC_API_KEY is required when CLIENT_MODE=hybrid")?;
-            Ok(Box::new(HybridClient::new(key)))
+            let keys = api_keys.context("ANTHROPIC_API_KEY is required when CLIENT_MODE=hybrid")?;
+            Ok(Box::new(
+                HybridClient::with_keys(keys).with_options(options.clone()),
+            ))
+        }
+        ClientMode::AgentTeams => Ok(Box::new(TeamsClient::discover()?.with_options(options))),
+        ClientMode::Mock => Ok(Box::new(MockClient::new().with_options(options))),
+        ClientMode::Exec => {
+            let command = options
+                .exec
+                .as_ref()
+                .map(|exec| exec.command.clone())
+                .filter(|command| !command.is_empty())
+                .context("client_mode exec needs an `exec.command`")?;
+            Ok(Box::new(ExecClient::new(command).with_options(options)))
+        }
+        ClientMode::Ssh => {
+            let ssh = options
+                .ssh
+                .clone()
+                .context("client_mode ssh needs an `ssh.host`")?;
+            Ok(Box::new(SshClient::new(ssh).with_options(options)))
+        }
+        ClientMode::Docker => {
+            let docker = options
+                .docker
+                .clone()
+                .context("client_mode docker needs a `docker.image`")?;
+            Ok(Box::new(DockerClient::new(docker).with_options(options)))
         }
-        ClientMode::AgentTeams => Ok(Box::new(TeamsClient::new())),
     }
 }
 
@@ -341,13 +2110,14 @@ pub fn create_client(mode: &ClientMode, api_key: Option<String>) -> Result<Box<d
 pub fn create_agent_client(
     agent_mode: Option<&str>,
     global_mode: &ClientMode,
-    api_key: Option<String>,
+    api_keys: Option<&Arc<KeyPool>>,
+    options: &ClientOptions,
 ) -> Result<Box<dyn AgentClient>> {
     let mode = match agent_mode {
         Some(m) => ClientMode::from_str(m)?,
         None => global_mode.clone(),
     };
-    create_client(&mode, api_key)
+    create_client_with_options(&mode, api_keys, options)
 }
 
 #[cfg(test)]
@@ -357,7 +2127,7 @@ mod tests {
     #[test]
     fn test_api_client_creation() {
         let client = ApiClient::new("test-key".to_string());
-        assert_eq!(client.api_key, "test-key");
+        assert_eq!(client.key.secret(), "test-key");
         assert_eq!(client.model, DEFAULT_MODEL);
     }
 
@@ -367,10 +2137,587 @@ mod tests {
         assert_eq!(client.model, "claude-opus-4-6");
     }
 
+    #[test]
+    fn test_api_request_uses_default_max_tokens() {
+        let client = ApiClient::new("test-key".to_string());
+        let request = client.build_request(&client.model, "hi", None);
+        assert_eq!(request.max_tokens, DEFAULT_MAX_TOKENS);
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("temperature").is_none());
+        assert!(json.get("stop_sequences").is_none());
+    }
+
+    #[test]
+    fn test_api_request_with_options() {
+        let client = ApiClient::new("test-key".to_string()).with_options(ClientOptions {
+            max_tokens: Some(512),
+            temperature: Some(0.0),
+            model: None,
+            fallback_models: Vec::new(),
+            stop_sequences: vec!["END".to_string()],
+            prefer_fallback: false,
+            egress: None,
+            working_dir: None,
+            env: BTreeMap::new(),
+            tools: ToolPolicy::default(),
+            continuation: Continuation::Fresh,
+            team: None,
+            mock_response: None,
+            exec: None,
+            ssh: None,
+            docker: None,
+            health: None,
+            circuit_breaker: None,
+        });
+        let json =
+            serde_json::to_value(client.build_request(&client.model, "hi", Some("sys"))).unwrap();
+        assert_eq!(json["max_tokens"], 512);
+        assert_eq!(json["temperature"], 0.0);
+        assert_eq!(json["stop_sequences"][0], "END");
+    }
+
+    #[test]
+    fn test_batch_requests_and_results() {
+        let client = ApiClient::new("test-key".to_string()).with_model("claude-haiku-4-5");
+        let json = serde_json::to_value(client.batch_request("agent-0", "hi", None)).unwrap();
+        assert_eq!(json["custom_id"], "agent-0");
+        assert_eq!(json["params"]["model"], "claude-haiku-4-5");
+        assert_eq!(json["params"]["messages"][0]["content"], "hi");
+
+        let jsonl = r#"{"custom_id":"agent-0","result":{"type":"succeeded","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"text","text":"3 errors"}],"usage":{"input_tokens":900,"output_tokens":40}}}}
+{"custom_id":"agent-1","result":{"type":"errored","error":{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long"}}}}
+{"custom_id":"agent-2","result":{"type":"expired"}}
+"#;
+        let results = parse_batch_results(jsonl);
+        let ok = results["agent-0"].as_ref().unwrap();
+        assert_eq!(ok.text, "3 errors");
+        assert_eq!(ok.usage.input_tokens, Some(900));
+        assert_eq!(
+            results["agent-1"].as_ref().unwrap_err(),
+            "Batch request failed: invalid_request_error: prompt is too long"
+        );
+        assert!(results["agent-2"].as_ref().unwrap_err().contains("expired"));
+    }
+
+    #[test]
+    fn test_api_request_reaches_remote_mcp_servers() {
+        let servers = BTreeMap::from([
+            (
+                "postgres".to_string(),
+                McpServer {
+                    command: Some("npx".to_string()),
+                    args: vec!["@modelcontextprotocol/server-postgres".to_string()],
+                    ..McpServer::default()
+                },
+            ),
+            (
+                "tickets".to_string(),
+                McpServer {
+                    url: Some("https://mcp.example.com/mcp".to_string()),
+                    token_env: Some("ORCHESTRA_TEST_UNSET_TOKEN".to_string()),
+                    ..McpServer::default()
+                },
+            ),
+        ]);
+        let client = ApiClient::new("test-key".to_string()).with_options(ClientOptions {
+            tools: ToolPolicy {
+                mcp_servers: servers,
+                ..ToolPolicy::default()
+            },
+            ..ClientOptions::default()
+        });
+        let json = serde_json::to_value(client.build_request(&client.model, "hi", None)).unwrap();
+        assert_eq!(
+            json["mcp_servers"],
+            serde_json::json!([
+                { "type": "url", "url": "https://mcp.example.com/mcp", "name": "tickets" }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_api_client_model_chain_from_options() {
+        let client = ApiClient::new("test-key".to_string()).with_options(ClientOptions {
+            model: Some("claude-opus-4-1".to_string()),
+            fallback_models: vec!["claude-sonnet-4-5".to_string()],
+            ..ClientOptions::default()
+        });
+        assert_eq!(client.model, "claude-opus-4-1");
+        assert_eq!(client.fallback_models, vec!["claude-sonnet-4-5"]);
+    }
+
+    #[test]
+    fn test_api_status_error_fallback_classification() {
+        let err = |status| ApiStatusError {
+            status,
+            body: String::new(),
+        };
+        assert!(err(529).is_model_specific());
+        assert!(err(404).is_model_specific());
+        assert!(!err(400).is_model_specific());
+        assert!(!err(401).is_model_specific());
+    }
+
+    #[test]
+    fn test_cli_client_takes_first_fallback() {
+        let client = CliClient::new().with_options(&ClientOptions {
+            model: Some("opus".to_string()),
+            fallback_models: vec!["sonnet".to_string(), "haiku".to_string()],
+            ..ClientOptions::default()
+        });
+        assert_eq!(client.model.as_deref(), Some("opus"));
+        assert_eq!(client.fallback_model.as_deref(), Some("sonnet"));
+    }
+
+    #[test]
+    fn test_cli_streams_output_under_watchdog() {
+        let args = |client: CliClient| {
+            let mut cmd = tokio::process::Command::new("claude");
+            client.apply_options(&mut cmd, false);
+            let args: Vec<String> = cmd
+                .as_std()
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect();
+            args
+        };
+        assert_eq!(args(CliClient::new()), ["--output-format", "json"]);
+        let watched = CliClient::new().with_options(&ClientOptions {
+            health: Some(HealthConfig::default()),
+            ..ClientOptions::default()
+        });
+        assert_eq!(
+            args(watched),
+            ["--output-format", "stream-json", "--verbose"]
+        );
+    }
+
+    #[test]
+    fn test_parse_cli_json_result() {
+        let stdout = br#"{"type":"result","subtype":"success","is_error":false,
+            "duration_ms":2345,"num_turns":3,"result":"All systems nominal",
+            "session_id":"4f1c","total_cost_usd":0.0123,
+            "usage":{"input_tokens":1200,"output_tokens":80},
+            "modelUsage":{"claude-haiku-4-5":{"outputTokens":5},
+                          "claude-sonnet-4-5":{"outputTokens":75}}}"#;
+        let response = parse_cli_output(stdout).unwrap();
+        assert_eq!(response.text, "All systems nominal");
+        assert_eq!(response.model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(response.session_id.as_deref(), Some("4f1c"));
+        assert_eq!(
+            response.usage,
+            Usage {
+                input_tokens: Some(1200),
+                output_tokens: Some(80),
+                cost_usd: Some(0.0123),
+            }
+        );
+
+        let plain = parse_cli_output(b"just text\n").unwrap();
+        assert_eq!(plain.text, "just text\n");
+        assert_eq!(plain.usage, Usage::default());
+
+        let failed =
+            br#"{"type":"result","subtype":"error_max_turns","is_error":true,"result":""}"#;
+        let err = parse_cli_output(failed).unwrap_err().to_string();
+        assert!(err.contains("error_max_turns"));
+
+        // stream-json, as run under the health watchdog
+        let streamed = br#"{"type":"system","subtype":"init","session_id":"4f1c"}
+{"type":"assistant","message":{"content":[{"type":"text","text":"Checking"}]}}
+{"type":"result","subtype":"success","is_error":false,"result":"Disks fine","session_id":"4f1c"}
+"#;
+        let response = parse_cli_output(streamed).unwrap();
+        assert_eq!(response.text, "Disks fine");
+        assert_eq!(response.session_id.as_deref(), Some("4f1c"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_system_prompt_uses_flag_when_cli_has_it() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-cli-help-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = |name: &str, help: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\necho '{}'\n", help)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let new_cli = script(
+            "new",
+            "  --append-system-prompt <prompt>  Append a system prompt",
+        );
+        let old_cli = script("old", "  -p, --print  Print response and exit");
+
+        let mut cmd = tokio::process::Command::new(&new_cli);
+        let prompt = apply_system_prompt(
+            &mut cmd,
+            &new_cli,
+            "Check disks",
+            Some("You are SRE"),
+            "CONTEXT",
+        )
+        .await;
+        assert_eq!(prompt, "Check disks");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert_eq!(args, ["--append-system-prompt", "You are SRE"]);
+
+        let mut cmd = tokio::process::Command::new(&old_cli);
+        let prompt = apply_system_prompt(
+            &mut cmd,
+            &old_cli,
+            "Check disks",
+            Some("You are SRE"),
+            "CONTEXT",
+        )
+        .await;
+        assert_eq!(prompt, "[CONTEXT: You are SRE]\n\nCheck disks");
+        assert_eq!(cmd.as_std().get_args().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cli_runs_in_working_dir_with_env() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-cli-env-{}", std::process::id()));
+        let checkout = dir.join("checkout");
+        std::fs::create_dir_all(&checkout).unwrap();
+        let script = dir.join("claude");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho \"$(pwd) $PROJECT ${ANTHROPIC_API_KEY:-none}\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let options = ClientOptions {
+            working_dir: Some(checkout.clone()),
+            env: BTreeMap::from([("PROJECT".to_string(), "billing".to_string())]),
+            ..ClientOptions::default()
+        };
+        let client = CliClient::with_cli_path(&script).with_options(&options);
+        let reply = client.send_message("hi", None).await.unwrap();
+        let checkout = checkout.canonicalize().unwrap();
+        assert_eq!(reply.trim(), format!("{} billing none", checkout.display()));
+
+        let options = ClientOptions {
+            working_dir: Some(dir.join("missing")),
+            ..ClientOptions::default()
+        };
+        let client = CliClient::with_cli_path(&script).with_options(&options);
+        let err = client.send_message("hi", None).await.unwrap_err();
+        assert!(err.to_string().contains("not a directory"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_ssh_client_runs_claude_remotely() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-ssh-{}", std::process::id()));
+        let remote = dir.join("remote dir");
+        std::fs::create_dir_all(&remote).unwrap();
+        let script = |name: &str, body: String| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{}", body)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+        // Runs the remote command locally, as the host's shell would
+        let ssh = script(
+            "ssh",
+            format!(
+                "for last; do :; done\nprintf '%s\\n' \"$@\" > '{}'\nexec sh -c \"$last\"\n",
+                dir.join("ssh-args").display()
+            ),
+        );
+        let claude = script(
+            "claude",
+            "[ \"$1\" = --help ] && echo '  --append-system-prompt <prompt>' && exit\necho \"$(pwd) $PROJECT $*\"; cat\n"
+                .to_string(),
+        );
+
+        let config = SshConfig {
+            host: "web-1".to_string(),
+            user: Some("ops".to_string()),
+            port: Some(2222),
+            key: None,
+            options: vec!["ConnectTimeout=5".to_string()],
+            claude_path: claude.display().to_string(),
+        };
+        let options = ClientOptions {
+            model: Some("claude-sonnet-4-5".to_string()),
+            working_dir: Some(remote.clone()),
+            env: BTreeMap::from([("PROJECT".to_string(), "it's billing".to_string())]),
+            ..ClientOptions::default()
+        };
+        let mut client = SshClient::new(config).with_options(&options);
+        client.ssh_path = ssh.display().to_string();
+        let reply = client
+            .send("Check disks", Some("You are SRE"))
+            .await
+            .unwrap();
+        assert_eq!(
+            reply.text,
+            format!(
+                "{} it's billing -p --output-format stream-json --verbose --model claude-sonnet-4-5 --append-system-prompt You are SRE\nCheck disks",
+                remote.display()
+            )
+        );
+        let args = std::fs::read_to_string(dir.join("ssh-args")).unwrap();
+        assert!(args
+            .starts_with("-T\n-o\nBatchMode=yes\n-o\nConnectTimeout=5\n-p\n2222\n--\nops@web-1\n"));
+        assert!(!args.contains("billing"));
+
+        // A remote CLI without the flag gets the system prompt in front
+        let old_claude = script("old-claude", "echo \"$*\"; cat\n".to_string());
+        let config = SshConfig {
+            host: "-oProxyCommand=touch pwned".to_string(),
+            user: None,
+            port: None,
+            key: None,
+            options: Vec::new(),
+            claude_path: old_claude.display().to_string(),
+        };
+        let mut old = SshClient::new(config);
+        old.ssh_path = ssh.display().to_string();
+        let reply = old.send("Check disks", Some("You are SRE")).await.unwrap();
+        assert_eq!(
+            reply.text,
+            "-p --output-format stream-json --verbose\n[CONTEXT: You are SRE]\n\nCheck disks"
+        );
+        let args = std::fs::read_to_string(dir.join("ssh-args")).unwrap();
+        assert!(args.starts_with("-T\n-o\nBatchMode=yes\n--\n-oProxyCommand=touch pwned\n"));
+
+        client.ssh_path = script(
+            "ssh",
+            "echo 'Connection refused' >&2; exit 255\n".to_string(),
+        )
+        .display()
+        .to_string();
+        let err = client.send_message("hi", None).await.unwrap_err();
+        assert_eq!(err.to_string(), "ssh to web-1 failed: Connection refused");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_docker_client_runs_claude_in_container() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-docker-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let docker = dir.join("docker");
+        let write_docker = |body: &str| {
+            std::fs::write(&docker, format!("#!/bin/sh\n{}", body)).unwrap();
+            std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        write_docker(&format!(
+            "case \"$*\" in *--help) echo '  --append-system-prompt <prompt>'; exit;; esac\nprintf '%s\\n' \"$@\" > '{}'\necho \"$PROJECT\"; cat\n",
+            dir.join("docker-args").display()
+        ));
+
+        let config = DockerConfig {
+            image: "ghcr.io/acme/claude-agent:1".to_string(),
+            memory: Some("2g".to_string()),
+            cpus: Some(1.5),
+            network: Some("none".to_string()),
+            read_only: true,
+            args: vec!["--pids-limit=256".to_string()],
+            claude_path: "claude".to_string(),
+        };
+        let options = ClientOptions {
+            working_dir: Some(dir.join("checkout")),
+            env: BTreeMap::from([("PROJECT".to_string(), "billing".to_string())]),
+            ..ClientOptions::default()
+        };
+        let mut client = DockerClient::new(config).with_options(&options);
+        client.docker_path = docker.display().to_string();
+        let reply = client
+            .send("Check disks", Some("You are SRE"))
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "billing\nCheck disks");
+
+        let args = std::fs::read_to_string(dir.join("docker-args")).unwrap();
+        let args: Vec<&str> = args.lines().collect();
+        assert_eq!(&args[..3], ["run", "--rm", "-i"]);
+        assert!(args[4].starts_with("orchestra-"));
+        assert_eq!(
+            &args[5..],
+            [
+                "--memory",
+                "2g",
+                "--cpus",
+                "1.5",
+                "--network",
+                "none",
+                "-v",
+                &format!("{}:/workspace:ro", dir.join("checkout").display()),
+                "-w",
+                "/workspace",
+                "-e",
+                "PROJECT",
+                "--pids-limit=256",
+                "ghcr.io/acme/claude-agent:1",
+                "claude",
+                "-p",
+                "--output-format",
+                "json",
+                "--append-system-prompt",
+                "You are SRE",
+            ]
+        );
+
+        write_docker("echo 'Unable to find image' >&2; exit 125\n");
+        let err = client.send_message("hi", None).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "docker couldn't start ghcr.io/acme/claude-agent:1: Unable to find image"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tool_policy_merges_with_egress() {
+        let tools = ToolPolicy {
+            allowed_tools: vec!["Read".to_string(), "Bash(git log:*)".to_string()],
+            disallowed_tools: vec!["Edit".to_string()],
+            permission_mode: Some("plan".to_string()),
+            mcp_servers: BTreeMap::from([(
+                "tickets".to_string(),
+                McpServer {
+                    url: Some("https://mcp.example.com/mcp".to_string()),
+                    token_env: Some("TICKETS_TOKEN".to_string()),
+                    ..McpServer::default()
+                },
+            )]),
+        };
+        let egress = EgressPolicy {
+            allowed_domains: vec!["docs.rs".to_string()],
+            ..EgressPolicy::default()
+        };
+        let mut cmd = tokio::process::Command::new("claude");
+        apply_tools(&mut cmd, &tools, Some(&egress));
+        let args: Vec<&std::ffi::OsStr> = cmd.as_std().get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "--allowedTools",
+                "Read",
+                "Bash(git log:*)",
+                "WebFetch(domain:docs.rs)",
+                "WebFetch(domain:*.docs.rs)",
+                "mcp__tickets",
+                "--disallowedTools",
+                "Edit",
+                "WebSearch",
+                "--permission-mode",
+                "plan",
+                "--mcp-config",
+                r#"{"mcpServers":{"tickets":{"headers":{"Authorization":"Bearer ${TICKETS_TOKEN}"},"type":"http","url":"https://mcp.example.com/mcp"}}}"#,
+            ]
+        );
+
+        let mut cmd = tokio::process::Command::new("claude");
+        apply_tools(&mut cmd, &ToolPolicy::default(), None);
+        assert_eq!(cmd.as_std().get_args().count(), 0);
+    }
+
+    #[test]
+    fn test_mcp_server_env_stays_off_the_command_line() {
+        let tools = ToolPolicy {
+            mcp_servers: BTreeMap::from([(
+                "git-hub".to_string(),
+                McpServer {
+                    command: Some("gh-mcp".to_string()),
+                    env: BTreeMap::from([("TOKEN".to_string(), "hunter2".to_string())]),
+                    ..McpServer::default()
+                },
+            )]),
+            ..ToolPolicy::default()
+        };
+        let mut cmd = tokio::process::Command::new("claude");
+        apply_tools(&mut cmd, &tools, None);
+        let args: Vec<&std::ffi::OsStr> = cmd.as_std().get_args().collect();
+        assert_eq!(
+            args.last().unwrap().to_str().unwrap(),
+            r#"{"mcpServers":{"git-hub":{"args":[],"command":"gh-mcp","env":{"TOKEN":"${ORCHESTRA_MCP_GIT_HUB_TOKEN}"}}}}"#
+        );
+        let envs: Vec<_> = cmd.as_std().get_envs().collect();
+        assert_eq!(
+            envs,
+            [(
+                std::ffi::OsStr::new("ORCHESTRA_MCP_GIT_HUB_TOKEN"),
+                Some(std::ffi::OsStr::new("hunter2"))
+            )]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_teams_client_collects_teammate_outputs() {
+        use crate::config::{TeamDefinition, TeammateDefinition};
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-team-run-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Plays the lead: only the architect gets around to its task
+        let script = dir.join("claude");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do\n  if [ \"$1\" = --add-dir ]; then echo 'Plan ready' > \"$2/architect.md\"; fi\n  shift\ndone\necho 'Team finished'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let definition = TeamDefinition {
+            description: "Build a feature".to_string(),
+            teammates: ["architect", "reviewer"]
+                .into_iter()
+                .map(|name| TeammateDefinition {
+                    name: name.to_string(),
+                    role: format!("You are the {}.", name),
+                    timeout_seconds: 60,
+                })
+                .collect(),
+        };
+        let options = ClientOptions {
+            team: Some(TeamSession::new(
+                "feature-dev",
+                definition,
+                &dir.join("tasks").to_string_lossy(),
+                "run-1",
+            )),
+            ..ClientOptions::default()
+        };
+        let client = TeamsClient::with_cli_path(&script).with_options(&options);
+        let reply = client.send("Build a feature", None).await.unwrap();
+        assert_eq!(reply.text.trim(), "Team finished");
+        assert_eq!(reply.teammates.len(), 2);
+        assert_eq!(reply.teammates[0].output.as_deref(), Some("Plan ready\n"));
+        assert_eq!(reply.teammates[1].status, "pending");
+        assert!(dir.join("tasks/run-1/reviewer.json").is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_cli_client_creation() {
         let client = CliClient::new();
         assert!(!client.cli_path.is_empty());
+        assert!(client.model.is_none());
+    }
+
+    #[test]
+    fn test_hybrid_with_model_sets_both_backends() {
+        let client = HybridClient::new("sk-test".to_string()).with_model("claude-haiku-4-5");
+        assert_eq!(client.api.model, "claude-haiku-4-5");
+        assert_eq!(client.cli.model.as_deref(), Some("claude-haiku-4-5"));
     }
 
     #[test]
@@ -385,15 +2732,82 @@ mod tests {
             ClientMode::from_str("agent-teams").unwrap(),
             ClientMode::AgentTeams
         );
+        assert_eq!(ClientMode::from_str("mock").unwrap(), ClientMode::Mock);
+        assert_eq!(ClientMode::from_str("exec").unwrap(), ClientMode::Exec);
+        assert_eq!(ClientMode::from_str("ssh").unwrap(), ClientMode::Ssh);
+        assert_eq!(ClientMode::from_str("docker").unwrap(), ClientMode::Docker);
         assert!(ClientMode::from_str("invalid").is_err());
     }
 
+    #[tokio::test]
+    async fn test_mock_client_replies() {
+        let echo = MockClient::new()
+            .send("\n  Check disk usage\nmore", None)
+            .await
+            .unwrap();
+        assert_eq!(echo.text, "[mock] Check disk usage");
+        assert_eq!(echo.model.as_deref(), Some(MOCK_MODEL));
+        let options = ClientOptions {
+            mock_response: Some("ok".to_string()),
+            ..ClientOptions::default()
+        };
+        let client = create_client_with_options(&ClientMode::Mock, None, &options).unwrap();
+        assert_eq!(client.send_message("anything", None).await.unwrap(), "ok");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exec_client_passes_prompt() {
+        let exec = |command: &[&str]| ClientOptions {
+            model: Some("llama3".to_string()),
+            exec: Some(ExecConfig {
+                command: command.iter().map(|a| a.to_string()).collect(),
+            }),
+            ..ClientOptions::default()
+        };
+
+        // Without a `{{ prompt }}` argument the prompt arrives on stdin
+        let options = exec(&["sh", "-c", "echo \"$0:\"; cat", "{{ model }}"]);
+        let client = create_client_with_options(&ClientMode::Exec, None, &options).unwrap();
+        let reply = client
+            .send("Check disks", Some("You are SRE"))
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "llama3:\n[CONTEXT: You are SRE]\n\nCheck disks");
+        assert_eq!(reply.model.as_deref(), Some("llama3"));
+
+        let options = exec(&["printf", "%s|%s", "{{ system_prompt }}", "{{prompt}}"]);
+        let client = create_client_with_options(&ClientMode::Exec, None, &options).unwrap();
+        let reply = client
+            .send("Check disks", Some("You are SRE"))
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "You are SRE|Check disks");
+        assert_eq!(reply.model, None);
+
+        let options = exec(&["sh", "-c", "echo out of tokens >&2; exit 2"]);
+        let client = create_client_with_options(&ClientMode::Exec, None, &options).unwrap();
+        let err = client
+            .send_message("hi", None)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("sh exited with exit status: 2"));
+        assert!(err.ends_with("out of tokens"));
+
+        let err = create_client_with_options(&ClientMode::Exec, None, &ClientOptions::default());
+        assert!(err.is_err());
+    }
+
     #[test]
     fn test_client_mode_display() {
         assert_eq!(ClientMode::Api.to_string(), "api");
         assert_eq!(ClientMode::ClaudeCode.to_string(), "claude-code");
         assert_eq!(ClientMode::Hybrid.to_string(), "hybrid");
         assert_eq!(ClientMode::AgentTeams.to_string(), "agent-teams");
+        assert_eq!(ClientMode::Exec.to_string(), "exec");
+        assert_eq!(ClientMode::Ssh.to_string(), "ssh");
+        assert_eq!(ClientMode::Docker.to_string(), "docker");
     }
 
     #[test]
@@ -404,14 +2818,15 @@ mod tests {
 
     #[test]
     fn test_create_client_api_with_key() {
-        let result = create_client(&ClientMode::Api, Some("sk-test".to_string()));
+        let result = create_client(&ClientMode::Api, Some(&KeyPool::single("sk-test")));
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_create_client_claude_code() {
+        // Fails up front exactly when there's no CLI to run
         let result = create_client(&ClientMode::ClaudeCode, None);
-        assert!(result.is_ok());
+        assert_eq!(result.is_ok(), locate_claude_cli().is_ok());
     }
 
     #[test]
@@ -422,36 +2837,91 @@ mod tests {
 
     #[test]
     fn test_create_client_hybrid_with_key() {
-        let result = create_client(&ClientMode::Hybrid, Some("sk-test".to_string()));
+        let result = create_client(&ClientMode::Hybrid, Some(&KeyPool::single("sk-test")));
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_create_agent_client_override() {
+        // API mode without a key would fail, so the override must win
         let result = create_agent_client(
-            Some("claude-code"),
+            Some("mock"),
             &ClientMode::Api,
-            Some("sk-test".to_string()),
+            None,
+            &ClientOptions::default(),
         );
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_create_agent_client_fallback() {
-        let result = create_agent_client(None, &ClientMode::ClaudeCode, None);
+        let result = create_agent_client(
+            None,
+            &ClientMode::Api,
+            Some(&KeyPool::single("sk-test")),
+            &ClientOptions::default(),
+        );
         assert!(result.is_ok());
+        let result = create_agent_client(None, &ClientMode::Api, None, &ClientOptions::default());
+        assert!(result.is_err());
     }
 
     #[test]
     fn test_create_agent_client_invalid_override() {
-        let result = create_agent_client(Some("bad"), &ClientMode::Api, Some("sk".to_string()));
+        let result = create_agent_client(
+            Some("bad"),
+            &ClientMode::Api,
+            Some(&KeyPool::single("sk")),
+            &ClientOptions::default(),
+        );
         assert!(result.is_err());
     }
 
     #[test]
     fn test_create_client_agent_teams() {
         let result = create_client(&ClientMode::AgentTeams, None);
-        assert!(result.is_ok());
+        assert_eq!(result.is_ok(), locate_claude_cli().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_cli_search_order() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("orchestra-find-cli-{}", std::process::id()));
+        let (bin, home) = (root.join("bin"), root.join("home"));
+        let install = |path: &Path| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        let path_var = Some(bin.clone().into_os_string());
+        let local = home.join(".claude/local/claude");
+        let locations = vec![local.clone(), home.join(".local/bin/claude")];
+
+        let err = find_cli(None, path_var.clone(), locations.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("claude CLI not found"));
+        assert!(err.contains(&bin.join("claude").display().to_string()));
+        assert!(err.contains(&home.join(".local/bin/claude").display().to_string()));
+
+        // Only when nothing is on PATH does an install location count
+        install(&local);
+        let found = find_cli(None, path_var.clone(), locations.clone()).unwrap();
+        assert_eq!(found, local);
+        install(&bin.join("claude"));
+        let found = find_cli(None, path_var.clone(), locations).unwrap();
+        assert_eq!(found, bin.join("claude"));
+
+        // CLAUDE_CLI_PATH wins, and a bare name is looked up on PATH
+        let found = find_cli(Some(local.clone().into()), path_var.clone(), Vec::new()).unwrap();
+        assert_eq!(found, local);
+        let found = find_cli(Some("claude".into()), path_var.clone(), Vec::new()).unwrap();
+        assert_eq!(found, bin.join("claude"));
+        let err = find_cli(Some(root.join("nope").into()), path_var, Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("CLAUDE_CLI_PATH="));
+        std::fs::remove_dir_all(&root).unwrap();
     }
 
     #[test]
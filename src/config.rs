@@ -1,7 +1,10 @@
-use anyhow::Result;
+use crate::client::{ClientOptions, ToolPolicy};
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -20,12 +23,434 @@ pub struct Config {
     pub features: FeaturesConfig,
     #[serde(default)]
     pub teams: TeamsConfig,
+    #[serde(default)]
+    pub actions: ActionsConfig,
+    #[serde(default)]
+    pub approvals: ApprovalsConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub distributed: DistributedConfig,
+    /// Watchdog settings used when `features.health_monitoring` is on.
+    #[serde(default)]
+    pub health: HealthConfig,
+    /// Credentials read from somewhere other than the environment, by the
+    /// name of the variable they would otherwise be in.
+    #[serde(default)]
+    pub secrets: BTreeMap<String, SecretSource>,
+    /// Consensus patterns, each run as a mode of the same name.
+    #[serde(default)]
+    pub consensus: BTreeMap<String, ConsensusConfig>,
+    /// Critic/refinement loops, each run as a mode of the same name.
+    #[serde(default)]
+    pub review: BTreeMap<String, ReviewConfig>,
+    /// Map-reduce patterns over input files, each run as a mode of the same name.
+    #[serde(default)]
+    pub map: BTreeMap<String, MapConfig>,
+    /// Prompts and backends compared by `agent-orchestra bench`.
+    #[serde(default)]
+    pub bench: BenchConfig,
+    /// The file this config was loaded from, recorded in run manifests.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+/// Run history store used by `history`, `costs` and `import`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_history_path")]
+    pub path: String,
+    /// Database used instead of `path` when `outputs.store` is `sqlite`.
+    #[serde(default = "default_history_sqlite_path")]
+    pub sqlite_path: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_history_path() -> String {
+    "outputs/history.jsonl".to_string()
+}
+
+fn default_history_sqlite_path() -> String {
+    "outputs/history.db".to_string()
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: default_history_path(),
+            sqlite_path: default_history_sqlite_path(),
+        }
+    }
+}
+
+/// Append-only JSONL log with one line per agent completion, for log shippers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_events_path")]
+    pub path: String,
+}
+
+fn default_events_path() -> String {
+    "outputs/events.jsonl".to_string()
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: default_events_path(),
+        }
+    }
+}
+
+/// Extraction of agent-proposed actions into a reviewed queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_actions_queue_file")]
+    pub queue_file: String,
+}
+
+fn default_actions_queue_file() -> String {
+    "outputs/actions.json".to_string()
+}
+
+/// Where pending approval requests wait for a decision when nobody is at
+/// the terminal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalsConfig {
+    #[serde(default = "default_approvals_dir")]
+    pub dir: String,
+    /// How often a waiting run checks for a decision.
+    #[serde(default = "default_approvals_poll")]
+    pub poll_seconds: u64,
+}
+
+fn default_approvals_dir() -> String {
+    "outputs/approvals".to_string()
+}
+
+fn default_approvals_poll() -> u64 {
+    2
+}
+
+impl Default for ApprovalsConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_approvals_dir(),
+            poll_seconds: default_approvals_poll(),
+        }
+    }
+}
+
+/// Shell commands run around every run and every agent. Each runs with
+/// `sh -c`; pre hooks that fail stop what they guard, post hooks only warn.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    /// Before any agent starts; a failure aborts the run.
+    #[serde(default)]
+    pub pre_run: Vec<String>,
+    /// After the results file is written, with the results JSON on stdin.
+    #[serde(default)]
+    pub post_run: Vec<String>,
+    /// Before each agent; a failure fails that agent.
+    #[serde(default)]
+    pub pre_agent: Vec<String>,
+    /// After each agent, with its result JSON on stdin.
+    #[serde(default)]
+    pub post_agent: Vec<String>,
+    /// How long each command may run; if absent, 60 seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+}
+
+/// A shared Redis queue. When enabled, runs push their agents onto it and
+/// collect the results; `worker` processes elsewhere take and run them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistributedConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_distributed_url")]
+    pub url: String,
+    /// Prefix of every key the queue uses, so orchestras can share a server.
+    #[serde(default = "default_distributed_queue")]
+    pub queue: String,
+    /// How long an agent may wait for a worker to take it, on top of its
+    /// own timeout.
+    #[serde(default = "default_claim_timeout")]
+    pub claim_timeout_seconds: u64,
+    /// How many agents one `worker` process runs at a time.
+    #[serde(default = "default_worker_concurrency")]
+    pub worker_concurrency: usize,
+    /// Environment variable holding the secret jobs and results are signed
+    /// with. The coordinator and its workers need the same one.
+    #[serde(default = "default_distributed_secret_env")]
+    pub secret_env: String,
+}
+
+fn default_distributed_url() -> String {
+    "redis://127.0.0.1:6379".to_string()
+}
+
+fn default_distributed_queue() -> String {
+    "orchestra".to_string()
+}
+
+fn default_claim_timeout() -> u64 {
+    300
+}
+
+fn default_worker_concurrency() -> usize {
+    2
+}
+
+fn default_distributed_secret_env() -> String {
+    "ORCHESTRA_QUEUE_SECRET".to_string()
+}
+
+impl Default for DistributedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: default_distributed_url(),
+            queue: default_distributed_queue(),
+            claim_timeout_seconds: default_claim_timeout(),
+            worker_concurrency: default_worker_concurrency(),
+            secret_env: default_distributed_secret_env(),
+        }
+    }
+}
+
+/// Where a credential is read from instead of an environment variable.
+/// Exactly one source is set.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct SecretSource {
+    /// A file's contents, such as a mounted Docker or Kubernetes secret.
+    /// Relative paths are resolved from the config file's directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// The first line a command prints, such as `[pass, show, anthropic]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    /// A field of a HashiCorp Vault KV secret.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultSecret>,
+    /// An AWS Secrets Manager secret, or a field of a JSON one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_secrets_manager: Option<AwsSecret>,
+}
+
+impl SecretSource {
+    /// How many sources are set; anything but one is a config error.
+    pub fn count(&self) -> usize {
+        [
+            self.file.is_some(),
+            self.command.is_some(),
+            self.vault.is_some(),
+            self.aws_secrets_manager.is_some(),
+        ]
+        .iter()
+        .filter(|set| **set)
+        .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultSecret {
+    /// API path after `/v1/`, such as `secret/data/orchestra` for KV v2.
+    pub path: String,
+    pub field: String,
+    /// Server address; `VAULT_ADDR` if unset. The token is `VAULT_TOKEN`
+    /// or `~/.vault-token`.
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AwsSecret {
+    pub secret_id: String,
+    /// Key to take from a secret stored as JSON; the whole string if unset.
+    #[serde(default)]
+    pub field: Option<String>,
+    /// `AWS_REGION` or `AWS_DEFAULT_REGION` if unset. Credentials come from
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// The watchdog that watches CLI and exec agents' processes while
+/// `features.health_monitoring` is on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthConfig {
+    /// A process that writes nothing for this long is taken to be hung and
+    /// killed, before the agent's own timeout.
+    #[serde(default = "default_stall_seconds")]
+    pub stall_seconds: u64,
+    /// How often each process's heartbeat is logged.
+    #[serde(default = "default_heartbeat_seconds")]
+    pub heartbeat_seconds: u64,
+}
+
+fn default_stall_seconds() -> u64 {
+    300
+}
+
+fn default_heartbeat_seconds() -> u64 {
+    15
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            stall_seconds: default_stall_seconds(),
+            heartbeat_seconds: default_heartbeat_seconds(),
+        }
+    }
+}
+
+/// Hooks for a single agent, run after the global `pre_agent`/`post_agent`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentHooks {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post: Vec<String>,
+}
+
+impl Default for ActionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            queue_file: default_actions_queue_file(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     #[serde(default = "default_client_mode")]
     pub default_mode: String,
+    /// Model (or fallback chain) used by agents that don't set their own `model`.
+    /// If absent, each client falls back to its built-in default.
+    #[serde(default)]
+    pub default_model: Option<ModelSpec>,
+    /// Provider status-page polling before each run.
+    #[serde(default)]
+    pub status_check: StatusCheckConfig,
+    /// When hybrid agents stop trying the API after it keeps failing.
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// More API keys to spread API and hybrid agents across.
+    #[serde(default)]
+    pub api_keys: ApiKeysConfig,
+    /// Client mode → most of a parallel run's agents using it at once, e.g.
+    /// `api: 2` and `claude-code: 4`. Modes not listed have no limit.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub concurrency: BTreeMap<String, usize>,
+}
+
+/// API keys beyond `ANTHROPIC_API_KEY` and `ANTHROPIC_API_KEYS`, and how
+/// each new client picks one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiKeysConfig {
+    /// Variables holding one key each; the keys never go in the config.
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub strategy: KeyStrategy,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStrategy {
+    /// Each client takes the next key, passing over recently throttled ones.
+    #[default]
+    RoundRobin,
+    /// Each client takes the key that was throttled longest ago, or never.
+    LeastRecentlyThrottled,
+}
+
+/// Statuspage endpoints polled before a run. When a provider reports a major
+/// outage, hybrid agents go straight to their CLI fallback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusCheckConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_status_timeout")]
+    pub timeout_seconds: u64,
+    /// Provider name → Statuspage `status.json` URL.
+    #[serde(default = "default_status_providers")]
+    pub providers: BTreeMap<String, String>,
+}
+
+fn default_status_timeout() -> u64 {
+    5
+}
+
+fn default_status_providers() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            "anthropic".to_string(),
+            "https://status.anthropic.com/api/v2/status.json".to_string(),
+        ),
+        (
+            "openai".to_string(),
+            "https://status.openai.com/api/v2/status.json".to_string(),
+        ),
+    ])
+}
+
+impl Default for StatusCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_seconds: default_status_timeout(),
+            providers: default_status_providers(),
+        }
+    }
+}
+
+/// After `failure_threshold` API failures in a row, hybrid agents go straight
+/// to the CLI for `cooldown_seconds`; then one request tries the API again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_cooldown_seconds")]
+    pub cooldown_seconds: u64,
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_cooldown_seconds() -> u64 {
+    120
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: default_failure_threshold(),
+            cooldown_seconds: default_cooldown_seconds(),
+        }
+    }
 }
 
 fn default_client_mode() -> String {
@@ -36,6 +461,11 @@ impl Default for ClientConfig {
     fn default() -> Self {
         Self {
             default_mode: default_client_mode(),
+            default_model: None,
+            status_check: StatusCheckConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            api_keys: ApiKeysConfig::default(),
+            concurrency: BTreeMap::new(),
         }
     }
 }
@@ -47,6 +477,65 @@ pub struct OrchestraConfig {
     pub default_mode: String,
     #[serde(default)]
     pub schedule: Option<ScheduleConfig>,
+    /// How many agents may fail (or be cancelled) before `run` exits
+    /// non-zero.
+    #[serde(default)]
+    pub max_failed_agents: usize,
+    /// Keeps two runs from sharing the output directory at once.
+    #[serde(default)]
+    pub lock: RunLockConfig,
+    /// How `run --watch` paces the runs file changes trigger.
+    #[serde(default)]
+    pub watch: WatchConfig,
+}
+
+/// The lock a run or rerun holds on its output directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunLockConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// How long a run waits for the one holding the lock to finish; `0`
+    /// refuses to start straight away.
+    #[serde(default)]
+    pub wait_seconds: u64,
+}
+
+impl Default for RunLockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            wait_seconds: 0,
+        }
+    }
+}
+
+/// Pacing for `run --watch`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchConfig {
+    /// Changes are gathered until none has come for this long, so a file
+    /// being written or a batch of files landing starts one run.
+    #[serde(default = "default_watch_debounce")]
+    pub debounce_seconds: u64,
+    /// Least time between the starts of two runs.
+    #[serde(default = "default_watch_min_interval")]
+    pub min_interval_seconds: u64,
+}
+
+fn default_watch_debounce() -> u64 {
+    5
+}
+
+fn default_watch_min_interval() -> u64 {
+    60
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            debounce_seconds: default_watch_debounce(),
+            min_interval_seconds: default_watch_min_interval(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,8 +551,21 @@ pub struct AgentsConfig {
     pub analyzer: AgentConfig,
     pub researcher: AgentConfig,
     pub reporter: AgentConfig,
+    /// Any other agents, keyed by name. A custom agent named after a mode's
+    /// task slot (e.g. `synthesizer`) takes that slot over from its built-in.
+    #[serde(flatten)]
+    pub custom: BTreeMap<String, AgentConfig>,
 }
 
+/// Task slots used by the built-in modes that map onto a built-in agent unless
+/// an agent claims them by name or `aliases`.
+const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("health_checker", "monitor"),
+    ("data_analyst", "analyzer"),
+    ("synthesizer", "analyzer"),
+    ("alert_manager", "reporter"),
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     pub enabled: bool,
@@ -75,6 +577,644 @@ pub struct AgentConfig {
     /// System prompt that gives this agent its identity/role.
     #[serde(default)]
     pub system_prompt: Option<String>,
+    /// File to read `system_prompt` from, relative to the config file.
+    #[serde(default)]
+    pub system_prompt_file: Option<String>,
+    /// Replaces the mode's built-in task prompt for this agent.
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// File to read `prompt` from, relative to the config file.
+    #[serde(default)]
+    pub prompt_file: Option<String>,
+    /// Per-agent model override. If absent, inherits `client.default_model`.
+    #[serde(default)]
+    pub model: Option<ModelSpec>,
+    /// Maximum tokens to generate. If absent, the client default (4096) is used.
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Sampling temperature (0.0–1.0). If absent, the API default is used.
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Sequences that stop generation when emitted.
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// Log files whose (sampled) contents are prepended to the prompt.
+    #[serde(default)]
+    pub logs: Vec<LogSource>,
+    /// Files, command output and URLs captured at run time and prepended to the prompt.
+    #[serde(default)]
+    pub context: Option<ContextConfig>,
+    /// Network egress allow-list for tool-using agents.
+    #[serde(default)]
+    pub egress: Option<EgressPolicy>,
+    /// Directory CLI-based clients run `claude` in, e.g. a repository
+    /// checkout. Relative to the config file; defaults to the current one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    /// Extra environment variables for CLI-based clients' `claude` process.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Claude Code permission rules the agent may use without asking, e.g.
+    /// `Read` or `Bash(git log:*)`. CLI-based clients only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_tools: Vec<String>,
+    /// Permission rules the agent may never use, e.g. `Edit` or `Bash(rm:*)`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disallowed_tools: Vec<String>,
+    /// Claude Code permission mode: `default`, `acceptEdits`, `plan` or
+    /// `bypassPermissions`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<String>,
+    /// MCP servers whose tools the agent may use, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mcp_servers: BTreeMap<String, McpServer>,
+    /// Whether a CLI agent starts a new conversation each run or carries on
+    /// from an earlier one.
+    #[serde(default)]
+    pub session: SessionMode,
+    /// Reply used when the agent runs with `client_mode: mock`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mock_response: Option<String>,
+    /// Command run for the agent with `client_mode: exec`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec: Option<ExecConfig>,
+    /// Host the agent runs `claude` on with `client_mode: ssh`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh: Option<SshConfig>,
+    /// Container the agent runs `claude` in with `client_mode: docker`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docker: Option<DockerConfig>,
+    /// Hold the agent's output for a person to approve before any agent
+    /// after it in the run starts.
+    #[serde(default)]
+    pub requires_approval: bool,
+    /// Agents with a higher priority start first when they can't all run
+    /// at once. Defaults to 0; negative values go after the rest.
+    #[serde(default)]
+    pub priority: i32,
+    /// Commands run just before and after this agent.
+    #[serde(default)]
+    pub hooks: AgentHooks,
+    /// JSON Schema the agent's reply must match; the parsed value is kept
+    /// as the result's `structured_output`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+    /// Cap on the prompt's estimated size, enforced just before sending.
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    /// Agent whose settings this one starts from; keys set here override it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherits: Option<String>,
+    /// Other names (such as mode task slots) that resolve to this agent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Smoke tests run by `agents test`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<AgentTest>,
+}
+
+/// A small prompt sent with the agent's system prompt and backend, and the
+/// assertions its reply must satisfy.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AgentTest {
+    pub name: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub expect: Vec<Expectation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Expectation {
+    /// Substring that must appear in the reply.
+    Contains(String),
+    /// Regex the reply must match somewhere.
+    Matches(String),
+    /// Regex the reply must not match.
+    NotMatches(String),
+    /// Predicate on the first JSON value in the reply.
+    Json(JsonPredicate),
+}
+
+/// Checks the value at a JSON pointer (`/status`, `/items/0/id`). With no
+/// `equals`, the value only has to exist.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonPredicate {
+    #[serde(default)]
+    pub pointer: String,
+    #[serde(default)]
+    pub equals: Option<serde_json::Value>,
+}
+
+/// Hosts a tool-using agent may reach. When set, everything else is denied:
+/// the CLI only gets WebFetch permission for the listed domains, and execution
+/// targets receive the proxy settings.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct EgressPolicy {
+    /// Domains (and their subdomains); `*.example.com` matches subdomains only.
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    /// IP ranges in CIDR notation, for IP-literal destinations. Only
+    /// context URLs are checked against these; `claude`'s tools can't be, so
+    /// CLI-based modes refuse to start an agent that sets them.
+    #[serde(default)]
+    pub allowed_cidrs: Vec<String>,
+    /// Egress proxy URL exported as HTTP(S)_PROXY to agent processes.
+    #[serde(default)]
+    pub proxy: Option<String>,
+}
+
+/// An MCP server, either started from `command` or reached at `url`.
+/// CLI-based agents can use both kinds; API agents only reach `url` servers.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct McpServer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Extra environment for the server process.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Environment variable holding the bearer token for `url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+}
+
+/// The command an agent with `client_mode: exec` runs; its stdout is the
+/// agent's output. Arguments may use `{{ prompt }}`, `{{ system_prompt }}`
+/// and `{{ model }}`. Without `{{ prompt }}`, the prompt goes to stdin.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ExecConfig {
+    /// Program and arguments, run without a shell.
+    pub command: Vec<String>,
+}
+
+/// The host an agent with `client_mode: ssh` runs `claude -p` on. Its
+/// `working_dir` and `env` apply on that host.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SshConfig {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Private key file, passed to `ssh -i`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Extra `ssh -o` options, e.g. `StrictHostKeyChecking=accept-new`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    /// `claude` on the remote host.
+    #[serde(default = "default_claude_path")]
+    pub claude_path: String,
+}
+
+fn default_claude_path() -> String {
+    "claude".to_string()
+}
+
+/// The image an agent with `client_mode: docker` runs `claude -p` in, one
+/// container per call. Its `working_dir` is mounted at `/workspace`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DockerConfig {
+    pub image: String,
+    /// Memory limit, e.g. `2g` (`docker run --memory`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// CPU limit, e.g. `1.5` (`docker run --cpus`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<f64>,
+    /// Network to attach; `none` cuts the agent off entirely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// Mount the workspace read-only.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Extra `docker run` arguments, e.g. `--pids-limit=256`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// `claude` inside the image.
+    #[serde(default = "default_claude_path")]
+    pub claude_path: String,
+}
+
+/// Material captured when the agent runs and prepended to its prompt.
+/// Each source is truncated to `max_bytes_per_source`, and sources stop being
+/// added once `max_total_bytes` is used up.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContextConfig {
+    /// File paths; `*` and `?` wildcards are expanded. Files keep their tail.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Shell commands run with `sh -c`; stdout and stderr are captured.
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// URLs fetched with GET. Subject to the agent's `egress` policy, if any.
+    #[serde(default)]
+    pub urls: Vec<String>,
+    #[serde(default = "default_context_source_bytes")]
+    pub max_bytes_per_source: usize,
+    #[serde(default = "default_context_total_bytes")]
+    pub max_total_bytes: usize,
+    /// Per-command and per-URL timeout.
+    #[serde(default = "default_context_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_context_source_bytes() -> usize {
+    8 * 1024
+}
+
+fn default_context_total_bytes() -> usize {
+    32 * 1024
+}
+
+fn default_context_timeout() -> u64 {
+    30
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            commands: Vec::new(),
+            urls: Vec::new(),
+            max_bytes_per_source: default_context_source_bytes(),
+            max_total_bytes: default_context_total_bytes(),
+            timeout_seconds: default_context_timeout(),
+        }
+    }
+}
+
+/// A log file injected into an agent's prompt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogSource {
+    pub path: String,
+    #[serde(default)]
+    pub sampling: LogSampling,
+}
+
+/// How to cut a high-volume log down to fit the prompt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogSampling {
+    #[serde(default)]
+    pub strategy: SamplingStrategy,
+    /// Tail: total lines kept. Stratified: lines kept per severity.
+    #[serde(default = "default_sample_lines")]
+    pub lines: usize,
+}
+
+fn default_sample_lines() -> usize {
+    100
+}
+
+impl Default for LogSampling {
+    fn default() -> Self {
+        Self {
+            strategy: SamplingStrategy::default(),
+            lines: default_sample_lines(),
+        }
+    }
+}
+
+impl std::fmt::Display for LogSampling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.strategy {
+            SamplingStrategy::Tail => write!(f, "tail {}", self.lines),
+            SamplingStrategy::Stratified => write!(f, "stratified {}/severity", self.lines),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingStrategy {
+    /// The last N lines.
+    #[default]
+    Tail,
+    /// N lines per severity (error/warn/info/debug/other), spread evenly
+    /// across the file, so rare errors aren't crowded out by noisy info lines.
+    Stratified,
+}
+
+/// How much an agent may send, estimated at four characters per token, and
+/// what happens to a prompt over it. The default leaves Claude's 200k-token
+/// context room for the reply and for the estimate being rough.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BudgetConfig {
+    /// Prompt plus system prompt.
+    #[serde(default = "default_max_input_tokens")]
+    pub max_input_tokens: u64,
+    #[serde(default)]
+    pub strategy: BudgetStrategy,
+    /// Agent that condenses the cut middle, for `summarize`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summarizer: Option<String>,
+}
+
+fn default_max_input_tokens() -> u64 {
+    150_000
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            max_input_tokens: default_max_input_tokens(),
+            strategy: BudgetStrategy::default(),
+            summarizer: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetStrategy {
+    /// Keep the start and the end of the prompt, cutting the middle.
+    #[default]
+    HeadTail,
+    /// Replace the middle with the `summarizer` agent's summary of it,
+    /// falling back to `head_tail` if that fails.
+    Summarize,
+}
+
+impl AgentsConfig {
+    pub fn all_mut(&mut self) -> impl Iterator<Item = (&str, &mut AgentConfig)> {
+        [
+            ("monitor", &mut self.monitor),
+            ("analyzer", &mut self.analyzer),
+            ("researcher", &mut self.researcher),
+            ("reporter", &mut self.reporter),
+        ]
+        .into_iter()
+        .chain(self.custom.iter_mut().map(|(n, a)| (n.as_str(), a)))
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = (&str, &AgentConfig)> {
+        [
+            ("monitor", &self.monitor),
+            ("analyzer", &self.analyzer),
+            ("researcher", &self.researcher),
+            ("reporter", &self.reporter),
+        ]
+        .into_iter()
+        .chain(self.custom.iter().map(|(n, a)| (n.as_str(), a)))
+    }
+
+    fn get(&self, name: &str) -> Option<&AgentConfig> {
+        match name {
+            "monitor" => Some(&self.monitor),
+            "analyzer" => Some(&self.analyzer),
+            "researcher" => Some(&self.researcher),
+            "reporter" => Some(&self.reporter),
+            _ => self.custom.get(name),
+        }
+    }
+
+    /// Find the agent for a task name: an agent of that name, then one
+    /// declaring it in `aliases`, then the built-in mode slot mapping.
+    pub fn lookup(&self, name: &str) -> Option<&AgentConfig> {
+        if let Some(agent) = self.get(name) {
+            return Some(agent);
+        }
+        let declared = self
+            .all()
+            .map(|(_, a)| a)
+            .find(|a| a.aliases.iter().any(|alias| alias == name));
+        declared.or_else(|| {
+            BUILTIN_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .and_then(|(_, target)| self.get(target))
+        })
+    }
+
+    /// Reject aliases that shadow an agent name or are claimed twice.
+    pub fn check_aliases(&self) -> Result<()> {
+        let mut seen: BTreeMap<&str, &str> = BTreeMap::new();
+        for (name, agent) in self.all() {
+            for alias in &agent.aliases {
+                if self.get(alias).is_some() {
+                    anyhow::bail!("agent {}: alias '{}' is already an agent name", name, alias);
+                }
+                if let Some(other) = seen.insert(alias, name) {
+                    anyhow::bail!(
+                        "alias '{}' is claimed by both {} and {}",
+                        alias,
+                        other,
+                        name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keys that come in inline/file pairs: setting either one in a child drops
+/// both from the parent so the two never end up set together.
+const PAIRED_KEYS: &[(&str, &str)] = &[
+    ("prompt", "prompt_file"),
+    ("system_prompt", "system_prompt_file"),
+];
+
+/// Apply `inherits:` in the raw `agents` mapping before it is deserialized, so
+/// children only need the keys they override. Merging is shallow: a child's
+/// `context:` or `logs:` replaces the parent's entirely. Aliases are not inherited.
+pub fn resolve_inheritance(raw: &mut serde_yml::Value) -> Result<()> {
+    use serde_yml::Value;
+
+    let Some(agents) = raw.get_mut("agents").and_then(Value::as_mapping_mut) else {
+        return Ok(());
+    };
+    let original = agents.clone();
+
+    fn resolve(
+        name: &str,
+        original: &serde_yml::Mapping,
+        stack: &mut Vec<String>,
+    ) -> Result<serde_yml::Mapping> {
+        let agent = original
+            .get(name)
+            .and_then(Value::as_mapping)
+            .with_context(|| format!("unknown agent '{}'", name))?;
+        let Some(parent) = agent.get("inherits").and_then(Value::as_str) else {
+            return Ok(agent.clone());
+        };
+        if stack.iter().any(|s| s == parent) || parent == name {
+            stack.push(parent.to_string());
+            anyhow::bail!("inheritance cycle: {}", stack.join(" -> "));
+        }
+        stack.push(parent.to_string());
+        let mut merged = resolve(parent, original, stack)
+            .with_context(|| format!("agent {} inherits {}", name, parent))?;
+        stack.pop();
+
+        merged.remove("aliases");
+        for (inline, file) in PAIRED_KEYS {
+            if agent.contains_key(*inline) || agent.contains_key(*file) {
+                merged.remove(*inline);
+                merged.remove(*file);
+            }
+        }
+        for (key, value) in agent {
+            merged.insert(key.clone(), value.clone());
+        }
+        Ok(merged)
+    }
+
+    for (key, value) in agents.iter_mut() {
+        let Some(name) = key.as_str() else { continue };
+        if value.get("inherits").is_some() {
+            let mut stack = vec![name.to_string()];
+            *value = Value::Mapping(resolve(name, &original, &mut stack)?);
+        }
+    }
+    Ok(())
+}
+
+/// Read `file` (relative to `base`) into `inline`, refusing to have both set.
+fn resolve_prompt_file(
+    agent: &str,
+    field: &str,
+    inline: &mut Option<String>,
+    file: Option<&str>,
+    base: &Path,
+) -> Result<()> {
+    let Some(file) = file else {
+        return Ok(());
+    };
+    if inline.is_some() {
+        anyhow::bail!(
+            "agents.{}: set either {} or {}_file, not both",
+            agent,
+            field,
+            field
+        );
+    }
+    let path = base.join(file);
+    let content = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "agents.{}: failed to read {}_file {}",
+            agent,
+            field,
+            path.display()
+        )
+    })?;
+    *inline = Some(content);
+    Ok(())
+}
+
+impl AgentConfig {
+    /// Request options to hand to the client for this agent, falling back to
+    /// `default_model` when the agent doesn't pick one.
+    pub fn client_options(&self, default_model: Option<&ModelSpec>) -> ClientOptions {
+        let mut options = self
+            .model
+            .as_ref()
+            .or(default_model)
+            .map(ModelSpec::client_options)
+            .unwrap_or_default();
+        options.max_tokens = self.max_tokens;
+        options.temperature = self.temperature;
+        options.stop_sequences = self.stop_sequences.clone();
+        options.egress = self.egress.clone();
+        options.working_dir = self.working_dir.as_ref().map(PathBuf::from);
+        options.env = self.env.clone();
+        options.tools = ToolPolicy {
+            allowed_tools: self.allowed_tools.clone(),
+            disallowed_tools: self.disallowed_tools.clone(),
+            permission_mode: self.permission_mode.clone(),
+            mcp_servers: self.mcp_servers.clone(),
+        };
+        options.mock_response = self.mock_response.clone();
+        options.exec = self.exec.clone();
+        options.ssh = self.ssh.clone();
+        options.docker = self.docker.clone();
+        options
+    }
+}
+
+/// Which conversation a CLI agent works in, so it can keep its context
+/// between daemon cycles.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionMode {
+    /// A new conversation every run.
+    #[default]
+    Fresh,
+    /// `--resume` the session this agent's last recorded run reported.
+    Resume,
+    /// `--continue` the most recent conversation in the agent's `working_dir`.
+    Continue,
+}
+
+/// A single model or an ordered fallback chain:
+/// `model: claude-opus-4-1` or `model: [claude-opus-4-1, claude-sonnet-4-5]`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ModelSpec {
+    Single(String),
+    Chain(Vec<String>),
+}
+
+impl ModelSpec {
+    /// Split into the primary model and its fallbacks.
+    pub fn client_options(&self) -> ClientOptions {
+        let (model, fallback_models) = match self {
+            ModelSpec::Single(m) => (Some(m.clone()), Vec::new()),
+            ModelSpec::Chain(chain) => (
+                chain.first().cloned(),
+                chain.iter().skip(1).cloned().collect(),
+            ),
+        };
+        ClientOptions {
+            model,
+            fallback_models,
+            ..ClientOptions::default()
+        }
+    }
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_seconds: 120,
+            client_mode: None,
+            system_prompt: None,
+            system_prompt_file: None,
+            prompt: None,
+            prompt_file: None,
+            model: None,
+            max_tokens: None,
+            temperature: None,
+            stop_sequences: Vec::new(),
+            logs: Vec::new(),
+            context: None,
+            inherits: None,
+            aliases: Vec::new(),
+            tests: Vec::new(),
+            egress: None,
+            working_dir: None,
+            env: BTreeMap::new(),
+            allowed_tools: Vec::new(),
+            disallowed_tools: Vec::new(),
+            permission_mode: None,
+            mcp_servers: BTreeMap::new(),
+            session: SessionMode::Fresh,
+            mock_response: None,
+            exec: None,
+            ssh: None,
+            docker: None,
+            requires_approval: false,
+            priority: 0,
+            hooks: AgentHooks::default(),
+            output_schema: None,
+            budget: BudgetConfig::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,6 +1222,109 @@ pub struct OutputsConfig {
     pub directory: String,
     pub retention_days: u32,
     pub formats: Vec<String>,
+    /// Backend for the run history: `jsonl` (default) or `sqlite`.
+    #[serde(default)]
+    pub store: StoreKind,
+    /// Bucket every run's outputs are also uploaded to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteConfig>,
+    /// Write results and summary files encrypted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionConfig>,
+    /// Git checkout every run's outputs are also committed to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitOutputsConfig>,
+}
+
+/// A git checkout each run's outputs are committed to, for a versioned
+/// history of reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitOutputsConfig {
+    /// Working tree to commit in, relative to the config file.
+    pub repo: String,
+    /// Branch to commit on, created if missing; if absent, the branch
+    /// checked out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Directory inside the repository the files go in (templated).
+    #[serde(default = "default_git_path")]
+    pub path: String,
+    /// Commit message (templated); `succeeded`, `failed` and `total` count
+    /// the run's agents.
+    #[serde(default = "default_git_message")]
+    pub message: String,
+    /// Push the commit after making it.
+    #[serde(default)]
+    pub push: bool,
+    #[serde(default = "default_git_remote")]
+    pub remote: String,
+}
+
+fn default_git_path() -> String {
+    "{{ mode }}".to_string()
+}
+
+fn default_git_message() -> String {
+    "{{ mode }} run {{ run_id }}: {{ succeeded }}/{{ total }} agents succeeded".to_string()
+}
+
+fn default_git_remote() -> String {
+    "origin".to_string()
+}
+
+/// AES-256-GCM encryption of output files at rest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// Variable (or `secrets:` name) holding the key as 64 hex digits.
+    #[serde(default = "default_encryption_key_env")]
+    pub key_env: String,
+}
+
+fn default_encryption_key_env() -> String {
+    "OUTPUTS_ENCRYPTION_KEY".to_string()
+}
+
+/// An S3-compatible bucket, such as a DigitalOcean Space. Each run is
+/// uploaded under `<prefix>/<mode>/<run_id>/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    pub bucket: String,
+    /// e.g. `https://nyc3.digitaloceanspaces.com`; if absent, the Spaces
+    /// endpoint for `region`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Region requests are signed for; if absent, `digitalocean.region`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default = "default_remote_prefix")]
+    pub prefix: String,
+    #[serde(default = "default_access_key_env")]
+    pub access_key_env: String,
+    #[serde(default = "default_secret_key_env")]
+    pub secret_key_env: String,
+    /// How long the signed links in notifications work; no links if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_url_seconds: Option<u64>,
+}
+
+fn default_remote_prefix() -> String {
+    "agent-orchestra".to_string()
+}
+
+fn default_access_key_env() -> String {
+    "SPACES_ACCESS_KEY_ID".to_string()
+}
+
+fn default_secret_key_env() -> String {
+    "SPACES_SECRET_ACCESS_KEY".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    #[default]
+    Jsonl,
+    Sqlite,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -90,12 +1333,99 @@ pub struct DigitalOceanConfig {
     pub region: String,
     #[serde(default)]
     pub registry: String,
+    /// Droplets started for large runs when `features.auto_scaling` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workers: Option<WorkersConfig>,
+}
+
+/// Ephemeral droplets a large run's CLI agents are spread over. Each worker
+/// runs `claude` from `image` in `digitalocean.registry` and is deleted when
+/// the agents finish.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WorkersConfig {
+    /// Image in the registry with `claude` installed, e.g. `claude-agent:1`.
+    pub image: String,
+    /// Droplet size slug.
+    #[serde(default = "default_worker_size")]
+    pub size: String,
+    /// Droplet image the workers boot; it must have Docker.
+    #[serde(default = "default_worker_droplet_image")]
+    pub droplet_image: String,
+    /// Runs with fewer eligible agents than this stay local.
+    #[serde(default = "default_worker_min_agents")]
+    pub min_agents: usize,
+    #[serde(default = "default_agents_per_worker")]
+    pub agents_per_worker: usize,
+    #[serde(default = "default_max_workers")]
+    pub max_workers: usize,
+    /// Fewer workers are started if they would cost more than this per hour.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_hourly_cost_usd: Option<f64>,
+    /// IDs or fingerprints of DigitalOcean SSH keys added to the workers.
+    #[serde(default)]
+    pub ssh_keys: Vec<String>,
+    /// Private key the orchestrator logs in with, passed to `ssh -i`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<String>,
+    /// Variable holding the DigitalOcean API token.
+    #[serde(default = "default_digitalocean_token_env")]
+    pub token_env: String,
+    /// How long a worker may take to boot and pull the image.
+    #[serde(default = "default_worker_boot_timeout")]
+    pub boot_timeout_seconds: u64,
+}
+
+fn default_worker_size() -> String {
+    "s-2vcpu-4gb".to_string()
+}
+
+fn default_worker_droplet_image() -> String {
+    "docker-20-04".to_string()
+}
+
+fn default_worker_min_agents() -> usize {
+    8
+}
+
+fn default_agents_per_worker() -> usize {
+    4
+}
+
+fn default_max_workers() -> usize {
+    4
+}
+
+fn default_digitalocean_token_env() -> String {
+    "DIGITALOCEAN_TOKEN".to_string()
+}
+
+fn default_worker_boot_timeout() -> u64 {
+    300
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct NotificationsConfig {
     #[serde(default)]
     pub enabled: bool,
+    /// Completion webhook, POSTed once per run when notifications are enabled.
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Env var holding the shared secret used to HMAC-sign payloads.
+    #[serde(default = "default_webhook_secret_env")]
+    pub secret_env: String,
+    /// Send payloads unsigned when `secret_env` is unset. Without this, a
+    /// missing secret fails delivery.
+    #[serde(default)]
+    pub unsigned: bool,
+}
+
+fn default_webhook_secret_env() -> String {
+    "ORCHESTRA_WEBHOOK_SECRET".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +1434,10 @@ pub struct LoggingConfig {
     pub level: String,
     #[serde(default = "default_log_format")]
     pub format: String,
+    /// Directory for per-agent log files (`<dir>/<run-id>/<agent>.log`);
+    /// `null` turns them off.
+    #[serde(default = "default_agent_logs")]
+    pub agent_logs: Option<String>,
 }
 
 fn default_log_level() -> String {
@@ -112,16 +1446,57 @@ fn default_log_level() -> String {
 fn default_log_format() -> String {
     "json".to_string()
 }
+fn default_agent_logs() -> Option<String> {
+    Some("outputs/logs".to_string())
+}
 
 impl Default for LoggingConfig {
     fn default() -> Self {
         Self {
             level: default_log_level(),
             format: default_log_format(),
+            agent_logs: default_agent_logs(),
         }
     }
 }
 
+impl LoggingConfig {
+    /// `level` as a tracing level, in any case; `warning` is accepted too.
+    pub fn tracing_level(&self) -> Result<tracing::Level> {
+        match self.level.to_lowercase().as_str() {
+            "warning" => Ok(tracing::Level::WARN),
+            level => level.parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "Unknown log level '{}'. Use: trace, debug, info, warn or error",
+                    self.level
+                )
+            }),
+        }
+    }
+
+    /// Whether `format` asks for JSON lines; anything else must be `text`.
+    pub fn is_json(&self) -> Result<bool> {
+        match self.format.to_lowercase().as_str() {
+            "json" => Ok(true),
+            "text" => Ok(false),
+            _ => anyhow::bail!("Unknown log format '{}'. Use: json or text", self.format),
+        }
+    }
+
+    /// Read only the `logging` section of a config file, so logging can be
+    /// set up before the rest of the file is loaded and validated. Falls back
+    /// to the defaults when the file or section is missing or unreadable.
+    pub fn from_file(path: impl AsRef<Path>) -> Self {
+        let section = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_yml::from_str::<serde_yml::Value>(&content).ok())
+            .and_then(|raw| raw.get("logging").cloned());
+        section
+            .and_then(|logging| serde_yml::from_value(logging).ok())
+            .unwrap_or_default()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FeaturesConfig {
     #[serde(default)]
@@ -130,6 +1505,174 @@ pub struct FeaturesConfig {
     pub auto_scaling: bool,
     #[serde(default)]
     pub health_monitoring: bool,
+    /// Cancel the remaining agents as soon as one fails.
+    #[serde(default)]
+    pub fail_fast: bool,
+}
+
+/// The same prompt sent to several agents in parallel, whose answers an
+/// aggregator agent then combines.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConsensusConfig {
+    pub prompt: String,
+    /// Agents that answer independently, each with its own client and model.
+    pub voters: Vec<String>,
+    /// Agent that sees every answer and writes the final one.
+    pub aggregator: String,
+    #[serde(default)]
+    pub strategy: ConsensusStrategy,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsensusStrategy {
+    /// The aggregator merges the answers into one.
+    #[default]
+    Synthesize,
+    /// Voters end with a `VOTE:` line, the votes are counted and the
+    /// aggregator reports the outcome.
+    Vote,
+}
+
+/// An author agent drafts, a critic reviews the draft against `criteria`,
+/// and the author revises until the critic approves or `max_rounds` drafts
+/// have been reviewed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReviewConfig {
+    pub prompt: String,
+    pub author: String,
+    pub critic: String,
+    #[serde(default)]
+    pub criteria: Vec<String>,
+    #[serde(default = "default_max_rounds")]
+    pub max_rounds: u32,
+}
+
+fn default_max_rounds() -> u32 {
+    3
+}
+
+/// The mapper agent runs `prompt` once per file matching `inputs`, with the
+/// file's content in front of it, then the reducer combines the answers.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MapConfig {
+    /// File pattern; `*` and `?` wildcards are expanded at run time.
+    pub inputs: String,
+    /// Per-file prompt; `{{ file }}` is the file's path.
+    pub prompt: String,
+    pub mapper: String,
+    pub reducer: String,
+    /// The reducer's task; the partial answers are appended to it.
+    pub reduce_prompt: String,
+    /// Mapper calls in flight at once.
+    #[serde(default = "default_map_concurrency")]
+    pub concurrency: usize,
+    /// Bytes of each file the mapper sees; longer files keep their tail.
+    #[serde(default = "default_map_file_bytes")]
+    pub max_bytes_per_file: usize,
+    /// Send the mapper's prompts as one Message Batch: half the price, but
+    /// results can take up to 24 hours. Only for an API-backed mapper.
+    #[serde(default)]
+    pub batch: bool,
+}
+
+fn default_map_concurrency() -> usize {
+    4
+}
+
+fn default_map_file_bytes() -> usize {
+    64 * 1024
+}
+
+/// `bench` sends every prompt to every target `runs` times and compares
+/// latency, failures, tokens and cost per target.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BenchConfig {
+    #[serde(default)]
+    pub prompts: Vec<BenchPrompt>,
+    #[serde(default = "default_bench_targets")]
+    pub targets: Vec<BenchTarget>,
+    /// Times each prompt is sent to each target; `--runs` overrides it.
+    #[serde(default = "default_bench_runs")]
+    pub runs: u32,
+    /// Per call.
+    #[serde(default = "default_bench_timeout")]
+    pub timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BenchPrompt {
+    pub name: String,
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+/// A client mode, and optionally the model it is asked for.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BenchTarget {
+    pub mode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl BenchTarget {
+    /// `mode` or `mode:model`, as `--target` takes it and reports show it.
+    pub fn label(&self) -> String {
+        match &self.model {
+            Some(model) => format!("{}:{}", self.mode, model),
+            None => self.mode.clone(),
+        }
+    }
+}
+
+impl FromStr for BenchTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (mode, model) = match s.split_once(':') {
+            Some((mode, model)) => (mode, Some(model.to_string())),
+            None => (s, None),
+        };
+        if mode.is_empty() || model.as_deref() == Some("") {
+            anyhow::bail!("'{}' is not a target; expected mode or mode:model", s);
+        }
+        Ok(Self {
+            mode: mode.to_string(),
+            model,
+        })
+    }
+}
+
+fn default_bench_targets() -> Vec<BenchTarget> {
+    ["api", "claude-code", "hybrid"]
+        .into_iter()
+        .map(|mode| BenchTarget {
+            mode: mode.to_string(),
+            model: None,
+        })
+        .collect()
+}
+
+fn default_bench_runs() -> u32 {
+    3
+}
+
+fn default_bench_timeout() -> u64 {
+    180
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            prompts: Vec::new(),
+            targets: default_bench_targets(),
+            runs: default_bench_runs(),
+            timeout_seconds: default_bench_timeout(),
+        }
+    }
 }
 
 /// Configuration for Agent Teams integration.
@@ -165,14 +1708,14 @@ impl Default for TeamsConfig {
 }
 
 /// A team definition with a description and list of teammates.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TeamDefinition {
     pub description: String,
     pub teammates: Vec<TeammateDefinition>,
 }
 
 /// A teammate within a team definition.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TeammateDefinition {
     pub name: String,
     pub role: String,
@@ -186,10 +1729,67 @@ fn default_teammate_timeout() -> u64 {
 
 impl Config {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
         let content = fs::read_to_string(path)?;
-        let config: Config = serde_yml::from_str(&content)?;
+        let mut raw: serde_yml::Value = serde_yml::from_str(&content)?;
+        resolve_inheritance(&mut raw)?;
+        let mut config: Config = serde_yml::from_value(raw)?;
+        config.agents.check_aliases()?;
+        config.resolve_prompt_files(path.parent().unwrap_or(Path::new(".")))?;
+        config.source = Some(path.to_path_buf());
         Ok(config)
     }
+
+    /// The `prompt_file`s and `system_prompt_file`s agents read their
+    /// prompts from, relative to the directory holding the config file.
+    pub fn prompt_files(&self) -> Vec<PathBuf> {
+        let base = self
+            .source
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new("."));
+        self.agents
+            .all()
+            .flat_map(|(_, agent)| [&agent.prompt_file, &agent.system_prompt_file])
+            .flatten()
+            .map(|file| base.join(file))
+            .collect()
+    }
+
+    /// Load `prompt_file`/`system_prompt_file` contents and anchor
+    /// `working_dir`, secret files and the outputs repository, resolving
+    /// paths relative to the directory holding the config file.
+    fn resolve_prompt_files(&mut self, base: &Path) -> Result<()> {
+        if let Some(git) = self.outputs.git.as_mut() {
+            git.repo = base.join(&git.repo).to_string_lossy().into_owned();
+        }
+        for source in self.secrets.values_mut() {
+            if let Some(path) = source.file.as_mut() {
+                *path = base.join(&*path).to_string_lossy().into_owned();
+            }
+        }
+        for (name, agent) in self.agents.all_mut() {
+            // An ssh agent's working_dir is on the remote host
+            if let Some(dir) = agent.working_dir.as_mut().filter(|_| agent.ssh.is_none()) {
+                *dir = base.join(&*dir).to_string_lossy().into_owned();
+            }
+            resolve_prompt_file(
+                name,
+                "system_prompt",
+                &mut agent.system_prompt,
+                agent.system_prompt_file.as_deref(),
+                base,
+            )?;
+            resolve_prompt_file(
+                name,
+                "prompt",
+                &mut agent.prompt,
+                agent.prompt_file.as_deref(),
+                base,
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl Default for Config {
@@ -200,47 +1800,249 @@ impl Default for Config {
                 version: "1.0.0".to_string(),
                 default_mode: "auto".to_string(),
                 schedule: None,
+                max_failed_agents: 0,
+                lock: RunLockConfig::default(),
+                watch: WatchConfig::default(),
             },
             client: ClientConfig::default(),
             agents: AgentsConfig {
                 monitor: AgentConfig {
-                    enabled: true,
                     timeout_seconds: 120,
-                    client_mode: None,
-                    system_prompt: None,
+                    ..AgentConfig::default()
                 },
                 analyzer: AgentConfig {
-                    enabled: true,
                     timeout_seconds: 180,
-                    client_mode: None,
-                    system_prompt: None,
+                    ..AgentConfig::default()
                 },
                 researcher: AgentConfig {
-                    enabled: true,
                     timeout_seconds: 300,
-                    client_mode: None,
-                    system_prompt: None,
+                    ..AgentConfig::default()
                 },
                 reporter: AgentConfig {
-                    enabled: true,
                     timeout_seconds: 120,
-                    client_mode: None,
-                    system_prompt: None,
+                    ..AgentConfig::default()
                 },
+                custom: BTreeMap::new(),
             },
             outputs: OutputsConfig {
                 directory: "outputs".to_string(),
                 retention_days: 30,
                 formats: vec!["json".to_string(), "txt".to_string()],
+                store: StoreKind::default(),
+                remote: None,
+                encryption: None,
+                git: None,
             },
             digitalocean: DigitalOceanConfig {
                 region: "nyc3".to_string(),
                 registry: "agent-orchestra".to_string(),
+                workers: None,
             },
             notifications: NotificationsConfig::default(),
             logging: LoggingConfig::default(),
             features: FeaturesConfig::default(),
             teams: TeamsConfig::default(),
+            actions: ActionsConfig::default(),
+            approvals: ApprovalsConfig::default(),
+            history: HistoryConfig::default(),
+            events: EventsConfig::default(),
+            hooks: HooksConfig::default(),
+            distributed: DistributedConfig::default(),
+            health: HealthConfig::default(),
+            secrets: BTreeMap::new(),
+            consensus: BTreeMap::new(),
+            review: BTreeMap::new(),
+            map: BTreeMap::new(),
+            bench: BenchConfig::default(),
+            source: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_files_resolve_relative_to_config() {
+        let dir = std::env::temp_dir().join("orchestra-config-prompt-files");
+        fs::create_dir_all(dir.join("prompts")).unwrap();
+        fs::write(dir.join("prompts/monitor.md"), "You watch things.").unwrap();
+
+        let mut config = Config::default();
+        config.agents.monitor.system_prompt_file = Some("prompts/monitor.md".to_string());
+        config.agents.monitor.working_dir = Some("checkout".to_string());
+        config.agents.analyzer.working_dir = Some("srv/app".to_string());
+        config.agents.analyzer.ssh = Some(SshConfig {
+            host: "web-1".to_string(),
+            user: None,
+            port: None,
+            key: None,
+            options: Vec::new(),
+            claude_path: default_claude_path(),
+        });
+        config.resolve_prompt_files(&dir).unwrap();
+        assert_eq!(
+            config.agents.monitor.system_prompt.as_deref(),
+            Some("You watch things.")
+        );
+        let working_dir = config.agents.monitor.working_dir.as_deref().unwrap();
+        assert_eq!(Path::new(working_dir), dir.join("checkout"));
+        // On the remote host, not next to the config file
+        assert_eq!(
+            config.agents.analyzer.working_dir.as_deref(),
+            Some("srv/app")
+        );
+    }
+
+    #[test]
+    fn test_prompt_and_prompt_file_conflict() {
+        let mut config = Config::default();
+        config.agents.reporter.prompt = Some("inline".to_string());
+        config.agents.reporter.prompt_file = Some("prompts/reporter.md".to_string());
+        assert!(config.resolve_prompt_files(Path::new(".")).is_err());
+    }
+
+    fn load_yaml(yaml: &str) -> Result<Config> {
+        let path = std::env::temp_dir().join(format!(
+            "orchestra-config-{}-{}.yml",
+            std::process::id(),
+            yaml.len()
+        ));
+        fs::write(&path, yaml).unwrap();
+        let config = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    const AGENTS_BASE: &str = "
+orchestra: { name: t, version: '1', default_mode: auto }
+outputs: { directory: outputs, retention_days: 1, formats: [json] }
+digitalocean: { enabled: false, region: x, droplet_size: x }
+notifications: { enabled: false }
+logging: { level: info, format: json }
+agents:
+  monitor: { enabled: true, timeout_seconds: 120 }
+  researcher: { enabled: true, timeout_seconds: 300 }
+  reporter: { enabled: true, timeout_seconds: 120 }
+";
+
+    #[test]
+    fn test_inherits_merges_parent_settings() {
+        // A child's prompt_file replaces the parent's inline prompt rather
+        // than conflicting with it.
+        let mut raw: serde_yml::Value = serde_yml::from_str(
+            "agents: { analyzer: { prompt: base }, b: { inherits: analyzer, prompt_file: b.md } }",
+        )
+        .unwrap();
+        resolve_inheritance(&mut raw).unwrap();
+        let b = &raw["agents"]["b"];
+        assert!(b.get("prompt").is_none());
+        assert_eq!(b["prompt_file"].as_str(), Some("b.md"));
+
+        let config = load_yaml(&format!(
+            "{AGENTS_BASE}
+  analyzer:
+    enabled: true
+    timeout_seconds: 180
+    client_mode: api
+    aliases: [data_analyst]
+  synthesizer:
+    inherits: analyzer
+    timeout_seconds: 60
+    prompt: tighter
+"
+        ))
+        .unwrap();
+        let synth = &config.agents.custom["synthesizer"];
+        assert_eq!(synth.timeout_seconds, 60);
+        assert_eq!(synth.client_mode.as_deref(), Some("api"));
+        assert!(synth.aliases.is_empty());
+        // Named agents win over the built-in slot mapping; aliases resolve too
+        assert_eq!(
+            config
+                .agents
+                .lookup("synthesizer")
+                .unwrap()
+                .prompt
+                .as_deref(),
+            Some("tighter")
+        );
+        assert_eq!(
+            config
+                .agents
+                .lookup("data_analyst")
+                .unwrap()
+                .timeout_seconds,
+            180
+        );
+        assert_eq!(
+            config
+                .agents
+                .lookup("health_checker")
+                .unwrap()
+                .timeout_seconds,
+            120
+        );
+        assert!(config.agents.lookup("nobody").is_none());
+    }
+
+    #[test]
+    fn test_inherits_rejects_cycles_and_bad_aliases() {
+        let cycle = load_yaml(&format!(
+            "{AGENTS_BASE}
+  analyzer: {{ inherits: a }}
+  a: {{ inherits: analyzer }}
+"
+        ));
+        assert!(format!("{:#}", cycle.unwrap_err()).contains("cycle"));
+
+        let missing = load_yaml(&format!(
+            "{AGENTS_BASE}
+  analyzer: {{ inherits: nobody }}
+"
+        ));
+        assert!(format!("{:#}", missing.unwrap_err()).contains("unknown agent 'nobody'"));
+
+        let shadow = load_yaml(&format!(
+            "{AGENTS_BASE}
+  analyzer: {{ enabled: true, timeout_seconds: 1, aliases: [monitor] }}
+"
+        ));
+        assert!(shadow.is_err());
+    }
+
+    #[test]
+    fn test_shipped_config_loads() {
+        Config::load("config/orchestra.yml").unwrap();
+    }
+
+    #[test]
+    fn test_logging_section() {
+        let path =
+            std::env::temp_dir().join(format!("orchestra-logging-{}.yml", std::process::id()));
+        // The rest of the file doesn't have to be valid
+        fs::write(
+            &path,
+            "agents: 3\nlogging:\n  level: Warning\n  format: text\n  agent_logs: null\n",
+        )
+        .unwrap();
+        let logging = LoggingConfig::from_file(&path);
+        assert_eq!(logging.tracing_level().unwrap(), tracing::Level::WARN);
+        assert!(!logging.is_json().unwrap());
+        assert!(logging.agent_logs.is_none());
+        fs::remove_file(&path).unwrap();
+
+        let defaults = LoggingConfig::from_file(&path);
+        assert_eq!(defaults.tracing_level().unwrap(), tracing::Level::INFO);
+        assert!(defaults.is_json().unwrap());
+        assert_eq!(defaults.agent_logs.as_deref(), Some("outputs/logs"));
+        let bad = LoggingConfig {
+            level: "loud".to_string(),
+            format: "xml".to_string(),
+            ..Default::default()
+        };
+        assert!(bad.tracing_level().is_err());
+        assert!(bad.is_json().is_err());
+    }
+}
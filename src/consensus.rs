@@ -0,0 +1,233 @@
+//! Consensus runs: every voter answers the same prompt, then an aggregator
+//! agent sees all the answers and writes the final one. With the `vote`
+//! strategy the voters' `VOTE:` lines are counted first.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::agents::AgentResult;
+use crate::config::{ConsensusConfig, ConsensusStrategy};
+
+/// Appended to voters' system prompts under the `vote` strategy.
+pub const VOTE_INSTRUCTION: &str = "End your answer with a final line of the form \
+`VOTE: <answer>`, giving your answer in as few words as possible.";
+
+/// How a consensus run came out; recorded in the results file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConsensusOutcome {
+    pub strategy: ConsensusStrategy,
+    pub aggregator: String,
+    /// Voters that answered; failed ones are left out of the count.
+    pub answered: Vec<String>,
+    /// Distinct votes, most votes first (`vote` strategy only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes: Vec<VoteCount>,
+    /// The vote a strict majority of answering voters cast, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub majority: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VoteCount {
+    pub vote: String,
+    pub voters: Vec<String>,
+}
+
+impl ConsensusOutcome {
+    /// Count the successful voters' answers in `results`.
+    pub fn tally(spec: &ConsensusConfig, results: &[AgentResult]) -> Self {
+        let answers: Vec<(&str, &str)> = spec
+            .voters
+            .iter()
+            .filter_map(|voter| {
+                results
+                    .iter()
+                    .find(|r| &r.agent == voter && r.status == "success")
+                    .map(|r| (voter.as_str(), r.output.as_deref().unwrap_or("")))
+            })
+            .collect();
+
+        let mut votes: Vec<VoteCount> = Vec::new();
+        if spec.strategy == ConsensusStrategy::Vote {
+            for (voter, output) in &answers {
+                let Some(vote) = parse_vote(output) else {
+                    continue;
+                };
+                match votes.iter_mut().find(|v| same_vote(&v.vote, &vote)) {
+                    Some(count) => count.voters.push(voter.to_string()),
+                    None => votes.push(VoteCount {
+                        vote,
+                        voters: vec![voter.to_string()],
+                    }),
+                }
+            }
+            // Stable, so ties keep the order votes were first cast in
+            votes.sort_by_key(|v| std::cmp::Reverse(v.voters.len()));
+        }
+        let majority = votes
+            .first()
+            .filter(|v| v.voters.len() * 2 > answers.len())
+            .map(|v| v.vote.clone());
+
+        Self {
+            strategy: spec.strategy,
+            aggregator: spec.aggregator.clone(),
+            answered: answers.iter().map(|(v, _)| v.to_string()).collect(),
+            votes,
+            majority,
+        }
+    }
+
+    /// One line for the run summary.
+    pub fn describe(&self) -> String {
+        match self.strategy {
+            ConsensusStrategy::Synthesize => format!(
+                "synthesized by {} from {} answer(s)",
+                self.aggregator,
+                self.answered.len()
+            ),
+            ConsensusStrategy::Vote => match &self.majority {
+                Some(vote) => format!(
+                    "'{}' ({} of {} votes)",
+                    vote,
+                    self.votes[0].voters.len(),
+                    self.answered.len()
+                ),
+                None => format!("no majority among {} answer(s)", self.answered.len()),
+            },
+        }
+    }
+}
+
+/// The aggregator's prompt: the original task, every answer, and what to do
+/// with them.
+pub fn aggregator_prompt(
+    spec: &ConsensusConfig,
+    prompt: &str,
+    results: &[AgentResult],
+    outcome: &ConsensusOutcome,
+) -> String {
+    let mut out = format!(
+        "Original task:\n{}\n\n{} agent(s) answered it independently.\n",
+        prompt.trim(),
+        spec.voters.len()
+    );
+    for voter in &spec.voters {
+        let Some(result) = results.iter().find(|r| &r.agent == voter) else {
+            continue;
+        };
+        let source = result.model.as_deref().unwrap_or(&result.client_mode);
+        match (&result.output, result.status.as_str()) {
+            (Some(output), "success") => {
+                let _ = write!(out, "\n### {} ({})\n{}\n", voter, source, output.trim());
+            }
+            _ => {
+                let _ = write!(
+                    out,
+                    "\n### {} ({})\nNo answer: {}\n",
+                    voter,
+                    source,
+                    result.error.as_deref().unwrap_or(&result.status)
+                );
+            }
+        }
+    }
+    match spec.strategy {
+        ConsensusStrategy::Synthesize => out.push_str(
+            "\nCombine these into one answer. Say where they agree and where they \
+             disagree, and which position you trust more and why.",
+        ),
+        ConsensusStrategy::Vote => {
+            out.push_str("\nVotes:\n");
+            for count in &outcome.votes {
+                let _ = writeln!(out, "- {}: {}", count.vote, count.voters.join(", "));
+            }
+            match &outcome.majority {
+                Some(vote) => {
+                    let _ = write!(out, "\nThe majority voted '{}'.", vote);
+                }
+                None => out.push_str("\nNo answer has a majority."),
+            }
+            out.push_str(
+                " State the outcome, then summarize the reasoning behind it and any \
+                 dissent worth acting on.",
+            );
+        }
+    }
+    out
+}
+
+/// The last `VOTE:` line of an answer, without trailing punctuation.
+fn parse_vote(output: &str) -> Option<String> {
+    output.lines().rev().find_map(|line| {
+        let line = line.trim().trim_matches('*').trim();
+        let (key, vote) = line.split_once(':')?;
+        if !key.trim().eq_ignore_ascii_case("vote") {
+            return None;
+        }
+        let vote = vote.trim().trim_end_matches(['.', '!']).trim();
+        (!vote.is_empty()).then(|| vote.to_string())
+    })
+}
+
+fn same_vote(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(strategy: ConsensusStrategy) -> ConsensusConfig {
+        ConsensusConfig {
+            prompt: "Ship it?".to_string(),
+            voters: vec!["opus".into(), "sonnet".into(), "cli".into(), "api".into()],
+            aggregator: "judge".to_string(),
+            strategy,
+        }
+    }
+
+    fn answer(agent: &str, output: &str) -> AgentResult {
+        AgentResult::success(agent.to_string(), output.to_string(), "api".to_string())
+    }
+
+    #[test]
+    fn test_tally_votes() {
+        let results = vec![
+            answer("opus", "Looks safe.\nVOTE: Yes."),
+            answer("sonnet", "Risky migration.\n**Vote: no**"),
+            answer("cli", "Fine by me\nvote: yes"),
+            AgentResult::failed(
+                "api".to_string(),
+                "timed out".to_string(),
+                "api".to_string(),
+            ),
+        ];
+        let outcome = ConsensusOutcome::tally(&spec(ConsensusStrategy::Vote), &results);
+        assert_eq!(outcome.answered, ["opus", "sonnet", "cli"]);
+        assert_eq!(outcome.votes[0].vote, "Yes");
+        assert_eq!(outcome.votes[0].voters, ["opus", "cli"]);
+        assert_eq!(outcome.majority.as_deref(), Some("Yes"));
+        assert_eq!(outcome.describe(), "'Yes' (2 of 3 votes)");
+
+        let prompt = aggregator_prompt(
+            &spec(ConsensusStrategy::Vote),
+            "Ship it?",
+            &results,
+            &outcome,
+        );
+        assert!(prompt.contains("### api (api)\nNo answer: timed out"));
+        assert!(prompt.contains("- Yes: opus, cli\n- no: sonnet\n"));
+        assert!(prompt.contains("The majority voted 'Yes'."));
+    }
+
+    #[test]
+    fn test_no_majority_on_a_tie() {
+        let results = vec![answer("opus", "VOTE: yes"), answer("sonnet", "VOTE: no")];
+        let outcome = ConsensusOutcome::tally(&spec(ConsensusStrategy::Vote), &results);
+        assert_eq!(outcome.majority, None);
+        let outcome = ConsensusOutcome::tally(&spec(ConsensusStrategy::Synthesize), &results);
+        assert!(outcome.votes.is_empty());
+        assert_eq!(outcome.describe(), "synthesized by judge from 2 answer(s)");
+    }
+}
@@ -0,0 +1,410 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::{ContextConfig, EgressPolicy, LogSampling, LogSource, SamplingStrategy};
+
+/// Severity buckets used for stratified sampling, in the order they are
+/// matched against a line. Lines matching none fall into "other".
+const SEVERITIES: &[(&str, &[&str])] = &[
+    ("error", &["FATAL", "CRITICAL", "CRIT", "ERROR", "ERR"]),
+    ("warn", &["WARNING", "WARN"]),
+    ("info", &["INFO", "NOTICE"]),
+    ("debug", &["DEBUG", "TRACE"]),
+];
+
+/// Record of how a log source was sampled for an agent, kept in the run output
+/// so readers know the agent saw a sample rather than the full log.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SamplingRecord {
+    pub agent: String,
+    pub source: String,
+    pub strategy: SamplingStrategy,
+    pub lines: usize,
+    pub total_lines: usize,
+    pub sampled_lines: usize,
+}
+
+/// Classify a log line into a severity bucket by looking for a level token.
+pub fn severity_of(line: &str) -> &'static str {
+    let upper = line.to_ascii_uppercase();
+    let tokens: Vec<&str> = upper
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect();
+    for (bucket, levels) in SEVERITIES {
+        if tokens.iter().any(|t| levels.contains(t)) {
+            return bucket;
+        }
+    }
+    "other"
+}
+
+/// Pick `n` indices spread evenly over `0..len`, always keeping the last one
+/// so the most recent line of each bucket is represented.
+fn spread(len: usize, n: usize) -> Vec<usize> {
+    if len <= n {
+        return (0..len).collect();
+    }
+    if n == 1 {
+        return vec![len - 1];
+    }
+    (0..n).map(|i| i * (len - 1) / (n - 1)).collect()
+}
+
+/// Sample lines according to the strategy. Selected lines keep their original order.
+pub fn sample_lines<'a>(lines: &[&'a str], sampling: &LogSampling) -> Vec<&'a str> {
+    match sampling.strategy {
+        SamplingStrategy::Tail => {
+            let start = lines.len().saturating_sub(sampling.lines);
+            lines[start..].to_vec()
+        }
+        SamplingStrategy::Stratified => {
+            let mut buckets: Vec<(&str, Vec<usize>)> = Vec::new();
+            for (i, line) in lines.iter().enumerate() {
+                let severity = severity_of(line);
+                match buckets.iter_mut().find(|(s, _)| *s == severity) {
+                    Some((_, idx)) => idx.push(i),
+                    None => buckets.push((severity, vec![i])),
+                }
+            }
+            let mut keep: Vec<usize> = buckets
+                .iter()
+                .flat_map(|(_, idx)| {
+                    spread(idx.len(), sampling.lines)
+                        .into_iter()
+                        .map(|j| idx[j])
+                })
+                .collect();
+            keep.sort_unstable();
+            keep.into_iter().map(|i| lines[i]).collect()
+        }
+    }
+}
+
+/// Read and sample each log source, returning the block to prepend to the
+/// prompt along with a record of how each source was sampled.
+pub fn render_logs(agent: &str, sources: &[LogSource]) -> Result<(String, Vec<SamplingRecord>)> {
+    let mut block = String::new();
+    let mut records = Vec::new();
+    for source in sources {
+        let content = fs::read_to_string(&source.path)
+            .with_context(|| format!("Failed to read log source {}", source.path))?;
+        let lines: Vec<&str> = content.lines().collect();
+        let sampled = sample_lines(&lines, &source.sampling);
+
+        block.push_str(&format!(
+            "[LOG: {} — {} of {} lines, {}]\n",
+            source.path,
+            sampled.len(),
+            lines.len(),
+            source.sampling
+        ));
+        for line in &sampled {
+            block.push_str(line);
+            block.push('\n');
+        }
+        block.push('\n');
+
+        records.push(SamplingRecord {
+            agent: agent.to_string(),
+            source: source.path.clone(),
+            strategy: source.sampling.strategy,
+            lines: source.sampling.lines,
+            total_lines: lines.len(),
+            sampled_lines: sampled.len(),
+        });
+    }
+    Ok((block, records))
+}
+
+/// Which end of an oversized source to keep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Keep {
+    Head,
+    Tail,
+}
+
+/// Cut `text` down to at most `max` bytes (on a char boundary), noting how
+/// much was dropped.
+fn truncate(text: &str, max: usize, keep: Keep) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let dropped = text.len() - max;
+    match keep {
+        Keep::Head => {
+            let mut end = max;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{}\n[... truncated {} bytes]\n", &text[..end], dropped)
+        }
+        Keep::Tail => {
+            let mut start = text.len() - max;
+            while !text.is_char_boundary(start) {
+                start += 1;
+            }
+            format!("[... truncated {} bytes]\n{}", dropped, &text[start..])
+        }
+    }
+}
+
+/// Match a single path component against a pattern with `*` and `?`.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Expand `*` and `?` wildcards in any component of `pattern` into the
+/// sorted list of existing files it matches.
+pub fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    let path = Path::new(pattern);
+    let mut candidates = vec![PathBuf::new()];
+    for component in path.components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !part.contains(['*', '?']) {
+            for c in &mut candidates {
+                c.push(component);
+            }
+            continue;
+        }
+        let mut next = Vec::new();
+        for dir in &candidates {
+            let read_from = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir.as_path()
+            };
+            let Ok(entries) = fs::read_dir(read_from) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !name.starts_with('.') && wildcard_match(&part, &name) {
+                    next.push(dir.join(name));
+                }
+            }
+        }
+        candidates = next;
+    }
+    let mut files: Vec<PathBuf> = candidates.into_iter().filter(|p| p.is_file()).collect();
+    files.sort();
+    files
+}
+
+async fn run_command(command: &str, timeout: Duration) -> Result<String> {
+    let output = tokio::time::timeout(
+        timeout,
+        tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .context("timed out")??;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if !output.status.success() {
+        text.push_str(&format!("[exit status: {}]\n", output.status));
+    }
+    Ok(text)
+}
+
+async fn fetch_url(url: &str, timeout: Duration, egress: Option<&EgressPolicy>) -> Result<String> {
+    if let Some(policy) = egress {
+        if !policy.allows_url(url) {
+            anyhow::bail!("blocked by egress policy");
+        }
+    }
+    let response = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()?
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.text().await?)
+}
+
+/// Capture every source in `spec` and format them as a block to prepend to
+/// the prompt. Failures are reported inline rather than aborting the agent.
+pub async fn gather(agent: &str, spec: &ContextConfig, egress: Option<&EgressPolicy>) -> String {
+    let timeout = Duration::from_secs(spec.timeout_seconds);
+    let mut sources: Vec<(String, Result<String>, Keep)> = Vec::new();
+
+    for pattern in &spec.files {
+        let matches = expand_glob(pattern);
+        if matches.is_empty() {
+            sources.push((
+                format!("FILE: {}", pattern),
+                Err(anyhow::anyhow!("no matching files")),
+                Keep::Tail,
+            ));
+        }
+        for path in matches {
+            let content = fs::read(&path)
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                .map_err(Into::into);
+            sources.push((format!("FILE: {}", path.display()), content, Keep::Tail));
+        }
+    }
+    for command in &spec.commands {
+        let output = run_command(command, timeout).await;
+        sources.push((format!("COMMAND: {}", command), output, Keep::Head));
+    }
+    for url in &spec.urls {
+        let body = fetch_url(url, timeout, egress).await;
+        sources.push((format!("URL: {}", url), body, Keep::Head));
+    }
+
+    let mut block = String::new();
+    let mut remaining = spec.max_total_bytes;
+    let mut skipped = 0;
+    for (label, content, keep) in sources {
+        let text = match content {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("Agent {}: context {}: {:#}", agent, label, e);
+                block.push_str(&format!("[{} — unavailable: {:#}]\n\n", label, e));
+                continue;
+            }
+        };
+        if remaining == 0 {
+            skipped += 1;
+            continue;
+        }
+        let text = truncate(&text, spec.max_bytes_per_source.min(remaining), keep);
+        remaining = remaining.saturating_sub(text.len());
+        block.push_str(&format!("[{}]\n{}", label, text));
+        if !text.ends_with('\n') {
+            block.push('\n');
+        }
+        block.push('\n');
+    }
+    if skipped > 0 {
+        block.push_str(&format!(
+            "[{} more context source(s) omitted: {} byte limit reached]\n\n",
+            skipped, spec.max_total_bytes
+        ));
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampling(strategy: SamplingStrategy, lines: usize) -> LogSampling {
+        LogSampling { strategy, lines }
+    }
+
+    #[test]
+    fn test_severity_of() {
+        assert_eq!(severity_of("2025-01-01 ERROR disk full"), "error");
+        assert_eq!(severity_of("[warn] slow query"), "warn");
+        assert_eq!(severity_of("level=info msg=ok"), "info");
+        assert_eq!(severity_of("kernel: eth0 up"), "other");
+        // Level tokens must be whole words
+        assert_eq!(severity_of("terror inbound"), "other");
+    }
+
+    #[test]
+    fn test_tail_sampling() {
+        let lines = vec!["a", "b", "c", "d"];
+        let out = sample_lines(&lines, &sampling(SamplingStrategy::Tail, 2));
+        assert_eq!(out, vec!["c", "d"]);
+    }
+
+    #[test]
+    fn test_stratified_keeps_rare_severities() {
+        let mut lines: Vec<String> = (0..100).map(|i| format!("INFO request {}", i)).collect();
+        lines.insert(10, "ERROR out of memory".to_string());
+        let refs: Vec<&str> = lines.iter().map(|s| s.as_str()).collect();
+
+        let out = sample_lines(&refs, &sampling(SamplingStrategy::Stratified, 3));
+        assert_eq!(out.len(), 4);
+        assert!(out.contains(&"ERROR out of memory"));
+        assert_eq!(out.last(), Some(&"INFO request 99"));
+        // A plain tail of the same size would have missed the error
+        let tail = sample_lines(&refs, &sampling(SamplingStrategy::Tail, 4));
+        assert!(!tail.contains(&"ERROR out of memory"));
+    }
+
+    #[test]
+    fn test_truncate_keeps_requested_end() {
+        assert_eq!(truncate("short", 10, Keep::Head), "short");
+        let head = truncate("abcdefghij", 4, Keep::Head);
+        assert!(head.starts_with("abcd\n"));
+        assert!(head.contains("truncated 6 bytes"));
+        let tail = truncate("abcdefghij", 4, Keep::Tail);
+        assert!(tail.ends_with("\nghij"));
+        // Never splits a multi-byte character
+        let t = truncate("ééé", 3, Keep::Head);
+        assert!(t.starts_with("é\n"));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.log", "app.log"));
+        assert!(wildcard_match("app-?.log", "app-1.log"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(!wildcard_match("*.log", "app.log.1"));
+        assert!(wildcard_match("*.log*", "app.log.1"));
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = std::env::temp_dir().join(format!("orchestra-glob-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["b.log", "a.log", "c.txt"] {
+            fs::write(dir.join(name), "x").unwrap();
+        }
+        let found = expand_glob(&format!("{}/*.log", dir.display()));
+        assert_eq!(found, vec![dir.join("a.log"), dir.join("b.log")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gather_respects_budget_and_egress() {
+        let spec = ContextConfig {
+            commands: vec!["printf 0123456789".into(), "echo second".into()],
+            urls: vec!["https://blocked.example/".into()],
+            max_bytes_per_source: 4,
+            max_total_bytes: 4,
+            ..ContextConfig::default()
+        };
+        let egress = EgressPolicy {
+            allowed_domains: vec!["allowed.example".into()],
+            ..EgressPolicy::default()
+        };
+        let block = gather("monitor", &spec, Some(&egress)).await;
+        assert!(block.contains("[COMMAND: printf 0123456789]\n0123\n[... truncated 6 bytes]"));
+        assert!(!block.contains("second"));
+        assert!(block.contains("1 more context source(s) omitted"));
+        assert!(block
+            .contains("[URL: https://blocked.example/ — unavailable: blocked by egress policy]"));
+    }
+}
@@ -0,0 +1,119 @@
+/// Rough chars-per-token ratio, matching the dashboard's estimator.
+pub const CHARS_PER_TOKEN: f64 = 4.0;
+
+/// Output price per million tokens. Overridable with `COST_PER_1M_OUTPUT`,
+/// the same variable the dashboard reads.
+pub fn cost_per_1m_output() -> f64 {
+    std::env::var("COST_PER_1M_OUTPUT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(15.0)
+}
+
+/// Input price per million tokens. Overridable with `COST_PER_1M_INPUT`.
+pub fn cost_per_1m_input() -> f64 {
+    std::env::var("COST_PER_1M_INPUT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3.0)
+}
+
+/// Message Batches are billed at this fraction of the usual token prices.
+pub const BATCH_DISCOUNT: f64 = 0.5;
+
+/// Modes that run through the local CLI subscription (or nothing at all)
+/// rather than the metered API.
+fn is_free(client_mode: &str) -> bool {
+    matches!(client_mode, "claude-code" | "agent-teams" | "mock")
+}
+
+/// Estimate the API cost of one agent result from its output length.
+/// `claude-code` runs through the local CLI subscription, so it's free.
+pub fn estimate_cost(text: Option<&str>, client_mode: &str) -> f64 {
+    let Some(text) = text else {
+        return 0.0;
+    };
+    if is_free(client_mode) {
+        return 0.0;
+    }
+    let tokens = text.len() as f64 / CHARS_PER_TOKEN;
+    tokens / 1_000_000.0 * cost_per_1m_output()
+}
+
+/// Rough token count of `text`, from its length.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.len() as f64 / CHARS_PER_TOKEN).ceil() as u64
+}
+
+/// Upper-bound estimate for an agent call that hasn't run yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CallEstimate {
+    pub input_tokens: u64,
+    pub max_output_tokens: u64,
+    pub cost: f64,
+}
+
+/// Estimate a call before it is made: the prompt at the input rate plus the
+/// full `max_tokens` budget at the output rate.
+pub fn estimate_call(prompt_chars: usize, max_tokens: u32, client_mode: &str) -> CallEstimate {
+    let input_tokens = (prompt_chars as f64 / CHARS_PER_TOKEN).ceil() as u64;
+    let max_output_tokens = u64::from(max_tokens);
+    let cost = if is_free(client_mode) {
+        0.0
+    } else {
+        (input_tokens as f64 * cost_per_1m_input()
+            + max_output_tokens as f64 * cost_per_1m_output())
+            / 1_000_000.0
+    };
+    CallEstimate {
+        input_tokens,
+        max_output_tokens,
+        cost,
+    }
+}
+
+/// Cost of a call from the tokens it used; nothing for CLI modes.
+pub fn call_cost(input_tokens: u64, output_tokens: u64, client_mode: &str) -> f64 {
+    if is_free(client_mode) {
+        return 0.0;
+    }
+    (input_tokens as f64 * cost_per_1m_input() + output_tokens as f64 * cost_per_1m_output())
+        / 1_000_000.0
+}
+
+/// Cost of a batched request from the tokens it used.
+pub fn batch_cost(input_tokens: u64, output_tokens: u64) -> f64 {
+    call_cost(input_tokens, output_tokens, "api") * BATCH_DISCOUNT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_runs_are_free() {
+        assert_eq!(estimate_cost(Some("hello world"), "claude-code"), 0.0);
+        assert_eq!(estimate_cost(None, "api"), 0.0);
+    }
+
+    #[test]
+    fn test_api_cost_from_output_length() {
+        // 4M chars ≈ 1M tokens ≈ $15 at the default rate
+        let text = "x".repeat(4_000_000);
+        assert!((estimate_cost(Some(&text), "api") - 15.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_estimate_call_upper_bound() {
+        let est = estimate_call(4_000, 1_000, "api");
+        assert_eq!(est.input_tokens, 1_000);
+        assert_eq!(est.max_output_tokens, 1_000);
+        // 1k input at $3/M + 1k output at $15/M
+        assert!((est.cost - 0.018).abs() < 1e-9);
+        assert_eq!(estimate_call(4_000, 1_000, "claude-code").cost, 0.0);
+        assert!((call_cost(1_000, 1_000, "api") - 0.018).abs() < 1e-9);
+        assert_eq!(call_cost(1_000, 1_000, "claude-code"), 0.0);
+        // The same tokens in a batch cost half
+        assert!((batch_cost(1_000, 1_000) - 0.009).abs() < 1e-9);
+    }
+}
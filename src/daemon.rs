@@ -0,0 +1,176 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+
+use agent_orchestra::config::{Config, ScheduleConfig};
+use agent_orchestra::shutdown::Shutdown;
+
+use crate::cli::Args;
+use crate::{build_orchestrator, load_config, CONFIG_PATH};
+
+/// Notices when any of a set of files' modification times changes between
+/// polls: the config file and the prompt files it points to.
+pub struct ConfigWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigWatcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = mtime(&path);
+                (path, modified)
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// The config file at `path` and the prompt files `config` reads.
+    pub fn for_config(path: impl Into<PathBuf>, config: &Config) -> Self {
+        Self::new(std::iter::once(path.into()).chain(config.prompt_files()))
+    }
+
+    /// True once per change since the last call (including deletion).
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, modified) in &mut self.files {
+            let now = mtime(path);
+            if now != *modified {
+                *modified = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn schedule(config: &Config) -> ScheduleConfig {
+    config.orchestra.schedule.clone().unwrap_or(ScheduleConfig {
+        interval_hours: 1,
+        max_retries: 0,
+        retry_delay_seconds: 60,
+    })
+}
+
+/// Run the orchestration every `orchestra.schedule.interval_hours`. Before each
+/// cycle the config is re-read if it or one of its prompt files changed on
+/// disk; a config that fails validation is reported and the last valid one
+/// keeps running.
+pub async fn run(args: Args) -> Result<()> {
+    if args.stdin_var.is_some() {
+        anyhow::bail!("--stdin-var can't be used with daemon: stdin is read only once");
+    }
+    if args.resume.is_some() {
+        anyhow::bail!("--resume can't be used with daemon; resume the run with `run --resume`");
+    }
+    let mut config = load_config().await?;
+    let mut watcher = ConfigWatcher::for_config(CONFIG_PATH, &config);
+    let shutdown = Shutdown::on_signals();
+    info!(
+        "Daemon started; watching {} and its prompt files",
+        CONFIG_PATH
+    );
+
+    loop {
+        if watcher.changed() {
+            match load_config().await {
+                Ok(new) => {
+                    info!("Reloaded {}", CONFIG_PATH);
+                    config = new;
+                    // Agents may point at other prompt files now
+                    watcher = ConfigWatcher::for_config(CONFIG_PATH, &config);
+                }
+                Err(e) => warn!("Keeping previous config: {:#}", e),
+            }
+        }
+
+        let schedule = schedule(&config);
+        for attempt in 0..=schedule.max_retries {
+            if attempt > 0 {
+                warn!(
+                    "Retrying in {}s (attempt {}/{})",
+                    schedule.retry_delay_seconds, attempt, schedule.max_retries
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(schedule.retry_delay_seconds)) => {}
+                    _ = shutdown.wait() => break,
+                }
+            }
+            let result = match build_orchestrator(&args, config.clone(), None) {
+                Ok(orchestrator) => orchestrator.run().await.map(|_| ()),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => break,
+                Err(e) => error!("Cycle failed: {:#}", e),
+            }
+            if shutdown.is_triggered() {
+                break;
+            }
+        }
+        if shutdown.is_triggered() {
+            info!("Shutting down daemon");
+            return Ok(());
+        }
+
+        let interval = Duration::from_secs(u64::from(schedule.interval_hours.max(1)) * 3600);
+        info!("Next cycle in {}h", interval.as_secs() / 3600);
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.wait() => {
+                info!("Shutting down daemon");
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher_reports_each_change_once() {
+        let dir = std::env::temp_dir().join(format!("orchestra-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("orchestra.yml");
+        let prompt = dir.join("monitor.md");
+        fs::write(&path, "a: 1").unwrap();
+        fs::write(&prompt, "Check the logs.").unwrap();
+        let mut config = Config {
+            source: Some(path.clone()),
+            ..Config::default()
+        };
+        config.agents.monitor.prompt_file = Some("monitor.md".to_string());
+        let mut watcher = ConfigWatcher::for_config(&path, &config);
+        assert!(!watcher.changed());
+
+        let touch = |path: &Path| {
+            let later = SystemTime::now() + Duration::from_secs(5);
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+        };
+        touch(&path);
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        // A prompt file the config reads counts too
+        touch(&prompt);
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+use std::fmt::Write;
+
+use crate::OrchestrationResult;
+
+/// One line of a line-based diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line diff of `old` against `new` via longest common subsequence. The
+/// common prefix and suffix are trimmed first, which keeps the table small
+/// for reports that change in a few places.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (ma, mb) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    // lcs[i][j]: LCS length of ma[i..] and mb[j..]
+    let mut lcs = vec![vec![0u32; mb.len() + 1]; ma.len() + 1];
+    for i in (0..ma.len()).rev() {
+        for j in (0..mb.len()).rev() {
+            lcs[i][j] = if ma[i] == mb[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out: Vec<Line> = a[..prefix].iter().map(|l| Line::Same(l)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < ma.len() && j < mb.len() {
+        if ma[i] == mb[j] {
+            out.push(Line::Same(ma[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push(Line::Removed(ma[i]));
+            i += 1;
+        } else {
+            out.push(Line::Added(mb[j]));
+            j += 1;
+        }
+    }
+    out.extend(ma[i..].iter().map(|l| Line::Removed(l)));
+    out.extend(mb[j..].iter().map(|l| Line::Added(l)));
+    out.extend(a[a.len() - suffix..].iter().map(|l| Line::Same(l)));
+    out
+}
+
+/// Render `lines` as unified-diff hunks with `context` lines around each
+/// change. Empty when nothing changed.
+pub fn unified(old_label: &str, new_label: &str, lines: &[Line], context: usize) -> String {
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !matches!(l, Line::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Group changes whose context windows touch into one hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for (start, end) in hunks {
+        // 1-based line numbers in each file where the hunk starts
+        let old_line = 1 + lines[..start]
+            .iter()
+            .filter(|l| !matches!(l, Line::Added(_)))
+            .count();
+        let new_line = 1 + lines[..start]
+            .iter()
+            .filter(|l| !matches!(l, Line::Removed(_)))
+            .count();
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_len = hunk
+            .iter()
+            .filter(|l| !matches!(l, Line::Removed(_)))
+            .count();
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_line, old_len, new_line, new_len
+        );
+        for line in hunk {
+            let _ = match line {
+                Line::Same(l) => writeln!(out, " {}", l),
+                Line::Removed(l) => writeln!(out, "-{}", l),
+                Line::Added(l) => writeln!(out, "+{}", l),
+            };
+        }
+    }
+    out
+}
+
+/// How one agent's result changed between two runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentDiff {
+    pub agent: String,
+    /// `None` when the agent didn't run in that run.
+    pub old_status: Option<String>,
+    pub new_status: Option<String>,
+    pub added: usize,
+    pub removed: usize,
+    /// Unified diff of the outputs (errors for failed agents); empty if equal.
+    pub unified: String,
+}
+
+impl AgentDiff {
+    /// One-line description of the change.
+    pub fn summary(&self) -> String {
+        match (&self.old_status, &self.new_status) {
+            (None, Some(s)) => format!("only in new run ({})", s),
+            (Some(s), None) => format!("only in old run ({})", s),
+            (Some(old), Some(new)) if old != new => format!(
+                "{} -> {}, {} line(s) added, {} removed",
+                old, new, self.added, self.removed
+            ),
+            _ if self.unified.is_empty() => "unchanged".to_string(),
+            _ => format!("{} line(s) added, {} removed", self.added, self.removed),
+        }
+    }
+}
+
+/// Compare each agent's output across two runs, in the new run's agent
+/// order followed by agents only the old run had.
+pub fn compare(
+    old: &OrchestrationResult,
+    new: &OrchestrationResult,
+    agent: Option<&str>,
+) -> Vec<AgentDiff> {
+    let mut names: Vec<&str> = new.results.iter().map(|r| r.agent.as_str()).collect();
+    for r in &old.results {
+        if !names.contains(&r.agent.as_str()) {
+            names.push(&r.agent);
+        }
+    }
+    names.retain(|n| agent.is_none_or(|a| a == *n));
+
+    names
+        .into_iter()
+        .map(|name| {
+            let find =
+                |run: &OrchestrationResult| run.results.iter().find(|r| r.agent == name).cloned();
+            let (before, after) = (find(old), find(new));
+            let text = |r: &Option<crate::agents::AgentResult>| {
+                r.as_ref()
+                    .and_then(|r| r.output.clone().or_else(|| r.error.clone()))
+                    .unwrap_or_default()
+            };
+            let (old_text, new_text) = (text(&before), text(&after));
+            let lines = diff_lines(&old_text, &new_text);
+            AgentDiff {
+                agent: name.to_string(),
+                old_status: before.map(|r| r.status),
+                new_status: after.map(|r| r.status),
+                added: lines.iter().filter(|l| matches!(l, Line::Added(_))).count(),
+                removed: lines
+                    .iter()
+                    .filter(|l| matches!(l, Line::Removed(_)))
+                    .count(),
+                unified: unified(
+                    &format!("{}/{}", old.run_id, name),
+                    &format!("{}/{}", new.run_id, name),
+                    &lines,
+                    3,
+                ),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::AgentResult;
+    use chrono::Utc;
+
+    fn run(run_id: &str, results: Vec<AgentResult>) -> OrchestrationResult {
+        OrchestrationResult {
+            run_id: run_id.to_string(),
+            timestamp: Utc::now(),
+            mode: "monitoring".to_string(),
+            global_client_mode: "api".to_string(),
+            provider_outages: Vec::new(),
+            log_sampling: Vec::new(),
+            actions: Vec::new(),
+            rerun_of: None,
+            duration_ms: None,
+            consensus: None,
+            review: None,
+            results,
+        }
+    }
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        let lines = diff_lines(old, new);
+        let diff = unified("old", "new", &lines, 1);
+        assert_eq!(
+            diff,
+            "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -10,1 +10,2 @@\n j\n+k\n"
+        );
+        assert!(unified("old", "new", &diff_lines(old, old), 3).is_empty());
+    }
+
+    #[test]
+    fn test_compare_runs() {
+        let ok = |agent: &str, out: &str| {
+            AgentResult::success(agent.to_string(), out.to_string(), "api".to_string())
+        };
+        let old = run(
+            "monitoring-1",
+            vec![ok("health_checker", "disk: 40%\ncpu: ok"), ok("gone", "x")],
+        );
+        let new = run(
+            "monitoring-2",
+            vec![
+                ok("health_checker", "disk: 85%\ncpu: ok"),
+                AgentResult::failed("alert_manager".into(), "timeout".into(), "api".into()),
+            ],
+        );
+        let diffs = compare(&old, &new, None);
+        let summaries: Vec<(&str, String)> = diffs
+            .iter()
+            .map(|d| (d.agent.as_str(), d.summary()))
+            .collect();
+        assert_eq!(
+            summaries,
+            vec![
+                ("health_checker", "1 line(s) added, 1 removed".to_string()),
+                ("alert_manager", "only in new run (failed)".to_string()),
+                ("gone", "only in old run (success)".to_string()),
+            ]
+        );
+        assert!(diffs[0].unified.contains("-disk: 40%\n+disk: 85%\n"));
+        assert_eq!(compare(&old, &new, Some("gone")).len(), 1);
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use std::net::IpAddr;
+
+use crate::config::EgressPolicy;
+
+impl EgressPolicy {
+    /// Whether a host (domain name or IP literal) may be contacted.
+    /// Domains match exactly or as a parent (`example.com` allows
+    /// `api.example.com`); a leading `*.` only allows subdomains.
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return self.allowed_cidrs.iter().any(|c| cidr_contains(c, ip));
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.allowed_domains.iter().any(|d| {
+            let d = d.to_ascii_lowercase();
+            match d.strip_prefix("*.") {
+                Some(parent) => host.ends_with(&format!(".{}", parent)),
+                None => host == d || host.ends_with(&format!(".{}", d)),
+            }
+        })
+    }
+
+    /// Whether a URL's host is allowed. URLs without a host are refused.
+    pub fn allows_url(&self, url: &str) -> bool {
+        reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| self.allows_host(h)))
+            .unwrap_or(false)
+    }
+
+    /// Claude Code permission rules restricting WebFetch to the allowed
+    /// domains, matching as [`allows_host`](Self::allows_host) does: a plain
+    /// domain gets a rule for itself and a `*.` rule for its subdomains, a
+    /// `*.` domain only the latter. Anything not listed is denied in
+    /// non-interactive `-p` mode.
+    pub fn cli_allowed_tools(&self) -> Vec<String> {
+        self.allowed_domains
+            .iter()
+            .flat_map(|d| match d.strip_prefix("*.") {
+                Some(_) => vec![format!("WebFetch(domain:{})", d)],
+                None => vec![
+                    format!("WebFetch(domain:{})", d),
+                    format!("WebFetch(domain:*.{})", d),
+                ],
+            })
+            .collect()
+    }
+
+    /// Refuse a policy `claude` can't be held to: its permission rules only
+    /// name domains, so `allowed_cidrs` would go unenforced for its tools.
+    pub fn check_cli(&self) -> Result<()> {
+        if !self.allowed_cidrs.is_empty() {
+            anyhow::bail!(
+                "egress.allowed_cidrs can't be enforced for claude's own tools, which only \
+                 take domain rules; remove it, or use client_mode api"
+            );
+        }
+        Ok(())
+    }
+
+    /// Proxy variables for subprocesses and remote execution targets, so
+    /// their traffic goes through the egress proxy when one is configured.
+    pub fn proxy_env(&self) -> Vec<(&'static str, String)> {
+        match self.proxy {
+            Some(ref proxy) => ["HTTPS_PROXY", "HTTP_PROXY", "https_proxy", "http_proxy"]
+                .into_iter()
+                .map(|k| (k, proxy.clone()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// `10.0.0.0/8`-style containment check; a bare address matches only itself.
+fn cidr_contains(cidr: &str, ip: IpAddr) -> bool {
+    let (net, prefix) = match cidr.split_once('/') {
+        Some((n, p)) => (n, p.parse::<u32>().ok()),
+        None => (cidr, None),
+    };
+    let Ok(net) = net.trim().parse::<IpAddr>() else {
+        return false;
+    };
+    match (net, ip) {
+        (IpAddr::V4(n), IpAddr::V4(a)) => {
+            let bits = prefix.unwrap_or(32).min(32);
+            let mask = if bits == 0 {
+                0
+            } else {
+                u32::MAX << (32 - bits)
+            };
+            u32::from(n) & mask == u32::from(a) & mask
+        }
+        (IpAddr::V6(n), IpAddr::V6(a)) => {
+            let bits = prefix.unwrap_or(128).min(128);
+            let mask = if bits == 0 {
+                0
+            } else {
+                u128::MAX << (128 - bits)
+            };
+            u128::from(n) & mask == u128::from(a) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> EgressPolicy {
+        EgressPolicy {
+            allowed_domains: vec!["example.com".to_string(), "*.internal.net".to_string()],
+            allowed_cidrs: vec!["10.0.0.0/8".to_string(), "::1".to_string()],
+            proxy: None,
+        }
+    }
+
+    #[test]
+    fn test_domain_matching() {
+        let p = policy();
+        assert!(p.allows_host("example.com"));
+        assert!(p.allows_host("API.Example.com"));
+        assert!(!p.allows_host("notexample.com"));
+        assert!(p.allows_host("svc.internal.net"));
+        assert!(!p.allows_host("internal.net"));
+        assert!(!p.allows_host("evil.com"));
+    }
+
+    #[test]
+    fn test_cli_rules_keep_wildcard_meaning() {
+        assert_eq!(
+            policy().cli_allowed_tools(),
+            [
+                "WebFetch(domain:example.com)",
+                "WebFetch(domain:*.example.com)",
+                "WebFetch(domain:*.internal.net)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cli_refuses_cidrs() {
+        let err = policy().check_cli().unwrap_err();
+        assert!(err.to_string().contains("allowed_cidrs"), "{}", err);
+        let domains_only = EgressPolicy {
+            allowed_cidrs: Vec::new(),
+            ..policy()
+        };
+        assert!(domains_only.check_cli().is_ok());
+    }
+
+    #[test]
+    fn test_cidr_matching() {
+        let p = policy();
+        assert!(p.allows_host("10.1.2.3"));
+        assert!(!p.allows_host("192.168.0.1"));
+        assert!(p.allows_host("[::1]"));
+        assert!(p.allows_url("http://10.0.0.5:8080/metrics"));
+        assert!(!p.allows_url("https://attacker.io/exfil"));
+        assert!(!p.allows_url("not a url"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Encryption of output files at rest (`outputs.encryption`). Results and
+//! summary files, and the reports uploaded to `outputs.remote`, are sealed
+//! with AES-256-GCM and written with an `.enc` suffix; `agent-orchestra
+//! decrypt` reads them back.
+//!
+//! A sealed file is [`MAGIC`], a random 96-bit nonce, then the ciphertext
+//! and its tag.
+
+use anyhow::{Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::path::{Path, PathBuf};
+
+use crate::config::EncryptionConfig;
+use crate::secrets;
+
+/// First bytes of every sealed file; also authenticated with it.
+pub const MAGIC: &[u8] = b"AOENC1\n";
+
+/// Appended to the name of a sealed file.
+pub const EXTENSION: &str = "enc";
+
+pub struct Cipher {
+    key: LessSafeKey,
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cipher(AES-256-GCM)")
+    }
+}
+
+impl Cipher {
+    /// The cipher for the key in `key_env` (or its `secrets:` source): 64
+    /// hex digits, as `openssl rand -hex 32` prints.
+    pub fn from_config(config: &EncryptionConfig) -> Result<Self> {
+        let hex = secrets::var(&config.key_env)
+            .with_context(|| format!("outputs.encryption is set but ${} is not", config.key_env))?;
+        let key = decode_hex(hex.trim())
+            .filter(|key| key.len() == 32)
+            .with_context(|| format!("${} must be 64 hex digits (32 bytes)", config.key_env))?;
+        Ok(Self::new(&key))
+    }
+
+    pub(crate) fn new(key: &[u8]) -> Self {
+        let key = UnboundKey::new(&AES_256_GCM, key).expect("AES-256 keys are 32 bytes");
+        Self {
+            key: LessSafeKey::new(key),
+        }
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("No randomness for a nonce"))?;
+        let mut sealed = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(MAGIC),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+        Ok([MAGIC, &nonce, &sealed].concat())
+    }
+
+    pub fn open(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let rest = data
+            .strip_prefix(MAGIC)
+            .context("Not an encrypted output file")?;
+        if rest.len() < NONCE_LEN {
+            anyhow::bail!("Encrypted file is truncated");
+        }
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).expect("nonce has NONCE_LEN bytes");
+        let mut sealed = sealed.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(MAGIC), &mut sealed)
+            .map_err(|_| anyhow::anyhow!("Decryption failed: wrong key or corrupted file"))?;
+        Ok(plaintext.to_vec())
+    }
+
+    /// Write `contents` sealed to `path` plus [`EXTENSION`], returning the
+    /// path written.
+    pub fn write(&self, path: &Path, contents: &[u8]) -> Result<PathBuf> {
+        let path = sealed_path(path);
+        std::fs::write(&path, self.seal(contents)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Whether `data` is a sealed file.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// `path` with [`EXTENSION`] appended: `results-x.json.enc`.
+pub fn sealed_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(EXTENSION);
+    PathBuf::from(name)
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open_round_trip() {
+        let cipher = Cipher::new(&[7u8; 32]);
+        let sealed = cipher.seal(b"disk /var at 97%").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(4).any(|w| w == b"disk"));
+        assert_ne!(sealed, cipher.seal(b"disk /var at 97%").unwrap());
+        assert_eq!(cipher.open(&sealed).unwrap(), b"disk /var at 97%");
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.open(&tampered).is_err());
+        assert!(Cipher::new(&[8u8; 32]).open(&sealed).is_err());
+        assert!(cipher.open(b"{\"run_id\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_hex_keys_and_sealed_paths() {
+        assert_eq!(decode_hex("0aff"), Some(vec![0x0a, 0xff]));
+        assert_eq!(decode_hex("0af"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(
+            sealed_path(Path::new("outputs/results-1.json")),
+            PathBuf::from("outputs/results-1.json.enc")
+        );
+    }
+}
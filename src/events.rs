@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::agents::AgentResult;
+use crate::cost;
+
+/// One agent completion, as a line of the event log.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AgentEvent {
+    pub timestamp: DateTime<Utc>,
+    pub run_id: String,
+    pub mode: String,
+    pub agent: String,
+    pub status: String,
+    pub client_mode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// As reported by the backend, else estimated from the output length.
+    pub output_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AgentEvent {
+    pub fn from_result(run_id: &str, mode: &str, result: &AgentResult) -> Self {
+        Self {
+            timestamp: result.timestamp,
+            run_id: run_id.to_string(),
+            mode: mode.to_string(),
+            agent: result.agent.clone(),
+            status: result.status.clone(),
+            client_mode: result.client_mode.clone(),
+            model: result.model.clone(),
+            duration_ms: result.duration_ms,
+            output_tokens: result
+                .output_tokens
+                .unwrap_or_else(|| result.output.as_deref().map_or(0, cost::estimate_tokens)),
+            error: result.error.clone(),
+        }
+    }
+}
+
+/// Append-only JSONL log of agent completions across all runs, for log
+/// shippers to tail. Unlike the results files it is never rewritten.
+pub struct EventLog {
+    path: PathBuf,
+    run_id: String,
+    mode: String,
+    lock: Mutex<()>,
+}
+
+impl EventLog {
+    pub fn new(path: impl Into<PathBuf>, run_id: &str, mode: &str) -> Self {
+        Self {
+            path: path.into(),
+            run_id: run_id.to_string(),
+            mode: mode.to_string(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Append an event for `result`. Failures are logged, not fatal.
+    pub fn record(&self, result: &AgentResult) {
+        let event = AgentEvent::from_result(&self.run_id, &self.mode, result);
+        if let Err(e) = self.append(&event) {
+            warn!("Failed to write event log: {:#}", e);
+        }
+    }
+
+    fn append(&self, event: &AgentEvent) -> Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        // One write per line so concurrent tailers never see half an event
+        let line = format!("{}\n", serde_json::to_string(event)?);
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_append_one_line_each() {
+        let path =
+            std::env::temp_dir().join(format!("orchestra-events-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let log = EventLog::new(&path, "auto-1", "auto");
+        log.record(
+            &AgentResult::success("monitor".into(), "x".repeat(400), "api".into())
+                .with_duration(std::time::Duration::from_millis(1500)),
+        );
+        log.record(&AgentResult::failed(
+            "analyzer".into(),
+            "timeout".into(),
+            "api".into(),
+        ));
+
+        let content = fs::read_to_string(&path).unwrap();
+        let events: Vec<AgentEvent> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].run_id, "auto-1");
+        assert_eq!(events[0].duration_ms, Some(1500));
+        assert_eq!(events[0].output_tokens, 100);
+        assert_eq!(events[1].error.as_deref(), Some("timeout"));
+        fs::remove_file(&path).unwrap();
+    }
+}
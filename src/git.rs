@@ -0,0 +1,179 @@
+//! Commits each run's outputs to a git checkout (`outputs.git`), for a
+//! versioned history of agent reports. The results, summary and manifest
+//! keep their per-run names; each agent's report is written to the same
+//! `reports/<agent>.md` every run, so `git log -p` shows how it changed.
+
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Output, Stdio};
+
+use crate::config::GitOutputsConfig;
+
+pub struct GitOutputs {
+    repo: PathBuf,
+    branch: Option<String>,
+    remote: String,
+    push: bool,
+}
+
+impl GitOutputs {
+    pub fn new(config: &GitOutputsConfig) -> Self {
+        Self {
+            repo: PathBuf::from(&config.repo),
+            branch: config.branch.clone(),
+            remote: config.remote.clone(),
+            push: config.push,
+        }
+    }
+
+    /// Write `files`, named relative to `dir` in the repository, and commit
+    /// them with `message`. Returns the new commit, or `None` if the files
+    /// were already there as they are.
+    pub async fn commit(
+        &self,
+        dir: &str,
+        files: &[(String, Vec<u8>)],
+        message: &str,
+    ) -> Result<Option<String>> {
+        if !Path::new(dir)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            anyhow::bail!("outputs.git.path {:?} must stay inside the repository", dir);
+        }
+        if let Some(branch) = &self.branch {
+            self.switch(branch).await?;
+        }
+        let base = self.repo.join(dir);
+        for (name, body) in files {
+            let path = base.join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            std::fs::write(&path, body)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        let dir = if dir.is_empty() { "." } else { dir };
+        self.git(&["add", "--", dir]).await?;
+        if self
+            .run(&["diff", "--cached", "--quiet", "--", dir])
+            .await?
+            .status
+            .success()
+        {
+            return Ok(None);
+        }
+        // Only the outputs, whatever else is staged
+        self.git(&["commit", "--quiet", "-m", message, "--", dir])
+            .await?;
+        let sha = self.git(&["rev-parse", "HEAD"]).await?;
+        if self.push {
+            let refspec = match &self.branch {
+                Some(branch) => format!("HEAD:refs/heads/{}", branch),
+                None => "HEAD".to_string(),
+            };
+            self.git(&["push", "--quiet", &self.remote, &refspec])
+                .await
+                .with_context(|| format!("Committed {} but failed to push it", sha))?;
+        }
+        Ok(Some(sha))
+    }
+
+    /// Check out `branch`: the local one, else one tracking a remote branch
+    /// of that name, else a new one from the current commit.
+    async fn switch(&self, branch: &str) -> Result<()> {
+        if self.git(&["branch", "--show-current"]).await? == branch {
+            return Ok(());
+        }
+        let local = format!("refs/heads/{}", branch);
+        let exists = self
+            .run(&["rev-parse", "--verify", "--quiet", &local])
+            .await?
+            .status
+            .success();
+        if exists {
+            return self.git(&["switch", "--quiet", branch]).await.map(drop);
+        }
+        // Given only a branch name, git sets up one tracking the remote's
+        if self.git(&["switch", "--quiet", branch]).await.is_ok() {
+            return Ok(());
+        }
+        self.git(&["switch", "--quiet", "-c", branch])
+            .await
+            .map(drop)
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<Output> {
+        tokio::process::Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Failed to run git")
+    }
+
+    /// Run git and return what it printed, failing with its error output.
+    async fn git(&self, args: &[&str]) -> Result<String> {
+        let output = self.run(args).await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git {} in {} failed: {}",
+                args.first().unwrap_or(&""),
+                self.repo.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_commits_outputs_on_branch() {
+        let repo = std::env::temp_dir().join(format!("orchestra-git-{}", std::process::id()));
+        std::fs::create_dir_all(&repo).unwrap();
+        let config = GitOutputsConfig {
+            repo: repo.to_string_lossy().into_owned(),
+            branch: Some("reports".to_string()),
+            path: String::new(),
+            message: String::new(),
+            push: false,
+            remote: "origin".to_string(),
+        };
+        let outputs = GitOutputs::new(&config);
+        for args in [
+            &["init", "--quiet"][..],
+            &["config", "user.name", "Orchestra"],
+            &["config", "user.email", "orchestra@example.com"],
+            &["commit", "--quiet", "--allow-empty", "-m", "init"],
+        ] {
+            outputs.git(args).await.unwrap();
+        }
+
+        let files = vec![
+            ("summary-1.txt".to_string(), b"1 ok".to_vec()),
+            ("reports/monitor.md".to_string(), b"All healthy".to_vec()),
+        ];
+        let first = outputs.commit("auto", &files, "auto run 1").await.unwrap();
+        assert!(first.is_some());
+        assert_eq!(
+            outputs.git(&["branch", "--show-current"]).await.unwrap(),
+            "reports"
+        );
+        assert_eq!(
+            outputs.git(&["log", "-1", "--format=%s"]).await.unwrap(),
+            "auto run 1"
+        );
+        assert!(repo.join("auto/reports/monitor.md").is_file());
+        // Nothing new to commit
+        assert_eq!(outputs.commit("auto", &files, "again").await.unwrap(), None);
+        assert!(outputs.commit("../elsewhere", &files, "x").await.is_err());
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+}
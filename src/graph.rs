@@ -0,0 +1,238 @@
+use anyhow::Result;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::agents::AgentTask;
+
+/// Output syntax for `plan --graph`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "dot" | "graphviz" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            _ => anyhow::bail!("Unknown graph format '{}'. Use: dot or mermaid", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub id: String,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub label: Option<String>,
+    /// Only taken when the label's condition holds (fallbacks, follow-ups).
+    pub conditional: bool,
+}
+
+/// The resolved execution graph of one run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Graph {
+    fn node(&mut self, id: impl Into<String>, label: impl Into<String>) {
+        self.nodes.push(Node {
+            id: id.into(),
+            label: label.into(),
+        });
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: Option<&str>, conditional: bool) {
+        self.edges.push(Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            label: label.map(str::to_string),
+            conditional,
+        });
+    }
+
+    /// Build the graph for `tasks` as `run` would execute them: a chain when
+    /// sequential, a fan-out/fan-in when parallel. Model fallbacks, hybrid CLI
+    /// fallback and the action queue appear as conditional edges.
+    pub fn build(
+        mode: &str,
+        tasks: &[AgentTask],
+        global_mode: &str,
+        parallel: bool,
+        actions: bool,
+    ) -> Self {
+        let mut g = Graph::default();
+        g.node("start", format!("run: {}", mode));
+        g.node("results", "results");
+
+        let mut prev = "start".to_string();
+        for task in tasks {
+            let id = node_id(&task.name);
+            let client_mode = task.client_mode.as_deref().unwrap_or(global_mode);
+            let mut label = format!("{}\n{}", task.name, client_mode);
+            if let Some(model) = &task.options.model {
+                let _ = write!(label, " · {}", model);
+            }
+            g.node(&id, label);
+
+            if parallel {
+                g.edge("start", &id, None, false);
+                g.edge(&id, "results", None, false);
+            } else {
+                g.edge(&prev, &id, None, false);
+            }
+
+            let mut from = id.clone();
+            for (i, model) in task.options.fallback_models.iter().enumerate() {
+                let fallback = format!("{}_fallback_{}", id, i + 1);
+                g.node(&fallback, format!("{}\n{}", task.name, model));
+                g.edge(&from, &fallback, Some("model unavailable"), true);
+                if parallel {
+                    g.edge(&fallback, "results", None, false);
+                }
+                from = fallback;
+            }
+            if client_mode == "hybrid" {
+                let cli = format!("{}_cli", id);
+                g.node(&cli, format!("{}\nclaude-code", task.name));
+                g.edge(&id, &cli, Some("API failure"), true);
+                if parallel {
+                    g.edge(&cli, "results", None, false);
+                }
+            }
+            prev = id;
+        }
+        if !parallel {
+            g.edge(&prev, "results", None, false);
+        }
+        if actions {
+            g.node("actions", "action queue");
+            g.edge("results", "actions", Some("actions proposed"), true);
+        }
+        g
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph orchestra {\n  rankdir=LR;\n  node [shape=box];\n");
+        for n in &self.nodes {
+            let _ = writeln!(out, "  {} [label=\"{}\"];", n.id, escape(&n.label, "\\n"));
+        }
+        for e in &self.edges {
+            let mut attrs = Vec::new();
+            if let Some(label) = &e.label {
+                attrs.push(format!("label=\"{}\"", escape(label, " ")));
+            }
+            if e.conditional {
+                attrs.push("style=dashed".to_string());
+            }
+            let attrs = if attrs.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attrs.join(", "))
+            };
+            let _ = writeln!(out, "  {} -> {}{};", e.from, e.to, attrs);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for n in &self.nodes {
+            let label = n.label.replace('"', "#quot;").replace('\n', "<br/>");
+            let _ = writeln!(out, "  {}[\"{}\"]", n.id, label);
+        }
+        for e in &self.edges {
+            let arrow = if e.conditional { "-.->" } else { "-->" };
+            match &e.label {
+                Some(label) => {
+                    let _ = writeln!(out, "  {} {}|{}| {}", e.from, arrow, label, e.to);
+                }
+                None => {
+                    let _ = writeln!(out, "  {} {} {}", e.from, arrow, e.to);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Node IDs must be bare identifiers in both DOT and Mermaid.
+fn node_id(name: &str) -> String {
+    let id: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("agent_{}", id)
+}
+
+fn escape(label: &str, newline: &str) -> String {
+    label.replace('"', "\\\"").replace('\n', newline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(name: &str, mode: Option<&str>, fallbacks: &[&str]) -> AgentTask {
+        let mut t = AgentTask::new(name, "p", 60).with_client_mode(mode.map(str::to_string));
+        t.options.fallback_models = fallbacks.iter().map(|s| s.to_string()).collect();
+        t
+    }
+
+    #[test]
+    fn test_sequential_chain_with_conditional_edges() {
+        let tasks = [
+            task("monitor", None, &["claude-haiku-4-5"]),
+            task("analyzer", Some("hybrid"), &[]),
+        ];
+        let g = Graph::build("auto", &tasks, "api", false, true);
+        let plain: Vec<(&str, &str)> = g
+            .edges
+            .iter()
+            .filter(|e| !e.conditional)
+            .map(|e| (e.from.as_str(), e.to.as_str()))
+            .collect();
+        assert_eq!(
+            plain,
+            vec![
+                ("start", "agent_monitor"),
+                ("agent_monitor", "agent_analyzer"),
+                ("agent_analyzer", "results"),
+            ]
+        );
+        assert_eq!(g.edges.iter().filter(|e| e.conditional).count(), 3);
+    }
+
+    #[test]
+    fn test_parallel_fan_out_renders() {
+        let tasks = [task("monitor", None, &[]), task("data analyst", None, &[])];
+        let g = Graph::build("auto", &tasks, "claude-code", true, false);
+        let dot = g.render(GraphFormat::Dot);
+        assert!(dot.contains("start -> agent_data_analyst;"));
+        assert!(dot.contains("agent_data_analyst -> results;"));
+        assert!(dot.contains("label=\"data analyst\\nclaude-code\""));
+        let mermaid = g.render(GraphFormat::Mermaid);
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("  start --> agent_monitor\n"));
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! The watchdog behind `features.health_monitoring`. While a CLI or exec
+//! agent's process runs, its output is counted as it arrives and a heartbeat
+//! (alive, bytes written, seconds since the last output) is logged every
+//! `health.heartbeat_seconds`. A process that writes nothing for
+//! `health.stall_seconds` is killed instead of holding its slot until the
+//! agent's timeout.
+//!
+//! Events go to the [`HEALTH_TARGET`] log target, inside the agent's span,
+//! so they also land in the agent's own log file.
+
+use std::io;
+use std::process::Output;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Child;
+use tracing::{debug, warn};
+
+use crate::client::kill_process_group;
+use crate::config::HealthConfig;
+
+/// Log target of heartbeats and stall warnings, for filtering them in or
+/// out with `RUST_LOG`.
+pub const HEALTH_TARGET: &str = "agent_orchestra::health";
+
+/// Output seen so far from one process.
+struct Activity {
+    bytes: u64,
+    last_output: Instant,
+}
+
+impl Activity {
+    fn record(state: &Mutex<Activity>, n: usize) {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.bytes += n as u64;
+        state.last_output = Instant::now();
+    }
+}
+
+/// Given each line of a process's stdout, without the newline, as it arrives.
+pub(crate) type OnLine<'a> = &'a (dyn Fn(&[u8]) + Sync);
+
+/// Collect `child`'s output and exit status like
+/// [`wait_with_output`](Child::wait_with_output), logging heartbeats as it
+/// runs. Fails with [`io::ErrorKind::TimedOut`] if it stalls; dropping the
+/// child then kills it. The caller kills the rest of its process group.
+pub(crate) async fn watch(
+    mut child: Child,
+    config: &HealthConfig,
+    on_line: Option<OnLine<'_>>,
+) -> io::Result<Output> {
+    let pid = child.id().unwrap_or(0);
+    let stall = Duration::from_secs(config.stall_seconds);
+    // Stalls are only noticed on a tick, so tick at least that often
+    let interval = Duration::from_secs(config.heartbeat_seconds)
+        .min(stall)
+        .max(Duration::from_secs(1));
+    let activity = Mutex::new(Activity {
+        bytes: 0,
+        last_output: Instant::now(),
+    });
+
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let reading = async {
+        tokio::try_join!(
+            drain(stdout, &activity, on_line),
+            drain(stderr, &activity, None)
+        )
+    };
+    let waiting = child.wait();
+    tokio::pin!(reading, waiting);
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    let mut status = None;
+    let mut exited_at = None;
+
+    loop {
+        tokio::select! {
+            exited = &mut waiting, if status.is_none() => {
+                status = Some(exited?);
+                exited_at = Some(Instant::now());
+            }
+            read = &mut reading => {
+                let (stdout, stderr) = read?;
+                let status = match status {
+                    Some(status) => status,
+                    None => waiting.await?,
+                };
+                return Ok(Output { status, stdout, stderr });
+            }
+            _ = ticks.tick() => {
+                let (bytes, idle) = {
+                    let state = activity.lock().unwrap_or_else(|e| e.into_inner());
+                    (state.bytes, state.last_output.elapsed())
+                };
+                debug!(
+                    target: HEALTH_TARGET,
+                    pid,
+                    alive = status.is_none(),
+                    bytes,
+                    idle_seconds = idle.as_secs(),
+                    "heartbeat"
+                );
+                if exited_at.is_some_and(|at| at.elapsed() >= interval) {
+                    // Something it started still holds its output open
+                    warn!(
+                        target: HEALTH_TARGET,
+                        pid,
+                        "Process exited, but its children are still running; killing them"
+                    );
+                    kill_process_group(pid);
+                } else if status.is_none() && idle >= stall {
+                    warn!(
+                        target: HEALTH_TARGET,
+                        pid,
+                        bytes,
+                        idle_seconds = idle.as_secs(),
+                        "Process stalled; killing it"
+                    );
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("stalled: no output for {}s", idle.as_secs()),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Like [`watch`] without the watchdog: collect `child`'s output and exit
+/// status, handing each line of stdout to `on_line` as it arrives.
+pub(crate) async fn collect(mut child: Child, on_line: OnLine<'_>) -> io::Result<Output> {
+    let activity = Mutex::new(Activity {
+        bytes: 0,
+        last_output: Instant::now(),
+    });
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let (stdout, stderr, status) = tokio::try_join!(
+        drain(stdout, &activity, Some(on_line)),
+        drain(stderr, &activity, None),
+        child.wait()
+    )?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Read `pipe` to the end, recording each chunk in `activity` and handing
+/// each complete line to `on_line`.
+async fn drain(
+    pipe: Option<impl AsyncRead + Unpin>,
+    activity: &Mutex<Activity>,
+    on_line: Option<OnLine<'_>>,
+) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let Some(mut pipe) = pipe else {
+        return Ok(out);
+    };
+    let mut buf = [0u8; 8192];
+    let mut line_start = 0;
+    loop {
+        let n = pipe.read(&mut buf).await?;
+        if n == 0 {
+            if let Some(on_line) = on_line.filter(|_| line_start < out.len()) {
+                on_line(&out[line_start..]);
+            }
+            return Ok(out);
+        }
+        out.extend_from_slice(&buf[..n]);
+        Activity::record(activity, n);
+        if let Some(on_line) = on_line {
+            while let Some(end) = out[line_start..].iter().position(|b| *b == b'\n') {
+                on_line(&out[line_start..line_start + end]);
+                line_start += end + 1;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+
+    fn spawn(script: &str) -> Child {
+        tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap()
+    }
+
+    fn config(stall_seconds: u64) -> HealthConfig {
+        HealthConfig {
+            stall_seconds,
+            heartbeat_seconds: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch_collects_output_of_a_healthy_process() {
+        let child = spawn("echo one; sleep 1.5; echo two; echo oops >&2");
+        let output = watch(child, &config(3), None).await.unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"one\ntwo\n");
+        assert_eq!(output.stderr, b"oops\n");
+    }
+
+    #[tokio::test]
+    async fn test_collect_hands_over_each_line() {
+        let lines = Mutex::new(Vec::new());
+        let on_line = |line: &[u8]| lines.lock().unwrap().push(line.to_vec());
+        let child = spawn("echo one; sleep 0.2; printf 'two\\nthree'");
+        let output = collect(child, &on_line).await.unwrap();
+        assert_eq!(output.stdout, b"one\ntwo\nthree");
+        assert_eq!(
+            lines.into_inner().unwrap(),
+            [b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_kills_a_stalled_process() {
+        let started = Instant::now();
+        let err = watch(spawn("echo start; exec sleep 30"), &config(1), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,250 @@
+//! Shell hooks run around a run and around each agent. Every command runs
+//! with `sh -c` and sees the run through `ORCHESTRA_*` variables; post hooks
+//! also get the result JSON on stdin.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
+
+use crate::agents::AgentResult;
+use crate::client::kill_process_group;
+use crate::config::{AgentHooks, HooksConfig};
+use crate::OrchestrationResult;
+
+const DEFAULT_TIMEOUT_SECONDS: u64 = 60;
+
+/// Runs the configured hooks for one run.
+pub struct HookRunner {
+    config: HooksConfig,
+    run_id: String,
+    mode: String,
+}
+
+impl HookRunner {
+    pub fn new(config: &HooksConfig, run_id: &str, mode: &str) -> Self {
+        Self {
+            config: config.clone(),
+            run_id: run_id.to_string(),
+            mode: mode.to_string(),
+        }
+    }
+
+    /// `hooks.pre_run`; the first command that fails aborts the run.
+    pub async fn pre_run(&self) -> Result<()> {
+        self.run_all("pre_run", self.config.pre_run.iter(), &[], "")
+            .await
+    }
+
+    /// `hooks.post_run`, with the results JSON on stdin and the path of the
+    /// results file in `ORCHESTRA_RESULTS_FILE`. Failures are only logged.
+    pub async fn post_run(&self, orchestration: &OrchestrationResult, results_file: &Path) {
+        if self.config.post_run.is_empty() {
+            return;
+        }
+        let json = serde_json::to_string(orchestration).unwrap_or_default();
+        let env = [("ORCHESTRA_RESULTS_FILE", results_file.display().to_string())];
+        if let Err(e) = self
+            .run_all("post_run", self.config.post_run.iter(), &env, &json)
+            .await
+        {
+            warn!("{:#}", e);
+        }
+    }
+
+    /// `hooks.pre_agent`, then the agent's own `hooks.pre`; the first
+    /// command that fails fails the agent.
+    pub async fn pre_agent(&self, agent: &str, own: &AgentHooks) -> Result<()> {
+        let commands = self.config.pre_agent.iter().chain(&own.pre);
+        let env = [("ORCHESTRA_AGENT", agent.to_string())];
+        self.run_all("pre_agent", commands, &env, "").await
+    }
+
+    /// `hooks.post_agent`, then the agent's own `hooks.post`, with the
+    /// result JSON on stdin. Failures are only logged.
+    pub async fn post_agent(&self, result: &AgentResult, own: &AgentHooks) {
+        if self.config.post_agent.is_empty() && own.post.is_empty() {
+            return;
+        }
+        let commands = self.config.post_agent.iter().chain(&own.post);
+        let json = serde_json::to_string(result).unwrap_or_default();
+        let env = [
+            ("ORCHESTRA_AGENT", result.agent.clone()),
+            ("ORCHESTRA_STATUS", result.status.clone()),
+        ];
+        if let Err(e) = self.run_all("post_agent", commands, &env, &json).await {
+            warn!("Agent {}: {:#}", result.agent, e);
+        }
+    }
+
+    /// Run `commands` in order, stopping at the first that fails.
+    async fn run_all<'a>(
+        &self,
+        hook: &str,
+        commands: impl Iterator<Item = &'a String>,
+        env: &[(&str, String)],
+        stdin: &str,
+    ) -> Result<()> {
+        let timeout = Duration::from_secs(
+            self.config
+                .timeout_seconds
+                .unwrap_or(DEFAULT_TIMEOUT_SECONDS),
+        );
+        let mut env = env.to_vec();
+        env.extend([
+            ("ORCHESTRA_HOOK", hook.to_string()),
+            ("ORCHESTRA_RUN_ID", self.run_id.clone()),
+            ("ORCHESTRA_MODE", self.mode.clone()),
+        ]);
+        for command in commands {
+            info!("Running {} hook: {}", hook, command);
+            run_command(command, &env, stdin, timeout)
+                .await
+                .with_context(|| format!("{} hook `{}` failed", hook, command))?;
+        }
+        Ok(())
+    }
+}
+
+async fn run_command(
+    command: &str,
+    env: &[(&str, String)],
+    stdin: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let mut cmd = tokio::process::Command::new("sh");
+    // Its own process group, so a timeout also stops what the hook started
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let mut child = cmd
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let pid = child.id();
+    let mut pipe = child.stdin.take().context("no stdin")?;
+    let input = stdin.as_bytes().to_vec();
+    // A hook that never reads stdin closes the pipe; that's not an error
+    let write = async move {
+        let _ = pipe.write_all(&input).await;
+    };
+    let Ok((_, output)) = tokio::time::timeout(timeout, async {
+        tokio::join!(write, child.wait_with_output())
+    })
+    .await
+    else {
+        if let Some(pid) = pid {
+            kill_process_group(pid);
+        }
+        anyhow::bail!("timed out");
+    };
+    let output = output?;
+    debug!("{}", String::from_utf8_lossy(&output.stdout).trim_end());
+    if !output.status.success() {
+        anyhow::bail!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_post_agent_gets_result_on_stdin() {
+        let dir = std::env::temp_dir().join(format!("orchestra-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("post.txt");
+        let config = HooksConfig {
+            post_agent: vec![format!(
+                "echo \"$ORCHESTRA_HOOK $ORCHESTRA_RUN_ID $ORCHESTRA_AGENT $ORCHESTRA_STATUS\" > {0}; cat >> {0}",
+                out.display()
+            )],
+            ..HooksConfig::default()
+        };
+        let hooks = HookRunner::new(&config, "auto-1", "analysis");
+        let result = AgentResult::success(
+            "monitor".to_string(),
+            "All good".to_string(),
+            "mock".to_string(),
+        );
+        hooks.post_agent(&result, &AgentHooks::default()).await;
+
+        let written = std::fs::read_to_string(&out).unwrap();
+        let (env, json) = written.split_once('\n').unwrap();
+        assert_eq!(env, "post_agent auto-1 monitor success");
+        let parsed: AgentResult = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.output.as_deref(), Some("All good"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pre_agent_stops_at_first_failure() {
+        let dir = std::env::temp_dir().join(format!("orchestra-hooks-stop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let marker = dir.join("ran");
+        let config = HooksConfig {
+            pre_agent: vec!["echo nope >&2; exit 3".to_string()],
+            timeout_seconds: Some(5),
+            ..HooksConfig::default()
+        };
+        let hooks = HookRunner::new(&config, "auto-1", "analysis");
+        let own = AgentHooks {
+            pre: vec![format!("touch {}", marker.display())],
+            post: Vec::new(),
+        };
+        let err = hooks.pre_agent("monitor", &own).await.unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("pre_agent hook `echo nope >&2; exit 3` failed"));
+        assert!(message.ends_with("exit status: 3: nope"));
+        assert!(!marker.exists());
+
+        // The same hook does run when the one before it succeeds
+        let hooks = HookRunner::new(&HooksConfig::default(), "auto-1", "analysis");
+        hooks.pre_agent("monitor", &own).await.unwrap();
+        assert!(marker.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_hook_timeout_kills_what_it_started() {
+        let dir = std::env::temp_dir().join(format!("orchestra-hooks-kill-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pid_file = dir.join("pid");
+        let config = HooksConfig {
+            pre_run: vec![format!("sleep 30 & echo $! > {}; wait", pid_file.display())],
+            timeout_seconds: Some(1),
+            ..HooksConfig::default()
+        };
+        let err = HookRunner::new(&config, "auto-1", "analysis")
+            .pre_run()
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("failed: timed out"));
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let stat = format!("/proc/{}/stat", pid.trim());
+        let mut alive = true;
+        for _ in 0..50 {
+            // Gone, or a zombie nobody has reaped yet
+            alive = std::fs::read_to_string(&stat).is_ok_and(|s| !s.contains(") Z "));
+            if !alive {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!alive, "sleep {} outlived its hook", pid.trim());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
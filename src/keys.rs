@@ -0,0 +1,246 @@
+//! A pool of Anthropic API keys. Each API client takes a key from the pool
+//! when it's created, so parallel agents spread their load across keys. A
+//! key the API rejects (401) is retired, and a key that gets throttled
+//! (429) is avoided for a while. Either way the request is retried with
+//! another key, so one revoked key doesn't fail the whole orchestra.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::config::{ApiKeysConfig, KeyStrategy};
+use crate::secrets;
+
+/// Variable holding extra keys, comma-separated.
+pub const KEYS_ENV: &str = "ANTHROPIC_API_KEYS";
+
+/// The keys in `ANTHROPIC_API_KEY`, [`KEYS_ENV`] and each variable named
+/// in `client.api_keys.env`, in that order, each from `secrets:` if it has
+/// a source there. Unset variables are skipped.
+pub fn env_keys(config: &ApiKeysConfig) -> Vec<String> {
+    let var = |name: &str| secrets::var(name).unwrap_or_default();
+    let mut keys = vec![var("ANTHROPIC_API_KEY")];
+    keys.extend(var(KEYS_ENV).split(',').map(String::from));
+    keys.extend(config.env.iter().map(|name| var(name)));
+    keys.retain(|key| !key.trim().is_empty());
+    keys
+}
+
+/// How long a throttled key is passed over when another one is free.
+pub const THROTTLE_BACKOFF: Duration = Duration::from_secs(60);
+
+struct PooledKey {
+    secret: String,
+    revoked: AtomicBool,
+    throttled_at: Mutex<Option<Instant>>,
+}
+
+impl PooledKey {
+    fn throttled_at(&self) -> Option<Instant> {
+        *self.throttled_at.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_throttled(&self, now: Instant) -> bool {
+        self.throttled_at()
+            .is_some_and(|at| now.saturating_duration_since(at) < THROTTLE_BACKOFF)
+    }
+
+    fn is_revoked(&self) -> bool {
+        self.revoked.load(Ordering::Relaxed)
+    }
+}
+
+pub struct KeyPool {
+    keys: Vec<PooledKey>,
+    strategy: KeyStrategy,
+    next: AtomicUsize,
+}
+
+/// One key from a [`KeyPool`], which it reports back to.
+#[derive(Clone)]
+pub struct ApiKey {
+    pool: Arc<KeyPool>,
+    index: usize,
+}
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ApiKey({})", self.label())
+    }
+}
+
+impl KeyPool {
+    /// A pool of `keys`, without blanks or repeats, or `None` if that
+    /// leaves none.
+    pub fn new(keys: impl IntoIterator<Item = String>, strategy: KeyStrategy) -> Option<Arc<Self>> {
+        let mut secrets: Vec<String> = Vec::new();
+        for key in keys {
+            let key = key.trim().to_string();
+            if !key.is_empty() && !secrets.contains(&key) {
+                secrets.push(key);
+            }
+        }
+        (!secrets.is_empty()).then(|| Self::with_secrets(secrets, strategy))
+    }
+
+    /// A pool of just `key`.
+    pub fn single(key: impl Into<String>) -> Arc<Self> {
+        Self::with_secrets(vec![key.into()], KeyStrategy::default())
+    }
+
+    fn with_secrets(secrets: Vec<String>, strategy: KeyStrategy) -> Arc<Self> {
+        Arc::new(Self {
+            keys: secrets
+                .into_iter()
+                .map(|secret| PooledKey {
+                    secret,
+                    revoked: AtomicBool::new(false),
+                    throttled_at: Mutex::new(None),
+                })
+                .collect(),
+            strategy,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The key for a new client. Retired keys are skipped unless every key
+    /// is retired, in which case requests fail as they would with one key.
+    pub fn pick(self: &Arc<Self>) -> ApiKey {
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % self.keys.len();
+        let now = Instant::now();
+        let live: Vec<usize> = self
+            .order(start)
+            .filter(|&i| !self.keys[i].is_revoked())
+            .collect();
+        let chosen = match self.strategy {
+            KeyStrategy::RoundRobin => live
+                .iter()
+                .find(|&&i| !self.keys[i].is_throttled(now))
+                .or(live.first()),
+            // `None` (never throttled) sorts first; ties go round-robin
+            KeyStrategy::LeastRecentlyThrottled => {
+                live.iter().min_by_key(|&&i| self.keys[i].throttled_at())
+            }
+        };
+        self.key(chosen.copied().unwrap_or(start))
+    }
+
+    /// Another key to retry with after `tried` failed: one that is neither
+    /// retired nor throttled.
+    pub fn replacement(self: &Arc<Self>, tried: &[usize]) -> Option<ApiKey> {
+        let now = Instant::now();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % self.keys.len();
+        self.order(start)
+            .find(|i| {
+                !tried.contains(i)
+                    && !self.keys[*i].is_revoked()
+                    && !self.keys[*i].is_throttled(now)
+            })
+            .map(|i| self.key(i))
+    }
+
+    fn order(&self, start: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.keys.len()).map(move |i| (start + i) % self.keys.len())
+    }
+
+    fn key(self: &Arc<Self>, index: usize) -> ApiKey {
+        ApiKey {
+            pool: Arc::clone(self),
+            index,
+        }
+    }
+}
+
+impl ApiKey {
+    pub fn secret(&self) -> &str {
+        &self.pool.keys[self.index].secret
+    }
+
+    /// Position in the pool, for [`KeyPool::replacement`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// `key 2/3`, for logs; never the key itself.
+    pub fn label(&self) -> String {
+        format!("key {}/{}", self.index + 1, self.pool.len())
+    }
+
+    pub fn pool(&self) -> &Arc<KeyPool> {
+        &self.pool
+    }
+
+    /// Note the API's answer: a 401 retires the key and a 429 marks it
+    /// throttled. Returns whether another key might do better.
+    pub fn report(&self, status: u16) -> bool {
+        let key = &self.pool.keys[self.index];
+        match status {
+            401 => {
+                if !key.revoked.swap(true, Ordering::Relaxed) {
+                    warn!("API rejected {}; no longer using it", self.label());
+                }
+                true
+            }
+            429 => {
+                *key.throttled_at.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(strategy: KeyStrategy) -> Arc<KeyPool> {
+        KeyPool::new(["a", "b", " ", "a", "c"].map(String::from), strategy).unwrap()
+    }
+
+    #[test]
+    fn test_pool_rotates_and_skips_retired_keys() {
+        let keys = pool(KeyStrategy::RoundRobin);
+        assert_eq!(keys.len(), 3);
+        let picked: Vec<String> = (0..4).map(|_| keys.pick().secret().to_string()).collect();
+        assert_eq!(picked, ["a", "b", "c", "a"]);
+
+        let b = keys.pick();
+        assert_eq!(b.secret(), "b");
+        assert!(b.report(401));
+        let picked: Vec<String> = (0..3).map(|_| keys.pick().secret().to_string()).collect();
+        assert_eq!(picked, ["c", "a", "c"]);
+
+        let retry = keys.replacement(&[b.index()]).unwrap();
+        assert_ne!(retry.secret(), "b");
+        assert!(KeyPool::new([" ".to_string()], KeyStrategy::RoundRobin).is_none());
+    }
+
+    #[test]
+    fn test_least_recently_throttled_prefers_fresh_keys() {
+        let keys = pool(KeyStrategy::LeastRecentlyThrottled);
+        let first = keys.pick();
+        assert_eq!(first.secret(), "a");
+        first.report(429);
+        let picked: Vec<String> = (0..3).map(|_| keys.pick().secret().to_string()).collect();
+        assert!(!picked.contains(&"a".to_string()));
+
+        // With every key throttled, the one throttled longest ago wins
+        for secret in ["b", "c"] {
+            let key = keys.pick();
+            assert_eq!(key.secret(), secret);
+            key.report(429);
+        }
+        assert_eq!(keys.pick().secret(), "a");
+        assert!(keys.replacement(&[]).is_none());
+    }
+}
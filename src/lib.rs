@@ -0,0 +1,52 @@
+//! Agent Orchestra: runs groups of Claude agents over the API, the Claude
+//! Code CLI or Agent Teams, and records what they produced.
+//!
+//! The `agent-orchestra` binary is a thin CLI over this crate; other services
+//! can embed the engine through [`Orchestrator::builder`].
+
+pub mod actions;
+pub mod agent_log;
+pub mod agents;
+pub mod approval;
+pub mod bench;
+pub mod breaker;
+pub mod budget;
+pub mod checkpoint;
+pub mod client;
+pub mod config;
+pub mod consensus;
+pub mod context;
+pub mod cost;
+pub mod diff;
+pub mod distributed;
+pub mod egress;
+pub mod encryption;
+pub mod events;
+pub mod git;
+pub mod graph;
+pub mod health;
+pub mod hooks;
+pub mod keys;
+pub mod lock;
+pub mod manifest;
+pub mod map;
+mod orchestrator;
+pub mod progress;
+pub mod queue;
+pub mod remote;
+pub mod replay;
+pub mod review;
+pub mod secrets;
+pub mod selftest;
+pub mod shutdown;
+pub mod status;
+pub mod store;
+pub mod structured;
+pub mod teams;
+pub mod template;
+pub mod ulid;
+pub mod validate;
+pub mod webhook;
+pub mod workers;
+
+pub use orchestrator::{OrchestrationResult, Orchestrator, OrchestratorBuilder};
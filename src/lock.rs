@@ -0,0 +1,115 @@
+//! The run lock: an advisory lock on `<output dir>/.orchestra.lock` held for
+//! the whole of a run or rerun, so a cron-started run can't interleave with
+//! one still going over the same outputs and CLI sessions. The lock goes
+//! with the process, so a crashed run never leaves it stuck.
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Lock file name inside the output directory.
+pub const LOCK_FILE: &str = ".orchestra.lock";
+
+/// How often a waiting run tries the lock again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Held until dropped.
+#[derive(Debug)]
+pub struct RunLock {
+    // Closing the file releases the lock
+    _file: File,
+}
+
+impl RunLock {
+    /// Lock `dir` for `run_id`. If another run holds it, wait up to `wait`
+    /// for that run to finish, then give up naming it.
+    pub async fn acquire(dir: &Path, run_id: &str, wait: Duration) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(LOCK_FILE);
+        let deadline = Instant::now() + wait;
+        let mut waiting = false;
+        loop {
+            if let Some(lock) = Self::try_acquire(&path, run_id)? {
+                return Ok(lock);
+            }
+            let holder = holder(&path);
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "Another run is in progress ({}); it releases {} when it exits",
+                    holder,
+                    path.display()
+                );
+            }
+            if !waiting {
+                info!("Waiting for {} to finish before starting", holder);
+                waiting = true;
+            }
+            tokio::time::sleep(RETRY_INTERVAL.min(deadline - Instant::now())).await;
+        }
+    }
+
+    fn try_acquire(path: &Path, run_id: &str) -> Result<Option<Self>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+            }
+        }
+        // Who holds it, for anyone who finds it locked
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "run {} (pid {})", run_id, std::process::id())?;
+        Ok(Some(Self { _file: file }))
+    }
+}
+
+/// What the lock file says about the run holding it.
+fn holder(path: &Path) -> String {
+    let mut holder = String::new();
+    let _ = File::open(path).and_then(|mut f| f.read_to_string(&mut holder));
+    match holder.trim() {
+        "" => "another process".to_string(),
+        holder => holder.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_second_run_waits_then_gives_up() {
+        let dir = std::env::temp_dir().join(format!("orchestra-lock-{}", std::process::id()));
+        let first = RunLock::acquire(&dir, "auto-1", Duration::ZERO)
+            .await
+            .unwrap();
+        let started = Instant::now();
+        let err = RunLock::acquire(&dir, "auto-2", Duration::from_millis(1500))
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(1500));
+        assert!(format!("{:#}", err).contains("run auto-1"), "{:#}", err);
+
+        // A waiting run starts as soon as the first one is done
+        let dir2 = dir.clone();
+        let waiter = tokio::spawn(async move {
+            RunLock::acquire(&dir2, "auto-3", Duration::from_secs(10)).await
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        drop(first);
+        assert!(waiter.await.unwrap().is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
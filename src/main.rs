@@ -1,422 +1,644 @@
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
 use std::env;
-use std::fs;
-use std::path::PathBuf;
-use tracing::{error, info, warn};
-
-mod agents;
-mod client;
-mod config;
-
-use agents::{AgentResult, AgentTask};
-use client::{create_agent_client, create_client, AgentClient, ClientMode};
-use config::Config;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct OrchestrationResult {
-    timestamp: DateTime<Utc>,
-    mode: String,
-    global_client_mode: String,
-    results: Vec<AgentResult>,
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{error, warn};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+mod cli;
+mod daemon;
+mod tui;
+mod watch;
+
+use agent_orchestra::actions::{ActionQueue, ActionStatus};
+use agent_orchestra::agent_log::AgentLogLayer;
+use agent_orchestra::approval::{ApprovalQueue, TerminalApprover};
+use agent_orchestra::client::{create_client, ClientMode};
+use agent_orchestra::config::{Config, LoggingConfig};
+use agent_orchestra::diff;
+use agent_orchestra::distributed;
+use agent_orchestra::encryption::Cipher;
+use agent_orchestra::keys::{self, KeyPool};
+use agent_orchestra::progress::LiveProgress;
+use agent_orchestra::replay::Recording;
+use agent_orchestra::secrets;
+use agent_orchestra::shutdown::Shutdown;
+use agent_orchestra::store::{self, RunStore};
+use agent_orchestra::ulid;
+use agent_orchestra::validate::{self, Severity};
+use agent_orchestra::{OrchestrationResult, Orchestrator, OrchestratorBuilder};
+use cli::{ActionsCommand, AgentsCommand, ApprovalsCommand, Args, Command, HistoryCommand};
+
+const CONFIG_PATH: &str = "config/orchestra.yml";
+
+/// Read everything piped on stdin, refusing to block on an interactive terminal.
+fn read_stdin() -> Result<String> {
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        anyhow::bail!("--stdin-var expects input piped on stdin");
+    }
+    let mut input = String::new();
+    stdin
+        .read_to_string(&mut input)
+        .context("Failed to read stdin")?;
+    Ok(input.trim_end_matches(['\n', '\r']).to_string())
 }
 
-pub struct Orchestrator {
-    global_mode: ClientMode,
-    api_key: Option<String>,
+/// Build an orchestrator from the CLI arguments and environment
+/// (`CLIENT_MODE`, `ANTHROPIC_API_KEY`, `ORCHESTRATOR_MODE`, `.env`).
+fn build_orchestrator(
+    args: &Args,
     config: Config,
-    mode: String,
-    timestamp: DateTime<Utc>,
-    output_dir: PathBuf,
+    progress: Option<Arc<LiveProgress>>,
+) -> Result<Orchestrator> {
+    let mut builder = orchestrator_builder(args, config)?;
+    if asks_on_terminal(args) {
+        builder = builder.approver(Arc::new(TerminalApprover::new(progress.clone())));
+    }
+    if let Some(progress) = progress {
+        builder = builder.progress(progress);
+    }
+    builder.build()
 }
 
-impl Orchestrator {
-    pub fn new() -> Result<Self> {
-        // Load environment variables
-        dotenvy::dotenv().ok();
+/// Whether approval gates ask on the terminal rather than waiting for
+/// `approvals approve|reject`: for runs and reruns started from one.
+fn asks_on_terminal(args: &Args) -> bool {
+    let runs_agents = match args.command {
+        Command::Run => !args.dry_run && args.watch.is_empty(),
+        Command::Rerun(_) => true,
+        _ => false,
+    };
+    runs_agents && std::io::stdin().is_terminal()
+}
 
-        // Determine client mode (default: claude-code)
-        let client_mode_str = env::var("CLIENT_MODE").unwrap_or_else(|_| "claude-code".to_string());
-        let global_mode = ClientMode::from_str(&client_mode_str)?;
+/// The builder behind [`build_orchestrator`], for callers that set more
+/// options before building.
+fn orchestrator_builder(args: &Args, config: Config) -> Result<OrchestratorBuilder> {
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    // Determine client mode (default: claude-code)
+    let client_mode_str = env::var("CLIENT_MODE").unwrap_or_else(|_| "claude-code".to_string());
+    let global_mode = ClientMode::from_str(&client_mode_str)?;
+
+    let key_config = config.client.api_keys.clone();
+    let mut builder = Orchestrator::builder()
+        .output_dir(&config.outputs.directory)
+        .config(config)
+        .client_mode(global_mode.clone());
+
+    // API keys (required for api/hybrid modes)
+    let api_keys = keys::env_keys(&key_config);
+    if args.dry_run {
+        // A dry run never calls the backend, so a missing key only warns
+        let pool = KeyPool::new(api_keys.clone(), key_config.strategy);
+        if let Err(e) = create_client(&global_mode, pool.as_ref()) {
+            warn!("{:#}", e);
+        }
+        builder = builder.skip_client_check();
+    } else {
+        builder = builder.shutdown(Shutdown::on_signals());
+    }
+    builder = builder.api_keys(api_keys);
+    if let Some(run_id) = &args.resume {
+        // A resumed run keeps its own mode; only an explicit --mode is checked against it
+        builder = builder.resume(run_id);
+        if let Some(mode) = &args.mode {
+            builder = builder.mode(mode);
+        }
+    } else if let Some(mode) = args
+        .mode
+        .clone()
+        .or_else(|| env::var("ORCHESTRATOR_MODE").ok())
+    {
+        builder = builder.mode(mode);
+    }
+    for (key, value) in &args.vars {
+        builder = builder.var(key, value);
+    }
+    if let Some(name) = &args.stdin_var {
+        builder = builder.var(name, read_stdin()?);
+    }
+    Ok(match &args.recording {
+        Some(Recording::Record(dir)) => builder.record(dir),
+        Some(Recording::Replay(dir)) => builder.replay(dir),
+        None => builder,
+    })
+}
 
-        // API key (required for api/hybrid modes)
-        let api_key = env::var("ANTHROPIC_API_KEY").ok();
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::from_env()?;
+    let progress = live_progress(&args);
+    let log_writer = match (&args.command, &progress) {
+        // The dashboard owns the screen; agent log files still get everything
+        (Command::Tui, _) => BoxMakeWriter::new(std::io::sink),
+        (_, Some(progress)) => BoxMakeWriter::new(progress.log_writer()),
+        (_, None) => BoxMakeWriter::new(std::io::stderr),
+    };
+    init_logging(&LoggingConfig::from_file(CONFIG_PATH), log_writer);
+
+    match args.command {
+        Command::Run if !args.watch.is_empty() => watch::run(args).await?,
+        Command::Run => {
+            let config = load_config().await?;
+            let max_failed = config.orchestra.max_failed_agents;
+            let orchestrator = build_orchestrator(&args, config, progress)?;
+            if args.dry_run {
+                print!("{}", orchestrator.plan());
+            } else {
+                let result = orchestrator.run().await?;
+                exit_for(&result, max_failed);
+            }
+        }
+        Command::Actions(ref cmd) => run_actions_command(cmd).await?,
+        Command::Approvals(ref cmd) => run_approvals_command(cmd).await?,
+        Command::Import(ref dir) => {
+            let config = load_config().await?;
+            let dirs = match dir {
+                Some(dir) => vec![PathBuf::from(dir)],
+                // Older versions wrote their results to `outputs` whatever
+                // outputs.directory said
+                None => {
+                    let mut dirs = vec![PathBuf::from(&config.outputs.directory)];
+                    let legacy = PathBuf::from("outputs");
+                    if legacy.is_dir() && !same_dir(&legacy, &dirs[0]) {
+                        dirs.push(legacy);
+                    }
+                    dirs
+                }
+            };
+            let store = RunStore::from_config(&config);
+            let mut n = 0;
+            for dir in &dirs {
+                n += store.import_dir(dir)?;
+            }
+            println!("Imported {} run(s) into {}", n, store.path().display());
+        }
+        Command::History(HistoryCommand::List) => print_history(args.limit.unwrap_or(20)).await?,
+        Command::History(HistoryCommand::Stats(ref agent)) => {
+            print_agent_stats(agent.as_deref(), args.days).await?
+        }
+        Command::Costs => print_costs().await?,
+        Command::Daemon => daemon::run(args).await?,
+        Command::Worker => {
+            let config = load_config().await?;
+            let queue = config.distributed.clone();
+            let orchestrator = build_orchestrator(&args, config, None)?;
+            distributed::serve(Arc::new(orchestrator), &queue, Shutdown::on_signals()).await?
+        }
+        Command::Tui => tui::run(args).await?,
+        Command::Validate(ref path) => run_validate(path.as_deref())?,
+        Command::Decrypt(ref path) => decrypt_file(path).await?,
+        Command::Bench => {
+            let config = load_config().await?;
+            let rounds = args.runs.unwrap_or(config.bench.runs);
+            let targets = if args.targets.is_empty() {
+                config.bench.targets.clone()
+            } else {
+                args.targets.clone()
+            };
+            for target in &targets {
+                ClientMode::from_str(&target.mode)?;
+            }
+            // Each target makes its own client, whatever CLIENT_MODE says
+            let orchestrator = orchestrator_builder(&args, config)?
+                .skip_client_check()
+                .build()?;
+            let (report, file) = orchestrator.bench(rounds, &targets).await?;
+            print!("{}", report.markdown());
+            println!("\nWritten to {}", file.display());
+        }
+        Command::Diff(ref files) => {
+            let config = load_config().await?;
+            let cipher = output_cipher(&config)?;
+            let (old, new) = match files {
+                Some((old, new)) => (PathBuf::from(old), PathBuf::from(new)),
+                None => latest_results_pair(&config.outputs.directory, cipher.as_ref())?,
+            };
+            let (old, new) = (
+                OrchestrationResult::load_with(&old, cipher.as_ref())?,
+                OrchestrationResult::load_with(&new, cipher.as_ref())?,
+            );
+            let diffs = diff::compare(&old, &new, args.agent.as_deref());
+            if diffs.is_empty() {
+                anyhow::bail!("No results to compare");
+            }
+            println!("{} -> {}", old.run_id, new.run_id);
+            for d in &diffs {
+                println!("  {:<24} {}", d.agent, d.summary());
+            }
+            for d in &diffs {
+                if !d.unified.is_empty() {
+                    print!("\n{}", d.unified);
+                }
+            }
+        }
+        Command::Rerun(ref path) => {
+            let cipher = output_cipher(&load_config().await?)?;
+            let previous =
+                OrchestrationResult::load_with(std::path::Path::new(path), cipher.as_ref())?;
+            let failed = previous.failed_agents();
+            if failed.is_empty() {
+                println!("No failed agents in {}; nothing to rerun.", path);
+                return Ok(());
+            }
+            println!("Rerunning {}: {}", previous.run_id, failed.join(", "));
+            // The rerun has to resolve the same mode the original run used
+            let args = Args {
+                mode: Some(previous.mode.clone()),
+                ..args
+            };
+            let config = load_config().await?;
+            let max_failed = config.orchestra.max_failed_agents;
+            let orchestrator = build_orchestrator(&args, config, progress)?;
+            let result = orchestrator.rerun(&previous).await?;
+            exit_for(&result, max_failed);
+        }
+        Command::Plan => {
+            let args = Args {
+                dry_run: true,
+                ..args
+            };
+            let orchestrator = build_orchestrator(&args, load_config().await?, None)?;
+            match args.graph {
+                Some(format) => print!("{}", orchestrator.graph().render(format)),
+                None => print!("{}", orchestrator.plan()),
+            }
+        }
+        Command::Agents(AgentsCommand::Test(ref name)) => {
+            let orchestrator = build_orchestrator(&args, load_config().await?, None)?;
+            let outcomes = orchestrator.test_agents(name.as_deref()).await?;
+            if outcomes.is_empty() {
+                println!("No agent tests configured.");
+            }
+            for o in &outcomes {
+                let status = if o.passed() { "PASS" } else { "FAIL" };
+                println!("{}  {}/{}", status, o.agent, o.test);
+                for failure in &o.failures {
+                    println!("      {}", failure);
+                }
+            }
+            let failed = outcomes.iter().filter(|o| !o.passed()).count();
+            if failed > 0 {
+                anyhow::bail!("{} of {} agent test(s) failed", failed, outcomes.len());
+            }
+        }
+    }
 
-        // Validate that the global mode can be created (e.g. key present for api/hybrid)
-        let _validate = create_client(&global_mode, api_key.clone())?;
-        drop(_validate);
+    Ok(())
+}
 
-        info!("Global client mode: {}", global_mode);
+/// Exit status of a run that got as far as writing its results: 130 (the
+/// shell's Ctrl-C status) if a signal cut it short, 2 if more agents didn't
+/// succeed than `orchestra.max_failed_agents` allows. Errors before that exit 1.
+fn exit_for(result: &OrchestrationResult, max_failed: usize) {
+    if Shutdown::on_signals().is_triggered() {
+        std::process::exit(130);
+    }
+    let failed = result.failed_agents();
+    if failed.len() > max_failed {
+        error!(
+            "{} agent(s) didn't succeed (allowed: {}): {}",
+            failed.len(),
+            max_failed,
+            failed.join(", ")
+        );
+        std::process::exit(2);
+    }
+}
 
-        let mode = env::var("ORCHESTRATOR_MODE").unwrap_or_else(|_| "auto".to_string());
+/// The live per-agent view, for runs and reruns whose stdout is a terminal
+/// unless `--no-progress` is given.
+fn live_progress(args: &Args) -> Option<Arc<LiveProgress>> {
+    let runs_agents = match args.command {
+        Command::Run => !args.dry_run && args.watch.is_empty(),
+        Command::Rerun(_) => true,
+        _ => false,
+    };
+    (runs_agents && !args.no_progress && std::io::stdout().is_terminal())
+        .then(|| Arc::new(LiveProgress::new()))
+}
 
-        let timestamp = Utc::now();
+/// Set up logging from the config's `logging` section. Logs go to `writer`,
+/// normally stderr so stdout stays clean for command output such as
+/// `plan --graph`. `RUST_LOG`, when set, takes precedence over `logging.level`.
+fn init_logging(logging: &LoggingConfig, writer: BoxMakeWriter) {
+    use tracing_subscriber::filter::Targets;
+    use tracing_subscriber::prelude::*;
+
+    // Bad values are reported by validation once the config loads
+    let level = logging.tracing_level().unwrap_or(tracing::Level::INFO);
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        tracing_subscriber::EnvFilter::new(format!(
+            "agent_orchestra={}",
+            level.as_str().to_lowercase()
+        ))
+    });
+    let fmt = tracing_subscriber::fmt::layer().with_writer(writer);
+    let fmt = if logging.is_json().unwrap_or(false) {
+        fmt.json().boxed()
+    } else {
+        fmt.boxed()
+    };
+    // Agent log files keep debug detail whatever the global level is
+    let agent_logs = logging.agent_logs.as_ref().map(|dir| {
+        AgentLogLayer::new(dir)
+            .with_filter(Targets::new().with_target("agent_orchestra", tracing::Level::DEBUG))
+    });
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(filter))
+        .with(agent_logs)
+        .init();
+}
 
-        let output_dir = PathBuf::from("outputs");
-        fs::create_dir_all(&output_dir).context("Failed to create output directory")?;
+/// Load the config, falling back to defaults only when the file is absent.
+/// A config that exists but doesn't validate stops the command.
+async fn load_config() -> Result<Config> {
+    let path = std::path::Path::new(CONFIG_PATH);
+    if !path.exists() {
+        warn!("{} not found; using defaults", CONFIG_PATH);
+        return Ok(Config::default());
+    }
+    let diagnostics = validate::validate_file(path);
+    let mut errors = Vec::new();
+    for d in &diagnostics {
+        match d.severity {
+            Severity::Warning => warn!("{}", d.render(CONFIG_PATH)),
+            Severity::Error => errors.push(format!("  {}", d.render(CONFIG_PATH))),
+        }
+    }
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Invalid config:\n{}\nRun `agent-orchestra validate` to check it.",
+            errors.join("\n")
+        );
+    }
+    let config = Config::load(path).with_context(|| format!("Failed to load {}", CONFIG_PATH))?;
+    secrets::configure(&config.secrets).await;
+    Ok(config)
+}
 
-        let config = Config::load("config/orchestra.yml").unwrap_or_else(|_| Config::default());
+/// The cipher for `outputs.encryption`, if it is set.
+fn output_cipher(config: &Config) -> Result<Option<Cipher>> {
+    config
+        .outputs
+        .encryption
+        .as_ref()
+        .map(Cipher::from_config)
+        .transpose()
+}
 
-        Ok(Self {
-            global_mode,
-            api_key,
-            config,
-            mode,
-            timestamp,
-            output_dir,
-        })
+/// Whether `a` and `b` name the same directory, however they're spelled.
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
     }
+}
 
-    pub async fn run(&self) -> Result<()> {
-        info!("Starting Agent Orchestra - Mode: {}", self.mode);
-        info!("Timestamp: {}", self.timestamp.format("%Y%m%d-%H%M%S"));
-
-        let tasks = self.get_agent_tasks();
-        info!("Running {} agents", tasks.len());
+/// Print a file sealed under `outputs.encryption`.
+async fn decrypt_file(path: &str) -> Result<()> {
+    use std::io::Write;
+    let cipher = output_cipher(&load_config().await?)?
+        .context("outputs.encryption is not set, so there is no key to decrypt with")?;
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+    let plaintext = cipher
+        .open(&data)
+        .with_context(|| format!("Failed to decrypt {}", path))?;
+    std::io::stdout().write_all(&plaintext)?;
+    Ok(())
+}
 
-        let results = if self.config.features.parallel_execution {
-            info!("Parallel execution enabled");
-            self.run_parallel(tasks).await
-        } else {
-            self.run_sequential(tasks).await
-        };
+/// The newest results file in `dir` and the newest earlier one from a run
+/// of the same mode. Files are ordered by the run ID or timestamp in their
+/// name.
+fn latest_results_pair(dir: &str, cipher: Option<&Cipher>) -> Result<(PathBuf, PathBuf)> {
+    let mut files: Vec<(chrono::DateTime<chrono::Utc>, PathBuf)> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir))?
+        .filter_map(|e| {
+            let path = e.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            let stem = name
+                .strip_suffix(".enc")
+                .unwrap_or(name)
+                .strip_suffix(".json")?;
+            // Manifests share the naming but aren't results
+            if stem.starts_with("manifest-") {
+                return None;
+            }
+            Some((ulid::file_time(stem)?, path))
+        })
+        .collect();
+    files.sort();
 
-        self.save_results(&results)?;
-        self.generate_summary(&results)?;
+    let (_, newest) = files.pop().context("No results files to compare")?;
+    let mode = OrchestrationResult::load_with(&newest, cipher)?.mode;
+    let previous = files
+        .into_iter()
+        .rev()
+        .map(|(_, path)| path)
+        .find(|path| OrchestrationResult::load_with(path, cipher).is_ok_and(|r| r.mode == mode))
+        .with_context(|| format!("No earlier {} run in {} to compare with", mode, dir))?;
+    Ok((previous, newest))
+}
 
-        info!("Orchestration complete!");
-        Ok(())
+/// Print every diagnostic for a config file; fails if any are errors.
+fn run_validate(path: Option<&str>) -> Result<()> {
+    let path = path.unwrap_or(CONFIG_PATH);
+    let diagnostics = validate::validate_file(std::path::Path::new(path));
+    for d in &diagnostics {
+        println!("{}", d.render(path));
     }
-
-    /// Run agents one at a time (original behaviour).
-    async fn run_sequential(&self, tasks: Vec<AgentTask>) -> Vec<AgentResult> {
-        let mut results = Vec::new();
-        for task in tasks {
-            let agent_name = task.name.clone();
-            let mode_label = task
-                .client_mode
-                .as_deref()
-                .unwrap_or(&self.global_mode.to_string())
-                .to_string();
-
-            match self.run_agent(task).await {
-                Ok(result) => results.push(result),
-                Err(e) => {
-                    error!("Agent execution failed: {:?}", e);
-                    results.push(AgentResult::failed(
-                        agent_name,
-                        format!("{:?}", e),
-                        mode_label,
-                    ));
-                }
-            }
-
-            // Small delay between agents
-            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-        }
-        results
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("{}: {} error(s)", path, errors);
     }
+    println!("{}: OK ({} warning(s))", path, diagnostics.len() - errors);
+    Ok(())
+}
 
-    /// Run all agents concurrently via tokio::spawn.
-    async fn run_parallel(&self, tasks: Vec<AgentTask>) -> Vec<AgentResult> {
-        let mut handles = Vec::new();
-
-        for task in tasks {
-            let agent_name = task.name.clone();
-            let mode_label = task
-                .client_mode
-                .as_deref()
-                .unwrap_or(&self.global_mode.to_string())
-                .to_string();
-            let global_mode = self.global_mode.clone();
-            let api_key = self.api_key.clone();
-
-            // Each spawned task gets its own client
-            let client: Box<dyn AgentClient> =
-                match create_agent_client(task.client_mode.as_deref(), &global_mode, api_key) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        handles.push(tokio::spawn(async move {
-                            AgentResult::failed(agent_name, format!("{:?}", e), mode_label)
-                        }));
-                        continue;
-                    }
-                };
-
-            let timeout_secs = task.timeout_seconds;
-            let prompt = task.prompt.clone();
-            let system_prompt = task.system_prompt.clone();
-
-            handles.push(tokio::spawn(async move {
-                info!("Running agent: {} (timeout: {}s)", agent_name, timeout_secs);
-                let timeout = std::time::Duration::from_secs(timeout_secs);
-                match tokio::time::timeout(
-                    timeout,
-                    client.send_message(&prompt, system_prompt.as_deref()),
-                )
-                .await
-                {
-                    Ok(Ok(response)) => {
-                        info!("Agent {} completed", agent_name);
-                        AgentResult::success(agent_name, response, mode_label)
-                    }
-                    Ok(Err(e)) => {
-                        error!("Agent {} failed: {:?}", agent_name, e);
-                        AgentResult::failed(agent_name, format!("{:?}", e), mode_label)
-                    }
-                    Err(_) => {
-                        error!("Agent {} timed out after {}s", agent_name, timeout_secs);
-                        AgentResult::failed(
-                            agent_name,
-                            format!("Timed out after {}s", timeout_secs),
-                            mode_label,
-                        )
-                    }
-                }
-            }));
-        }
+async fn print_history(limit: usize) -> Result<()> {
+    let config = load_config().await?;
+    let runs = RunStore::from_config(&config).load()?;
+    if runs.is_empty() {
+        println!("No runs recorded. Use `import` to backfill older outputs.");
+    }
+    for run in runs.iter().rev().take(limit) {
+        println!(
+            "{}  {:<28} {:<12} {}/{} ok  ${:.4}  {}",
+            run.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            run.run_id,
+            run.mode,
+            run.successful(),
+            run.agents.len(),
+            run.estimated_cost(),
+            run.source
+        );
+    }
+    Ok(())
+}
 
-        let mut results = Vec::new();
-        for handle in handles {
-            match handle.await {
-                Ok(result) => results.push(result),
-                Err(e) => {
-                    error!("Task join error: {:?}", e);
-                    results.push(AgentResult::failed(
-                        "unknown".to_string(),
-                        format!("Task panicked: {:?}", e),
-                        "unknown".to_string(),
-                    ));
-                }
-            }
+/// Per-agent success rate and latency, overall and then day by day.
+async fn print_agent_stats(agent: Option<&str>, days: Option<u32>) -> Result<()> {
+    let config = load_config().await?;
+    let mut runs = RunStore::from_config(&config).load()?;
+    if let Some(days) = days {
+        let since = chrono::Utc::now() - chrono::Duration::days(i64::from(days));
+        runs.retain(|r| r.timestamp >= since);
+    }
+    if let Some(agent) = agent {
+        for run in &mut runs {
+            run.agents.retain(|a| a.agent == agent);
         }
-        results
+    }
+    let overall = store::agent_stats(&runs, false);
+    if overall.is_empty() {
+        println!("No agent results recorded.");
+        return Ok(());
     }
 
-    async fn run_agent(&self, task: AgentTask) -> Result<AgentResult> {
-        info!(
-            "Running agent: {} (timeout: {}s)",
-            task.name, task.timeout_seconds
+    let latency = |s: &store::AgentStats| match s.avg_latency_ms() {
+        Some(ms) => format!("{:.1}s", ms as f64 / 1000.0),
+        None => "-".to_string(),
+    };
+    println!(
+        "{:<24} {:>5} {:>8} {:>11} {:>10}",
+        "agent", "runs", "success", "avg latency", "out tokens"
+    );
+    for s in &overall {
+        println!(
+            "{:<24} {:>5} {:>7.1}% {:>11} {:>10}",
+            s.agent,
+            s.runs,
+            s.success_rate() * 100.0,
+            latency(s),
+            s.output_tokens
         );
-
-        let mode_label = task
-            .client_mode
-            .as_deref()
-            .unwrap_or(&self.global_mode.to_string())
-            .to_string();
-
-        let client = create_agent_client(
-            task.client_mode.as_deref(),
-            &self.global_mode,
-            self.api_key.clone(),
-        )?;
-
-        let timeout = std::time::Duration::from_secs(task.timeout_seconds);
-        let response = tokio::time::timeout(
-            timeout,
-            client.send_message(&task.prompt, task.system_prompt.as_deref()),
-        )
-        .await
-        .context(format!(
-            "Agent {} timed out after {}s",
-            task.name, task.timeout_seconds
-        ))?
-        .context("Failed to send message to Claude")?;
-
-        info!("Agent {} completed", task.name);
-
-        Ok(AgentResult::success(task.name, response, mode_label))
     }
+    println!("\nBy day:");
+    for s in store::agent_stats(&runs, true) {
+        let day = s.day.map(|d| d.to_string()).unwrap_or_default();
+        println!(
+            "  {}  {:<24} {:>3} run(s) {:>6.1}% ok  avg {}",
+            day,
+            s.agent,
+            s.runs,
+            s.success_rate() * 100.0,
+            latency(&s)
+        );
+    }
+    Ok(())
+}
 
-    fn get_agent_tasks(&self) -> Vec<AgentTask> {
-        let agents = &self.config.agents;
-
-        let filter = |name: &str, prompt: &str| -> Option<AgentTask> {
-            let agent_config = match name {
-                "monitor" | "health_checker" => &agents.monitor,
-                "analyzer" | "data_analyst" | "synthesizer" => &agents.analyzer,
-                "researcher" => &agents.researcher,
-                "reporter" | "alert_manager" => &agents.reporter,
-                _ => {
-                    return Some(AgentTask::new(name, prompt, 120));
-                }
-            };
-            if agent_config.enabled {
-                Some(
-                    AgentTask::new(name, prompt, agent_config.timeout_seconds)
-                        .with_client_mode(agent_config.client_mode.clone())
-                        .with_system_prompt(agent_config.system_prompt.clone()),
-                )
-            } else {
-                warn!("Skipping disabled agent: {}", name);
-                None
-            }
-        };
-
-        let tasks: Vec<AgentTask> = match self.mode.as_str() {
-            "auto" => vec![
-                filter(
-                    "monitor",
-                    "Check system health, review logs, and identify any issues that need attention. Provide a brief status report.",
-                ),
-                filter(
-                    "analyzer",
-                    "Analyze recent activity patterns and suggest optimizations or improvements for the system.",
-                ),
-            ],
-            "research" => vec![
-                filter(
-                    "researcher",
-                    "Research the latest developments in AI agent orchestration and multi-agent systems. Summarize key findings.",
-                ),
-                filter(
-                    "synthesizer",
-                    "Based on current trends, suggest improvements to our agent orchestration framework.",
-                ),
-            ],
-            "analysis" => vec![
-                filter(
-                    "data_analyst",
-                    "Analyze system performance metrics and identify bottlenecks or areas for improvement.",
-                ),
-                filter(
-                    "reporter",
-                    "Generate a comprehensive report on system status and recommendations.",
-                ),
-            ],
-            "monitoring" => vec![
-                filter(
-                    "health_checker",
-                    "Perform comprehensive health checks on all system components and services.",
-                ),
-                filter(
-                    "alert_manager",
-                    "Review recent alerts and events, prioritize issues, and suggest actions.",
-                ),
-            ],
-            // Agent Teams modes: use team definitions from config
-            mode if self.config.teams.enabled && self.config.teams.definitions.contains_key(mode) => {
-                let team_def = &self.config.teams.definitions[mode];
-                info!("Using Agent Teams definition: {} ({})", mode, team_def.description);
-                team_def.teammates.iter().map(|teammate| {
-                    Some(
-                        AgentTask::new(&teammate.name, &teammate.role, teammate.timeout_seconds)
-                            .with_client_mode(Some("agent-teams".to_string()))
-                            .with_system_prompt(Some(teammate.role.clone())),
-                    )
-                }).collect()
-            }
-            _ => {
-                warn!("Unknown mode '{}', using 'auto'", self.mode);
-                vec![
-                    filter(
-                        "monitor",
-                        "Check system health, review logs, and identify any issues that need attention. Provide a brief status report.",
-                    ),
-                    filter(
-                        "analyzer",
-                        "Analyze recent activity patterns and suggest optimizations or improvements for the system.",
-                    ),
-                ]
-            }
+async fn print_costs() -> Result<()> {
+    use std::collections::BTreeMap;
+
+    let config = load_config().await?;
+    let runs = RunStore::from_config(&config).load()?;
+    let mut by_mode: BTreeMap<&str, f64> = BTreeMap::new();
+    let mut by_agent: BTreeMap<&str, f64> = BTreeMap::new();
+    let mut by_day: BTreeMap<String, f64> = BTreeMap::new();
+    for run in &runs {
+        *by_mode.entry(&run.mode).or_default() += run.estimated_cost();
+        *by_day
+            .entry(run.timestamp.format("%Y-%m-%d").to_string())
+            .or_default() += run.estimated_cost();
+        for agent in &run.agents {
+            *by_agent.entry(&agent.agent).or_default() += agent.estimated_cost;
         }
-        .into_iter()
-        .flatten()
-        .collect();
+    }
 
-        if tasks.is_empty() {
-            warn!("All agents disabled for mode '{}'", self.mode);
+    let total = runs.iter().fold(0.0, |sum, r| sum + r.estimated_cost());
+    println!("Estimated total: ${:.4} over {} run(s)", total, runs.len());
+    for (title, rows) in [("mode", &by_mode), ("agent", &by_agent)] {
+        println!("\nBy {}:", title);
+        for (key, cost) in rows {
+            println!("  {:<24} ${:.4}", key, cost);
         }
-        tasks
     }
-
-    fn save_results(&self, results: &[AgentResult]) -> Result<()> {
-        let timestamp_str = self.timestamp.format("%Y%m%d-%H%M%S").to_string();
-        let is_team_mode =
-            self.config.teams.enabled && self.config.teams.definitions.contains_key(&self.mode);
-        let prefix = if is_team_mode {
-            &self.config.teams.output_prefix
-        } else {
-            "results"
-        };
-        let output_file = self
-            .output_dir
-            .join(format!("{}-{}.json", prefix, timestamp_str));
-
-        let orchestration = OrchestrationResult {
-            timestamp: self.timestamp,
-            mode: self.mode.clone(),
-            global_client_mode: self.global_mode.to_string(),
-            results: results.to_vec(),
-        };
-
-        let json =
-            serde_json::to_string_pretty(&orchestration).context("Failed to serialize results")?;
-
-        fs::write(&output_file, json).context("Failed to write results file")?;
-
-        info!("Results saved to {}", output_file.display());
-        Ok(())
+    println!("\nBy day:");
+    for (day, cost) in &by_day {
+        println!("  {:<24} ${:.4}", day, cost);
     }
+    Ok(())
+}
 
-    fn generate_summary(&self, results: &[AgentResult]) -> Result<()> {
-        let timestamp_str = self.timestamp.format("%Y%m%d-%H%M%S").to_string();
-        let summary_file = self
-            .output_dir
-            .join(format!("summary-{}.txt", timestamp_str));
-
-        let successful = results.iter().filter(|r| r.status == "success").count();
-        let failed = results.len() - successful;
-
-        let mut summary = String::new();
-        summary.push_str("Agent Orchestra Run Summary\n");
-        summary.push_str("==================================================\n\n");
-        summary.push_str(&format!("Timestamp: {}\n", timestamp_str));
-        summary.push_str(&format!("Mode: {}\n", self.mode));
-        summary.push_str(&format!("Global Client: {}\n", self.global_mode));
-        summary.push_str(&format!(
-            "Parallel: {}\n",
-            self.config.features.parallel_execution
-        ));
-        summary.push_str(&format!("Total Agents: {}\n", results.len()));
-        summary.push_str(&format!("Successful: {}\n", successful));
-        summary.push_str(&format!("Failed: {}\n\n", failed));
-
-        for result in results {
-            summary.push_str("\n──────────────────────────────────────────────────\n");
-            summary.push_str(&format!("Agent: {}\n", result.agent));
-            summary.push_str(&format!("Status: {}\n", result.status));
-            summary.push_str(&format!("Client: {}\n", result.client_mode));
-
-            if result.status == "success" {
-                if let Some(ref output) = result.output {
-                    summary.push_str(&format!("Output:\n{}\n", output));
-                }
-            } else if let Some(ref error) = result.error {
-                summary.push_str(&format!("Error: {}\n", error));
+async fn run_actions_command(cmd: &ActionsCommand) -> Result<()> {
+    let config = load_config().await?;
+    let mut queue = ActionQueue::load(&config.actions.queue_file)?;
+
+    match cmd {
+        ActionsCommand::List => {
+            if queue.actions.is_empty() {
+                println!("No queued actions.");
+            }
+            for a in &queue.actions {
+                println!(
+                    "{}  {:<9} {:<12} {} {} (x{}, last {})",
+                    a.id,
+                    format!("{:?}", a.status).to_lowercase(),
+                    a.agent,
+                    a.action.kind,
+                    a.action.target.as_deref().unwrap_or("-"),
+                    a.times_proposed,
+                    a.last_run_id
+                );
             }
+            return Ok(());
+        }
+        ActionsCommand::Approve(id) => queue.set_status(id, ActionStatus::Approved)?,
+        ActionsCommand::Reject(id) => queue.set_status(id, ActionStatus::Rejected)?,
+        ActionsCommand::Execute => {
+            let n = queue.execute_approved().await?;
+            println!("Executed {} approved action(s).", n);
         }
-
-        fs::write(&summary_file, summary).context("Failed to write summary file")?;
-
-        info!("Summary saved to {}", summary_file.display());
-        Ok(())
     }
+    queue.save()
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("agent_orchestra=info".parse().unwrap()),
-        )
-        .init();
-
-    let orchestrator = Orchestrator::new()?;
-    orchestrator.run().await?;
-
+async fn run_approvals_command(cmd: &ApprovalsCommand) -> Result<()> {
+    let config = load_config().await?;
+    let queue = ApprovalQueue::new(&config.approvals.dir);
+    let cipher = output_cipher(&config)?;
+    match cmd {
+        ApprovalsCommand::List => {
+            let pending = queue.pending()?;
+            if pending.is_empty() {
+                println!("No outputs waiting for approval.");
+            }
+            for request in &pending {
+                println!(
+                    "{}  {:<20} {}  {}",
+                    request.requested_at.format("%Y-%m-%d %H:%M:%S"),
+                    request.agent,
+                    request.run_id,
+                    request
+                        .output(cipher.as_ref())?
+                        .lines()
+                        .next()
+                        .unwrap_or("")
+                );
+            }
+        }
+        ApprovalsCommand::Show(run_id, agent) => {
+            let request = queue.load(run_id, agent)?;
+            println!(
+                "{} ({}), {:?}\n\n{}",
+                request.agent,
+                request.run_id,
+                request.status,
+                request.output(cipher.as_ref())?
+            );
+        }
+        ApprovalsCommand::Approve(run_id, agent) => queue.decide(run_id, agent, true)?,
+        ApprovalsCommand::Reject(run_id, agent) => queue.decide(run_id, agent, false)?,
+    }
     Ok(())
 }
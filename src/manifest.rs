@@ -0,0 +1,242 @@
+//! The per-run manifest, `manifest-<run-id>.json` next to the run's results:
+//! the resolved config, the git commit of the repository the config file is
+//! in, the binary's version and the environment the run started in. It is
+//! written when the run starts, so even an interrupted run has one.
+//!
+//! Values that may be credentials are left out: `env` maps in the config
+//! keep their keys with blank values, and `--var` inputs are recorded by
+//! name only.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+use crate::template;
+
+/// Variables that change what a run does, recorded when set. Credentials
+/// are never recorded, only how many API keys the run had.
+const RECORDED_ENV: &[&str] = &[
+    "CLIENT_MODE",
+    "ORCHESTRATOR_MODE",
+    "CLAUDE_CLI_PATH",
+    "RUST_LOG",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub run_id: String,
+    pub mode: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerun_of: Option<String>,
+    /// `agent-orchestra` version that ran it.
+    pub version: String,
+    /// Config file, as given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_file: Option<String>,
+    /// Commit of the repository holding the config file, if it is in one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_git: Option<GitState>,
+    pub environment: Environment,
+    /// Built-in template variables.
+    pub vars: BTreeMap<String, String>,
+    /// Names of the other template variables, from `--var` and
+    /// `--stdin-var`; their values aren't recorded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// The config after inheritance and defaults were applied.
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GitState {
+    pub sha: String,
+    /// The config file had uncommitted changes.
+    pub dirty: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Environment {
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    pub pid: u32,
+    pub working_dir: String,
+    pub client_mode: String,
+    pub api_keys: usize,
+    /// Those of [`RECORDED_ENV`] that are set.
+    pub env: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new(
+        config: &Config,
+        run_id: &str,
+        mode: &str,
+        timestamp: DateTime<Utc>,
+        client_mode: &str,
+        api_keys: usize,
+        vars: &BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            mode: mode.to_string(),
+            timestamp,
+            rerun_of: None,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config_file: config
+                .source
+                .as_ref()
+                .map(|p| p.to_string_lossy().into_owned()),
+            config_git: config.source.as_deref().and_then(git_state),
+            environment: Environment {
+                hostname: template::hostname(),
+                os: std::env::consts::OS.to_string(),
+                arch: std::env::consts::ARCH.to_string(),
+                pid: std::process::id(),
+                working_dir: std::env::current_dir()
+                    .map(|d| d.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                client_mode: client_mode.to_string(),
+                api_keys,
+                env: RECORDED_ENV
+                    .iter()
+                    .filter_map(|&name| Some((name.to_string(), std::env::var(name).ok()?)))
+                    .collect(),
+            },
+            vars: vars
+                .iter()
+                .filter(|(name, _)| template::BUILTINS.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            inputs: vars
+                .keys()
+                .filter(|name| !template::BUILTINS.contains(&name.as_str()))
+                .cloned()
+                .collect(),
+            config: serde_json::to_value(config)
+                .map(blank_env)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn with_rerun_of(mut self, run_id: &str) -> Self {
+        self.rerun_of = Some(run_id.to_string());
+        self
+    }
+}
+
+/// Blank the values of every `env` map (agents' `env`, MCP servers' `env`),
+/// keeping the keys, so the manifest shows which variables were set but not
+/// to what.
+fn blank_env(mut value: serde_json::Value) -> serde_json::Value {
+    fn walk(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    match child {
+                        serde_json::Value::Object(env) if key == "env" => {
+                            for value in env.values_mut() {
+                                *value = serde_json::Value::String(String::new());
+                            }
+                        }
+                        _ => walk(child),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(walk),
+            _ => {}
+        }
+    }
+    walk(&mut value);
+    value
+}
+
+/// The commit checked out in the repository holding `file`, or `None` if it
+/// isn't in one (or git isn't installed).
+fn git_state(file: &Path) -> Option<GitState> {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let sha = git(&["rev-parse", "HEAD"])?;
+    let name = file.file_name()?.to_str()?;
+    let status = git(&["status", "--porcelain", "--", name])?;
+    Some(GitState {
+        sha,
+        dirty: !status.is_empty(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::McpServer;
+
+    #[test]
+    fn test_manifest_records_config_and_environment() {
+        let config = Config {
+            source: Some("config/orchestra.yml".into()),
+            ..Config::default()
+        };
+        let vars = BTreeMap::from([
+            ("mode".to_string(), "auto".to_string()),
+            ("ticket".to_string(), "OPS-1".to_string()),
+        ]);
+        let manifest = Manifest::new(&config, "01J", "auto", Utc::now(), "mock", 0, &vars)
+            .with_rerun_of("01H");
+        assert_eq!(manifest.config["orchestra"]["default_mode"], "auto");
+        assert_eq!(
+            manifest.config_file.as_deref(),
+            Some("config/orchestra.yml")
+        );
+        assert_eq!(manifest.environment.client_mode, "mock");
+        assert_eq!(manifest.vars["mode"], "auto");
+        assert!(!manifest.vars.contains_key("ticket"));
+        assert_eq!(manifest.inputs, ["ticket"]);
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        let parsed: Manifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.rerun_of.as_deref(), Some("01H"));
+        assert_eq!(parsed.config_git, manifest.config_git);
+    }
+
+    #[test]
+    fn test_manifest_leaves_out_env_values() {
+        let mut config = Config::default();
+        let agent = &mut config.agents.monitor;
+        agent.env.insert("TOKEN".to_string(), "hunter2".to_string());
+        agent.mcp_servers.insert(
+            "github".to_string(),
+            McpServer {
+                command: Some("gh-mcp".to_string()),
+                env: BTreeMap::from([("GITHUB_TOKEN".to_string(), "hunter2".to_string())]),
+                ..McpServer::default()
+            },
+        );
+        let vars = BTreeMap::from([("password".to_string(), "hunter2".to_string())]);
+        let manifest = Manifest::new(&config, "01J", "auto", Utc::now(), "mock", 0, &vars);
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert!(!json.contains("hunter2"), "{}", json);
+        assert!(json.contains("TOKEN"));
+        assert!(json.contains("GITHUB_TOKEN"));
+        assert!(json.contains("password"));
+    }
+}
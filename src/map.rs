@@ -0,0 +1,93 @@
+//! Map-reduce runs: the mapper agent answers the same prompt once per input
+//! file, then a reducer agent combines the partial answers into one.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::agents::AgentResult;
+
+/// The agent name a mapper's result for `file` is recorded under.
+pub fn task_name(mapper: &str, file: &Path) -> String {
+    format!("{}[{}]", mapper, file.display())
+}
+
+/// The input file of a mapper result named by [`task_name`], if `agent` is one.
+pub fn input_of<'a>(mapper: &str, agent: &'a str) -> Option<&'a str> {
+    agent
+        .strip_prefix(mapper)?
+        .strip_prefix('[')?
+        .strip_suffix(']')
+}
+
+/// The reducer's prompt: its task, then the mapper's partial answer for
+/// every input file in `results`.
+pub fn reducer_prompt(mapper: &str, prompt: &str, results: &[AgentResult]) -> String {
+    let partials: Vec<(&str, &AgentResult)> = results
+        .iter()
+        .filter_map(|r| Some((input_of(mapper, &r.agent)?, r)))
+        .collect();
+    let mut out = format!(
+        "{}\n\nThe task was run over {} file(s) separately. Partial results:\n",
+        prompt.trim(),
+        partials.len()
+    );
+    for (file, result) in partials {
+        match (&result.output, result.status.as_str()) {
+            (Some(output), "success") => {
+                let _ = write!(out, "\n### {}\n{}\n", file, output.trim());
+            }
+            _ => {
+                let _ = write!(
+                    out,
+                    "\n### {}\nNo result: {}\n",
+                    file,
+                    result.error.as_deref().unwrap_or(&result.status)
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reducer_prompt_lists_partials() {
+        let ok = AgentResult::success(
+            "summarizer[logs/a.log]".to_string(),
+            "3 errors\n".to_string(),
+            "api".to_string(),
+        );
+        let failed = AgentResult::failed(
+            "summarizer[logs/b.log]".to_string(),
+            "Timed out after 60s".to_string(),
+            "api".to_string(),
+        );
+        let other = AgentResult::success(
+            "reporter".to_string(),
+            "unrelated".to_string(),
+            "api".to_string(),
+        );
+        assert_eq!(
+            task_name("summarizer", Path::new("logs/a.log")),
+            "summarizer[logs/a.log]"
+        );
+        assert_eq!(
+            input_of("summarizer", "summarizer[logs/a.log]"),
+            Some("logs/a.log")
+        );
+        assert_eq!(input_of("summarizer", "summarizer"), None);
+        let prompt = reducer_prompt(
+            "summarizer",
+            "Combine the error summaries.",
+            &[ok, other, failed],
+        );
+        assert!(
+            prompt.starts_with("Combine the error summaries.\n\nThe task was run over 2 file(s)")
+        );
+        assert!(prompt.contains("\n### logs/a.log\n3 errors\n"));
+        assert!(prompt.ends_with("\n### logs/b.log\nNo result: Timed out after 60s\n"));
+    }
+}
@@ -0,0 +1,3975 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn, Instrument, Span};
+
+use crate::actions::{self, ActionQueue};
+use crate::agent_log::{safe_name, AGENT_SPAN};
+use crate::agents::{self, AgentResult, AgentTask};
+use crate::approval::{Approver, FileApprover};
+use crate::bench::{self, BenchReport};
+use crate::budget;
+use crate::checkpoint::{Checkpoint, CheckpointWriter};
+use crate::client::{
+    create_agent_client, create_client, AgentClient, AgentResponse, ApiClient, BatchClient,
+    BatchRequest, ClientMode, ClientOptions, Continuation, Usage, BATCH_POLL_INTERVAL,
+    DEFAULT_MAX_TOKENS, DEFAULT_MODEL,
+};
+use crate::config::{
+    AgentHooks, BenchPrompt, BenchTarget, BudgetConfig, BudgetStrategy, Config, ConsensusConfig,
+    ConsensusStrategy, ContextConfig, MapConfig, ModelSpec, ReviewConfig, SessionMode,
+};
+use crate::consensus::{self, ConsensusOutcome};
+use crate::context::{self, SamplingRecord};
+use crate::cost;
+use crate::distributed::{self, Job, Queue};
+use crate::encryption::{self, Cipher};
+use crate::events::EventLog;
+use crate::git::GitOutputs;
+use crate::graph;
+use crate::hooks::HookRunner;
+use crate::keys::KeyPool;
+use crate::lock::RunLock;
+use crate::manifest::Manifest;
+use crate::map;
+use crate::progress::Progress;
+use crate::queue::TaskQueue;
+use crate::remote::RemoteStore;
+use crate::replay::Recording;
+use crate::review::{self, ReviewOutcome, ReviewRound};
+use crate::selftest::{self, TestOutcome};
+use crate::shutdown::{AgentCancel, Shutdown};
+use crate::status::{self, ProviderStatus};
+use crate::store::{self, AgentRecord, RunRecord, RunStore};
+use crate::structured::{self, Schema};
+use crate::teams::TeamSession;
+use crate::template::{self, TemplateVars};
+use crate::ulid;
+use crate::webhook;
+use crate::workers::{self, WorkerPool};
+
+/// Everything a run produced; also what `results-*.json` contains.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrchestrationResult {
+    #[serde(default)]
+    pub run_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub mode: String,
+    pub global_client_mode: String,
+    /// Providers that reported an outage when the run started.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provider_outages: Vec<ProviderStatus>,
+    /// How injected logs were sampled, per agent and source.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub log_sampling: Vec<SamplingRecord>,
+    /// IDs of queued actions proposed during this run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<String>,
+    /// Run whose failed agents this run re-executed (`rerun`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerun_of: Option<String>,
+    /// Wall time of this invocation (not of earlier, resumed ones), in ms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// How the voters of a consensus mode came out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consensus: Option<ConsensusOutcome>,
+    /// Every draft and critique of a review mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<ReviewOutcome>,
+    pub results: Vec<AgentResult>,
+}
+
+impl OrchestrationResult {
+    /// Load a `results-*.json` file written by an earlier run.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with(path, None)
+    }
+
+    /// Load a results file that may be encrypted, opening it with `cipher`.
+    pub fn load_with(path: &Path, cipher: Option<&Cipher>) -> Result<Self> {
+        let mut content =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if encryption::is_sealed(&content) {
+            let cipher = cipher.with_context(|| {
+                format!("{} is encrypted; set outputs.encryption", path.display())
+            })?;
+            content = cipher
+                .open(&content)
+                .with_context(|| format!("Failed to decrypt {}", path.display()))?;
+        }
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Agents whose result isn't a success.
+    pub fn failed_agents(&self) -> Vec<&str> {
+        let mut failed: Vec<&str> = Vec::new();
+        // A teammate can only be rerun with the rest of its team
+        for result in self.results.iter().filter(|r| r.status != "success") {
+            let agent = result.team.as_deref().unwrap_or(&result.agent);
+            if !failed.contains(&agent) {
+                failed.push(agent);
+            }
+        }
+        failed
+    }
+}
+
+pub struct Orchestrator {
+    global_mode: ClientMode,
+    api_keys: Option<Arc<KeyPool>>,
+    /// Seals output files, checkpoints and approval requests when
+    /// `outputs.encryption` is set.
+    cipher: Option<Arc<Cipher>>,
+    config: Config,
+    mode: String,
+    timestamp: DateTime<Utc>,
+    run_id: String,
+    output_dir: PathBuf,
+    vars: TemplateVars,
+    recording: Option<Recording>,
+    /// Progress of an interrupted run being resumed.
+    resumed: Option<Checkpoint>,
+    /// Cancels agents still running when it fires.
+    shutdown: Shutdown,
+    agent_cancel: AgentCancel,
+    progress: Option<Arc<dyn Progress>>,
+    /// Decides on the output of agents with `requires_approval`.
+    approver: Arc<dyn Approver>,
+}
+
+impl Orchestrator {
+    /// Start configuring an orchestrator. Unset options default to what the
+    /// CLI uses: mode `auto`, the `claude-code` client, output to `outputs/`.
+    pub fn builder() -> OrchestratorBuilder {
+        OrchestratorBuilder::default()
+    }
+
+    /// ID of the run this orchestrator executes, e.g. `01KF3M2Q8V6W9XJ5T0R7C4N1BD`.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Describe what `run` would do — agents, backends, rendered prompts and
+    /// an upper-bound cost — without contacting any backend or running commands.
+    pub fn plan(&self) -> String {
+        let tasks = self.get_agent_tasks();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "Dry run: mode {}, global client mode {}, {} agent(s){}",
+            self.mode,
+            self.global_mode,
+            tasks.len(),
+            if self.config.features.parallel_execution {
+                ", parallel"
+            } else {
+                ""
+            }
+        );
+
+        let mut total = 0.0;
+        for task in &tasks {
+            let mode_label = task
+                .client_mode
+                .clone()
+                .unwrap_or_else(|| self.global_mode.to_string());
+            let model = task.options.model.as_deref().unwrap_or(DEFAULT_MODEL);
+            let max_tokens = task.options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+            let _ = writeln!(
+                out,
+                "\n[{}] client: {}  model: {}  timeout: {}s  max_tokens: {}",
+                task.name, mode_label, model, task.timeout_seconds, max_tokens
+            );
+            if !task.options.fallback_models.is_empty() {
+                let _ = writeln!(
+                    out,
+                    "  fallbacks: {}",
+                    task.options.fallback_models.join(", ")
+                );
+            }
+            if let Some(spec) = &task.context {
+                let sources: Vec<&str> = spec
+                    .files
+                    .iter()
+                    .chain(&spec.commands)
+                    .chain(&spec.urls)
+                    .map(String::as_str)
+                    .collect();
+                let _ = writeln!(
+                    out,
+                    "  context (captured at run time, up to {} bytes): {}",
+                    spec.max_total_bytes,
+                    sources.join(", ")
+                );
+            }
+            if task.requires_approval {
+                out.push_str("  output held for approval before later agents start\n");
+            }
+            let reads = result_refs(task);
+            if !reads.is_empty() {
+                let _ = writeln!(out, "  reads the results of: {}", reads.join(", "));
+            }
+            if let Some(system) = &task.system_prompt {
+                out.push_str("  system prompt:\n");
+                push_indented(&mut out, system);
+            }
+            let prompt = task.full_prompt();
+            out.push_str("  prompt:\n");
+            push_indented(&mut out, &prompt);
+
+            let prompt_chars = prompt.len()
+                + task.system_prompt.as_deref().map_or(0, str::len)
+                + task.context.as_ref().map_or(0, |c| c.max_total_bytes);
+            let mut est = cost::estimate_call(prompt_chars, max_tokens, &mode_label);
+            if self.map().is_some_and(|spec| spec.batch) && self.batchable(task) {
+                out.push_str("  sent in a Message Batch, at half price\n");
+                est.cost *= cost::BATCH_DISCOUNT;
+            }
+            total += est.cost;
+            let _ = writeln!(
+                out,
+                "  estimate: ~{} input tokens, up to {} output tokens, up to ${:.4}",
+                est.input_tokens, est.max_output_tokens, est.cost
+            );
+        }
+        if let Some(spec) = self.consensus() {
+            // Its prompt holds the answers, so it can't be shown or costed yet
+            let _ = writeln!(
+                out,
+                "\n[{}] aggregates the answers ({:?}) once every voter has finished",
+                spec.aggregator, spec.strategy
+            );
+        }
+        if let Some(spec) = self.map() {
+            let _ = writeln!(
+                out,
+                "\n[{}] reduces the {} partial result(s) once every file is mapped, {} at a time",
+                spec.reducer,
+                tasks.len(),
+                spec.concurrency
+            );
+        }
+        if let Some(spec) = self.review() {
+            let _ = writeln!(
+                out,
+                "\n[{}] critiques each draft and {} revises it, for up to {} round(s)",
+                spec.critic, spec.author, spec.max_rounds
+            );
+        }
+        let _ = writeln!(
+            out,
+            "\nEstimated total: up to ${:.4} (no backend was called)",
+            total
+        );
+        out
+    }
+
+    /// The run's task graph, as `plan --graph` prints it.
+    pub fn graph(&self) -> graph::Graph {
+        graph::Graph::build(
+            &self.mode,
+            &self.get_agent_tasks(),
+            &self.global_mode.to_string(),
+            self.config.features.parallel_execution,
+            self.config.actions.enabled,
+        )
+    }
+
+    /// Run each agent's configured self-tests against its real backend.
+    pub async fn test_agents(&self, only: Option<&str>) -> Result<Vec<TestOutcome>> {
+        let agents = &self.config.agents;
+        if let Some(name) = only {
+            if agents.lookup(name).is_none() {
+                anyhow::bail!("Unknown agent '{}'", name);
+            }
+        }
+        let default_model = self.config.client.default_model.as_ref();
+        let mut outcomes = Vec::new();
+
+        for (name, agent) in agents.all() {
+            // `only` may be an alias, so compare the agent it resolves to
+            if only.is_some_and(|o| !agents.lookup(o).is_some_and(|a| std::ptr::eq(a, agent))) {
+                continue;
+            }
+            if agent.tests.is_empty() {
+                continue;
+            }
+            let options = agent.client_options(default_model);
+            let system_prompt = agent.system_prompt.as_deref().map(|p| self.render(name, p));
+            let timeout = std::time::Duration::from_secs(agent.timeout_seconds);
+
+            for test in &agent.tests {
+                info!("Testing agent {}: {}", name, test.name);
+                let client = self.client_for(
+                    &format!("{}--{}", name, test.name),
+                    agent.client_mode.as_deref(),
+                    &options,
+                )?;
+                let prompt = self.render(name, &test.prompt);
+                let failures = match tokio::time::timeout(
+                    timeout,
+                    client.send(&prompt, system_prompt.as_deref()),
+                )
+                .await
+                {
+                    Ok(Ok(response)) => selftest::check_all(&test.expect, &response.text),
+                    Ok(Err(e)) => vec![format!("backend error: {:#}", e)],
+                    Err(_) => vec![format!("timed out after {}s", agent.timeout_seconds)],
+                };
+                outcomes.push(TestOutcome {
+                    agent: name.to_string(),
+                    test: test.name.clone(),
+                    failures,
+                });
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// Send every `bench` prompt to each of `targets`, `rounds` times over,
+    /// one call at a time so the calls don't slow each other down. The
+    /// report is written as `bench-<run-id>.json` and `.md`; returns it and
+    /// the JSON file.
+    pub async fn bench(
+        &self,
+        rounds: u32,
+        targets: &[BenchTarget],
+    ) -> Result<(BenchReport, PathBuf)> {
+        let prompts = &self.config.bench.prompts;
+        if prompts.is_empty() {
+            anyhow::bail!("No prompts to benchmark; add some under bench.prompts");
+        }
+        let timeout = Duration::from_secs(self.config.bench.timeout_seconds);
+        let mut samples = Vec::new();
+        for round in 1..=rounds {
+            for prompt in prompts {
+                for target in targets {
+                    info!(
+                        "Bench round {}/{}: {} on {}",
+                        round,
+                        rounds,
+                        prompt.name,
+                        target.label()
+                    );
+                    samples.push(self.bench_call(prompt, target, round, timeout).await);
+                }
+            }
+        }
+        let report = BenchReport::new(
+            &self.run_id,
+            self.timestamp,
+            rounds,
+            &targets.iter().map(BenchTarget::label).collect::<Vec<_>>(),
+            &prompts.iter().map(|p| p.name.clone()).collect::<Vec<_>>(),
+            samples,
+        );
+
+        fs::create_dir_all(&self.output_dir).context("Failed to create output directory")?;
+        let json = serde_json::to_string_pretty(&report).context("Failed to serialize report")?;
+        let file = self
+            .write_output(&self.output_file("bench", "json"), json.as_bytes())
+            .context("Failed to write bench report")?;
+        self.write_output(
+            &self.output_file("bench", "md"),
+            report.markdown().as_bytes(),
+        )
+        .context("Failed to write bench report")?;
+        Ok((report, file))
+    }
+
+    async fn bench_call(
+        &self,
+        prompt: &BenchPrompt,
+        target: &BenchTarget,
+        round: u32,
+        timeout: Duration,
+    ) -> bench::Sample {
+        let model = match &target.model {
+            Some(model) => Some(ModelSpec::Single(model.clone())),
+            None => self.config.client.default_model.clone(),
+        };
+        let options = ClientOptions {
+            max_tokens: prompt.max_tokens,
+            ..model
+                .as_ref()
+                .map(ModelSpec::client_options)
+                .unwrap_or_default()
+        };
+        let label = format!("bench--{}--{}--{}", target.label(), prompt.name, round);
+        let system_prompt = prompt.system_prompt.as_deref();
+        let started = Instant::now();
+        let reply = match self.client_for(&label, Some(&target.mode), &options) {
+            Ok(client) => tokio::time::timeout(timeout, client.send(&prompt.prompt, system_prompt))
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out after {}s", timeout.as_secs()))),
+            Err(e) => Err(e),
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let input = || {
+            cost::estimate_tokens(&prompt.prompt) + system_prompt.map_or(0, cost::estimate_tokens)
+        };
+        let mut sample = bench::Sample {
+            prompt: prompt.name.clone(),
+            target: target.label(),
+            round,
+            latency_ms,
+            error: None,
+            model: None,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost_usd: 0.0,
+        };
+        match reply {
+            Ok(response) => {
+                sample.input_tokens = response.usage.input_tokens.unwrap_or_else(input);
+                sample.output_tokens = response
+                    .usage
+                    .output_tokens
+                    .unwrap_or_else(|| cost::estimate_tokens(&response.text));
+                sample.cost_usd = response.usage.cost_usd.unwrap_or_else(|| {
+                    cost::call_cost(sample.input_tokens, sample.output_tokens, &target.mode)
+                });
+                sample.model = response.model;
+            }
+            Err(e) => {
+                warn!("Bench {} on {}: {:#}", prompt.name, target.label(), e);
+                sample.error = Some(format!("{:#}", e));
+            }
+        }
+        sample
+    }
+
+    /// Run every agent for the mode, write the results and summary files,
+    /// record history, queue proposed actions and send the webhook.
+    pub async fn run(&self) -> Result<OrchestrationResult> {
+        self.run_all().instrument(self.run_span()).await
+    }
+
+    async fn run_all(&self) -> Result<OrchestrationResult> {
+        let _lock = self.lock().await?;
+        let started = Instant::now();
+        self.write_manifest(None);
+        info!("Starting Agent Orchestra - Mode: {}", self.mode);
+        info!("Timestamp: {}", self.timestamp.format("%Y%m%d-%H%M%S"));
+
+        let mut tasks = self.get_agent_tasks();
+        let order: Vec<String> = tasks.iter().map(|t| t.name.clone()).collect();
+        let mut checkpoint = Checkpoint::new(&self.run_id, &self.mode, self.timestamp);
+        if let Some(resumed) = &self.resumed {
+            // A gated agent whose output wasn't approved asks again
+            tasks.retain(|t| {
+                match resumed.completed(&t.name).filter(|done| {
+                    !t.requires_approval || done.approval.as_ref().is_some_and(|a| a.approved)
+                }) {
+                    Some(done) => {
+                        checkpoint.results.push(done.clone());
+                        false
+                    }
+                    None => true,
+                }
+            });
+            info!(
+                "Resuming {}: skipping {} completed agent(s)",
+                self.run_id,
+                checkpoint.results.len()
+            );
+        }
+        info!("Running {} agents", tasks.len());
+        self.hooks().pre_run().await?;
+
+        let writer = Arc::new(CheckpointWriter::new(
+            &self.output_dir,
+            checkpoint.clone(),
+            self.cipher.clone(),
+        ));
+        let sinks = ResultSinks {
+            checkpoint: Some(Arc::clone(&writer)),
+            ..self.sinks()
+        };
+        let (provider_outages, log_sampling, mut fresh) = self
+            .execute(tasks, &checkpoint.results, sinks.clone())
+            .await;
+        let mut order = order;
+        let mut consensus = None;
+        if let Some(spec) = self.consensus() {
+            let answers: Vec<AgentResult> =
+                fresh.iter().chain(&checkpoint.results).cloned().collect();
+            let resumed = self
+                .resumed
+                .as_ref()
+                .and_then(|r| r.completed(&spec.aggregator));
+            let outcome = match resumed {
+                Some(done) => {
+                    checkpoint.results.push(done.clone());
+                    ConsensusOutcome::tally(spec, &answers)
+                }
+                None => {
+                    let (outcome, aggregated) = self
+                        .aggregate(spec, &answers, &provider_outages, sinks.clone())
+                        .await;
+                    fresh.extend(aggregated);
+                    outcome
+                }
+            };
+            order.push(spec.aggregator.clone());
+            consensus = Some(outcome);
+        }
+        if let Some(spec) = self.map() {
+            let resumed = self
+                .resumed
+                .as_ref()
+                .and_then(|r| r.completed(&spec.reducer));
+            match resumed {
+                Some(done) => checkpoint.results.push(done.clone()),
+                None => {
+                    let partials: Vec<AgentResult> =
+                        fresh.iter().chain(&checkpoint.results).cloned().collect();
+                    let reduced = self
+                        .reduce(spec, &partials, &provider_outages, sinks.clone())
+                        .await;
+                    fresh.extend(reduced);
+                }
+            }
+            order.push(spec.reducer.clone());
+        }
+        let mut review = None;
+        if let Some(spec) = self.review() {
+            let draft = fresh
+                .iter()
+                .chain(&checkpoint.results)
+                .find(|r| r.agent == spec.author)
+                .cloned();
+            if let Some(draft) = draft {
+                let (outcome, steps) = self.refine(spec, &draft, &provider_outages, sinks).await;
+                fresh.extend(steps);
+                review = Some(outcome);
+            }
+            order.push(spec.critic.clone());
+        }
+        // Keep the mode's agent order regardless of which run produced a
+        // result; an agent that ran more than once keeps its latest one
+        let results: Vec<AgentResult> = order
+            .iter()
+            .filter_map(|name| {
+                fresh
+                    .iter()
+                    .rev()
+                    .chain(&checkpoint.results)
+                    .find(|r| &r.agent == name)
+                    .cloned()
+            })
+            .collect();
+        let results = agents::flatten_teams(results);
+        let fresh = agents::flatten_teams(fresh);
+        let actions = self.queue_actions(&results);
+
+        let orchestration = OrchestrationResult {
+            run_id: self.run_id.clone(),
+            timestamp: self.timestamp,
+            mode: self.mode.clone(),
+            global_client_mode: self.global_mode.to_string(),
+            provider_outages,
+            log_sampling,
+            actions,
+            rerun_of: None,
+            duration_ms: Some(started.elapsed().as_millis() as u64),
+            consensus,
+            review,
+            results,
+        };
+        self.finish(&orchestration, &fresh).await?;
+        if self.shutdown.is_triggered() {
+            warn!("Run interrupted; continue it with --resume {}", self.run_id);
+        } else {
+            writer.remove();
+        }
+        Ok(orchestration)
+    }
+
+    /// Re-execute only the agents that failed in `previous`, and write a new
+    /// results file where their fresh results replace the failed ones.
+    /// Agents that succeeded keep their earlier output.
+    pub async fn rerun(&self, previous: &OrchestrationResult) -> Result<OrchestrationResult> {
+        self.rerun_agents(previous, &previous.failed_agents()).await
+    }
+
+    /// Like [`rerun`](Self::rerun), but re-executes exactly `agents`.
+    pub async fn rerun_agents(
+        &self,
+        previous: &OrchestrationResult,
+        agents: &[&str],
+    ) -> Result<OrchestrationResult> {
+        self.rerun_some(previous, agents)
+            .instrument(self.run_span())
+            .await
+    }
+
+    async fn rerun_some(
+        &self,
+        previous: &OrchestrationResult,
+        agents: &[&str],
+    ) -> Result<OrchestrationResult> {
+        if previous.mode != self.mode {
+            anyhow::bail!(
+                "Run {} used mode '{}', but this orchestrator is in mode '{}'",
+                previous.run_id,
+                previous.mode,
+                self.mode
+            );
+        }
+        let _lock = self.lock().await?;
+        let started = Instant::now();
+        self.write_manifest(Some(&previous.run_id));
+        let tasks: Vec<AgentTask> = self
+            .get_agent_tasks()
+            .into_iter()
+            .filter(|t| agents.contains(&t.name.as_str()))
+            .collect();
+        let aggregator = self.consensus().map(|spec| spec.aggregator.as_str());
+        let critic = self.review().map(|spec| spec.critic.as_str());
+        let reducer = self.map().map(|spec| spec.reducer.as_str());
+        for name in agents {
+            let derived = [aggregator, critic, reducer].contains(&Some(*name));
+            if !tasks.iter().any(|t| t.name == *name) && !derived {
+                warn!(
+                    "Agent {} is no longer part of mode {}; keeping its earlier result",
+                    name, self.mode
+                );
+            }
+        }
+        info!(
+            "Rerunning {} agent(s) from {}",
+            tasks.len(),
+            previous.run_id
+        );
+
+        self.hooks().pre_run().await?;
+        let sinks = self.sinks();
+        let (provider_outages, sampling, fresh) =
+            self.execute(tasks, &previous.results, sinks.clone()).await;
+        let mut fresh = agents::flatten_teams(fresh);
+        let mut consensus = previous.consensus.clone();
+        if let Some(spec) = self.consensus() {
+            // New answers, or a rerun of the aggregator itself, need a new verdict
+            if !fresh.is_empty() || agents.contains(&spec.aggregator.as_str()) {
+                let answers: Vec<AgentResult> = previous
+                    .results
+                    .iter()
+                    .map(|old| fresh.iter().find(|r| r.agent == old.agent).unwrap_or(old))
+                    .chain(fresh.iter())
+                    .cloned()
+                    .collect();
+                let (outcome, aggregated) = self
+                    .aggregate(spec, &answers, &provider_outages, sinks.clone())
+                    .await;
+                fresh.extend(aggregated);
+                consensus = Some(outcome);
+            }
+        }
+        if let Some(spec) = self.map() {
+            // New partial results, or a rerun of the reducer itself, need a new reduction
+            if !fresh.is_empty() || agents.contains(&spec.reducer.as_str()) {
+                let partials: Vec<AgentResult> = previous
+                    .results
+                    .iter()
+                    .map(|old| fresh.iter().find(|r| r.agent == old.agent).unwrap_or(old))
+                    .cloned()
+                    .collect();
+                let reduced = self
+                    .reduce(spec, &partials, &provider_outages, sinks.clone())
+                    .await;
+                fresh.extend(reduced);
+            }
+        }
+        let mut review = previous.review.clone();
+        if let Some(spec) = self.review() {
+            // A new first draft, or a rerun of the critic, restarts the review
+            let draft = fresh
+                .iter()
+                .chain(&previous.results)
+                .find(|r| r.agent == spec.author)
+                .cloned();
+            let restart = !fresh.is_empty() || agents.contains(&spec.critic.as_str());
+            if let Some(draft) = draft.filter(|_| restart) {
+                let (outcome, steps) = self
+                    .refine(spec, &draft, &provider_outages, sinks.clone())
+                    .await;
+                fresh.extend(steps);
+                review = Some(outcome);
+            }
+        }
+        let mut actions = previous.actions.clone();
+        actions.extend(self.queue_actions(&fresh));
+        let rerun = |agent: &str| fresh.iter().any(|r| r.agent == agent);
+        let log_sampling = previous
+            .log_sampling
+            .iter()
+            .filter(|s| !rerun(&s.agent))
+            .cloned()
+            .chain(sampling)
+            .collect();
+        let results = previous
+            .results
+            .iter()
+            .map(|old| {
+                fresh
+                    .iter()
+                    .rev()
+                    .find(|r| r.agent == old.agent)
+                    .unwrap_or(old)
+                    .clone()
+            })
+            .collect();
+
+        let orchestration = OrchestrationResult {
+            run_id: self.run_id.clone(),
+            timestamp: self.timestamp,
+            mode: self.mode.clone(),
+            global_client_mode: self.global_mode.to_string(),
+            provider_outages,
+            log_sampling,
+            actions,
+            rerun_of: Some(previous.run_id.clone()),
+            duration_ms: Some(started.elapsed().as_millis() as u64),
+            consensus,
+            review,
+            results,
+        };
+        // History only gets the re-executed agents so costs aren't counted twice
+        self.finish(&orchestration, &fresh).await?;
+        Ok(orchestration)
+    }
+
+    /// Check provider status, gather context and run `tasks`, saving each
+    /// result to `sinks` as it arrives.
+    async fn execute(
+        &self,
+        mut tasks: Vec<AgentTask>,
+        earlier: &[AgentResult],
+        sinks: ResultSinks,
+    ) -> (Vec<ProviderStatus>, Vec<SamplingRecord>, Vec<AgentResult>) {
+        let outages = if self.config.client.status_check.enabled {
+            status::check_outages(&self.config.client.status_check).await
+        } else {
+            Vec::new()
+        };
+        if outages
+            .iter()
+            .any(|o| o.provider == status::ANTHROPIC_PROVIDER)
+        {
+            warn!("Anthropic outage reported; hybrid agents will use their CLI fallback");
+            for task in &mut tasks {
+                task.options.prefer_fallback = true;
+            }
+        }
+
+        for task in &mut tasks {
+            if let Some(spec) = &task.context {
+                let block = context::gather(&task.name, spec, task.options.egress.as_ref()).await;
+                task.injected = format!("{}{}", block, task.injected);
+            }
+        }
+
+        let log_sampling: Vec<SamplingRecord> = tasks
+            .iter()
+            .flat_map(|t| t.sampling.iter().cloned())
+            .collect();
+
+        let workers = self.scale_out(&mut tasks).await;
+        let results = self.run_tasks(tasks, earlier, sinks).await;
+        if let Some(workers) = workers {
+            workers.teardown().await;
+        }
+        (outages, log_sampling, results)
+    }
+
+    /// With `features.auto_scaling`, start workers for a large run and move
+    /// its eligible agents onto them. If that fails, the agents run locally.
+    async fn scale_out(&self, tasks: &mut [AgentTask]) -> Option<WorkerPool> {
+        if !self.config.features.auto_scaling {
+            return None;
+        }
+        let Some(ref config) = self.config.digitalocean.workers else {
+            warn!("features.auto_scaling is on, but digitalocean.workers is not configured");
+            return None;
+        };
+        if matches!(self.recording, Some(Recording::Replay(_))) {
+            return None;
+        }
+        let eligible = tasks
+            .iter()
+            .filter(|t| workers::eligible(t, &self.global_mode))
+            .count();
+        let provisioned =
+            WorkerPool::provision(config, &self.config.digitalocean, &self.run_id, eligible).await;
+        match provisioned {
+            Ok(Some(pool)) => {
+                let moved = pool.dispatch(tasks, &self.global_mode, self.api_keys.as_ref());
+                info!("Dispatched {} agent(s) to {} worker(s)", moved, pool.len());
+                Some(pool)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Auto-scaling failed; running agents locally: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Run `tasks` in parallel or one by one, reporting progress. A
+    /// consensus mode's voters and a map mode's mappers always run in
+    /// parallel. An agent whose prompt
+    /// reads `{{ results.NAME.output }}` runs after NAME when both are in
+    /// `tasks`, and otherwise sees NAME's result from `earlier`.
+    async fn run_tasks(
+        &self,
+        tasks: Vec<AgentTask>,
+        earlier: &[AgentResult],
+        sinks: ResultSinks,
+    ) -> Vec<AgentResult> {
+        let progress = sinks.progress.clone();
+        if let Some(progress) = &progress {
+            let names: Vec<String> = tasks.iter().map(|t| t.name.clone()).collect();
+            progress.queued(&names);
+        }
+        let parallel = self.config.features.parallel_execution
+            || self.consensus().is_some()
+            || self.map().is_some();
+        if parallel {
+            info!("Parallel execution enabled");
+        }
+        let mut results: Vec<AgentResult> = Vec::new();
+        for stage in stages(tasks) {
+            let gated: Vec<String> = stage
+                .iter()
+                .filter(|t| t.requires_approval)
+                .map(|t| t.name.clone())
+                .collect();
+            // All of them, for Tera templates left whole until now
+            let mut vars = self.vars.clone();
+            for result in earlier.iter().chain(&results) {
+                vars.extend(template::result_vars(result));
+            }
+            let mut stage: Vec<AgentTask> = stage
+                .into_iter()
+                .map(|task| {
+                    let mut task = with_results(task, &vars);
+                    task.inject();
+                    task
+                })
+                .collect();
+            stage.sort_by_key(|task| std::cmp::Reverse(task.priority));
+            let mut finished = if self.map().is_some_and(|spec| spec.batch) {
+                self.run_batched(stage, &sinks).await
+            } else if self.config.distributed.enabled {
+                self.run_distributed(stage, &sinks).await
+            } else if parallel {
+                self.run_parallel(stage, sinks.clone()).await
+            } else {
+                self.run_sequential(stage, &sinks).await
+            };
+            self.gate(&mut finished, &gated, &sinks).await;
+            results.extend(finished);
+        }
+        if let Some(progress) = &progress {
+            progress.done();
+        }
+        results
+    }
+
+    /// Wait for a decision on each `gated` result in `finished` and record
+    /// it on the result. A gate that doesn't pass cancels the rest of the run.
+    async fn gate(&self, finished: &mut [AgentResult], gated: &[String], sinks: &ResultSinks) {
+        for result in finished.iter_mut().filter(|r| gated.contains(&r.agent)) {
+            if sinks.halt.reason().is_some() {
+                return;
+            }
+            if result.status != "success" {
+                warn!("{} has no output to approve", result.agent);
+                sinks.halt.reject();
+                return;
+            }
+            info!("Waiting for approval of {}", result.agent);
+            let decision = tokio::select! {
+                decision = self.approver.decide(&self.run_id, result) => decision,
+                _ = sinks.halt.wait() => return,
+            };
+            let approval = match decision {
+                Ok(approval) => approval,
+                Err(e) => {
+                    error!("Approval of {} failed: {:#}", result.agent, e);
+                    sinks.halt.reject();
+                    return;
+                }
+            };
+            let approved = approval.approved;
+            result.approval = Some(approval);
+            // So a resumed run doesn't ask again
+            if let Some(checkpoint) = &sinks.checkpoint {
+                checkpoint.record(result);
+            }
+            if !approved {
+                warn!(
+                    "{} was rejected; cancelling the agents after it",
+                    result.agent
+                );
+                sinks.halt.reject();
+                return;
+            }
+            info!("{} approved", result.agent);
+        }
+    }
+
+    /// The consensus pattern this run's mode names, if any.
+    fn consensus(&self) -> Option<&ConsensusConfig> {
+        self.config.consensus.get(&self.mode)
+    }
+
+    /// Count the voters' answers in `results` and have the aggregator write
+    /// the final answer, unless it's disabled.
+    async fn aggregate(
+        &self,
+        spec: &ConsensusConfig,
+        results: &[AgentResult],
+        outages: &[ProviderStatus],
+        sinks: ResultSinks,
+    ) -> (ConsensusOutcome, Option<AgentResult>) {
+        let outcome = ConsensusOutcome::tally(spec, results);
+        info!("Consensus: {}", outcome.describe());
+        let Some(task) = self.aggregator_task(spec, results, &outcome) else {
+            return (outcome, None);
+        };
+        let aggregated = self.run_step(task, outages, sinks).await;
+        (outcome, aggregated)
+    }
+
+    /// The map-reduce pattern this run's mode names, if any.
+    fn map(&self) -> Option<&MapConfig> {
+        self.config.map.get(&self.mode)
+    }
+
+    /// Have the reducer combine the mapper's partial answers in `results`.
+    /// `None` if there is nothing to reduce or the reducer is disabled.
+    async fn reduce(
+        &self,
+        spec: &MapConfig,
+        results: &[AgentResult],
+        outages: &[ProviderStatus],
+        sinks: ResultSinks,
+    ) -> Option<AgentResult> {
+        if !results
+            .iter()
+            .any(|r| map::input_of(&spec.mapper, &r.agent).is_some())
+        {
+            warn!("No partial results for {} to reduce", spec.reducer);
+            return None;
+        }
+        let prompt = self.render(&spec.reducer, &spec.reduce_prompt);
+        let prompt = map::reducer_prompt(&spec.mapper, &prompt, results);
+        let Some(task) = self.prompted_task(&spec.reducer, prompt) else {
+            warn!(
+                "Reducer {} is disabled; leaving the partial results as they are",
+                spec.reducer
+            );
+            return None;
+        };
+        self.run_step(task, outages, sinks).await
+    }
+
+    /// The review pattern this run's mode names, if any.
+    fn review(&self) -> Option<&ReviewConfig> {
+        self.config.review.get(&self.mode)
+    }
+
+    /// Pass `draft` back and forth between critic and author until the
+    /// critic approves, `max_rounds` critiques are in, or a step fails. The
+    /// last draft isn't revised again, since nobody would review it. Returns
+    /// every step's result, in the order they ran.
+    async fn refine(
+        &self,
+        spec: &ReviewConfig,
+        draft: &AgentResult,
+        outages: &[ProviderStatus],
+        sinks: ResultSinks,
+    ) -> (ReviewOutcome, Vec<AgentResult>) {
+        let task = self.render(&spec.author, &spec.prompt);
+        let mut outcome = ReviewOutcome::default();
+        let mut steps = Vec::new();
+        let mut draft = draft.clone();
+        for round in 1..=spec.max_rounds {
+            if draft.status != "success" || self.shutdown.is_triggered() {
+                break;
+            }
+            let text = draft.output.clone().unwrap_or_default();
+            let prompt = review::critic_prompt(spec, &task, &text);
+            let Some(critic) = self.prompted_task(&spec.critic, prompt) else {
+                warn!(
+                    "Critic {} is disabled; keeping the draft as it is",
+                    spec.critic
+                );
+                break;
+            };
+            let Some(critique) = self.run_step(critic, outages, sinks.clone()).await else {
+                break;
+            };
+            let answered = critique.status == "success";
+            let feedback = critique.output.clone().filter(|_| answered);
+            let approved = feedback.as_deref().is_some_and(review::approved);
+            outcome.rounds.push(ReviewRound {
+                round,
+                draft: text.clone(),
+                critique: feedback.clone(),
+                approved,
+            });
+            steps.push(critique);
+            if approved {
+                outcome.approved = true;
+                break;
+            }
+            let Some(feedback) = feedback else {
+                break;
+            };
+            if round == spec.max_rounds {
+                break;
+            }
+            let prompt = review::revision_prompt(&task, &text, &feedback);
+            let Some(author) = self.prompted_task(&spec.author, prompt) else {
+                break;
+            };
+            let Some(revised) = self.run_step(author, outages, sinks.clone()).await else {
+                break;
+            };
+            steps.push(revised.clone());
+            draft = revised;
+        }
+        info!("Review: {}", outcome.describe());
+        (outcome, steps)
+    }
+
+    /// Run one orchestrator-built task on its own, on the CLI fallback if
+    /// the run started during an Anthropic outage.
+    async fn run_step(
+        &self,
+        mut task: AgentTask,
+        outages: &[ProviderStatus],
+        sinks: ResultSinks,
+    ) -> Option<AgentResult> {
+        if outages
+            .iter()
+            .any(|o| o.provider == status::ANTHROPIC_PROVIDER)
+        {
+            task.options.prefer_fallback = true;
+        }
+        self.run_tasks(vec![task], &[], sinks).await.pop()
+    }
+
+    /// Take the output directory's run lock, unless `orchestra.lock` is off.
+    async fn lock(&self) -> Result<Option<RunLock>> {
+        let config = &self.config.orchestra.lock;
+        if !config.enabled {
+            return Ok(None);
+        }
+        let wait = Duration::from_secs(config.wait_seconds);
+        RunLock::acquire(&self.output_dir, &self.run_id, wait)
+            .await
+            .map(Some)
+    }
+
+    /// Write the results and summary, record `executed` in history and notify.
+    async fn finish(
+        &self,
+        orchestration: &OrchestrationResult,
+        executed: &[AgentResult],
+    ) -> Result<()> {
+        fs::create_dir_all(&self.output_dir).context("Failed to create output directory")?;
+        let results_file = self.save_results(orchestration)?;
+        let summary_file = self.generate_summary(orchestration)?;
+        self.record_history(orchestration, executed);
+        let manifest_file = self.written_path(self.output_file("manifest", "json"));
+        let outputs = &self.config.outputs;
+        let files = if outputs.remote.is_some() || outputs.git.is_some() {
+            self.output_files(orchestration, &results_file, &summary_file, &manifest_file)
+        } else {
+            Vec::new()
+        };
+        let links = self.upload(&files).await;
+        self.commit_outputs(orchestration, &files).await;
+        self.notify(&orchestration.results, links).await;
+        self.hooks().post_run(orchestration, &results_file).await;
+
+        info!("Orchestration complete!");
+        Ok(())
+    }
+
+    /// Run agents one at a time (original behaviour).
+    async fn run_sequential(&self, tasks: Vec<AgentTask>, sinks: &ResultSinks) -> Vec<AgentResult> {
+        let mut results = Vec::new();
+        for task in tasks {
+            let agent_name = task.name.clone();
+            let mode_label = task
+                .client_mode
+                .as_deref()
+                .unwrap_or(&self.global_mode.to_string())
+                .to_string();
+
+            if let Some(reason) = sinks.halt.reason_for(&agent_name) {
+                let result = AgentResult::cancelled(agent_name, reason.to_string(), mode_label);
+                sinks.record(&result);
+                results.push(result);
+                continue;
+            }
+
+            sinks.started(&agent_name);
+            let (started_at, started) = (Utc::now(), Instant::now());
+            let span = self.agent_span(&agent_name);
+            let hooks = task.hooks.clone();
+            let result = async {
+                // Checked first so an agent cancelled before it started never runs
+                tokio::select! {
+                    biased;
+                    reason = sinks.halt.wait_for(&agent_name) => {
+                        warn!("Agent {} cancelled", agent_name);
+                        AgentResult::cancelled(agent_name, reason.to_string(), mode_label)
+                    }
+                    outcome = async {
+                        sinks.before(&agent_name, &hooks).await?;
+                        self.run_agent(task).await
+                    } => match outcome {
+                        Ok(result) => result,
+                        Err(e) => {
+                            error!("Agent execution failed: {:?}", e);
+                            AgentResult::failed(agent_name, format!("{:?}", e), mode_label)
+                        }
+                    },
+                }
+            }
+            .instrument(span)
+            .await
+            .with_started_at(started_at)
+            .with_duration(started.elapsed());
+            sinks.record(&result);
+            sinks.after(&result, &hooks).await;
+            results.push(result);
+
+            // Small delay between agents
+            tokio::select! {
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(2)) => {}
+                _ = sinks.halt.wait() => {}
+            }
+        }
+        results
+    }
+
+    /// Run agents concurrently via tokio::spawn, queued by priority and
+    /// started as `client.concurrency` allows, at most a map mode's
+    /// `concurrency` at a time.
+    async fn run_parallel(&self, tasks: Vec<AgentTask>, sinks: ResultSinks) -> Vec<AgentResult> {
+        let mut results: Vec<(usize, AgentResult)> = Vec::new();
+        let mut queue = TaskQueue::new(
+            self.config.client.concurrency.clone(),
+            self.map().map(|spec| spec.concurrency),
+        );
+
+        for (index, task) in tasks.into_iter().enumerate() {
+            let agent_name = task.name.clone();
+            let mode_label = task
+                .client_mode
+                .as_deref()
+                .unwrap_or(&self.global_mode.to_string())
+                .to_string();
+            // Each spawned task gets its own client
+            let client: Box<dyn AgentClient> =
+                match self.client_for(&task.name, task.client_mode.as_deref(), &task.options) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        let result =
+                            AgentResult::failed(agent_name, format!("{:?}", e), mode_label);
+                        sinks.record(&result);
+                        results.push((index, result));
+                        continue;
+                    }
+                };
+
+            let schema = match task.output_schema.as_ref().map(Schema::new).transpose() {
+                Ok(schema) => schema,
+                Err(e) => {
+                    let result = AgentResult::failed(agent_name, format!("{:?}", e), mode_label);
+                    sinks.record(&result);
+                    results.push((index, result));
+                    continue;
+                }
+            };
+            let instruction = schema.as_ref().map(Schema::instruction).unwrap_or_default();
+            let (prompt, budget_usage) = self.fit_budget(&task, instruction.len()).await;
+            let prompt = format!("{}{}", prompt, instruction);
+
+            let timeout_secs = task.timeout_seconds;
+            let system_prompt = task.system_prompt.clone();
+            let hooks = task.hooks.clone();
+            let sinks = sinks.clone();
+            let span = self.agent_span(&agent_name);
+            let (mode, priority) = (mode_label.clone(), task.priority);
+
+            let job = async move {
+                info!("Running agent: {} (timeout: {}s)", agent_name, timeout_secs);
+                sinks.started(&agent_name);
+                log_prompt(&prompt, system_prompt.as_deref());
+                let timeout = std::time::Duration::from_secs(timeout_secs);
+                let (started_at, started) = (Utc::now(), Instant::now());
+                let outcome = tokio::select! {
+                    biased;
+                    reason = sinks.halt.wait_for(&agent_name) => Err(reason),
+                    outcome = async {
+                        if let Err(e) = sinks.before(&agent_name, &hooks).await {
+                            return Ok(Err(e));
+                        }
+                        tokio::time::timeout(
+                            timeout,
+                            send_structured(
+                                client.as_ref(),
+                                &agent_name,
+                                &prompt,
+                                system_prompt.as_deref(),
+                                schema.as_ref(),
+                                budget_usage,
+                            ),
+                        )
+                        .await
+                    } => Ok(outcome),
+                };
+                let result = match outcome {
+                    Ok(Ok(Ok((response, structured_output)))) => {
+                        info!("Agent {} completed", agent_name);
+                        AgentResult::success(agent_name, response.text, mode_label)
+                            .with_model(response.model)
+                            .with_attempts(response.attempts)
+                            .with_usage(response.usage)
+                            .with_session_id(response.session_id)
+                            .with_teammates(response.teammates)
+                            .with_structured_output(structured_output)
+                    }
+                    Ok(Ok(Err(e))) => {
+                        error!("Agent {} failed: {:?}", agent_name, e);
+                        AgentResult::failed(agent_name, format!("{:?}", e), mode_label)
+                    }
+                    Ok(Err(_)) => {
+                        error!("Agent {} timed out after {}s", agent_name, timeout_secs);
+                        AgentResult::failed(
+                            agent_name,
+                            format!("Timed out after {}s", timeout_secs),
+                            mode_label,
+                        )
+                    }
+                    Err(reason) => {
+                        warn!("Agent {} cancelled", agent_name);
+                        AgentResult::cancelled(agent_name, reason.to_string(), mode_label)
+                    }
+                }
+                .with_started_at(started_at)
+                .with_duration(started.elapsed());
+                sinks.record(&result);
+                sinks.after(&result, &hooks).await;
+                result
+            }
+            .instrument(span);
+            queue.push(&mode, priority, (index, job));
+        }
+
+        let mut running = JoinSet::new();
+        let mut spawned = HashMap::new();
+        loop {
+            while let Some((mode, (index, job))) = queue.pop() {
+                let handle = running.spawn(job);
+                spawned.insert(handle.id(), (mode, index));
+            }
+            let Some(joined) = running.join_next_with_id().await else {
+                break;
+            };
+            let id = match &joined {
+                Ok((id, _)) => *id,
+                Err(e) => e.id(),
+            };
+            let (mode, index) = spawned.remove(&id).unwrap_or_default();
+            queue.finished(&mode);
+            match joined {
+                Ok((_, result)) => results.push((index, result)),
+                Err(e) => {
+                    error!("Task join error: {:?}", e);
+                    results.push((
+                        index,
+                        AgentResult::failed(
+                            "unknown".to_string(),
+                            format!("Task panicked: {:?}", e),
+                            "unknown".to_string(),
+                        ),
+                    ));
+                }
+            }
+        }
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Push `tasks` onto the distributed queue and collect what the workers
+    /// send back. Agent hooks still run here; an agent no worker returns
+    /// within its timeout plus `claim_timeout_seconds` fails.
+    async fn run_distributed(
+        &self,
+        tasks: Vec<AgentTask>,
+        sinks: &ResultSinks,
+    ) -> Vec<AgentResult> {
+        let config = &self.config.distributed;
+        let mut results = Vec::new();
+        let queue = match Queue::connect(config).await {
+            Ok(queue) => queue,
+            Err(e) => {
+                error!("{:#}", e);
+                for task in tasks {
+                    let mode_label = task
+                        .client_mode
+                        .as_deref()
+                        .unwrap_or(&self.global_mode.to_string())
+                        .to_string();
+                    let result = AgentResult::failed(task.name, format!("{:#}", e), mode_label);
+                    sinks.record(&result);
+                    results.push(result);
+                }
+                return results;
+            }
+        };
+        let wait = tasks.iter().map(|t| t.timeout_seconds).max().unwrap_or(0)
+            + config.claim_timeout_seconds;
+        let expires_at = Utc::now() + chrono::Duration::seconds(wait as i64);
+
+        // Job ID, agent, client label and hooks of each queued agent
+        let mut pending = Vec::new();
+        for mut task in tasks {
+            let agent_name = task.name.clone();
+            // Workers have their own CLIENT_MODE, so the coordinator's is sent along
+            let mode_label = task
+                .client_mode
+                .get_or_insert_with(|| self.global_mode.to_string())
+                .clone();
+            if let Some(reason) = sinks.halt.reason_for(&agent_name) {
+                let result = AgentResult::cancelled(agent_name, reason.to_string(), mode_label);
+                sinks.record(&result);
+                results.push(result);
+                continue;
+            }
+            // Hooks run here, and workers refuse jobs that carry any
+            let hooks = std::mem::take(&mut task.hooks);
+            if let Some(field) = distributed::refusal(&task) {
+                let result = AgentResult::failed(
+                    agent_name,
+                    format!("Can't run on a distributed worker: it sets {}", field),
+                    mode_label,
+                );
+                sinks.record(&result);
+                results.push(result);
+                continue;
+            }
+            sinks.started(&agent_name);
+            let queued = async {
+                sinks.before(&agent_name, &hooks).await?;
+                let job = Job::new(&self.run_id, task, expires_at);
+                queue.push(&job).await?;
+                Ok::<_, anyhow::Error>(job.id)
+            }
+            .await;
+            match queued {
+                Ok(id) => pending.push((id, agent_name, mode_label, hooks)),
+                Err(e) => {
+                    error!("Agent execution failed: {:?}", e);
+                    let result = AgentResult::failed(agent_name, format!("{:?}", e), mode_label);
+                    sinks.record(&result);
+                    sinks.after(&result, &hooks).await;
+                    results.push(result);
+                }
+            }
+        }
+        info!("Queued {} agent(s) for workers", pending.len());
+
+        let deadline = Instant::now() + Duration::from_secs(wait);
+        while !pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let reply = tokio::select! {
+                reply = queue.next_reply(&self.run_id, remaining.min(distributed::POLL_INTERVAL)) => reply,
+                _ = sinks.halt.wait() => break,
+            };
+            let reply = match reply {
+                Ok(Some(reply)) => reply,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Queue unavailable: {:#}", e);
+                    tokio::time::sleep(distributed::POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+            let Some(index) = pending.iter().position(|(id, ..)| *id == reply.job) else {
+                debug!("Ignoring a result for {}", reply.job);
+                continue;
+            };
+            let (_, _, _, hooks) = pending.remove(index);
+            info!("Agent {} finished on {}", reply.result.agent, reply.worker);
+            sinks.record(&reply.result);
+            sinks.after(&reply.result, &hooks).await;
+            results.push(reply.result);
+        }
+
+        // Workers that already took a cancelled agent still run it
+        let reason = sinks.halt.reason();
+        for (_, agent_name, mode_label, hooks) in pending {
+            let result = match reason {
+                Some(reason) => AgentResult::cancelled(agent_name, reason.to_string(), mode_label),
+                None => AgentResult::failed(
+                    agent_name,
+                    format!("No worker returned a result within {}s", wait),
+                    mode_label,
+                ),
+            };
+            sinks.record(&result);
+            sinks.after(&result, &hooks).await;
+            results.push(result);
+        }
+        results
+    }
+
+    /// Run one agent for `run_id`, as a `worker` does for a distributed run.
+    /// Hooks, checkpoints and approval stay with the coordinator.
+    pub async fn run_task(&self, run_id: &str, task: AgentTask) -> AgentResult {
+        let agent_name = task.name.clone();
+        let mode_label = task
+            .client_mode
+            .as_deref()
+            .unwrap_or(&self.global_mode.to_string())
+            .to_string();
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let span = tracing::info_span!(AGENT_SPAN, agent = %agent_name, run_id = %run_id);
+        match self.run_agent(task).instrument(span).await {
+            Ok(result) => result,
+            Err(e) => {
+                error!("Agent execution failed: {:?}", e);
+                AgentResult::failed(agent_name, format!("{:?}", e), mode_label)
+            }
+        }
+        .with_started_at(started_at)
+        .with_duration(started.elapsed())
+    }
+
+    async fn run_agent(&self, task: AgentTask) -> Result<AgentResult> {
+        info!(
+            "Running agent: {} (timeout: {}s)",
+            task.name, task.timeout_seconds
+        );
+
+        let mode_label = task
+            .client_mode
+            .as_deref()
+            .unwrap_or(&self.global_mode.to_string())
+            .to_string();
+
+        let client = self.client_for(&task.name, task.client_mode.as_deref(), &task.options)?;
+        let schema = task.output_schema.as_ref().map(Schema::new).transpose()?;
+        let instruction = schema.as_ref().map(Schema::instruction).unwrap_or_default();
+        let (prompt, budget_usage) = self.fit_budget(&task, instruction.len()).await;
+        let prompt = format!("{}{}", prompt, instruction);
+        log_prompt(&prompt, task.system_prompt.as_deref());
+
+        // The timeout covers every try at matching the schema
+        let timeout = std::time::Duration::from_secs(task.timeout_seconds);
+        let (response, structured_output) = tokio::time::timeout(
+            timeout,
+            send_structured(
+                client.as_ref(),
+                &task.name,
+                &prompt,
+                task.system_prompt.as_deref(),
+                schema.as_ref(),
+                budget_usage,
+            ),
+        )
+        .await
+        .context(format!(
+            "Agent {} timed out after {}s",
+            task.name, task.timeout_seconds
+        ))??;
+
+        info!("Agent {} completed", task.name);
+
+        Ok(AgentResult::success(task.name, response.text, mode_label)
+            .with_model(response.model)
+            .with_attempts(response.attempts)
+            .with_usage(response.usage)
+            .with_session_id(response.session_id)
+            .with_teammates(response.teammates)
+            .with_structured_output(structured_output))
+    }
+
+    /// `task`'s prompt within its input budget, leaving `reserved` bytes for
+    /// what is appended to it, and the usage of any summarizer calls.
+    async fn fit_budget(&self, task: &AgentTask, reserved: usize) -> (String, Usage) {
+        let system = task.system_prompt.as_deref().map_or(0, str::len);
+        let max = budget::prompt_bytes(&task.budget, system + reserved);
+        if task.prompt.len() <= max {
+            return (task.prompt.clone(), Usage::default());
+        }
+        warn!(
+            "Agent {}: prompt is ~{} tokens, over its budget of {}; cutting its middle",
+            task.name,
+            cost::estimate_tokens(&task.prompt) + (system as f64 / cost::CHARS_PER_TOKEN) as u64,
+            task.budget.max_input_tokens
+        );
+        if task.budget.strategy == BudgetStrategy::Summarize {
+            // The summary gets half of what is kept
+            if let Some((head, middle, tail)) = budget::split(&task.prompt, max / 2) {
+                match self.summarize(&task.budget, middle, max / 2).await {
+                    Ok((summary, usage)) => {
+                        let prompt = budget::with_summary(head, middle.len(), &summary, tail);
+                        return (prompt, usage);
+                    }
+                    Err(e) => warn!(
+                        "Agent {}: couldn't summarize the cut: {:#}; cutting it instead",
+                        task.name, e
+                    ),
+                }
+            }
+        }
+        (budget::head_tail(&task.prompt, max), Usage::default())
+    }
+
+    /// `text` condensed by `budget.summarizer` to at most `max` bytes, in as
+    /// many calls as the summarizer's own budget needs.
+    async fn summarize(
+        &self,
+        budget: &BudgetConfig,
+        text: &str,
+        max: usize,
+    ) -> Result<(String, Usage)> {
+        let name = budget
+            .summarizer
+            .as_deref()
+            .context("budget.summarizer is not set")?;
+        let summarizer = self
+            .agent_task(name, String::new())
+            .with_context(|| format!("Summarizer {} is disabled", name))?;
+        let client =
+            self.client_for(name, summarizer.client_mode.as_deref(), &summarizer.options)?;
+        let system = summarizer.system_prompt.as_deref();
+        let reserved = system.map_or(0, str::len) + budget::summary_prompt("").len();
+        let pieces = budget::chunks(text, budget::prompt_bytes(&summarizer.budget, reserved));
+        info!(
+            "Summarizing {} bytes with {} in {} call(s)",
+            text.len(),
+            name,
+            pieces.len()
+        );
+        let timeout = Duration::from_secs(summarizer.timeout_seconds);
+        let (mut summaries, mut usage) = (Vec::new(), Usage::default());
+        for piece in pieces {
+            let response =
+                tokio::time::timeout(timeout, client.send(&budget::summary_prompt(piece), system))
+                    .await
+                    .with_context(|| {
+                        format!("{} timed out after {}s", name, summarizer.timeout_seconds)
+                    })??;
+            usage.add(&response.usage);
+            summaries.push(response.text);
+        }
+        Ok((budget::head_tail(&summaries.join("\n"), max), usage))
+    }
+
+    /// Send the API-backed agents of `tasks` as one Message Batch, running
+    /// the rest in parallel as usual while it is processed.
+    async fn run_batched(&self, tasks: Vec<AgentTask>, sinks: &ResultSinks) -> Vec<AgentResult> {
+        // A recording wraps each agent's own client, so batches can't be recorded
+        let keys = self.api_keys.as_ref().filter(|_| self.recording.is_none());
+        let (batched, others): (Vec<AgentTask>, Vec<AgentTask>) = tasks
+            .into_iter()
+            .partition(|t| keys.is_some() && self.batchable(t));
+        if !others.is_empty() {
+            info!(
+                "{} agent(s) aren't API-backed or need several calls; running them as usual",
+                others.len()
+            );
+        }
+        let batch = async {
+            match keys {
+                Some(keys) if !batched.is_empty() => self.run_batch(batched, keys, sinks).await,
+                _ => Vec::new(),
+            }
+        };
+        let (mut results, batch_results) =
+            tokio::join!(self.run_parallel(others, sinks.clone()), batch);
+        results.extend(batch_results);
+        results
+    }
+
+    /// Whether `task` is a single call to the Messages API, which a batch
+    /// can make instead. An `output_schema` may need several.
+    fn batchable(&self, task: &AgentTask) -> bool {
+        let mode = match task.client_mode.as_deref() {
+            Some(mode) => ClientMode::from_str(mode).ok(),
+            None => Some(self.global_mode.clone()),
+        };
+        mode == Some(ClientMode::Api) && task.output_schema.is_none()
+    }
+
+    async fn run_batch(
+        &self,
+        tasks: Vec<AgentTask>,
+        keys: &Arc<KeyPool>,
+        sinks: &ResultSinks,
+    ) -> Vec<AgentResult> {
+        let mode_label = ClientMode::Api.to_string();
+        let mut results = Vec::new();
+        let mut requests = Vec::new();
+        // Custom ID, agent and hooks of each request
+        let mut pending = Vec::new();
+        for task in tasks {
+            let agent_name = task.name.clone();
+            if let Some(reason) = sinks.halt.reason_for(&agent_name) {
+                let result =
+                    AgentResult::cancelled(agent_name, reason.to_string(), mode_label.clone());
+                sinks.record(&result);
+                results.push(result);
+                continue;
+            }
+            sinks.started(&agent_name);
+            let hooks = task.hooks.clone();
+            if let Err(e) = sinks.before(&agent_name, &hooks).await {
+                error!("Agent execution failed: {:?}", e);
+                let result =
+                    AgentResult::failed(agent_name, format!("{:?}", e), mode_label.clone());
+                sinks.record(&result);
+                sinks.after(&result, &hooks).await;
+                results.push(result);
+                continue;
+            }
+            // Batch IDs allow only letters, digits, `-` and `_`
+            let custom_id = format!("agent-{}", pending.len());
+            let (prompt, budget_usage) = self.fit_budget(&task, 0).await;
+            let client = ApiClient::with_keys(keys).with_options(task.options.clone());
+            requests.push(client.batch_request(&custom_id, &prompt, task.system_prompt.as_deref()));
+            pending.push((custom_id, agent_name, hooks, budget_usage));
+        }
+        if pending.is_empty() {
+            return results;
+        }
+
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let mut replies = self
+            .await_batch(&BatchClient::with_keys(keys), &requests, sinks)
+            .await;
+        for (custom_id, agent_name, hooks, budget_usage) in pending {
+            let reply = match &mut replies {
+                Ok(replies) => replies
+                    .remove(&custom_id)
+                    .unwrap_or_else(|| Err("The batch returned no result for it".to_string())),
+                Err(e) => Err(format!("{:#}", e)),
+            };
+            let result = match (reply, sinks.halt.reason()) {
+                (Ok(mut response), _) => {
+                    if let (Some(input), Some(output)) =
+                        (response.usage.input_tokens, response.usage.output_tokens)
+                    {
+                        response.usage.cost_usd = Some(cost::batch_cost(input, output));
+                    }
+                    response.usage.add(&budget_usage);
+                    AgentResult::success(agent_name, response.text, mode_label.clone())
+                        .with_model(response.model)
+                        .with_attempts(response.attempts)
+                        .with_usage(response.usage)
+                }
+                (Err(_), Some(reason)) => {
+                    AgentResult::cancelled(agent_name, reason.to_string(), mode_label.clone())
+                }
+                (Err(e), None) => {
+                    error!("Agent {} failed: {}", agent_name, e);
+                    AgentResult::failed(agent_name, e, mode_label.clone())
+                }
+            }
+            .with_started_at(started_at)
+            .with_duration(started.elapsed());
+            sinks.record(&result);
+            sinks.after(&result, &hooks).await;
+            results.push(result);
+        }
+        results
+    }
+
+    /// Submit `requests` as a batch and wait for it to end, cancelling it if
+    /// the run is halted first.
+    async fn await_batch(
+        &self,
+        batch: &BatchClient,
+        requests: &[BatchRequest],
+        sinks: &ResultSinks,
+    ) -> Result<HashMap<String, Result<AgentResponse, String>>> {
+        let mut status = batch
+            .submit(requests)
+            .await
+            .context("Failed to submit the batch")?;
+        info!(
+            "Submitted {} request(s) as batch {}; results can take up to 24 hours",
+            requests.len(),
+            status.id
+        );
+        let mut counts = status.request_counts.clone();
+        while !status.ended() {
+            tokio::select! {
+                _ = tokio::time::sleep(BATCH_POLL_INTERVAL) => {}
+                _ = sinks.halt.wait() => {
+                    warn!("Cancelling batch {}", status.id);
+                    if let Err(e) = batch.cancel(&status.id).await {
+                        warn!("Failed to cancel batch {}: {:#}", status.id, e);
+                    }
+                    anyhow::bail!("The run stopped before batch {} ended", status.id);
+                }
+            }
+            match batch.status(&status.id).await {
+                Ok(latest) => status = latest,
+                Err(e) => warn!("Failed to check batch {}: {:#}", status.id, e),
+            }
+            if status.request_counts != counts {
+                counts = status.request_counts.clone();
+                info!(
+                    "Batch {}: {} succeeded, {} errored, {} processing",
+                    status.id, counts.succeeded, counts.errored, counts.processing
+                );
+            }
+        }
+        batch.results(&status).await
+    }
+
+    /// Span around a whole run, so every line it logs carries the run ID.
+    fn run_span(&self) -> Span {
+        tracing::info_span!("run", run_id = %self.run_id, mode = %self.mode)
+    }
+
+    /// Span an agent runs in. Events inside it also go to the agent's own
+    /// log file when an [`AgentLogLayer`](crate::agent_log::AgentLogLayer)
+    /// is installed.
+    fn agent_span(&self, agent: &str) -> Span {
+        tracing::info_span!(AGENT_SPAN, agent, run_id = %self.run_id)
+    }
+
+    fn hooks(&self) -> HookRunner {
+        HookRunner::new(&self.config.hooks, &self.run_id, &self.mode)
+    }
+
+    /// Sinks every run reports finished agents to; `run` adds its checkpoint.
+    fn sinks(&self) -> ResultSinks {
+        let events = &self.config.events;
+        ResultSinks {
+            checkpoint: None,
+            events: events
+                .enabled
+                .then(|| Arc::new(EventLog::new(&events.path, &self.run_id, &self.mode))),
+            progress: self.progress.clone(),
+            hooks: Some(Arc::new(self.hooks())),
+            halt: Halt::new(
+                self.shutdown.clone(),
+                self.config.features.fail_fast,
+                self.agent_cancel.clone(),
+            ),
+        }
+    }
+
+    /// The backend for one exchange, wrapped for record/replay when enabled.
+    fn client_for(
+        &self,
+        label: &str,
+        client_mode: Option<&str>,
+        options: &ClientOptions,
+    ) -> Result<Box<dyn AgentClient>> {
+        let circuit_breaker = &self.config.client.circuit_breaker;
+        let options = &ClientOptions {
+            health: self
+                .config
+                .features
+                .health_monitoring
+                .then(|| self.config.health.clone()),
+            circuit_breaker: circuit_breaker.enabled.then(|| circuit_breaker.clone()),
+            ..options.clone()
+        };
+        let make = || {
+            create_agent_client(
+                client_mode,
+                &self.global_mode,
+                self.api_keys.as_ref(),
+                options,
+            )
+        };
+        match &self.recording {
+            Some(recording) => recording.wrap(label, make),
+            None => make(),
+        }
+    }
+
+    /// Each agent's last CLI session from the history store, for agents with
+    /// `session: resume`. Empty, with a warning, if history can't be read.
+    fn last_sessions(&self) -> HashMap<String, String> {
+        if !self.config.history.enabled {
+            warn!("History is disabled, so there are no sessions to resume");
+            return HashMap::new();
+        }
+        RunStore::from_config(&self.config)
+            .last_sessions()
+            .unwrap_or_else(|e| {
+                warn!("Failed to read sessions from run history: {:#}", e);
+                HashMap::new()
+            })
+    }
+
+    /// Render `{{ var }}` placeholders in a prompt, warning about unknown ones.
+    /// `results.*` placeholders are left for [`run_tasks`](Self::run_tasks).
+    fn render(&self, agent: &str, text: &str) -> String {
+        let (rendered, unknown) = self.vars.render(text);
+        for name in unknown {
+            if !name.starts_with(template::RESULTS_PREFIX) {
+                warn!("Agent {}: unknown template variable '{}'", agent, name);
+            }
+        }
+        rendered
+    }
+
+    fn get_agent_tasks(&self) -> Vec<AgentTask> {
+        let agents = &self.config.agents;
+        let default_model = self.config.client.default_model.as_ref();
+        let default_options = || {
+            default_model
+                .map(ModelSpec::client_options)
+                .unwrap_or_default()
+        };
+
+        let sessions = std::cell::OnceCell::new();
+
+        let filter = |name: &str, prompt: &str| -> Option<AgentTask> {
+            let Some(agent_config) = agents.lookup(name) else {
+                return Some(
+                    AgentTask::new(name, self.render(name, prompt), 120)
+                        .with_options(default_options()),
+                );
+            };
+            if agent_config.enabled {
+                let prompt = self.render(name, agent_config.prompt.as_deref().unwrap_or(prompt));
+                let system_prompt = agent_config
+                    .system_prompt
+                    .as_deref()
+                    .map(|p| self.render(name, p));
+                let (logs, sampling) = match context::render_logs(name, &agent_config.logs) {
+                    Ok(rendered) => rendered,
+                    Err(e) => {
+                        warn!("Agent {}: skipping log injection: {:#}", name, e);
+                        (String::new(), Vec::new())
+                    }
+                };
+                let mut task = AgentTask::new(name, prompt, agent_config.timeout_seconds)
+                    .with_client_mode(agent_config.client_mode.clone())
+                    .with_system_prompt(system_prompt)
+                    .with_options(agent_config.client_options(default_model));
+                task.injected = logs;
+                task.sampling = sampling;
+                task.context = agent_config.context.clone();
+                task.requires_approval = agent_config.requires_approval;
+                task.priority = agent_config.priority;
+                task.hooks = agent_config.hooks.clone();
+                task.output_schema = agent_config.output_schema.clone();
+                task.budget = agent_config.budget.clone();
+                task.options.continuation = match agent_config.session {
+                    SessionMode::Fresh => Continuation::Fresh,
+                    SessionMode::Continue => Continuation::Latest,
+                    SessionMode::Resume => {
+                        match sessions.get_or_init(|| self.last_sessions()).get(name) {
+                            Some(session) => Continuation::Resume(session.clone()),
+                            None => {
+                                info!("Agent {}: no earlier session to resume", name);
+                                Continuation::Fresh
+                            }
+                        }
+                    }
+                };
+                Some(task)
+            } else {
+                warn!("Skipping disabled agent: {}", name);
+                None
+            }
+        };
+
+        let mut tasks: Vec<AgentTask> = match self.mode.as_str() {
+            "auto" => vec![
+                filter(
+                    "monitor",
+                    "Check system health, review logs, and identify any issues that need attention. Provide a brief status report.",
+                ),
+                filter(
+                    "analyzer",
+                    "Analyze recent activity patterns and suggest optimizations or improvements for the system.",
+                ),
+            ],
+            "research" => vec![
+                filter(
+                    "researcher",
+                    "Research the latest developments in AI agent orchestration and multi-agent systems. Summarize key findings.",
+                ),
+                filter(
+                    "synthesizer",
+                    "Based on current trends, suggest improvements to our agent orchestration framework.",
+                ),
+            ],
+            "analysis" => vec![
+                filter(
+                    "data_analyst",
+                    "Analyze system performance metrics and identify bottlenecks or areas for improvement.",
+                ),
+                filter(
+                    "reporter",
+                    "Generate a comprehensive report on system status and recommendations.\n\nThe data analyst found:\n{{ results.data_analyst.output }}",
+                ),
+            ],
+            "monitoring" => vec![
+                filter(
+                    "health_checker",
+                    "Perform comprehensive health checks on all system components and services.",
+                ),
+                filter(
+                    "alert_manager",
+                    "Review recent alerts and events, prioritize issues, and suggest actions.",
+                ),
+            ],
+            mode if self.config.consensus.contains_key(mode) => {
+                let spec = &self.config.consensus[mode];
+                info!("Consensus mode {}: {} voter(s), aggregated by {}", mode, spec.voters.len(), spec.aggregator);
+                spec.voters.iter().map(|voter| {
+                    let mut task = filter(voter, &spec.prompt)?;
+                    if spec.strategy == ConsensusStrategy::Vote {
+                        append_system_prompt(&mut task, consensus::VOTE_INSTRUCTION);
+                    }
+                    Some(task)
+                }).collect()
+            }
+            mode if self.config.map.contains_key(mode) => {
+                let spec = &self.config.map[mode];
+                let files = context::expand_glob(&spec.inputs);
+                if files.is_empty() {
+                    warn!("Map mode {}: no files match {}", mode, spec.inputs);
+                }
+                info!("Map mode {}: {} over {} file(s), reduced by {}", mode, spec.mapper, files.len(), spec.reducer);
+                files.iter().map(|file| self.map_task(spec, file)).collect()
+            }
+            mode if self.config.review.contains_key(mode) => {
+                let spec = &self.config.review[mode];
+                info!("Review mode {}: {} drafts, {} critiques (up to {} rounds)", mode, spec.author, spec.critic, spec.max_rounds);
+                vec![filter(&spec.author, &spec.prompt)]
+            }
+            // Agent Teams modes: use team definitions from config
+            mode if self.config.teams.enabled && self.config.teams.definitions.contains_key(mode) => {
+                let mut definition = self.config.teams.definitions[mode].clone();
+                info!("Using Agent Teams definition: {} ({})", mode, definition.description);
+                for teammate in &mut definition.teammates {
+                    teammate.role = self.render(&teammate.name, &teammate.role);
+                }
+                // The whole team runs as one lead session, so it gets every
+                // teammate's time.
+                let timeout = definition.teammates.iter().map(|t| t.timeout_seconds).sum();
+                let prompt = self.render(mode, &definition.description);
+                let team = TeamSession::new(mode, definition, &self.config.teams.tasks_dir, &self.run_id);
+                vec![Some(
+                    AgentTask::new(mode, prompt, timeout)
+                        .with_client_mode(Some("agent-teams".to_string()))
+                        .with_options(ClientOptions {
+                            team: Some(team),
+                            ..default_options()
+                        }),
+                )]
+            }
+            _ => {
+                warn!("Unknown mode '{}', using 'auto'", self.mode);
+                vec![
+                    filter(
+                        "monitor",
+                        "Check system health, review logs, and identify any issues that need attention. Provide a brief status report.",
+                    ),
+                    filter(
+                        "analyzer",
+                        "Analyze recent activity patterns and suggest optimizations or improvements for the system.",
+                    ),
+                ]
+            }
+        }
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if tasks.is_empty() {
+            warn!("All agents disabled for mode '{}'", self.mode);
+        }
+
+        for task in &mut tasks {
+            self.add_actions_instruction(task);
+        }
+        tasks
+    }
+
+    /// Tell the agent how to propose actions, when the action queue is on.
+    fn add_actions_instruction(&self, task: &mut AgentTask) {
+        if self.config.actions.enabled {
+            append_system_prompt(task, actions::ACTIONS_INSTRUCTION);
+        }
+    }
+
+    /// The aggregator's task: its own agent settings, with a prompt holding
+    /// every voter's answer. `None` if the aggregator is disabled.
+    fn aggregator_task(
+        &self,
+        spec: &ConsensusConfig,
+        results: &[AgentResult],
+        outcome: &ConsensusOutcome,
+    ) -> Option<AgentTask> {
+        let name = spec.aggregator.as_str();
+        let prompt = self.render(name, &spec.prompt);
+        let prompt = consensus::aggregator_prompt(spec, &prompt, results, outcome);
+        let task = self.prompted_task(name, prompt);
+        if task.is_none() {
+            warn!(
+                "Aggregator {} is disabled; leaving the answers as they are",
+                name
+            );
+        }
+        task
+    }
+
+    /// The mapper's task for one input file, run under its own name with
+    /// the file as context. `None` if the mapper is disabled.
+    fn map_task(&self, spec: &MapConfig, file: &Path) -> Option<AgentTask> {
+        let mut vars = self.vars.clone();
+        vars.insert("file", file.display().to_string());
+        let (prompt, unknown) = vars.render(&spec.prompt);
+        for name in unknown {
+            warn!(
+                "Agent {}: unknown template variable '{}'",
+                spec.mapper, name
+            );
+        }
+        let Some(mut task) = self.agent_task(&spec.mapper, prompt) else {
+            warn!("Skipping disabled agent: {}", spec.mapper);
+            return None;
+        };
+        task.name = map::task_name(&spec.mapper, file);
+        task.context = Some(ContextConfig {
+            files: vec![file.display().to_string()],
+            max_bytes_per_source: spec.max_bytes_per_file,
+            max_total_bytes: spec.max_bytes_per_file,
+            ..ContextConfig::default()
+        });
+        Some(task)
+    }
+
+    /// A task for `name` with its own agent settings but a prompt built by
+    /// the orchestrator. `None` if the agent is disabled.
+    fn prompted_task(&self, name: &str, prompt: String) -> Option<AgentTask> {
+        let mut task = self.agent_task(name, prompt)?;
+        self.add_actions_instruction(&mut task);
+        Some(task)
+    }
+
+    /// Like [`prompted_task`](Self::prompted_task), without the actions
+    /// instruction.
+    fn agent_task(&self, name: &str, prompt: String) -> Option<AgentTask> {
+        let default_model = self.config.client.default_model.as_ref();
+        let task = match self.config.agents.lookup(name) {
+            Some(agent) if !agent.enabled => return None,
+            Some(agent) => {
+                let mut task = AgentTask::new(name, prompt, agent.timeout_seconds)
+                    .with_client_mode(agent.client_mode.clone())
+                    .with_system_prompt(
+                        agent.system_prompt.as_deref().map(|p| self.render(name, p)),
+                    )
+                    .with_options(agent.client_options(default_model));
+                task.requires_approval = agent.requires_approval;
+                task.priority = agent.priority;
+                task.hooks = agent.hooks.clone();
+                task.output_schema = agent.output_schema.clone();
+                task.budget = agent.budget.clone();
+                task
+            }
+            None => AgentTask::new(name, prompt, 120).with_options(
+                default_model
+                    .map(ModelSpec::client_options)
+                    .unwrap_or_default(),
+            ),
+        };
+        Some(task)
+    }
+
+    fn save_results(&self, orchestration: &OrchestrationResult) -> Result<PathBuf> {
+        let is_team_mode =
+            self.config.teams.enabled && self.config.teams.definitions.contains_key(&self.mode);
+        let prefix = if is_team_mode {
+            &self.config.teams.output_prefix
+        } else {
+            "results"
+        };
+        let output_file = self.output_file(prefix, "json");
+
+        let json =
+            serde_json::to_string_pretty(orchestration).context("Failed to serialize results")?;
+
+        let output_file = self
+            .write_output(&output_file, json.as_bytes())
+            .context("Failed to write results file")?;
+
+        info!("Results saved to {}", output_file.display());
+        Ok(output_file)
+    }
+
+    /// `<output dir>/<prefix>-<run id>.<extension>`.
+    fn output_file(&self, prefix: &str, extension: &str) -> PathBuf {
+        self.output_dir
+            .join(format!("{}-{}.{}", prefix, self.run_id, extension))
+    }
+
+    /// Where [`write_output`](Self::write_output) puts `path`.
+    fn written_path(&self, path: PathBuf) -> PathBuf {
+        match self.cipher {
+            Some(_) => encryption::sealed_path(&path),
+            None => path,
+        }
+    }
+
+    /// Record what this run started from in its manifest. A failure is
+    /// logged; the run goes ahead without one.
+    fn write_manifest(&self, rerun_of: Option<&str>) {
+        let mut manifest = Manifest::new(
+            &self.config,
+            &self.run_id,
+            &self.mode,
+            self.timestamp,
+            &self.global_mode.to_string(),
+            self.api_keys.as_ref().map_or(0, |pool| pool.len()),
+            self.vars.all(),
+        );
+        if let Some(previous) = rerun_of {
+            manifest = manifest.with_rerun_of(previous);
+        }
+        let written = fs::create_dir_all(&self.output_dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(serde_json::to_vec_pretty(&manifest)?))
+            .and_then(|json| self.write_output(&self.output_file("manifest", "json"), &json));
+        if let Err(e) = written {
+            warn!("Failed to write the run manifest: {:#}", e);
+        }
+    }
+
+    /// Write an output file, encrypted to `<path>.enc` when
+    /// `outputs.encryption` is set. Returns the path written.
+    fn write_output(&self, path: &Path, contents: &[u8]) -> Result<PathBuf> {
+        match self.cipher {
+            Some(ref cipher) => cipher.write(path, contents),
+            None => {
+                fs::write(path, contents)?;
+                Ok(path.to_path_buf())
+            }
+        }
+    }
+
+    /// Append the run, with the agents it actually executed, to the history
+    /// store. Failures are logged, not fatal. Under `outputs.encryption` the
+    /// store doesn't get the agents' output text.
+    fn record_history(&self, orchestration: &OrchestrationResult, executed: &[AgentResult]) {
+        if !self.config.history.enabled {
+            return;
+        }
+        let record = |result: &AgentResult| {
+            let mut record = AgentRecord::from_result(result);
+            if self.cipher.is_some() {
+                record.output = None;
+            }
+            record
+        };
+        let record = RunRecord {
+            schema_version: store::RUN_SCHEMA_VERSION,
+            run_id: orchestration.run_id.clone(),
+            timestamp: orchestration.timestamp,
+            mode: orchestration.mode.clone(),
+            global_client_mode: orchestration.global_client_mode.clone(),
+            source: if orchestration.rerun_of.is_some() {
+                "rerun"
+            } else {
+                "run"
+            }
+            .to_string(),
+            agents: executed.iter().map(record).collect(),
+        };
+        if let Err(e) = RunStore::from_config(&self.config).append(&record) {
+            warn!("Failed to record run history: {:#}", e);
+        }
+    }
+
+    /// Collect actions proposed in agent outputs into the persistent queue.
+    /// Nothing is executed here; approved actions run via `actions execute`.
+    fn queue_actions(&self, results: &[AgentResult]) -> Vec<String> {
+        if !self.config.actions.enabled {
+            return Vec::new();
+        }
+        let mut queue = match ActionQueue::load(&self.config.actions.queue_file) {
+            Ok(q) => q,
+            Err(e) => {
+                warn!("Action queue unavailable: {:#}", e);
+                return Vec::new();
+            }
+        };
+        let mut ids = Vec::new();
+        for result in results {
+            if let Some(ref output) = result.output {
+                let proposed = actions::extract(output);
+                ids.extend(queue.record(&self.run_id, &result.agent, &proposed));
+            }
+        }
+        if !ids.is_empty() {
+            info!("Queued {} proposed action(s) for review", ids.len());
+            if let Err(e) = queue.save() {
+                warn!("Failed to save action queue: {:#}", e);
+            }
+        }
+        ids
+    }
+
+    /// Fire the completion webhook, if configured. Delivery failures are logged
+    /// but never fail the run.
+    async fn notify(&self, results: &[AgentResult], links: BTreeMap<String, String>) {
+        let notifications = &self.config.notifications;
+        if !notifications.enabled {
+            return;
+        }
+        let Some(webhook_config) = notifications.webhook.as_ref() else {
+            return;
+        };
+
+        let payload =
+            webhook::WebhookPayload::completed(&self.run_id, &self.mode, results).with_links(links);
+        if let Err(e) = webhook::send(webhook_config, &payload).await {
+            warn!("Webhook delivery failed: {:#}", e);
+        }
+    }
+
+    /// The results, summary and manifest files, and each agent's output as
+    /// `reports/<agent>.md`, for `outputs.remote` and `outputs.git`.
+    /// Reports are sealed when outputs are encrypted.
+    fn output_files(
+        &self,
+        orchestration: &OrchestrationResult,
+        results_file: &Path,
+        summary_file: &Path,
+        manifest_file: &Path,
+    ) -> Vec<OutputFile> {
+        let mut files = Vec::new();
+        let (results_type, summary_type) = match self.cipher {
+            Some(_) => ("application/octet-stream", "application/octet-stream"),
+            None => ("application/json", "text/plain; charset=utf-8"),
+        };
+        for (link, path, content_type) in [
+            ("results", results_file, results_type),
+            ("summary", summary_file, summary_type),
+            ("manifest", manifest_file, results_type),
+        ] {
+            match fs::read(path) {
+                Ok(body) => {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    files.push(OutputFile {
+                        link: Some(link),
+                        name: name.to_string(),
+                        body,
+                        content_type,
+                    });
+                }
+                Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+        for result in &orchestration.results {
+            if let Some(ref output) = result.output {
+                let name = format!("reports/{}.md", safe_name(&result.agent));
+                // Reports are as sensitive as the results they come from
+                let report = match self.cipher {
+                    Some(ref cipher) => cipher.seal(output.as_bytes()).map(|body| OutputFile {
+                        link: None,
+                        name: format!("{}.{}", name, encryption::EXTENSION),
+                        body,
+                        content_type: "application/octet-stream",
+                    }),
+                    None => Ok(OutputFile {
+                        link: None,
+                        name,
+                        body: output.clone().into_bytes(),
+                        content_type: "text/markdown; charset=utf-8",
+                    }),
+                };
+                match report {
+                    Ok(file) => files.push(file),
+                    Err(e) => warn!("Failed to encrypt the report of {}: {:#}", result.agent, e),
+                }
+            }
+        }
+        files
+    }
+
+    /// Upload `files` to `outputs.remote`, returning signed links to the
+    /// results and summary when configured. Failures are logged, not fatal.
+    async fn upload(&self, files: &[OutputFile]) -> BTreeMap<String, String> {
+        let Some(ref config) = self.config.outputs.remote else {
+            return BTreeMap::new();
+        };
+        let remote = match RemoteStore::new(config, &self.config.digitalocean) {
+            Ok(remote) => remote,
+            Err(e) => {
+                warn!("Remote outputs unavailable: {:#}", e);
+                return BTreeMap::new();
+            }
+        };
+
+        let mut links = BTreeMap::new();
+        let mut uploaded = 0;
+        for file in files {
+            let key = remote.key(&self.mode, &self.run_id, &file.name);
+            if let Err(e) = remote.put(&key, file.body.clone(), file.content_type).await {
+                warn!("{:#}", e);
+                continue;
+            }
+            uploaded += 1;
+            if let (Some(name), Some(seconds)) = (file.link, config.signed_url_seconds) {
+                let url = remote.signed_url(&key, Duration::from_secs(seconds), Utc::now());
+                links.insert(name.to_string(), url);
+            }
+        }
+        if uploaded > 0 {
+            info!(
+                "Uploaded {} file(s) to {}",
+                uploaded,
+                remote.display(&remote.key(&self.mode, &self.run_id, ""))
+            );
+        }
+        links
+    }
+
+    /// Commit `files` to `outputs.git`. Failures are logged, not fatal.
+    async fn commit_outputs(&self, orchestration: &OrchestrationResult, files: &[OutputFile]) {
+        let Some(ref config) = self.config.outputs.git else {
+            return;
+        };
+        let results = &orchestration.results;
+        let succeeded = results.iter().filter(|r| r.status == "success").count();
+        let failed = results.iter().filter(|r| r.status == "failed").count();
+        let mut vars = self.vars.clone();
+        vars.insert("succeeded", succeeded.to_string());
+        vars.insert("failed", failed.to_string());
+        vars.insert("total", results.len().to_string());
+        let render = |field: &str, text: &str| {
+            let (rendered, unknown) = vars.render(text);
+            for name in unknown {
+                warn!(
+                    "outputs.git.{}: unknown template variable '{}'",
+                    field, name
+                );
+            }
+            rendered
+        };
+        let dir = render("path", &config.path);
+        let message = render("message", &config.message);
+        let files: Vec<(String, Vec<u8>)> = files
+            .iter()
+            .map(|f| (f.name.clone(), f.body.clone()))
+            .collect();
+        match GitOutputs::new(config).commit(&dir, &files, &message).await {
+            Ok(Some(sha)) => info!("Committed outputs to {} as {}", config.repo, sha),
+            Ok(None) => info!(
+                "Outputs in {} are unchanged; nothing to commit",
+                config.repo
+            ),
+            Err(e) => warn!("Failed to commit outputs: {:#}", e),
+        }
+    }
+
+    fn generate_summary(&self, orchestration: &OrchestrationResult) -> Result<PathBuf> {
+        let results = &orchestration.results;
+        let timestamp_str = self.timestamp.format("%Y%m%d-%H%M%S").to_string();
+        let summary_file = self.output_file("summary", "txt");
+
+        let successful = results.iter().filter(|r| r.status == "success").count();
+        let cancelled = results.iter().filter(|r| r.status == "cancelled").count();
+        let failed = results.len() - successful - cancelled;
+
+        let mut summary = String::new();
+        summary.push_str("Agent Orchestra Run Summary\n");
+        summary.push_str("==================================================\n\n");
+        summary.push_str(&format!("Run ID: {}\n", self.run_id));
+        if let Some(ref previous) = orchestration.rerun_of {
+            summary.push_str(&format!("Rerun of: {}\n", previous));
+        }
+        summary.push_str(&format!("Timestamp: {}\n", timestamp_str));
+        summary.push_str(&format!("Mode: {}\n", self.mode));
+        summary.push_str(&format!("Global Client: {}\n", self.global_mode));
+        summary.push_str(&format!(
+            "Parallel: {}\n",
+            self.config.features.parallel_execution
+        ));
+        summary.push_str(&format!("Total Agents: {}\n", results.len()));
+        summary.push_str(&format!("Successful: {}\n", successful));
+        summary.push_str(&format!("Failed: {}\n", failed));
+        if cancelled > 0 {
+            summary.push_str(&format!("Cancelled: {}\n", cancelled));
+        }
+        if let Some(ref consensus) = orchestration.consensus {
+            summary.push_str(&format!("Consensus: {}\n", consensus.describe()));
+        }
+        if let Some(ref review) = orchestration.review {
+            summary.push_str(&format!("Review: {}\n", review.describe()));
+        }
+        if let Some(ms) = orchestration.duration_ms {
+            summary.push_str(&format!("Wall time: {}\n", seconds(ms)));
+        }
+        let slowest = results
+            .iter()
+            .filter_map(|r| Some((r, r.duration_ms?)))
+            .max_by_key(|(_, ms)| *ms);
+        if let Some((result, ms)) = slowest {
+            summary.push_str(&format!(
+                "Slowest agent: {} ({})\n",
+                result.agent,
+                seconds(ms)
+            ));
+        }
+        summary.push('\n');
+
+        for outage in &orchestration.provider_outages {
+            summary.push_str(&format!(
+                "Provider outage: {} ({}: {})\n",
+                outage.provider, outage.indicator, outage.description
+            ));
+        }
+        if !orchestration.actions.is_empty() {
+            summary.push_str(&format!(
+                "Proposed actions queued for review: {}\n",
+                orchestration.actions.join(", ")
+            ));
+        }
+        for record in &orchestration.log_sampling {
+            summary.push_str(&format!(
+                "Log sample: {} <- {} ({:?} {}, {} of {} lines)\n",
+                record.agent,
+                record.source,
+                record.strategy,
+                record.lines,
+                record.sampled_lines,
+                record.total_lines
+            ));
+        }
+
+        for result in results {
+            summary.push_str("\n──────────────────────────────────────────────────\n");
+            summary.push_str(&format!("Agent: {}\n", result.agent));
+            if let Some(ref team) = result.team {
+                summary.push_str(&format!("Team: {}\n", team));
+            }
+            if let Some(ref role) = result.role {
+                summary.push_str(&format!("Role: {}\n", role));
+            }
+            summary.push_str(&format!("Status: {}\n", result.status));
+            summary.push_str(&format!("Client: {}\n", result.client_mode));
+            if let Some(ref model) = result.model {
+                summary.push_str(&format!("Model: {}\n", model));
+            }
+            if let Some(started_at) = result.started_at {
+                summary.push_str(&format!(
+                    "Started: {}\n",
+                    started_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+                ));
+            }
+            if let Some(ms) = result.duration_ms {
+                summary.push_str(&format!("Duration: {}\n", seconds(ms)));
+            }
+            if let Some(attempts) = result.attempts.filter(|&n| n > 1) {
+                summary.push_str(&format!("Attempts: {}\n", attempts));
+            }
+            if let (Some(input), Some(output)) = (result.input_tokens, result.output_tokens) {
+                summary.push_str(&format!("Tokens: {} in, {} out\n", input, output));
+            }
+            if let Some(cost) = result.cost_usd {
+                summary.push_str(&format!("Reported cost: ${:.4}\n", cost));
+            }
+            if let Some(ref session) = result.session_id {
+                summary.push_str(&format!("Session: {}\n", session));
+            }
+
+            if result.status == "success" {
+                if let Some(ref output) = result.output {
+                    summary.push_str(&format!("Output:\n{}\n", output));
+                }
+            } else if let Some(ref error) = result.error {
+                summary.push_str(&format!("Error: {}\n", error));
+            }
+        }
+
+        let summary_file = self
+            .write_output(&summary_file, summary.as_bytes())
+            .context("Failed to write summary file")?;
+
+        info!("Summary saved to {}", summary_file.display());
+        Ok(summary_file)
+    }
+}
+
+/// Milliseconds as seconds with one decimal, e.g. `12.3s`.
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+/// Send `prompt`, and again with what was wrong while the reply doesn't
+/// match `schema`, up to [`structured::MAX_ATTEMPTS`] tries. Returns the
+/// matching reply, with attempts and usage (on top of `usage`) summed over
+/// every try, and its parsed value.
+async fn send_structured(
+    client: &dyn AgentClient,
+    agent: &str,
+    prompt: &str,
+    system_prompt: Option<&str>,
+    schema: Option<&Schema>,
+    mut usage: Usage,
+) -> Result<(AgentResponse, Option<serde_json::Value>)> {
+    let mut request = prompt.to_string();
+    let mut attempts = 0;
+    let mut round = 0;
+    loop {
+        round += 1;
+        let mut response = client
+            .send(&request, system_prompt)
+            .await
+            .context("Failed to send message to Claude")?;
+        attempts += response.attempts;
+        usage.add(&response.usage);
+        let Some(schema) = schema else {
+            response.usage = usage;
+            return Ok((response, None));
+        };
+        match schema.extract(&response.text) {
+            Ok(value) => {
+                response.attempts = attempts;
+                response.usage = usage;
+                return Ok((response, Some(value)));
+            }
+            Err(errors) if round < structured::MAX_ATTEMPTS => {
+                warn!(
+                    "Agent {}: reply doesn't match output_schema ({}), asking again",
+                    agent,
+                    errors.join("; ")
+                );
+                request = structured::retry_prompt(prompt, &response.text, &errors);
+            }
+            Err(errors) => anyhow::bail!(
+                "No reply matched output_schema after {} tries: {}",
+                round,
+                errors.join("; ")
+            ),
+        }
+    }
+}
+
+/// Debug-level, so prompts only show up in the agent's own log file.
+fn log_prompt(prompt: &str, system_prompt: Option<&str>) {
+    if let Some(system) = system_prompt {
+        debug!("System prompt:\n{}", system);
+    }
+    debug!("Prompt:\n{}", prompt);
+}
+
+/// One of a run's output files, as uploaded or committed.
+struct OutputFile {
+    /// Name of the notification link to it, if it gets one.
+    link: Option<&'static str>,
+    /// Path relative to the run's outputs, e.g. `reports/monitor.md`.
+    name: String,
+    body: Vec<u8>,
+    content_type: &'static str,
+}
+
+/// Where each agent's result goes as soon as it finishes, before the run
+/// writes its results file. Cloned into parallel agent tasks.
+#[derive(Clone, Default)]
+struct ResultSinks {
+    checkpoint: Option<Arc<CheckpointWriter>>,
+    events: Option<Arc<EventLog>>,
+    progress: Option<Arc<dyn Progress>>,
+    hooks: Option<Arc<HookRunner>>,
+    /// Sees every result, so `fail_fast` can stop the rest.
+    halt: Halt,
+}
+
+impl ResultSinks {
+    fn started(&self, agent: &str) {
+        if let Some(progress) = &self.progress {
+            progress.started(agent);
+        }
+    }
+
+    fn record(&self, result: &AgentResult) {
+        if let Some(progress) = &self.progress {
+            progress.finished(result);
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.record(result);
+        }
+        if let Some(events) = &self.events {
+            events.record(result);
+        }
+        self.halt.observe(result);
+    }
+
+    /// The `pre_agent` hooks; an error fails the agent before it runs.
+    async fn before(&self, agent: &str, own: &AgentHooks) -> Result<()> {
+        match &self.hooks {
+            Some(hooks) => hooks.pre_agent(agent, own).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The `post_agent` hooks, once the result is recorded.
+    async fn after(&self, result: &AgentResult, own: &AgentHooks) {
+        if let Some(hooks) = &self.hooks {
+            hooks.post_agent(result, own).await;
+        }
+    }
+}
+
+/// Stops the remaining agents of a run when the shutdown fires, when an
+/// approval gate doesn't pass or, with `fail_fast`, once an agent fails.
+#[derive(Clone, Default)]
+struct Halt {
+    shutdown: Shutdown,
+    /// Fired by the first failure when `fail_fast` is on.
+    failure: Option<(Arc<watch::Sender<bool>>, Shutdown)>,
+    /// Fired when an approval gate doesn't pass.
+    rejection: Option<(Arc<watch::Sender<bool>>, Shutdown)>,
+    /// Agents cancelled one at a time.
+    agents: AgentCancel,
+}
+
+impl Halt {
+    fn new(shutdown: Shutdown, fail_fast: bool, agents: AgentCancel) -> Self {
+        let signal = || {
+            let (tx, fired) = Shutdown::channel();
+            (Arc::new(tx), fired)
+        };
+        Self {
+            shutdown,
+            failure: fail_fast.then(signal),
+            rejection: Some(signal()),
+            agents,
+        }
+    }
+
+    /// Cancel the remaining agents because a gated output wasn't approved.
+    fn reject(&self) {
+        if let Some((tx, _)) = &self.rejection {
+            tx.send_replace(true);
+        }
+    }
+
+    fn observe(&self, result: &AgentResult) {
+        if let Some((tx, _)) = &self.failure {
+            if result.status == "failed" && !tx.send_replace(true) {
+                warn!(
+                    "fail_fast: {} failed; cancelling the remaining agents",
+                    result.agent
+                );
+            }
+        }
+    }
+
+    /// Why the remaining agents are cancelled, once they should be.
+    fn reason(&self) -> Option<&'static str> {
+        if self.shutdown.is_triggered() {
+            Some("Cancelled by shutdown")
+        } else if self.failure.as_ref().is_some_and(|(_, f)| f.is_triggered()) {
+            Some("Cancelled by fail_fast after another agent failed")
+        } else if self
+            .rejection
+            .as_ref()
+            .is_some_and(|(_, r)| r.is_triggered())
+        {
+            Some("Cancelled because an approval gate didn't pass")
+        } else {
+            None
+        }
+    }
+
+    /// Resolves with the reason once the remaining agents should stop.
+    async fn wait(&self) -> &'static str {
+        let fired = |signal: &Option<(Arc<watch::Sender<bool>>, Shutdown)>| {
+            let signal = signal.as_ref().map(|(_, fired)| fired.clone());
+            async move {
+                match signal {
+                    Some(fired) => fired.wait().await,
+                    None => std::future::pending().await,
+                }
+            }
+        };
+        tokio::select! {
+            _ = self.shutdown.wait() => {}
+            _ = fired(&self.failure) => {}
+            _ = fired(&self.rejection) => {}
+        }
+        self.reason().unwrap_or("Cancelled")
+    }
+
+    /// Why `agent` is cancelled, whether with the rest or on its own.
+    fn reason_for(&self, agent: &str) -> Option<&'static str> {
+        self.reason().or_else(|| {
+            self.agents
+                .signal(agent)
+                .is_triggered()
+                .then_some("Cancelled by request")
+        })
+    }
+
+    /// Like [`wait`](Self::wait), but also resolves when only `agent` is
+    /// cancelled.
+    async fn wait_for(&self, agent: &str) -> &'static str {
+        let cancelled = self.agents.signal(agent);
+        tokio::select! {
+            reason = self.wait() => reason,
+            _ = cancelled.wait() => "Cancelled by request",
+        }
+    }
+}
+
+/// The agents whose results `task`'s prompts read.
+fn result_refs(task: &AgentTask) -> Vec<String> {
+    let mut refs = template::result_refs(&task.prompt);
+    if let Some(system) = &task.system_prompt {
+        refs.extend(template::result_refs(system));
+    }
+    refs
+}
+
+/// Batches of `tasks` in run order: each batch holds the tasks that read no
+/// result of a task in a later batch, and that come after no gated task of
+/// a later batch. Tasks reading each other in a cycle share the last batch
+/// and see neither result.
+fn stages(tasks: Vec<AgentTask>) -> Vec<Vec<AgentTask>> {
+    let mut stages = Vec::new();
+    let mut pending = tasks;
+    while !pending.is_empty() {
+        let waiting: Vec<String> = pending.iter().map(|t| t.name.clone()).collect();
+        let (mut ready, mut blocked) = (Vec::new(), Vec::new());
+        let mut gated = false;
+        for task in pending {
+            let reads_pending = result_refs(&task)
+                .iter()
+                .any(|agent| *agent != task.name && waiting.contains(agent));
+            let next = if gated || reads_pending {
+                &mut blocked
+            } else {
+                &mut ready
+            };
+            gated |= task.requires_approval;
+            next.push(task);
+        }
+        if ready.is_empty() {
+            stages.push(blocked);
+            break;
+        }
+        stages.push(ready);
+        pending = blocked;
+    }
+    stages
+}
+
+/// Fill in `task`'s `results.*` placeholders from `vars`, and nothing else,
+/// warning about agents that have no result to read. Runs before the logs
+/// and context are injected, which are never rendered.
+fn with_results(mut task: AgentTask, vars: &TemplateVars) -> AgentTask {
+    if result_refs(&task).is_empty() {
+        return task;
+    }
+    let (prompt, mut unknown) = vars.render_results(&task.prompt);
+    task.prompt = prompt;
+    if let Some(system) = &task.system_prompt {
+        let (system, missing) = vars.render_results(system);
+        task.system_prompt = Some(system);
+        unknown.extend(missing);
+    }
+    for name in unknown {
+        if name.starts_with(template::RESULTS_PREFIX) {
+            warn!("Agent {}: no result for '{}' in this run", task.name, name);
+        }
+    }
+    task
+}
+
+/// Add `text` to the end of the task's system prompt.
+fn append_system_prompt(task: &mut AgentTask, text: &str) {
+    task.system_prompt = Some(match task.system_prompt.take() {
+        Some(sys) => format!("{}\n\n{}", sys.trim_end(), text),
+        None => text.to_string(),
+    });
+}
+
+fn push_indented(out: &mut String, text: &str) {
+    for line in text.trim_end().lines() {
+        let _ = writeln!(out, "    {}", line);
+    }
+}
+
+/// Configures an [`Orchestrator`] without touching env vars or the CLI.
+///
+/// ```no_run
+/// # async fn demo() -> anyhow::Result<()> {
+/// use agent_orchestra::{client::ClientMode, config::Config, Orchestrator};
+///
+/// let orchestrator = Orchestrator::builder()
+///     .config(Config::load("config/orchestra.yml")?)
+///     .mode("analysis")
+///     .client_mode(ClientMode::Api)
+///     .api_key(std::env::var("ANTHROPIC_API_KEY")?)
+///     .build()?;
+/// let result = orchestrator.run().await?;
+/// println!("{} agents ran", result.results.len());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct OrchestratorBuilder {
+    config: Option<Config>,
+    mode: Option<String>,
+    client_mode: Option<ClientMode>,
+    api_keys: Vec<String>,
+    output_dir: Option<PathBuf>,
+    vars: Vec<(String, String)>,
+    skip_client_check: bool,
+    recording: Option<Recording>,
+    resume: Option<String>,
+    shutdown: Shutdown,
+    agent_cancel: AgentCancel,
+    progress: Option<Arc<dyn Progress>>,
+    approver: Option<Arc<dyn Approver>>,
+}
+
+impl OrchestratorBuilder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Orchestration mode: `auto`, `research`, `analysis`, `monitoring` or a team name.
+    pub fn mode(mut self, mode: impl Into<String>) -> Self {
+        self.mode = Some(mode.into());
+        self
+    }
+
+    /// Backend used by agents without their own `client_mode`.
+    pub fn client_mode(mut self, mode: ClientMode) -> Self {
+        self.client_mode = Some(mode);
+        self
+    }
+
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_keys.push(key.into());
+        self
+    }
+
+    /// Several API keys; each API or hybrid client takes one as set by
+    /// `client.api_keys.strategy`.
+    pub fn api_keys(mut self, keys: impl IntoIterator<Item = String>) -> Self {
+        self.api_keys.extend(keys);
+        self
+    }
+
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Add a template variable available to every prompt.
+    pub fn var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.push((key.into(), value.into()));
+        self
+    }
+
+    /// Don't require a usable client at build time (for plans and dry runs).
+    pub fn skip_client_check(mut self) -> Self {
+        self.skip_client_check = true;
+        self
+    }
+
+    /// Save every backend reply under `dir` as it arrives.
+    pub fn record(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.recording = Some(Recording::Record(dir.into()));
+        self
+    }
+
+    /// Answer from replies saved by [`record`](Self::record) instead of
+    /// calling a backend.
+    pub fn replay(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.recording = Some(Recording::Replay(dir.into()));
+        self
+    }
+
+    /// Continue an interrupted run from its checkpoint: its agents that
+    /// already succeeded are skipped, and the run keeps its ID and timestamp.
+    pub fn resume(mut self, run_id: impl Into<String>) -> Self {
+        self.resume = Some(run_id.into());
+        self
+    }
+
+    /// Cancel agents still running when `shutdown` fires. The run then
+    /// marks them `cancelled`, writes its results and keeps its checkpoint.
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Cancel single agents through `cancel`; they are marked `cancelled`
+    /// and the rest of the run carries on.
+    pub fn agent_cancel(mut self, cancel: AgentCancel) -> Self {
+        self.agent_cancel = cancel;
+        self
+    }
+
+    /// Report agents starting and finishing to `progress`, e.g. a
+    /// [`LiveProgress`](crate::progress::LiveProgress) view.
+    pub fn progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Ask `approver` about gated outputs instead of filing them under
+    /// `approvals.dir` for `approvals approve|reject`.
+    pub fn approver(mut self, approver: Arc<dyn Approver>) -> Self {
+        self.approver = Some(approver);
+        self
+    }
+
+    pub fn build(self) -> Result<Orchestrator> {
+        let global_mode = self.client_mode.unwrap_or(ClientMode::ClaudeCode);
+        let strategy = self
+            .config
+            .as_ref()
+            .map(|c| c.client.api_keys.strategy)
+            .unwrap_or_default();
+        let api_keys = KeyPool::new(self.api_keys, strategy);
+        // Checked up front so a missing key can't lose a run's results
+        let cipher = match self
+            .config
+            .as_ref()
+            .and_then(|c| c.outputs.encryption.as_ref())
+        {
+            Some(encryption) => Some(Arc::new(Cipher::from_config(encryption)?)),
+            None => None,
+        };
+        // Agent logs hold prompts and outputs in plain text
+        if let Some(ref dir) = self
+            .config
+            .as_ref()
+            .filter(|_| cipher.is_some())
+            .and_then(|c| c.logging.agent_logs.as_ref())
+        {
+            anyhow::bail!(
+                "outputs.encryption can't be used with agent logs ({}); set logging.agent_logs: null",
+                dir
+            );
+        }
+        let replaying = matches!(self.recording, Some(Recording::Replay(_)));
+        // Validate that the global mode can be created (e.g. key present for api/hybrid)
+        if !self.skip_client_check && !replaying {
+            create_client(&global_mode, api_keys.as_ref())?;
+        }
+        info!("Global client mode: {}", global_mode);
+
+        let output_dir = self.output_dir.unwrap_or_else(|| PathBuf::from("outputs"));
+        let resumed = match &self.resume {
+            Some(run_id) => Some(Checkpoint::load(&output_dir, run_id, cipher.as_deref())?),
+            None => None,
+        };
+        let (mode, timestamp, run_id) = match &resumed {
+            Some(checkpoint) => {
+                if self.mode.as_ref().is_some_and(|m| *m != checkpoint.mode) {
+                    anyhow::bail!(
+                        "Run {} used mode '{}'; it can't be resumed in another mode",
+                        checkpoint.run_id,
+                        checkpoint.mode
+                    );
+                }
+                (
+                    checkpoint.mode.clone(),
+                    checkpoint.timestamp,
+                    checkpoint.run_id.clone(),
+                )
+            }
+            None => {
+                let mode = self.mode.unwrap_or_else(|| "auto".to_string());
+                let timestamp = Utc::now();
+                let run_id = ulid::new(timestamp);
+                (mode, timestamp, run_id)
+            }
+        };
+
+        let mut vars = TemplateVars::new();
+        vars.insert("now", timestamp.to_rfc3339());
+        vars.insert("hostname", template::hostname());
+        vars.insert("mode", mode.clone());
+        vars.insert("run_id", run_id.clone());
+        vars.extend(self.vars);
+
+        let config = self.config.unwrap_or_default();
+        let approver = self.approver.unwrap_or_else(|| {
+            Arc::new(FileApprover::new(&config.approvals).with_cipher(cipher.clone()))
+        });
+        Ok(Orchestrator {
+            global_mode,
+            api_keys,
+            cipher,
+            config,
+            mode,
+            timestamp,
+            run_id,
+            output_dir,
+            vars,
+            recording: self.recording,
+            resumed,
+            shutdown: self.shutdown,
+            agent_cancel: self.agent_cancel,
+            progress: self.progress,
+            approver,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EncryptionConfig;
+
+    #[test]
+    fn test_builder_checks_client_unless_skipped() {
+        let missing_key = Orchestrator::builder().client_mode(ClientMode::Api).build();
+        assert!(missing_key.is_err());
+
+        let orchestrator = Orchestrator::builder()
+            .client_mode(ClientMode::Api)
+            .skip_client_check()
+            .build()
+            .unwrap();
+        assert_eq!(orchestrator.mode, "auto");
+        assert_eq!(orchestrator.output_dir, PathBuf::from("outputs"));
+    }
+
+    #[test]
+    fn test_builder_mode_and_vars_reach_prompts() {
+        let mut config = Config::default();
+        config.agents.reporter.prompt = Some("Report for {{ team }} ({{ mode }})".to_string());
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("analysis")
+            .api_key("test-key")
+            .client_mode(ClientMode::Api)
+            .var("team", "payments")
+            .build()
+            .unwrap();
+        assert!(ulid::timestamp(&orchestrator.run_id).is_some());
+        let plan = orchestrator.plan();
+        assert!(plan.contains("[reporter] client: api"));
+        assert!(plan.contains("Report for payments (analysis)"));
+    }
+
+    #[tokio::test]
+    async fn test_rerun_replaces_only_failed_agents() {
+        let dir = std::env::temp_dir().join(format!("orchestra-rerun-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.parallel_execution = true;
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("monitoring")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+
+        let mut kept = AgentResult::success(
+            "alert_manager".to_string(),
+            "old".to_string(),
+            "api".to_string(),
+        );
+        kept.model = Some("claude-sonnet-4-5".to_string());
+        let previous = OrchestrationResult {
+            run_id: "01KF3M2Q8V6W9XJ5T0R7C4N1BD".to_string(),
+            timestamp: Utc::now(),
+            mode: "monitoring".to_string(),
+            global_client_mode: "api".to_string(),
+            provider_outages: Vec::new(),
+            log_sampling: Vec::new(),
+            actions: Vec::new(),
+            rerun_of: None,
+            duration_ms: None,
+            consensus: None,
+            review: None,
+            results: vec![
+                AgentResult::failed(
+                    "health_checker".to_string(),
+                    "timed out".to_string(),
+                    "api".to_string(),
+                ),
+                kept.clone(),
+            ],
+        };
+
+        let result = orchestrator.rerun(&previous).await.unwrap();
+        assert_eq!(
+            result.rerun_of.as_deref(),
+            Some("01KF3M2Q8V6W9XJ5T0R7C4N1BD")
+        );
+        assert!(result.failed_agents().is_empty());
+        assert_eq!(result.results[0].agent, "health_checker");
+        assert!(result.results[0]
+            .output
+            .as_deref()
+            .unwrap()
+            .starts_with("[mock]"));
+        assert_eq!(result.results[1].output, kept.output);
+        assert_eq!(result.results[1].model, kept.model);
+        // The results, the summary, the manifest and the run lock
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let other_mode = OrchestrationResult {
+            mode: "research".to_string(),
+            ..previous
+        };
+        assert!(orchestrator.rerun(&other_mode).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_outputs_load_back() {
+        let dir = std::env::temp_dir().join(format!("orchestra-sealed-{}", std::process::id()));
+        std::env::set_var("ORCHESTRA_TEST_OUTPUTS_KEY", "07".repeat(32));
+        let encryption = EncryptionConfig {
+            key_env: "ORCHESTRA_TEST_OUTPUTS_KEY".to_string(),
+        };
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.outputs.encryption = Some(encryption.clone());
+        assert!(Orchestrator::builder()
+            .config(config.clone())
+            .client_mode(ClientMode::Mock)
+            .build()
+            .is_err());
+        config.logging.agent_logs = None;
+        let orchestrator = Orchestrator::builder()
+            .config(config.clone())
+            .mode("monitoring")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+        let result = orchestrator.run().await.unwrap();
+
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == encryption::EXTENSION))
+            .collect();
+        files.sort();
+        let names: Vec<String> = files
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        let run_id = &result.run_id;
+        assert_eq!(
+            names,
+            [
+                format!("manifest-{}.json.enc", run_id),
+                format!("results-{}.json.enc", run_id),
+                format!("summary-{}.txt.enc", run_id),
+            ]
+        );
+        assert!(!fs::read(&files[2]).unwrap().starts_with(b"Agent Orchestra"));
+
+        assert!(OrchestrationResult::load(&files[1]).is_err());
+        let cipher = Cipher::from_config(&encryption).unwrap();
+        let loaded = OrchestrationResult::load_with(&files[1], Some(&cipher)).unwrap();
+        assert_eq!(loaded.run_id, result.run_id);
+        fs::remove_dir_all(&dir).unwrap();
+
+        config.outputs.encryption = Some(EncryptionConfig {
+            key_env: "ORCHESTRA_TEST_OUTPUTS_KEY_UNSET".to_string(),
+        });
+        assert!(Orchestrator::builder()
+            .config(config)
+            .client_mode(ClientMode::Mock)
+            .build()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resume_skips_completed_agents() {
+        let dir = std::env::temp_dir().join(format!("orchestra-resume-{}", std::process::id()));
+        let mut checkpoint =
+            Checkpoint::new("01KF3M2Q8V6W9XJ5T0R7C4N1BD", "monitoring", Utc::now());
+        checkpoint.results = vec![
+            AgentResult::success(
+                "health_checker".to_string(),
+                "checked before the crash".to_string(),
+                "api".to_string(),
+            ),
+            AgentResult::failed(
+                "alert_manager".to_string(),
+                "killed".to_string(),
+                "api".to_string(),
+            ),
+        ];
+        CheckpointWriter::new(&dir, checkpoint, None).record(&AgentResult::failed(
+            "alert_manager".to_string(),
+            "killed".to_string(),
+            "api".to_string(),
+        ));
+
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.parallel_execution = true;
+        let build = |mode: &str| {
+            Orchestrator::builder()
+                .config(config.clone())
+                .mode(mode)
+                .client_mode(ClientMode::Mock)
+                .output_dir(&dir)
+                .resume("01KF3M2Q8V6W9XJ5T0R7C4N1BD")
+                .build()
+        };
+        assert!(build("research").is_err());
+
+        let result = build("monitoring").unwrap().run().await.unwrap();
+        assert_eq!(result.run_id, "01KF3M2Q8V6W9XJ5T0R7C4N1BD");
+        assert_eq!(
+            result.results[0].output.as_deref(),
+            Some("checked before the crash")
+        );
+        assert!(result.results[1]
+            .output
+            .as_deref()
+            .unwrap()
+            .starts_with("[mock]"));
+        let fresh = &result.results[1];
+        assert_eq!(fresh.attempts, Some(1));
+        assert!(fresh.started_at.is_some() && fresh.duration_ms.is_some());
+        assert!(result.duration_ms.is_some());
+        assert!(Checkpoint::load(&dir, "01KF3M2Q8V6W9XJ5T0R7C4N1BD", None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_and_keeps_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("orchestra-shutdown-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        let (tx, shutdown) = Shutdown::channel();
+        tx.send(true).unwrap();
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("monitoring")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .shutdown(shutdown)
+            .build()
+            .unwrap();
+
+        let result = orchestrator.run().await.unwrap();
+        assert!(result.results.iter().all(|r| r.status == "cancelled"));
+        assert_eq!(result.failed_agents().len(), result.results.len());
+        let summary = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.to_string_lossy().contains("summary-"))
+            .unwrap();
+        assert!(fs::read_to_string(summary)
+            .unwrap()
+            .contains("Cancelled: 2"));
+        assert!(Checkpoint::load(&dir, &result.run_id, None).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_cancels_remaining_agents() {
+        let dir = std::env::temp_dir().join(format!("orchestra-fail-fast-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.fail_fast = true;
+        // No API key, so the first agent fails before reaching a backend
+        config.agents.monitor.client_mode = Some("api".to_string());
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("monitoring")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+
+        let result = orchestrator.run().await.unwrap();
+        let statuses: Vec<&str> = result.results.iter().map(|r| r.status.as_str()).collect();
+        assert_eq!(statuses, vec!["failed", "cancelled"]);
+        assert!(result.results[1]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("fail_fast"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_agent_cancel_leaves_other_agents_running() {
+        let dir =
+            std::env::temp_dir().join(format!("orchestra-agent-cancel-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        let cancel = AgentCancel::new();
+        cancel.cancel("health_checker");
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("monitoring")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .agent_cancel(cancel)
+            .build()
+            .unwrap();
+
+        let result = orchestrator.run().await.unwrap();
+        let statuses: Vec<&str> = result.results.iter().map(|r| r.status.as_str()).collect();
+        assert_eq!(statuses, vec!["cancelled", "success"]);
+        assert_eq!(
+            result.results[0].error.as_deref(),
+            Some("Cancelled by request")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resume_session_from_history() {
+        let dir = std::env::temp_dir().join(format!("orchestra-sessions-{}", std::process::id()));
+        let mut config = Config::default();
+        config.events.enabled = false;
+        config.history.path = dir.join("history.jsonl").to_string_lossy().into_owned();
+        config.agents.monitor.session = SessionMode::Resume;
+        config.agents.analyzer.session = SessionMode::Continue;
+        let previous = AgentResult::success(
+            "monitor".to_string(),
+            "ok".to_string(),
+            "claude-code".to_string(),
+        )
+        .with_session_id(Some("s-1".to_string()));
+        RunStore::from_config(&config)
+            .append(&RunRecord {
+                schema_version: store::RUN_SCHEMA_VERSION,
+                run_id: "auto-1".to_string(),
+                timestamp: Utc::now(),
+                mode: "auto".to_string(),
+                global_client_mode: "claude-code".to_string(),
+                source: "run".to_string(),
+                agents: vec![AgentRecord::from_result(&previous)],
+            })
+            .unwrap();
+
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("auto")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+        let tasks = orchestrator.get_agent_tasks();
+        assert_eq!(
+            tasks[0].options.continuation,
+            Continuation::Resume("s-1".to_string())
+        );
+        assert_eq!(tasks[1].options.continuation, Continuation::Latest);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_teammates_become_results_of_their_own() {
+        use crate::teams::TeammateOutput;
+
+        let teammate = |name: &str, output: Option<&str>| TeammateOutput {
+            name: name.to_string(),
+            role: format!("You are the {}.\n", name),
+            status: if output.is_some() {
+                "completed"
+            } else {
+                "in_progress"
+            }
+            .to_string(),
+            output: output.map(str::to_string),
+        };
+        let lead = AgentResult::success(
+            "feature-dev".to_string(),
+            "Team finished".to_string(),
+            "agent-teams".to_string(),
+        )
+        .with_teammates(vec![
+            teammate("architect", Some("Plan ready")),
+            teammate("reviewer", None),
+        ]);
+        let results = agents::flatten_teams(vec![lead]);
+        let agents: Vec<&str> = results.iter().map(|r| r.agent.as_str()).collect();
+        assert_eq!(agents, ["feature-dev", "architect", "reviewer"]);
+        assert!(results[0].teammates.is_empty());
+        assert_eq!(results[1].team.as_deref(), Some("feature-dev"));
+        assert_eq!(results[1].role.as_deref(), Some("You are the architect."));
+        assert_eq!(results[1].output.as_deref(), Some("Plan ready"));
+        assert_eq!(results[2].status, "failed");
+        assert_eq!(
+            results[2].error.as_deref(),
+            Some("No output from teammate (task in_progress)")
+        );
+
+        let run = OrchestrationResult {
+            run_id: "01KF3M2Q8V6W9XJ5T0R7C4N1BD".to_string(),
+            timestamp: Utc::now(),
+            mode: "feature-dev".to_string(),
+            global_client_mode: "agent-teams".to_string(),
+            provider_outages: Vec::new(),
+            log_sampling: Vec::new(),
+            actions: Vec::new(),
+            rerun_of: None,
+            duration_ms: None,
+            consensus: None,
+            review: None,
+            results,
+        };
+        assert_eq!(run.failed_agents(), ["feature-dev"]);
+    }
+
+    #[tokio::test]
+    async fn test_consensus_runs_aggregator_after_voters() {
+        use crate::config::AgentConfig;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-consensus-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        for (name, vote) in [("opus", "yes"), ("sonnet", "no"), ("haiku", "Yes.")] {
+            config.agents.custom.insert(
+                name.to_string(),
+                AgentConfig {
+                    mock_response: Some(format!("Reasons.\nVOTE: {}", vote)),
+                    ..AgentConfig::default()
+                },
+            );
+        }
+        config.consensus.insert(
+            "ship".to_string(),
+            ConsensusConfig {
+                prompt: "Ship release {{ version }}?".to_string(),
+                voters: vec!["opus".into(), "sonnet".into(), "haiku".into()],
+                aggregator: "judge".to_string(),
+                strategy: ConsensusStrategy::Vote,
+            },
+        );
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("ship")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .var("version", "2.1")
+            .build()
+            .unwrap();
+
+        let tasks = orchestrator.get_agent_tasks();
+        assert_eq!(tasks.len(), 3);
+        assert!(tasks[0]
+            .system_prompt
+            .as_deref()
+            .unwrap()
+            .contains("VOTE: <answer>"));
+
+        let result = orchestrator.run().await.unwrap();
+        let agents: Vec<&str> = result.results.iter().map(|r| r.agent.as_str()).collect();
+        assert_eq!(agents, ["opus", "sonnet", "haiku", "judge"]);
+        let consensus = result.consensus.unwrap();
+        assert_eq!(consensus.majority.as_deref(), Some("yes"));
+        assert_eq!(consensus.votes[0].voters, ["opus", "haiku"]);
+        // The mock echoes the first line of the aggregator's prompt
+        assert_eq!(
+            result.results[3].output.as_deref(),
+            Some("[mock] Original task:")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_review_revises_until_rounds_run_out() {
+        use crate::config::AgentConfig;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-review-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.parallel_execution = true;
+        for (name, verdict) in [("editor", "REVISE"), ("approver", "APPROVED")] {
+            config.agents.custom.insert(
+                name.to_string(),
+                AgentConfig {
+                    mock_response: Some(format!("Tighten the intro.\nVERDICT: {}", verdict)),
+                    ..AgentConfig::default()
+                },
+            );
+        }
+        for (mode, critic) in [("draft", "editor"), ("quick", "approver")] {
+            config.review.insert(
+                mode.to_string(),
+                ReviewConfig {
+                    prompt: "Write the release notes".to_string(),
+                    author: "writer".to_string(),
+                    critic: critic.to_string(),
+                    criteria: vec!["Mentions breaking changes".to_string()],
+                    max_rounds: 2,
+                },
+            );
+        }
+        let build = |mode: &str| {
+            Orchestrator::builder()
+                .config(config.clone())
+                .mode(mode)
+                .client_mode(ClientMode::Mock)
+                .output_dir(&dir)
+                .build()
+                .unwrap()
+        };
+
+        let result = build("draft").run().await.unwrap();
+        let agents: Vec<&str> = result.results.iter().map(|r| r.agent.as_str()).collect();
+        assert_eq!(agents, ["writer", "editor"]);
+        // The mock echoes the first line of the revision prompt
+        assert_eq!(result.results[0].output.as_deref(), Some("[mock] Task:"));
+        let review = result.review.unwrap();
+        assert!(!review.approved);
+        assert_eq!(review.rounds.len(), 2);
+        assert_eq!(review.rounds[0].draft, "[mock] Write the release notes");
+        assert_eq!(review.rounds[1].draft, "[mock] Task:");
+        assert!(review.rounds[1]
+            .critique
+            .as_deref()
+            .unwrap()
+            .ends_with("REVISE"));
+
+        let result = build("quick").run().await.unwrap();
+        let review = result.review.unwrap();
+        assert!(review.approved);
+        assert_eq!(review.rounds.len(), 1);
+        assert_eq!(
+            result.results[0].output.as_deref(),
+            Some("[mock] Write the release notes")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_injected_text_is_never_rendered() {
+        std::env::set_var("ORCHESTRA_INJECT_TEST", "sk-secret");
+        let mut task = AgentTask::new("reporter", "Report on {{ results.monitor.output }}", 60);
+        task.injected =
+            "log: {{ env.ORCHESTRA_INJECT_TEST }} {{ results.analyst.output }}\n".to_string();
+        // Only the configured prompt decides which results the agent waits for
+        assert_eq!(result_refs(&task), ["monitor"]);
+
+        let mut vars = TemplateVars::new();
+        vars.insert("results.monitor.output", "All green");
+        vars.insert("results.analyst.output", "Not for this agent");
+        let mut task = with_results(task, &vars);
+        task.inject();
+        assert_eq!(
+            task.prompt,
+            "log: {{ env.ORCHESTRA_INJECT_TEST }} {{ results.analyst.output }}\nReport on All green"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reporter_reads_analyst_output() {
+        use crate::config::AgentConfig;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-chain-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.parallel_execution = true;
+        config.agents.custom.insert(
+            "data_analyst".to_string(),
+            AgentConfig {
+                mock_response: Some("Disk I/O is the bottleneck".to_string()),
+                ..AgentConfig::default()
+            },
+        );
+        config.agents.reporter.prompt = Some(
+            "{{ results.data_analyst.output }} ({{ results.data_analyst.status }})\nReport on it."
+                .to_string(),
+        );
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("analysis")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+        assert!(orchestrator
+            .plan()
+            .contains("  reads the results of: data_analyst\n"));
+
+        // Reading its output makes the reporter wait, even in parallel
+        let tasks = orchestrator.get_agent_tasks();
+        let batches: Vec<Vec<String>> = stages(tasks.into_iter().rev().collect())
+            .iter()
+            .map(|stage| stage.iter().map(|t| t.name.clone()).collect())
+            .collect();
+        assert_eq!(batches, [["data_analyst"], ["reporter"]]);
+
+        let result = orchestrator.run().await.unwrap();
+        // The mock echoes the first line of the reporter's prompt
+        assert_eq!(
+            result.results[1].output.as_deref(),
+            Some("[mock] Disk I/O is the bottleneck (success)")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_output_schema_parses_or_fails_the_agent() {
+        use crate::config::AgentConfig;
+
+        let dir = std::env::temp_dir().join(format!("orchestra-schema-{}", std::process::id()));
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["bottleneck"],
+            "properties": {"bottleneck": {"type": "string"}}
+        });
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.agents.custom.insert(
+            "data_analyst".to_string(),
+            AgentConfig {
+                mock_response: Some("```json\n{\"bottleneck\": \"disk\"}\n```".to_string()),
+                output_schema: Some(schema.clone()),
+                ..AgentConfig::default()
+            },
+        );
+        // The mock reporter echoes its prompt, which is never JSON
+        config.agents.reporter.output_schema = Some(schema);
+        for parallel in [false, true] {
+            config.features.parallel_execution = parallel;
+            let orchestrator = Orchestrator::builder()
+                .config(config.clone())
+                .mode("analysis")
+                .client_mode(ClientMode::Mock)
+                .output_dir(&dir)
+                .build()
+                .unwrap();
+
+            let result = orchestrator.run().await.unwrap();
+            let find = |name: &str| result.results.iter().find(|r| r.agent == name).unwrap();
+            let analyst = find("data_analyst");
+            assert_eq!(analyst.status, "success");
+            assert_eq!(
+                analyst.structured_output,
+                Some(serde_json::json!({"bottleneck": "disk"}))
+            );
+            let reporter = find("reporter");
+            assert_eq!(reporter.status, "failed");
+            assert!(reporter
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("No reply matched output_schema after 3 tries"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_parallel_agents_queue_by_priority_within_limits() {
+        let dir = std::env::temp_dir().join(format!("orchestra-queue-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.parallel_execution = true;
+        config.client.concurrency.insert("mock".to_string(), 1);
+        // The synthesizer slot runs the analyzer agent
+        config.agents.analyzer.priority = 5;
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("research")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+
+        let result = orchestrator.run().await.unwrap();
+        let find = |name: &str| result.results.iter().find(|r| r.agent == name).unwrap();
+        let (researcher, synthesizer) = (find("researcher"), find("synthesizer"));
+        assert_eq!(researcher.status, "success");
+        // One mock agent at a time, the higher priority first
+        assert!(synthesizer.started_at < researcher.started_at);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bench_writes_a_comparison() {
+        let dir = std::env::temp_dir().join(format!("orchestra-bench-{}", std::process::id()));
+        let mut config = Config::default();
+        config.bench.prompts.push(BenchPrompt {
+            name: "triage".to_string(),
+            prompt: "Which log line matters?".to_string(),
+            system_prompt: None,
+            max_tokens: None,
+        });
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .client_mode(ClientMode::Mock)
+            .skip_client_check()
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+        let targets: Vec<BenchTarget> = ["mock", "mock:claude-haiku-4-5", "nope"]
+            .iter()
+            .map(|t| t.parse().unwrap())
+            .collect();
+
+        let (report, file) = orchestrator.bench(2, &targets).await.unwrap();
+        assert_eq!(report.samples.len(), 6);
+        let mock = &report.targets[0];
+        assert_eq!((mock.calls, mock.failures), (2, 0));
+        assert!(mock.output_tokens > 0);
+        assert_eq!(report.targets[2].failure_rate(), 1.0);
+        assert!(file.is_file());
+        let markdown = fs::read_to_string(file.with_extension("md")).unwrap();
+        assert!(markdown.contains("| mock:claude-haiku-4-5 | 2 | 0% |"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fit_budget_summarizes_or_cuts_the_middle() {
+        use crate::config::AgentConfig;
+
+        let mut config = Config::default();
+        config.agents.custom.insert(
+            "condenser".to_string(),
+            AgentConfig {
+                mock_response: Some("disk 97% full on db-1".to_string()),
+                ..AgentConfig::default()
+            },
+        );
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .client_mode(ClientMode::Mock)
+            .build()
+            .unwrap();
+        let prompt = format!("LOGS\n{}Which disk is full?", "INFO ok\n".repeat(5000));
+        let mut task = AgentTask::new("monitor", prompt.clone(), 30);
+        task.budget = BudgetConfig {
+            max_input_tokens: 1000,
+            strategy: BudgetStrategy::Summarize,
+            summarizer: Some("condenser".to_string()),
+        };
+
+        let (fitted, _) = orchestrator.fit_budget(&task, 0).await;
+        assert!(fitted.len() <= 4000, "{}", fitted.len());
+        assert!(fitted.starts_with("LOGS\n") && fitted.ends_with("Which disk is full?"));
+        assert!(fitted.contains("summarized to fit the input budget:]\ndisk 97% full on db-1"));
+
+        // Without a summarizer the middle is just cut
+        task.budget.summarizer = None;
+        let (fitted, _) = orchestrator.fit_budget(&task, 0).await;
+        assert!(fitted.len() <= 4000, "{}", fitted.len());
+        assert!(fitted.contains("bytes cut to fit the input budget"));
+        assert!(fitted.ends_with("Which disk is full?"));
+
+        task.prompt = "Which disk is full?".to_string();
+        assert_eq!(orchestrator.fit_budget(&task, 0).await.0, task.prompt);
+    }
+
+    #[tokio::test]
+    async fn test_map_runs_mapper_per_file_then_reducer() {
+        let dir = std::env::temp_dir().join(format!("orchestra-map-{}", std::process::id()));
+        let logs = dir.join("logs");
+        fs::create_dir_all(&logs).unwrap();
+        for name in ["b.log", "a.log", "notes.txt"] {
+            fs::write(logs.join(name), "ERROR disk full\n").unwrap();
+        }
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.map.insert(
+            "triage".to_string(),
+            MapConfig {
+                inputs: logs.join("*.log").display().to_string(),
+                prompt: "Summarize the errors in {{ file }}".to_string(),
+                mapper: "summarizer".to_string(),
+                reducer: "lead".to_string(),
+                reduce_prompt: "Rank the problems across all hosts".to_string(),
+                concurrency: 1,
+                max_bytes_per_file: 1024,
+                batch: true,
+            },
+        );
+        // An API-backed mapper is batched; a mock one runs as usual
+        let api = Orchestrator::builder()
+            .config(config.clone())
+            .mode("triage")
+            .client_mode(ClientMode::Api)
+            .api_key("sk-test")
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+        assert!(api
+            .plan()
+            .contains("  sent in a Message Batch, at half price\n"));
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("triage")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+        assert!(!orchestrator.plan().contains("Message Batch"));
+        let a = logs.join("a.log").display().to_string();
+        let tasks = orchestrator.get_agent_tasks();
+        assert!(tasks[0].prompt.ends_with(&format!("errors in {}", a)));
+
+        let result = orchestrator.run().await.unwrap();
+        let agents: Vec<&str> = result.results.iter().map(|r| r.agent.as_str()).collect();
+        let b = logs.join("b.log").display().to_string();
+        assert_eq!(
+            agents,
+            [
+                format!("summarizer[{}]", a),
+                format!("summarizer[{}]", b),
+                "lead".to_string(),
+            ]
+        );
+        // The mock echoes the first line of each prompt
+        assert_eq!(
+            result.results[0].output.as_deref(),
+            Some(format!("[mock] [FILE: {}]", a).as_str())
+        );
+        assert_eq!(
+            result.results[2].output.as_deref(),
+            Some("[mock] Rank the problems across all hosts")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejected_gate_cancels_later_agents() {
+        use crate::approval::Approval;
+
+        struct Fixed(bool);
+
+        #[async_trait::async_trait]
+        impl Approver for Fixed {
+            async fn decide(&self, _: &str, result: &AgentResult) -> Result<Approval> {
+                assert_eq!(result.agent, "monitor");
+                Ok(Approval {
+                    approved: self.0,
+                    decided_at: Utc::now(),
+                })
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("orchestra-gate-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.parallel_execution = true;
+        config.agents.monitor.requires_approval = true;
+        let build = |approved: bool| {
+            Orchestrator::builder()
+                .config(config.clone())
+                .mode("auto")
+                .client_mode(ClientMode::Mock)
+                .output_dir(&dir)
+                .approver(Arc::new(Fixed(approved)))
+                .build()
+                .unwrap()
+        };
+        assert!(build(true)
+            .plan()
+            .contains("  output held for approval before later agents start\n"));
+
+        let result = build(false).run().await.unwrap();
+        assert_eq!(
+            result.results[0].approval.as_ref().map(|a| a.approved),
+            Some(false)
+        );
+        assert_eq!(result.results[1].status, "cancelled");
+        assert_eq!(
+            result.results[1].error.as_deref(),
+            Some("Cancelled because an approval gate didn't pass")
+        );
+
+        let result = build(true).run().await.unwrap();
+        assert!(result.results[0].approval.as_ref().unwrap().approved);
+        assert_eq!(result.results[1].status, "success");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hooks_run_around_agents_and_run() {
+        let dir = std::env::temp_dir().join(format!("orchestra-hooks-run-{}", std::process::id()));
+        let mut config = Config::default();
+        config.history.enabled = false;
+        config.events.enabled = false;
+        config.features.parallel_execution = true;
+        config.hooks.pre_run = vec![format!("mkdir -p {}", dir.display())];
+        config.hooks.post_agent = vec![format!(
+            "echo $ORCHESTRA_STATUS > {}/$ORCHESTRA_AGENT.status",
+            dir.display()
+        )];
+        config.hooks.post_run = vec![format!("cat > {}/post_run.json", dir.display())];
+        config.agents.monitor.hooks.pre = vec!["echo disk full >&2; exit 1".to_string()];
+        let orchestrator = Orchestrator::builder()
+            .config(config)
+            .mode("auto")
+            .client_mode(ClientMode::Mock)
+            .output_dir(&dir)
+            .build()
+            .unwrap();
+
+        let result = orchestrator.run().await.unwrap();
+        let monitor = &result.results[0];
+        assert_eq!(monitor.status, "failed");
+        assert!(monitor.error.as_deref().unwrap().contains("disk full"));
+        assert_eq!(result.results[1].status, "success");
+        let status = |agent: &str| fs::read_to_string(dir.join(format!("{}.status", agent)));
+        assert_eq!(status("monitor").unwrap(), "failed\n");
+        assert_eq!(status(&result.results[1].agent).unwrap(), "success\n");
+        let posted: OrchestrationResult =
+            serde_json::from_str(&fs::read_to_string(dir.join("post_run.json")).unwrap()).unwrap();
+        assert_eq!(posted.run_id, result.run_id);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::agents::AgentResult;
+
+/// Hears about agents as a run executes them, e.g. to draw a live view.
+pub trait Progress: Send + Sync {
+    /// The agents about to run, in order, before any of them starts.
+    fn queued(&self, agents: &[String]);
+    fn started(&self, agent: &str);
+    fn finished(&self, result: &AgentResult);
+    /// Every agent is done; called before results are written.
+    fn done(&self) {}
+}
+
+/// One line per agent on stdout: pending, then a spinner with the elapsed
+/// time while it runs, then its status and duration.
+pub struct LiveProgress {
+    multi: MultiProgress,
+    bars: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl Default for LiveProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveProgress {
+    pub fn new() -> Self {
+        Self {
+            multi: MultiProgress::with_draw_target(ProgressDrawTarget::stdout()),
+            bars: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A log writer that clears the bars while it writes to stderr, so log
+    /// lines don't tear the display.
+    pub fn log_writer(&self) -> SuspendingWriter {
+        SuspendingWriter(self.multi.clone())
+    }
+
+    /// Hide the bars while `f` runs, e.g. to ask a question on the terminal.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.multi.suspend(f)
+    }
+
+    fn bar(&self, agent: &str) -> Option<ProgressBar> {
+        let bars = self.bars.lock().unwrap_or_else(|e| e.into_inner());
+        bars.get(agent).cloned()
+    }
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template).unwrap_or_else(|_| ProgressStyle::default_spinner())
+}
+
+/// Status and duration shown once an agent is done, e.g. `✓ success 3.4s`.
+fn outcome(result: &AgentResult) -> String {
+    let mark = match result.status.as_str() {
+        "success" => "✓",
+        "cancelled" => "-",
+        _ => "✗",
+    };
+    match result.duration_ms {
+        Some(ms) => format!("{} {} {:.1}s", mark, result.status, ms as f64 / 1000.0),
+        None => format!("{} {}", mark, result.status),
+    }
+}
+
+impl Progress for LiveProgress {
+    fn queued(&self, agents: &[String]) {
+        let width = agents.iter().map(String::len).max().unwrap_or(0);
+        let mut bars = self.bars.lock().unwrap_or_else(|e| e.into_inner());
+        for agent in agents {
+            let bar = self.multi.add(ProgressBar::new_spinner());
+            bar.set_style(style("  {prefix} {msg}"));
+            bar.set_prefix(format!("{:<width$}", agent, width = width));
+            bar.set_message("pending");
+            bars.insert(agent.clone(), bar);
+        }
+    }
+
+    fn started(&self, agent: &str) {
+        if let Some(bar) = self.bar(agent) {
+            bar.set_style(style("{spinner} {prefix} running {elapsed}"));
+            bar.reset_elapsed();
+            bar.enable_steady_tick(Duration::from_millis(120));
+        }
+    }
+
+    fn finished(&self, result: &AgentResult) {
+        if let Some(bar) = self.bar(&result.agent) {
+            bar.set_style(style("  {prefix} {msg}"));
+            bar.finish_with_message(outcome(result));
+        }
+    }
+
+    fn done(&self) {
+        let bars = self.bars.lock().unwrap_or_else(|e| e.into_inner());
+        for bar in bars.values().filter(|b| !b.is_finished()) {
+            bar.finish();
+        }
+    }
+}
+
+/// Writes to stderr with the progress bars suspended. See
+/// [`LiveProgress::log_writer`].
+#[derive(Clone)]
+pub struct SuspendingWriter(MultiProgress);
+
+impl Write for SuspendingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.suspend(|| io::stderr().write(buf))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.suspend(|| io::stderr().write_all(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for SuspendingWriter {
+    type Writer = SuspendingWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome_labels() {
+        let ok = AgentResult::success("monitor".into(), "fine".into(), "api".into())
+            .with_duration(Duration::from_millis(3400));
+        assert_eq!(outcome(&ok), "✓ success 3.4s");
+        let failed = AgentResult::failed("monitor".into(), "boom".into(), "api".into());
+        assert_eq!(outcome(&failed), "✗ failed");
+    }
+
+    #[test]
+    fn test_live_progress_tracks_each_agent() {
+        let progress = LiveProgress {
+            multi: MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            bars: Mutex::new(HashMap::new()),
+        };
+        progress.queued(&["monitor".to_string(), "analyzer".to_string()]);
+        progress.started("monitor");
+        progress.finished(&AgentResult::success(
+            "monitor".into(),
+            "fine".into(),
+            "api".into(),
+        ));
+        progress.done();
+        let bars = progress.bars.lock().unwrap();
+        assert!(bars.values().all(ProgressBar::is_finished));
+        assert_eq!(bars["monitor"].message(), "✓ success");
+    }
+}
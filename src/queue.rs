@@ -0,0 +1,127 @@
+//! The queue a parallel run's agents wait in. The highest `priority` goes
+//! first, in run order among equals, but only while its client mode is under
+//! its `client.concurrency` limit: a slow CLI agent holding every CLI slot
+//! doesn't keep a waiting API agent from starting, and a high-priority agent
+//! is next for the first slot of its mode that frees up.
+
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug)]
+struct Waiting<T> {
+    mode: String,
+    priority: i32,
+    item: T,
+}
+
+#[derive(Debug)]
+pub struct TaskQueue<T> {
+    waiting: Vec<Waiting<T>>,
+    /// Client mode → most agents of that mode in flight at once.
+    limits: BTreeMap<String, usize>,
+    /// Most agents in flight at once, whatever their mode.
+    total: Option<usize>,
+    running: HashMap<String, usize>,
+}
+
+impl<T> TaskQueue<T> {
+    pub fn new(limits: BTreeMap<String, usize>, total: Option<usize>) -> Self {
+        Self {
+            waiting: Vec::new(),
+            limits,
+            total,
+            running: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, mode: &str, priority: i32, item: T) {
+        self.waiting.push(Waiting {
+            mode: mode.to_string(),
+            priority,
+            item,
+        });
+    }
+
+    /// The next item allowed to start, with its mode, counted as in flight
+    /// until [`finished`](Self::finished). `None` if nothing waits or every
+    /// waiting item's mode is at its limit.
+    pub fn pop(&mut self) -> Option<(String, T)> {
+        if self
+            .total
+            .is_some_and(|total| self.in_flight() >= total.max(1))
+        {
+            return None;
+        }
+        let mut next: Option<usize> = None;
+        for (i, waiting) in self.waiting.iter().enumerate() {
+            let full = self.limits.get(&waiting.mode).is_some_and(|&limit| {
+                self.running.get(&waiting.mode).copied().unwrap_or(0) >= limit.max(1)
+            });
+            if !full && next.is_none_or(|n| waiting.priority > self.waiting[n].priority) {
+                next = Some(i);
+            }
+        }
+        let Waiting { mode, item, .. } = self.waiting.remove(next?);
+        *self.running.entry(mode.clone()).or_insert(0) += 1;
+        Some((mode, item))
+    }
+
+    /// One item of `mode` is no longer in flight.
+    pub fn finished(&mut self, mode: &str) {
+        if let Some(running) = self.running.get_mut(mode) {
+            *running = running.saturating_sub(1);
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.running.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_within_mode_limits() {
+        let limits = BTreeMap::from([("api".to_string(), 1), ("claude-code".to_string(), 2)]);
+        let mut queue = TaskQueue::new(limits, None);
+        queue.push("claude-code", 0, "research-1");
+        queue.push("claude-code", 0, "research-2");
+        queue.push("claude-code", 0, "research-3");
+        queue.push("api", 0, "summary");
+        queue.push("claude-code", 10, "monitor");
+        queue.push("api", 5, "triage");
+        queue.push("mock", 0, "unlimited");
+
+        let mut started = Vec::new();
+        while let Some((_, item)) = queue.pop() {
+            started.push(item);
+        }
+        assert_eq!(started, ["monitor", "triage", "research-1", "unlimited"]);
+        assert_eq!(queue.in_flight(), 4);
+
+        // A freed CLI slot goes to the next CLI agent, in run order
+        queue.finished("claude-code");
+        assert_eq!(queue.pop(), Some(("claude-code".to_string(), "research-2")));
+        assert_eq!(queue.pop(), None);
+        queue.finished("api");
+        assert_eq!(queue.pop(), Some(("api".to_string(), "summary")));
+        assert!(!queue.is_empty());
+    }
+
+    #[test]
+    fn test_total_limit() {
+        let mut queue = TaskQueue::new(BTreeMap::new(), Some(1));
+        queue.push("api", 0, 1);
+        queue.push("claude-code", 1, 2);
+        assert_eq!(queue.pop(), Some(("claude-code".to_string(), 2)));
+        assert_eq!(queue.pop(), None);
+        queue.finished("claude-code");
+        assert_eq!(queue.pop(), Some(("api".to_string(), 1)));
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::client::{AgentClient, AgentResponse};
+
+/// Capture backend replies to disk, or answer from earlier captures.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recording {
+    /// Call the real backend and save each reply under the directory.
+    Record(PathBuf),
+    /// Answer from the directory without contacting any backend.
+    Replay(PathBuf),
+}
+
+/// One captured exchange. Keyed by a label (the agent name, or
+/// `agent--test` for self-tests) rather than the prompt, since prompts
+/// carry per-run values such as `{{ now }}` and `{{ run_id }}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cassette {
+    pub label: String,
+    pub recorded_at: DateTime<Utc>,
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Recording {
+    pub fn dir(&self) -> &Path {
+        match self {
+            Recording::Record(dir) | Recording::Replay(dir) => dir,
+        }
+    }
+
+    /// Wrap the client built by `make` for the exchange named `label`.
+    /// Replaying never calls `make`, so no API key or CLI is needed.
+    pub fn wrap<F>(&self, label: &str, make: F) -> Result<Box<dyn AgentClient>>
+    where
+        F: FnOnce() -> Result<Box<dyn AgentClient>>,
+    {
+        let path = cassette_path(self.dir(), label);
+        let label = label.to_string();
+        Ok(match self {
+            Recording::Record(_) => Box::new(RecordingClient {
+                inner: make()?,
+                label,
+                path,
+            }),
+            Recording::Replay(_) => Box::new(ReplayClient { label, path }),
+        })
+    }
+}
+
+/// `dir/<label>.json`, with anything but `[A-Za-z0-9_.-]` replaced.
+pub fn cassette_path(dir: &Path, label: &str) -> PathBuf {
+    let name: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{}.json", name))
+}
+
+pub struct RecordingClient {
+    inner: Box<dyn AgentClient>,
+    label: String,
+    path: PathBuf,
+}
+
+#[async_trait]
+impl AgentClient for RecordingClient {
+    async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
+
+    async fn send(&self, prompt: &str, system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let response = self.inner.send(prompt, system_prompt).await?;
+        let cassette = Cassette {
+            label: self.label.clone(),
+            recorded_at: Utc::now(),
+            prompt: prompt.to_string(),
+            system_prompt: system_prompt.map(str::to_string),
+            text: response.text.clone(),
+            model: response.model.clone(),
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&cassette)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        info!("Recorded {} to {}", self.label, self.path.display());
+        Ok(response)
+    }
+}
+
+pub struct ReplayClient {
+    label: String,
+    path: PathBuf,
+}
+
+#[async_trait]
+impl AgentClient for ReplayClient {
+    async fn send_message(&self, prompt: &str, system_prompt: Option<&str>) -> Result<String> {
+        Ok(self.send(prompt, system_prompt).await?.text)
+    }
+
+    async fn send(&self, prompt: &str, _system_prompt: Option<&str>) -> Result<AgentResponse> {
+        let content = fs::read_to_string(&self.path).with_context(|| {
+            format!(
+                "No recording for {} at {}; record one with --record",
+                self.label,
+                self.path.display()
+            )
+        })?;
+        let cassette: Cassette = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", self.path.display()))?;
+        if cassette.prompt != prompt {
+            warn!(
+                "Replaying {}: prompt differs from the one recorded {}",
+                self.label,
+                cassette.recorded_at.format("%Y-%m-%d %H:%M:%S")
+            );
+        }
+        Ok(AgentResponse {
+            text: cassette.text,
+            model: cassette.model,
+            attempts: 1,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::MockClient;
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = std::env::temp_dir().join(format!("orchestra-replay-{}", std::process::id()));
+        let record = Recording::Record(dir.clone());
+        let client = record
+            .wrap("monitor", || {
+                Ok(Box::new(MockClient::new().with_response("all healthy")))
+            })
+            .unwrap();
+        client.send("check it", Some("sys")).await.unwrap();
+
+        let replay = Recording::Replay(dir.clone());
+        let client = replay
+            .wrap("monitor", || panic!("replay must not build a backend"))
+            .unwrap();
+        let response = client.send("check it again", None).await.unwrap();
+        assert_eq!(response.text, "all healthy");
+        assert_eq!(response.model.as_deref(), Some("mock"));
+
+        let missing = replay.wrap("analyzer", || unreachable!()).unwrap();
+        assert!(missing.send("x", None).await.is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cassette_path_is_file_safe() {
+        let path = cassette_path(Path::new("rec"), "monitor--says ok/now");
+        assert_eq!(path, Path::new("rec/monitor--says_ok_now.json"));
+    }
+}
@@ -0,0 +1,120 @@
+//! Critic/refinement loops: the author drafts, the critic reviews the draft
+//! against the pattern's criteria and ends with a verdict, and the author
+//! revises with the critique in hand until the critic approves.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::config::ReviewConfig;
+
+/// Every draft and critique of a review run; recorded in the results file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReviewOutcome {
+    pub approved: bool,
+    pub rounds: Vec<ReviewRound>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReviewRound {
+    pub round: u32,
+    pub draft: String,
+    /// Missing if the critic failed on this draft.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critique: Option<String>,
+    pub approved: bool,
+}
+
+impl ReviewOutcome {
+    /// One line for the run summary.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} after {} round(s)",
+            if self.approved {
+                "approved"
+            } else {
+                "not approved"
+            },
+            self.rounds.len()
+        )
+    }
+}
+
+/// The critic's prompt for one draft.
+pub fn critic_prompt(spec: &ReviewConfig, task: &str, draft: &str) -> String {
+    let mut out = format!(
+        "Review this draft written for the task below.\n\nTask:\n{}\n\nDraft:\n{}\n",
+        task.trim(),
+        draft.trim()
+    );
+    if !spec.criteria.is_empty() {
+        out.push_str("\nJudge it against these criteria:\n");
+        for criterion in &spec.criteria {
+            let _ = writeln!(out, "- {}", criterion);
+        }
+    }
+    out.push_str(
+        "\nList concrete problems and how to fix them. End with a final line \
+         `VERDICT: APPROVED` if the draft is ready as it is, otherwise `VERDICT: REVISE`.",
+    );
+    out
+}
+
+/// The author's prompt for the next draft.
+pub fn revision_prompt(task: &str, draft: &str, critique: &str) -> String {
+    format!(
+        "Task:\n{}\n\nYour previous draft:\n{}\n\nA reviewer's critique:\n{}\n\n\
+         Write a revised draft that addresses the critique. Reply with the full \
+         draft only.",
+        task.trim(),
+        draft.trim(),
+        critique.trim()
+    )
+}
+
+/// Whether the critique's last `VERDICT:` line approves the draft. A
+/// critique without a verdict asks for another round.
+pub fn approved(critique: &str) -> bool {
+    critique
+        .lines()
+        .rev()
+        .find_map(|line| {
+            let line = line.trim().trim_matches(['*', '`']).trim();
+            let (key, verdict) = line.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case("verdict")
+                .then(|| verdict.trim().to_lowercase().starts_with("approve"))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verdicts() {
+        assert!(approved("Looks good.\nVERDICT: APPROVED"));
+        assert!(approved("**Verdict: approved.**\n"));
+        assert!(!approved("Fix the timeline.\nVERDICT: REVISE"));
+        assert!(!approved("I approve of the tone, but no verdict."));
+        // The last verdict wins
+        assert!(!approved(
+            "VERDICT: APPROVED\nOn reflection:\nVERDICT: REVISE"
+        ));
+    }
+
+    #[test]
+    fn test_critic_prompt_lists_criteria() {
+        let spec = ReviewConfig {
+            prompt: "Write the postmortem".to_string(),
+            author: "writer".to_string(),
+            critic: "editor".to_string(),
+            criteria: vec!["Names a root cause".to_string()],
+            max_rounds: 3,
+        };
+        let prompt = critic_prompt(&spec, "Write the postmortem", "It broke.");
+        assert!(prompt.contains("Draft:\nIt broke.\n"));
+        assert!(prompt.contains("criteria:\n- Names a root cause\n"));
+        assert!(prompt.ends_with("otherwise `VERDICT: REVISE`."));
+    }
+}
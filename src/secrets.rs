@@ -0,0 +1,356 @@
+//! Credentials from the config's `secrets:` section: files, command output
+//! (`pass`), HashiCorp Vault or AWS Secrets Manager. Everything that takes a
+//! credential from a variable (`ANTHROPIC_API_KEY`, a webhook's
+//! `secret_env`, `digitalocean.workers.token_env`, ...) reads it through
+//! [`var`], which prefers a secret of that name over the environment.
+//!
+//! Every secret is fetched when the config is loaded, all at once and each
+//! within [`FETCH_TIMEOUT`], so [`var`] never blocks. They are kept in
+//! memory and never put in the environment, so agent processes don't
+//! inherit them.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use ring::digest;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::config::{AwsSecret, SecretSource, VaultSecret};
+use crate::remote::{hex, Signer};
+
+/// How long one secret may take to fetch before it is treated as unset.
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A configured secret and what fetching it gave; `None` if that failed.
+#[derive(Clone)]
+struct Resolved {
+    source: SecretSource,
+    value: Option<String>,
+}
+
+static RESOLVED: RwLock<BTreeMap<String, Resolved>> = RwLock::new(BTreeMap::new());
+
+/// Fetch the credentials `sources` name and use them from now on. Called
+/// whenever the config is loaded. A secret whose source hasn't changed
+/// keeps the value it already has; one that failed is tried again.
+pub async fn configure(sources: &BTreeMap<String, SecretSource>) {
+    configure_within(sources, FETCH_TIMEOUT).await
+}
+
+async fn configure_within(sources: &BTreeMap<String, SecretSource>, timeout: Duration) {
+    let previous = RESOLVED.read().unwrap_or_else(|e| e.into_inner()).clone();
+    let mut resolved = BTreeMap::new();
+    let mut fetches = tokio::task::JoinSet::new();
+    for (name, source) in sources {
+        match previous.get(name) {
+            Some(known) if known.source == *source && known.value.is_some() => {
+                resolved.insert(name.clone(), known.clone());
+            }
+            _ => {
+                let (name, source) = (name.clone(), source.clone());
+                fetches.spawn(async move {
+                    let fetched = tokio::time::timeout(timeout, fetch(&source)).await;
+                    (name, source, fetched)
+                });
+            }
+        }
+    }
+    while let Some(joined) = fetches.join_next().await {
+        let Ok((name, source, fetched)) = joined else {
+            warn!("A secret fetch panicked");
+            continue;
+        };
+        let value = match fetched {
+            Ok(Ok(value)) => {
+                debug!("Read secret {}", name);
+                Some(value)
+            }
+            Ok(Err(e)) => {
+                warn!("Failed to read secret {}: {:#}", name, e);
+                None
+            }
+            Err(_) => {
+                warn!(
+                    "Failed to read secret {}: no answer within {}s",
+                    name,
+                    timeout.as_secs()
+                );
+                None
+            }
+        };
+        resolved.insert(name, Resolved { source, value });
+    }
+    *RESOLVED.write().unwrap_or_else(|e| e.into_inner()) = resolved;
+}
+
+/// The credential called `name`: the secret fetched for it if the config
+/// names one, else the environment variable. A secret that couldn't be
+/// fetched is unset, so callers report it as missing.
+pub fn var(name: &str) -> Option<String> {
+    match RESOLVED.read().unwrap_or_else(|e| e.into_inner()).get(name) {
+        Some(resolved) => resolved.value.clone(),
+        None => std::env::var(name).ok(),
+    }
+}
+
+async fn fetch(source: &SecretSource) -> Result<String> {
+    let value = if let Some(path) = &source.file {
+        tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path))?
+            .trim_end()
+            .to_string()
+    } else if let Some(argv) = &source.command {
+        run(argv).await?
+    } else if let Some(secret) = &source.vault {
+        vault(secret.clone()).await?
+    } else if let Some(secret) = &source.aws_secrets_manager {
+        aws(secret.clone()).await?
+    } else {
+        anyhow::bail!("no source is set");
+    };
+    if value.is_empty() {
+        anyhow::bail!("it is empty");
+    }
+    Ok(value)
+}
+
+/// The first line `argv` prints, as `pass show` puts the password there.
+/// The command is killed if the fetch is dropped on timeout.
+async fn run(argv: &[String]) -> Result<String> {
+    let (program, args) = argv.split_first().context("command is empty")?;
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_end()
+        .to_string())
+}
+
+fn http() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .context("Failed to build the HTTP client")
+}
+
+async fn vault(secret: VaultSecret) -> Result<String> {
+    let address = match secret.address {
+        Some(address) => address,
+        None => std::env::var("VAULT_ADDR").context("$VAULT_ADDR is not set")?,
+    };
+    let token = match std::env::var("VAULT_TOKEN") {
+        Ok(token) => token,
+        Err(_) => {
+            let home = std::env::var("HOME").context("$VAULT_TOKEN is not set")?;
+            std::fs::read_to_string(std::path::Path::new(&home).join(".vault-token"))
+                .context("$VAULT_TOKEN is not set and there is no ~/.vault-token")?
+                .trim()
+                .to_string()
+        }
+    };
+    let url = format!(
+        "{}/v1/{}",
+        address.trim_end_matches('/'),
+        secret.path.trim_start_matches('/')
+    );
+    let response = http()?
+        .get(&url)
+        .header("X-Vault-Token", token)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach Vault at {}", address))?;
+    if !response.status().is_success() {
+        anyhow::bail!("Vault returned {} for {}", response.status(), secret.path);
+    }
+    let body: Value = response.json().await.context("Malformed Vault response")?;
+    vault_field(&body, &secret.field)
+        .with_context(|| format!("{} has no field {}", secret.path, secret.field))
+}
+
+/// `field` of a Vault read: under `data.data` for KV v2, `data` for v1.
+fn vault_field(body: &Value, field: &str) -> Option<String> {
+    let data = &body["data"];
+    data["data"][field]
+        .as_str()
+        .or_else(|| data[field].as_str())
+        .map(String::from)
+}
+
+async fn aws(secret: AwsSecret) -> Result<String> {
+    let env = |var: &str| std::env::var(var).ok().filter(|v| !v.is_empty());
+    let region = secret
+        .region
+        .clone()
+        .or_else(|| env("AWS_REGION"))
+        .or_else(|| env("AWS_DEFAULT_REGION"))
+        .context("No region: set `region` or $AWS_REGION")?;
+    let signer = Signer {
+        access_key: env("AWS_ACCESS_KEY_ID").context("$AWS_ACCESS_KEY_ID is not set")?,
+        secret_key: env("AWS_SECRET_ACCESS_KEY").context("$AWS_SECRET_ACCESS_KEY is not set")?,
+        region: region.clone(),
+        service: "secretsmanager",
+    };
+    let session_token = env("AWS_SESSION_TOKEN");
+    let host = format!("secretsmanager.{}.amazonaws.com", region);
+    let body = serde_json::to_vec(&serde_json::json!({ "SecretId": secret.secret_id }))?;
+    let payload_hash = hex(digest::digest(&digest::SHA256, &body).as_ref());
+    let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let content_type = "application/x-amz-json-1.1";
+    let target = "secretsmanager.GetSecretValue";
+    let mut headers = vec![
+        ("content-type", content_type),
+        ("host", host.as_str()),
+        ("x-amz-date", amz_date.as_str()),
+    ];
+    if let Some(token) = &session_token {
+        headers.push(("x-amz-security-token", token.as_str()));
+    }
+    headers.push(("x-amz-target", target));
+    let authorization = signer.authorization("POST", "/", &headers, &payload_hash, &amz_date);
+
+    let mut request = http()?
+        .post(format!("https://{}/", host))
+        .header("content-type", content_type)
+        .header("x-amz-date", &amz_date)
+        .header("x-amz-target", target)
+        .header("authorization", authorization);
+    if let Some(token) = &session_token {
+        request = request.header("x-amz-security-token", token);
+    }
+    let response = request
+        .body(body)
+        .send()
+        .await
+        .context("Failed to reach AWS Secrets Manager")?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        anyhow::bail!(
+            "Secrets Manager returned {} for {}: {}",
+            status,
+            secret.secret_id,
+            text.trim()
+        );
+    }
+    let body: Value = response
+        .json()
+        .await
+        .context("Malformed Secrets Manager response")?;
+    let value = body["SecretString"]
+        .as_str()
+        .with_context(|| format!("{} has no SecretString", secret.secret_id))?;
+    match &secret.field {
+        None => Ok(value.to_string()),
+        Some(field) => serde_json::from_str::<Value>(value)
+            .ok()
+            .and_then(|json| json[field.as_str()].as_str().map(String::from))
+            .with_context(|| format!("{} has no field {}", secret.secret_id, field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_field_reads_kv_v1_and_v2() {
+        let v2 = serde_json::json!({"data": {"data": {"api_key": "sk-2"}, "metadata": {}}});
+        let v1 = serde_json::json!({"data": {"api_key": "sk-1"}});
+        assert_eq!(vault_field(&v2, "api_key").as_deref(), Some("sk-2"));
+        assert_eq!(vault_field(&v1, "api_key").as_deref(), Some("sk-1"));
+        assert_eq!(vault_field(&v1, "token"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_file_and_command_sources() {
+        let path = std::env::temp_dir().join(format!("orchestra-secret-{}", std::process::id()));
+        std::fs::write(&path, "sk-from-file\n").unwrap();
+        let file = SecretSource {
+            file: Some(path.to_string_lossy().into_owned()),
+            ..SecretSource::default()
+        };
+        assert_eq!(fetch(&file).await.unwrap(), "sk-from-file");
+        std::fs::remove_file(&path).unwrap();
+
+        let command = |argv: &[&str]| SecretSource {
+            command: Some(argv.iter().map(|arg| arg.to_string()).collect()),
+            ..SecretSource::default()
+        };
+        let pass = command(&["sh", "-c", "printf 'hunter2\\nlogin: bot\\n'"]);
+        assert_eq!(fetch(&pass).await.unwrap(), "hunter2");
+        assert!(fetch(&command(&["false"])).await.is_err());
+        assert!(fetch(&command(&["true"])).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_secrets_are_fetched_once_at_load() {
+        let dir = std::env::temp_dir().join(format!("orchestra-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let calls = dir.join("calls");
+        let command = |script: String| SecretSource {
+            command: Some(vec!["sh".to_string(), "-c".to_string(), script]),
+            ..SecretSource::default()
+        };
+        let sources = BTreeMap::from([
+            (
+                "ORCHESTRA_TEST_SECRET_FAST".to_string(),
+                command(format!("echo call >> '{}'; echo sk-fast", calls.display())),
+            ),
+            (
+                "ORCHESTRA_TEST_SECRET_SLOW".to_string(),
+                command("sleep 30; echo sk-slow".to_string()),
+            ),
+        ]);
+        std::env::set_var("ORCHESTRA_TEST_SECRET_SLOW", "from-env");
+
+        let started = std::time::Instant::now();
+        configure_within(&sources, Duration::from_secs(1)).await;
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(
+            var("ORCHESTRA_TEST_SECRET_FAST").as_deref(),
+            Some("sk-fast")
+        );
+        // A configured secret that can't be fetched doesn't fall back
+        assert_eq!(var("ORCHESTRA_TEST_SECRET_SLOW"), None);
+        assert_eq!(
+            var("ORCHESTRA_TEST_SECRET_FAST").as_deref(),
+            Some("sk-fast")
+        );
+
+        // Loading the same config again doesn't run the command again
+        configure_within(&sources, Duration::from_secs(1)).await;
+        assert_eq!(
+            var("ORCHESTRA_TEST_SECRET_FAST").as_deref(),
+            Some("sk-fast")
+        );
+        assert_eq!(std::fs::read_to_string(&calls).unwrap(), "call\n");
+
+        configure_within(&BTreeMap::new(), Duration::from_secs(1)).await;
+        assert_eq!(
+            var("ORCHESTRA_TEST_SECRET_SLOW").as_deref(),
+            Some("from-env")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+use regex_automata::meta::Regex;
+
+use crate::config::{Expectation, JsonPredicate};
+use crate::structured::first_json;
+
+/// Outcome of one agent self-test.
+#[derive(Debug, Clone)]
+pub struct TestOutcome {
+    pub agent: String,
+    pub test: String,
+    /// Assertion failures; empty means the test passed.
+    pub failures: Vec<String>,
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Check every expectation against `output`, returning a message per failure.
+pub fn check_all(expectations: &[Expectation], output: &str) -> Vec<String> {
+    expectations
+        .iter()
+        .filter_map(|e| check(e, output).err())
+        .collect()
+}
+
+fn check(expectation: &Expectation, output: &str) -> Result<(), String> {
+    match expectation {
+        Expectation::Contains(needle) => {
+            if output.contains(needle.as_str()) {
+                Ok(())
+            } else {
+                Err(format!("expected reply to contain {:?}", needle))
+            }
+        }
+        Expectation::Matches(pattern) => {
+            if compile(pattern)?.is_match(output) {
+                Ok(())
+            } else {
+                Err(format!("expected reply to match /{}/", pattern))
+            }
+        }
+        Expectation::NotMatches(pattern) => {
+            if compile(pattern)?.is_match(output) {
+                Err(format!("expected reply not to match /{}/", pattern))
+            } else {
+                Ok(())
+            }
+        }
+        Expectation::Json(predicate) => check_json(predicate, output),
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("invalid regex /{}/: {}", pattern, e))
+}
+
+fn check_json(predicate: &JsonPredicate, output: &str) -> Result<(), String> {
+    let value = first_json(output).ok_or("expected a JSON value in the reply")?;
+    let found = value
+        .pointer(&predicate.pointer)
+        .ok_or_else(|| format!("JSON has nothing at {:?}", predicate.pointer))?;
+    match &predicate.equals {
+        Some(expected) if expected != found => Err(format!(
+            "JSON at {:?} is {}, expected {}",
+            predicate.pointer, found, expected
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_text_expectations() {
+        let expect = vec![
+            Expectation::Contains("healthy".to_string()),
+            Expectation::Matches(r"(?i)^status:\s+ok".to_string()),
+            Expectation::NotMatches("ERROR".to_string()),
+        ];
+        assert!(check_all(&expect, "Status: OK, all healthy").is_empty());
+        assert_eq!(check_all(&expect, "status: ok but ERROR seen").len(), 2);
+        assert!(
+            check_all(&[Expectation::Matches("(".to_string())], "x")[0].contains("invalid regex")
+        );
+    }
+
+    #[test]
+    fn test_json_expectations() {
+        let reply = "Here you go:\n```json\n{\"status\": \"ok\", \"items\": [{\"id\": 3}]}\n```";
+        let exists = Expectation::Json(JsonPredicate {
+            pointer: "/items/0/id".to_string(),
+            equals: None,
+        });
+        let equals = Expectation::Json(JsonPredicate {
+            pointer: "/status".to_string(),
+            equals: Some(Value::from("degraded")),
+        });
+        assert!(check_all(std::slice::from_ref(&exists), reply).is_empty());
+        assert_eq!(
+            check_all(&[equals], reply),
+            vec!["JSON at \"/status\" is \"ok\", expected \"degraded\""]
+        );
+        assert!(!check_all(&[exists], "no json here").is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Cooperative cancellation for a run. Clones share one flag, which stays
+/// set once fired. The default never fires.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self(watch::channel(false).1)
+    }
+}
+
+impl Shutdown {
+    /// A shutdown fired by sending `true` on the returned sender.
+    pub fn channel() -> (watch::Sender<bool>, Self) {
+        let (tx, rx) = watch::channel(false);
+        (tx, Self(rx))
+    }
+
+    /// Fires on the first SIGINT or SIGTERM; a second signal exits at once
+    /// with status 130. The listener is installed once per process and
+    /// shared by later calls. Must be called inside a tokio runtime.
+    pub fn on_signals() -> Self {
+        static SIGNALS: OnceLock<Shutdown> = OnceLock::new();
+        SIGNALS
+            .get_or_init(|| {
+                let (tx, shutdown) = Self::channel();
+                tokio::spawn(async move {
+                    signal().await;
+                    warn!("Shutting down: cancelling running agents (signal again to exit now)");
+                    let _ = tx.send(true);
+                    signal().await;
+                    std::process::exit(130);
+                });
+                shutdown
+            })
+            .clone()
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once fired; never, if the sender is gone without firing.
+    pub async fn wait(&self) {
+        let mut rx = self.0.clone();
+        if rx.wait_for(|fired| *fired).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Cancels single agents of a run by name, leaving the others running.
+/// Clones share the same set.
+#[derive(Clone, Default)]
+pub struct AgentCancel(Arc<Mutex<HashMap<String, watch::Sender<bool>>>>);
+
+impl AgentCancel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel `agent` if it is running, or as soon as it starts.
+    pub fn cancel(&self, agent: &str) {
+        self.sender(agent, |tx| {
+            tx.send_replace(true);
+        });
+    }
+
+    /// Fires when `agent` is cancelled.
+    pub fn signal(&self, agent: &str) -> Shutdown {
+        self.sender(agent, |tx| Shutdown(tx.subscribe()))
+    }
+
+    fn sender<T>(&self, agent: &str, f: impl FnOnce(&watch::Sender<bool>) -> T) -> T {
+        let mut agents = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        f(agents
+            .entry(agent.to_string())
+            .or_insert_with(|| watch::channel(false).0))
+    }
+}
+
+/// SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!("Can't listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_fires_for_all_clones() {
+        let (tx, shutdown) = Shutdown::channel();
+        let clone = shutdown.clone();
+        assert!(!clone.is_triggered());
+        tx.send(true).unwrap();
+        clone.wait().await;
+        assert!(shutdown.is_triggered());
+
+        let agents = AgentCancel::new();
+        let monitor = agents.signal("monitor");
+        agents.cancel("monitor");
+        monitor.wait().await;
+        assert!(!agents.signal("analyzer").is_triggered());
+        assert!(agents.signal("monitor").is_triggered());
+
+        let never = Shutdown::default();
+        let waited = tokio::time::timeout(std::time::Duration::from_millis(20), never.wait()).await;
+        assert!(waited.is_err());
+    }
+}
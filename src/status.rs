@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::StatusCheckConfig;
+
+/// The provider whose outage should push hybrid agents onto their fallback.
+pub const ANTHROPIC_PROVIDER: &str = "anthropic";
+
+/// Status reported by a provider's Statuspage (`/api/v2/status.json`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProviderStatus {
+    pub provider: String,
+    /// Statuspage indicator: "none", "minor", "major" or "critical".
+    pub indicator: String,
+    pub description: String,
+}
+
+impl ProviderStatus {
+    /// Major and critical indicators are treated as an outage; minor
+    /// degradations are reported but don't change routing.
+    pub fn is_outage(&self) -> bool {
+        matches!(self.indicator.as_str(), "major" | "critical")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusPageResponse {
+    status: StatusPageStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusPageStatus {
+    indicator: String,
+    description: String,
+}
+
+async fn fetch(client: &Client, provider: &str, url: &str) -> Result<ProviderStatus> {
+    let response: StatusPageResponse = client
+        .get(url)
+        .send()
+        .await
+        .context("Failed to reach status page")?
+        .error_for_status()?
+        .json()
+        .await
+        .context("Failed to parse status page response")?;
+    Ok(ProviderStatus {
+        provider: provider.to_string(),
+        indicator: response.status.indicator,
+        description: response.status.description,
+    })
+}
+
+/// Poll every configured status page and return the providers currently in
+/// an outage. Unreachable status pages are logged and ignored, so a flaky
+/// status page never blocks a run.
+pub async fn check_outages(config: &StatusCheckConfig) -> Vec<ProviderStatus> {
+    let client = match Client::builder()
+        .timeout(Duration::from_secs(config.timeout_seconds))
+        .build()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Status check disabled: {:#}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut outages = Vec::new();
+    for (provider, url) in &config.providers {
+        match fetch(&client, provider, url).await {
+            Ok(status) if status.is_outage() => {
+                warn!(
+                    "Provider {} reports an outage: {}",
+                    provider, status.description
+                );
+                outages.push(status);
+            }
+            Ok(status) => info!("Provider {}: {}", provider, status.description),
+            Err(e) => warn!("Could not check {} status: {:#}", provider, e),
+        }
+    }
+    outages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outage_indicators() {
+        let status = |indicator: &str| ProviderStatus {
+            provider: ANTHROPIC_PROVIDER.to_string(),
+            indicator: indicator.to_string(),
+            description: String::new(),
+        };
+        assert!(!status("none").is_outage());
+        assert!(!status("minor").is_outage());
+        assert!(status("major").is_outage());
+        assert!(status("critical").is_outage());
+    }
+
+    #[test]
+    fn test_parse_statuspage_response() {
+        let body = r#"{"page":{"id":"x"},"status":{"indicator":"major","description":"Partial System Outage"}}"#;
+        let parsed: StatusPageResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(parsed.status.indicator, "major");
+        assert_eq!(parsed.status.description, "Partial System Outage");
+    }
+}
@@ -0,0 +1,579 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::agents::AgentResult;
+use crate::config::{Config, StoreKind};
+use crate::cost;
+
+mod sqlite;
+
+/// Bumped when `RunRecord` changes shape.
+pub const RUN_SCHEMA_VERSION: u32 = 1;
+
+/// Normalized record of one run, as kept in the history store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub schema_version: u32,
+    pub run_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub mode: String,
+    pub global_client_mode: String,
+    /// `run` for live runs, `import:<file>` for backfilled ones.
+    pub source: String,
+    pub agents: Vec<AgentRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRecord {
+    pub agent: String,
+    pub status: String,
+    pub client_mode: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub estimated_cost: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// As reported by the backend, else estimated from the output length.
+    #[serde(default)]
+    pub output_tokens: u64,
+    /// CLI session the agent ran in, for `session: resume` on later runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+}
+
+impl AgentRecord {
+    pub fn from_result(result: &AgentResult) -> Self {
+        Self {
+            agent: result.agent.clone(),
+            status: result.status.clone(),
+            client_mode: result.client_mode.clone(),
+            model: result.model.clone(),
+            output: result.output.clone(),
+            error: result.error.clone(),
+            estimated_cost: cost::estimate_cost(result.output.as_deref(), &result.client_mode),
+            duration_ms: result.duration_ms,
+            output_tokens: result
+                .output_tokens
+                .unwrap_or_else(|| result.output.as_deref().map_or(0, cost::estimate_tokens)),
+            session_id: result.session_id.clone(),
+        }
+    }
+}
+
+impl RunRecord {
+    pub fn successful(&self) -> usize {
+        self.agents.iter().filter(|a| a.status == "success").count()
+    }
+
+    pub fn estimated_cost(&self) -> f64 {
+        self.agents
+            .iter()
+            .fold(0.0, |sum, a| sum + a.estimated_cost)
+    }
+}
+
+/// History of runs: an append-only JSONL file, or a SQLite database with
+/// `runs` and `agents` tables for ad-hoc queries.
+pub struct RunStore {
+    path: PathBuf,
+    kind: StoreKind,
+}
+
+impl RunStore {
+    /// A JSONL store at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            kind: StoreKind::Jsonl,
+        }
+    }
+
+    /// A SQLite store at `path`, created on first write.
+    pub fn sqlite(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            kind: StoreKind::Sqlite,
+        }
+    }
+
+    /// The store selected by `outputs.store`.
+    pub fn from_config(config: &Config) -> Self {
+        match config.outputs.store {
+            StoreKind::Jsonl => Self::new(&config.history.path),
+            StoreKind::Sqlite => Self::sqlite(&config.history.sqlite_path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &RunRecord) -> Result<()> {
+        if self.kind == StoreKind::Sqlite {
+            return sqlite::append(&self.path, record);
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open history store {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// All runs, oldest first. Unparseable lines are skipped with a warning.
+    pub fn load(&self) -> Result<Vec<RunRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        if self.kind == StoreKind::Sqlite {
+            return sqlite::load(&self.path);
+        }
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read history store {}", self.path.display()))?;
+        let mut runs: Vec<RunRecord> = content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter_map(|l| match serde_json::from_str(l) {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    warn!("Skipping malformed history entry: {}", e);
+                    None
+                }
+            })
+            .collect();
+        runs.sort_by_key(|r| r.timestamp);
+        Ok(runs)
+    }
+
+    /// Backfill runs from `results-*.json` / `summary-*.txt` files written by
+    /// older versions. Runs already in the store are skipped, and a summary is
+    /// only used when no JSON exists for the same timestamp.
+    pub fn import_dir(&self, dir: &Path) -> Result<usize> {
+        let mut known: HashSet<String> = self.load()?.into_iter().map(|r| r.run_id).collect();
+        let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read {}", dir.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect();
+        entries.sort();
+
+        let json_stamps: HashSet<String> = entries
+            .iter()
+            .filter_map(|p| file_stamp(p, "results-", ".json"))
+            .collect();
+
+        let mut imported = 0;
+        for path in &entries {
+            let record = if file_stamp(path, "results-", ".json").is_some() {
+                parse_results_json(path)
+            } else if let Some(stamp) = file_stamp(path, "summary-", ".txt") {
+                if json_stamps.contains(&stamp) {
+                    continue;
+                }
+                parse_summary_txt(path)
+            } else {
+                continue;
+            };
+
+            match record {
+                Ok(record) if known.contains(&record.run_id) => {}
+                Ok(record) => {
+                    known.insert(record.run_id.clone());
+                    self.append(&record)?;
+                    imported += 1;
+                }
+                Err(e) => warn!("Skipping {}: {:#}", path.display(), e),
+            }
+        }
+        info!("Imported {} run(s) from {}", imported, dir.display());
+        Ok(imported)
+    }
+
+    /// The CLI session each agent's most recent run reported, by agent name.
+    pub fn last_sessions(&self) -> Result<HashMap<String, String>> {
+        let mut sessions = HashMap::new();
+        for run in self.load()? {
+            for agent in run.agents {
+                if let Some(session) = agent.session_id {
+                    sessions.insert(agent.agent, session);
+                }
+            }
+        }
+        Ok(sessions)
+    }
+}
+
+/// Success and latency totals for one agent, over all time or one day.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentStats {
+    pub agent: String,
+    pub day: Option<NaiveDate>,
+    pub runs: usize,
+    pub successes: usize,
+    /// Sum and count of recorded durations; older records have none.
+    pub total_ms: u64,
+    pub timed: usize,
+    pub output_tokens: u64,
+}
+
+impl AgentStats {
+    pub fn success_rate(&self) -> f64 {
+        if self.runs == 0 {
+            return 0.0;
+        }
+        self.successes as f64 / self.runs as f64
+    }
+
+    pub fn avg_latency_ms(&self) -> Option<u64> {
+        (self.timed > 0).then(|| self.total_ms / self.timed as u64)
+    }
+}
+
+/// Aggregate agent results per agent, or per agent and day when `daily`.
+/// Sorted by agent, then day.
+pub fn agent_stats(runs: &[RunRecord], daily: bool) -> Vec<AgentStats> {
+    let mut stats: BTreeMap<(String, Option<NaiveDate>), AgentStats> = BTreeMap::new();
+    for run in runs {
+        let day = daily.then(|| run.timestamp.date_naive());
+        for agent in &run.agents {
+            let entry = stats
+                .entry((agent.agent.clone(), day))
+                .or_insert_with(|| AgentStats {
+                    agent: agent.agent.clone(),
+                    day,
+                    ..AgentStats::default()
+                });
+            entry.runs += 1;
+            if agent.status == "success" {
+                entry.successes += 1;
+            }
+            if let Some(ms) = agent.duration_ms {
+                entry.total_ms += ms;
+                entry.timed += 1;
+            }
+            entry.output_tokens += agent.output_tokens;
+        }
+    }
+    stats.into_values().collect()
+}
+
+fn file_stamp(path: &Path, prefix: &str, suffix: &str) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    Some(name.strip_prefix(prefix)?.strip_suffix(suffix)?.to_string())
+}
+
+fn source_of(path: &Path) -> String {
+    format!(
+        "import:{}",
+        path.file_name().and_then(|n| n.to_str()).unwrap_or("?")
+    )
+}
+
+/// Run IDs for imported runs follow the live format: `<mode>-<YYYYmmdd-HHMMSS>`.
+fn legacy_run_id(mode: &str, timestamp: &DateTime<Utc>) -> String {
+    format!("{}-{}", mode, timestamp.format("%Y%m%d-%H%M%S"))
+}
+
+#[derive(Deserialize)]
+struct LegacyResults {
+    #[serde(default)]
+    run_id: Option<String>,
+    timestamp: DateTime<Utc>,
+    #[serde(default = "unknown")]
+    mode: String,
+    #[serde(default = "unknown")]
+    global_client_mode: String,
+    #[serde(default)]
+    results: Vec<AgentResult>,
+}
+
+fn unknown() -> String {
+    "unknown".to_string()
+}
+
+fn parse_results_json(path: &Path) -> Result<RunRecord> {
+    let content = fs::read_to_string(path)?;
+    let legacy: LegacyResults = serde_json::from_str(&content).context("Invalid results JSON")?;
+    let run_id = legacy
+        .run_id
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| legacy_run_id(&legacy.mode, &legacy.timestamp));
+    Ok(RunRecord {
+        schema_version: RUN_SCHEMA_VERSION,
+        run_id,
+        timestamp: legacy.timestamp,
+        mode: legacy.mode,
+        global_client_mode: legacy.global_client_mode,
+        source: source_of(path),
+        agents: legacy
+            .results
+            .iter()
+            .map(AgentRecord::from_result)
+            .collect(),
+    })
+}
+
+/// Parse the human-readable summary format written by `generate_summary`.
+fn parse_summary_txt(path: &Path) -> Result<RunRecord> {
+    let content = fs::read_to_string(path)?;
+    let mut sections = content.split("──────────────────────────────────────────────────");
+    let header = sections.next().unwrap_or_default();
+
+    let field = |text: &str, key: &str| -> Option<String> {
+        text.lines()
+            .find_map(|l| l.strip_prefix(key))
+            .map(|v| v.trim().to_string())
+    };
+
+    let stamp = field(header, "Timestamp:").context("Summary has no Timestamp")?;
+    let naive = NaiveDateTime::parse_from_str(&stamp, "%Y%m%d-%H%M%S")
+        .with_context(|| format!("Unrecognized timestamp '{}'", stamp))?;
+    let timestamp = Utc.from_utc_datetime(&naive);
+    let mode = field(header, "Mode:").unwrap_or_else(unknown);
+    let global_client_mode = field(header, "Global Client:").unwrap_or_else(unknown);
+    let run_id = field(header, "Run ID:").unwrap_or_else(|| legacy_run_id(&mode, &timestamp));
+
+    let mut agents = Vec::new();
+    for section in sections {
+        let Some(agent) = field(section, "Agent:") else {
+            continue;
+        };
+        let status = field(section, "Status:").unwrap_or_else(unknown);
+        let client_mode = field(section, "Client:").unwrap_or_else(unknown);
+        let output = section
+            .split_once("\nOutput:\n")
+            .map(|(_, out)| out.trim_end().to_string());
+        let error = field(section, "Error:");
+        agents.push(AgentRecord {
+            estimated_cost: cost::estimate_cost(output.as_deref(), &client_mode),
+            agent,
+            status,
+            client_mode,
+            model: field(section, "Model:"),
+            duration_ms: None,
+            output_tokens: output.as_deref().map_or(0, cost::estimate_tokens),
+            output,
+            error,
+            session_id: None,
+        });
+    }
+
+    Ok(RunRecord {
+        schema_version: RUN_SCHEMA_VERSION,
+        run_id,
+        timestamp,
+        mode,
+        global_client_mode,
+        source: source_of(path),
+        agents,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("orchestra-store-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_import_legacy_outputs() {
+        let dir = temp_dir("import");
+        fs::write(
+            dir.join("results-20250101-120000.json"),
+            r#"{"timestamp":"2025-01-01T12:00:00Z","mode":"auto","global_client_mode":"api",
+               "results":[{"agent":"monitor","status":"success","output":"all good",
+               "client_mode":"api","timestamp":"2025-01-01T12:00:05Z"}]}"#,
+        )
+        .unwrap();
+        // Same run as the JSON: must not be imported twice
+        fs::write(
+            dir.join("summary-20250101-120000.txt"),
+            "Timestamp: 20250101-120000\nMode: auto\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("summary-20250102-080000.txt"),
+            "Agent Orchestra Run Summary\n==========\n\nTimestamp: 20250102-080000\nMode: research\n\
+             Global Client: claude-code\n\n\n──────────────────────────────────────────────────\n\
+             Agent: researcher\nStatus: success\nClient: claude-code\nOutput:\nline one\nline two\n\
+             \n──────────────────────────────────────────────────\n\
+             Agent: synthesizer\nStatus: failed\nClient: api\nError: timed out\n",
+        )
+        .unwrap();
+
+        let store = RunStore::new(dir.join("history.jsonl"));
+        assert_eq!(store.import_dir(&dir).unwrap(), 2);
+        // Re-importing is a no-op
+        assert_eq!(store.import_dir(&dir).unwrap(), 0);
+
+        let runs = store.load().unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].run_id, "auto-20250101-120000");
+        assert_eq!(runs[1].run_id, "research-20250102-080000");
+        assert_eq!(runs[1].agents.len(), 2);
+        assert_eq!(
+            runs[1].agents[0].output.as_deref(),
+            Some("line one\nline two")
+        );
+        assert_eq!(runs[1].agents[1].error.as_deref(), Some("timed out"));
+        assert_eq!(runs[1].successful(), 1);
+    }
+
+    fn run(run_id: &str, timestamp: &str, agents: &[(&str, &str, Option<u64>)]) -> RunRecord {
+        RunRecord {
+            schema_version: RUN_SCHEMA_VERSION,
+            run_id: run_id.to_string(),
+            timestamp: timestamp.parse().unwrap(),
+            mode: "auto".to_string(),
+            global_client_mode: "api".to_string(),
+            source: "run".to_string(),
+            agents: agents
+                .iter()
+                .map(|(agent, status, duration_ms)| AgentRecord {
+                    agent: agent.to_string(),
+                    status: status.to_string(),
+                    client_mode: "api".to_string(),
+                    model: None,
+                    output: Some("x".repeat(40)),
+                    error: None,
+                    estimated_cost: 0.01,
+                    duration_ms: *duration_ms,
+                    output_tokens: 10,
+                    session_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_agent_stats_overall_and_daily() {
+        let runs = vec![
+            run(
+                "auto-1",
+                "2026-01-01T08:00:00Z",
+                &[
+                    ("monitor", "success", Some(1000)),
+                    ("analyzer", "failed", None),
+                ],
+            ),
+            run(
+                "auto-2",
+                "2026-01-02T08:00:00Z",
+                &[("monitor", "failed", Some(3000))],
+            ),
+        ];
+        let overall = agent_stats(&runs, false);
+        assert_eq!(overall.len(), 2);
+        assert_eq!(overall[1].agent, "monitor");
+        assert_eq!(overall[1].success_rate(), 0.5);
+        assert_eq!(overall[1].avg_latency_ms(), Some(2000));
+        assert_eq!(overall[0].avg_latency_ms(), None);
+
+        let daily = agent_stats(&runs, true);
+        assert_eq!(daily.len(), 3);
+        assert_eq!(daily[2].day, NaiveDate::from_ymd_opt(2026, 1, 2));
+        assert_eq!(daily[2].output_tokens, 10);
+    }
+
+    #[test]
+    fn test_sqlite_store_round_trip() {
+        let dir = temp_dir("sqlite");
+        let store = RunStore::sqlite(dir.join("history.db"));
+        assert!(store.load().unwrap().is_empty());
+        let first = run(
+            "auto-1",
+            "2026-01-01T08:00:00Z",
+            &[("monitor", "success", Some(1500))],
+        );
+        store.append(&first).unwrap();
+        store
+            .append(&run("auto-0", "2025-12-31T08:00:00Z", &[]))
+            .unwrap();
+        // Appending a run again replaces it rather than duplicating agents
+        store.append(&first).unwrap();
+
+        let runs = store.load().unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].run_id, "auto-0");
+        assert_eq!(runs[1].agents.len(), 1);
+        assert_eq!(runs[1].agents[0].duration_ms, Some(1500));
+        assert_eq!(
+            runs[1].agents[0].output.as_deref(),
+            Some("x".repeat(40).as_str())
+        );
+        assert_eq!(runs[1].timestamp, first.timestamp);
+    }
+
+    #[test]
+    fn test_sqlite_store_adds_missing_columns() {
+        let dir = temp_dir("migrate");
+        let path = dir.join("history.db");
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE agents (run_id TEXT NOT NULL, position INTEGER NOT NULL,
+                 agent TEXT NOT NULL, status TEXT NOT NULL, client_mode TEXT NOT NULL,
+                 model TEXT, output TEXT, error TEXT, duration_ms INTEGER,
+                 output_tokens INTEGER NOT NULL, estimated_cost REAL NOT NULL,
+                 PRIMARY KEY (run_id, position));",
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = RunStore::sqlite(&path);
+        let mut record = run(
+            "auto-1",
+            "2026-01-01T08:00:00Z",
+            &[("monitor", "success", None)],
+        );
+        record.agents[0].session_id = Some("s-1".to_string());
+        store.append(&record).unwrap();
+        assert_eq!(store.last_sessions().unwrap()["monitor"], "s-1");
+    }
+
+    #[test]
+    fn test_last_sessions_prefers_latest_run() {
+        let dir = temp_dir("sessions");
+        for store in [
+            RunStore::new(dir.join("history.jsonl")),
+            RunStore::sqlite(dir.join("history.db")),
+        ] {
+            let mut older = run(
+                "auto-1",
+                "2026-01-01T08:00:00Z",
+                &[("monitor", "success", None), ("analyzer", "success", None)],
+            );
+            older.agents[0].session_id = Some("s-old".to_string());
+            older.agents[1].session_id = Some("s-analyzer".to_string());
+            let mut newer = run(
+                "auto-2",
+                "2026-01-02T08:00:00Z",
+                &[("monitor", "success", None)],
+            );
+            newer.agents[0].session_id = Some("s-new".to_string());
+            store.append(&newer).unwrap();
+            store.append(&older).unwrap();
+
+            let sessions = store.last_sessions().unwrap();
+            assert_eq!(sessions["monitor"], "s-new");
+            assert_eq!(sessions["analyzer"], "s-analyzer");
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! SQLite backend for [`RunStore`](super::RunStore). One row per run in
+//! `runs` and one per agent result in `agents`, so success rates and
+//! latencies can also be queried with plain SQL.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::Path;
+
+use super::{AgentRecord, RunRecord};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    run_id             TEXT PRIMARY KEY,
+    schema_version     INTEGER NOT NULL,
+    timestamp          TEXT NOT NULL,
+    mode               TEXT NOT NULL,
+    global_client_mode TEXT NOT NULL,
+    source             TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS agents (
+    run_id         TEXT NOT NULL REFERENCES runs(run_id) ON DELETE CASCADE,
+    position       INTEGER NOT NULL,
+    agent          TEXT NOT NULL,
+    status         TEXT NOT NULL,
+    client_mode    TEXT NOT NULL,
+    model          TEXT,
+    output         TEXT,
+    error          TEXT,
+    duration_ms    INTEGER,
+    output_tokens  INTEGER NOT NULL,
+    estimated_cost REAL NOT NULL,
+    session_id     TEXT,
+    PRIMARY KEY (run_id, position)
+);
+CREATE INDEX IF NOT EXISTS agents_by_name ON agents(agent);
+CREATE INDEX IF NOT EXISTS runs_by_time ON runs(timestamp);
+";
+
+fn open(path: &Path) -> Result<Connection> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let conn = Connection::open(path)
+        .with_context(|| format!("Failed to open history database {}", path.display()))?;
+    conn.execute_batch(SCHEMA)
+        .context("Failed to create history tables")?;
+    migrate(&conn).context("Failed to upgrade history tables")?;
+    Ok(conn)
+}
+
+/// Add columns that databases created by older versions lack.
+fn migrate(conn: &Connection) -> Result<()> {
+    let columns = conn
+        .prepare("SELECT name FROM pragma_table_info('agents')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if !columns.iter().any(|c| c == "session_id") {
+        conn.execute("ALTER TABLE agents ADD COLUMN session_id TEXT", [])?;
+    }
+    Ok(())
+}
+
+/// Insert `record`, replacing any earlier row for the same run ID.
+pub fn append(path: &Path, record: &RunRecord) -> Result<()> {
+    let mut conn = open(path)?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM agents WHERE run_id = ?1", [&record.run_id])?;
+    tx.execute(
+        "INSERT OR REPLACE INTO runs
+             (run_id, schema_version, timestamp, mode, global_client_mode, source)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            record.run_id,
+            record.schema_version,
+            record.timestamp.to_rfc3339(),
+            record.mode,
+            record.global_client_mode,
+            record.source,
+        ],
+    )?;
+    for (position, agent) in record.agents.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO agents
+                 (run_id, position, agent, status, client_mode, model, output, error,
+                  duration_ms, output_tokens, estimated_cost, session_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                record.run_id,
+                position,
+                agent.agent,
+                agent.status,
+                agent.client_mode,
+                agent.model,
+                agent.output,
+                agent.error,
+                agent.duration_ms,
+                agent.output_tokens,
+                agent.estimated_cost,
+                agent.session_id,
+            ],
+        )?;
+    }
+    tx.commit()
+        .with_context(|| format!("Failed to write history database {}", path.display()))
+}
+
+/// All runs with their agents, oldest first.
+pub fn load(path: &Path) -> Result<Vec<RunRecord>> {
+    let conn = open(path)?;
+    let mut runs_stmt = conn.prepare(
+        "SELECT run_id, schema_version, timestamp, mode, global_client_mode, source
+         FROM runs ORDER BY timestamp",
+    )?;
+    let mut agents_stmt = conn.prepare(
+        "SELECT agent, status, client_mode, model, output, error,
+                duration_ms, output_tokens, estimated_cost, session_id
+         FROM agents WHERE run_id = ?1 ORDER BY position",
+    )?;
+
+    let rows = runs_stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, u32>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+        ))
+    })?;
+
+    let mut runs = Vec::new();
+    for row in rows {
+        let (run_id, schema_version, timestamp, mode, global_client_mode, source) = row?;
+        let timestamp: DateTime<Utc> = DateTime::parse_from_rfc3339(&timestamp)
+            .with_context(|| format!("Bad timestamp '{}' for run {}", timestamp, run_id))?
+            .with_timezone(&Utc);
+        let agents = agents_stmt
+            .query_map([&run_id], |row| {
+                Ok(AgentRecord {
+                    agent: row.get(0)?,
+                    status: row.get(1)?,
+                    client_mode: row.get(2)?,
+                    model: row.get(3)?,
+                    output: row.get(4)?,
+                    error: row.get(5)?,
+                    duration_ms: row.get(6)?,
+                    output_tokens: row.get(7)?,
+                    estimated_cost: row.get(8)?,
+                    session_id: row.get(9)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        runs.push(RunRecord {
+            schema_version,
+            run_id,
+            timestamp,
+            mode,
+            global_client_mode,
+            source,
+            agents,
+        });
+    }
+    Ok(runs)
+}
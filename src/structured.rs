@@ -0,0 +1,132 @@
+//! Structured output (`output_schema`): the agent is asked for a JSON value
+//! matching a JSON Schema, the reply is checked against it, and a reply that
+//! doesn't match is sent back with what was wrong until one does or
+//! [`MAX_ATTEMPTS`] replies have failed.
+
+use anyhow::Result;
+use serde_json::Value;
+
+/// Replies an agent gets to produce a value matching its schema.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Errors listed in a retry prompt; the rest are counted.
+const MAX_ERRORS: usize = 10;
+
+pub struct Schema {
+    schema: Value,
+    validator: jsonschema::Validator,
+}
+
+impl Schema {
+    pub fn new(schema: &Value) -> Result<Self> {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|e| anyhow::anyhow!("Invalid output_schema: {}", e))?;
+        Ok(Self {
+            schema: schema.clone(),
+            validator,
+        })
+    }
+
+    /// Appended to the agent's prompt.
+    pub fn instruction(&self) -> String {
+        format!(
+            "\n\nReply with a single JSON value that matches this JSON Schema, \
+             and nothing else:\n```json\n{}\n```\n",
+            serde_json::to_string_pretty(&self.schema).unwrap_or_default()
+        )
+    }
+
+    /// The JSON value in `reply`, or what is wrong with it.
+    pub fn extract(&self, reply: &str) -> Result<Value, Vec<String>> {
+        let value = first_json(reply)
+            .ok_or_else(|| vec!["the reply contains no JSON object or array".to_string()])?;
+        let errors: Vec<String> = self
+            .validator
+            .iter_errors(&value)
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{}: {}", path, e),
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The prompt for another try after `reply` failed with `errors`.
+pub fn retry_prompt(prompt: &str, reply: &str, errors: &[String]) -> String {
+    let mut listed: Vec<String> = errors
+        .iter()
+        .take(MAX_ERRORS)
+        .map(|e| format!("- {}", e))
+        .collect();
+    if errors.len() > MAX_ERRORS {
+        listed.push(format!("- and {} more", errors.len() - MAX_ERRORS));
+    }
+    format!(
+        "{}\n\nYour previous reply was:\n{}\n\nIt does not match the schema:\n{}\n\n\
+         Reply again with only the corrected JSON.",
+        prompt,
+        reply.trim(),
+        listed.join("\n")
+    )
+}
+
+/// The first JSON object or array in `text`, skipping prose and code fences.
+pub fn first_json(text: &str) -> Option<Value> {
+    text.match_indices(['{', '[']).find_map(|(i, _)| {
+        serde_json::Deserializer::from_str(&text[i..])
+            .into_iter::<Value>()
+            .next()?
+            .ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Schema {
+        Schema::new(&json!({
+            "type": "object",
+            "required": ["status", "disks"],
+            "properties": {
+                "status": {"enum": ["ok", "degraded", "down"]},
+                "disks": {"type": "array", "items": {"type": "integer", "maximum": 100}}
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_extract_validates_the_first_json_value() {
+        let schema = schema();
+        let reply = "Here you go:\n```json\n{\"status\": \"ok\", \"disks\": [40, 97]}\n```";
+        assert_eq!(
+            schema.extract(reply).unwrap(),
+            json!({"status": "ok", "disks": [40, 97]})
+        );
+
+        let errors = schema
+            .extract("{\"status\": \"fine\", \"disks\": [140]}")
+            .unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors.iter().any(|e| e.starts_with("/status:")));
+        assert!(errors.iter().any(|e| e.starts_with("/disks/0:")));
+        assert!(schema.extract("All disks are fine.").is_err());
+        assert!(Schema::new(&json!({"type": "nope"})).is_err());
+    }
+
+    #[test]
+    fn test_retry_prompt_lists_errors() {
+        let errors: Vec<String> = (0..12).map(|i| format!("error {}", i)).collect();
+        let prompt = retry_prompt("Check disks", "{}", &errors);
+        assert!(prompt.starts_with("Check disks\n\nYour previous reply was:\n{}"));
+        assert!(prompt.contains("- error 9\n- and 2 more"));
+        assert!(!prompt.contains("error 10"));
+    }
+}
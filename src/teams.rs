@@ -0,0 +1,211 @@
+//! Agent Teams sessions. A team definition becomes one task file per
+//! teammate under `teams.tasks_dir`, the lead session is told to hand them
+//! out, and what each teammate wrote back is collected afterwards.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::config::TeamDefinition;
+
+/// One team run: the definition and the directory its task files live in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamSession {
+    pub team: String,
+    pub definition: TeamDefinition,
+    /// `<tasks_dir>/<run-id>`.
+    pub dir: PathBuf,
+}
+
+/// A teammate's task file, `<name>.json` in the session directory.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TeammateTask {
+    pub id: String,
+    pub subject: String,
+    pub description: String,
+    pub owner: String,
+    /// `pending`, `in_progress` or `completed`.
+    pub status: String,
+    pub timeout_seconds: u64,
+    /// Where the teammate writes its final result.
+    pub output_file: PathBuf,
+}
+
+/// What a teammate left behind once the session ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeammateOutput {
+    pub name: String,
+    pub role: String,
+    pub status: String,
+    pub output: Option<String>,
+}
+
+impl TeamSession {
+    pub fn new(
+        team: impl Into<String>,
+        definition: TeamDefinition,
+        tasks_dir: &str,
+        run_id: &str,
+    ) -> Self {
+        Self {
+            team: team.into(),
+            definition,
+            dir: expand_home(tasks_dir).join(run_id),
+        }
+    }
+
+    fn task_file(&self, teammate: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", teammate))
+    }
+
+    fn output_file(&self, teammate: &str) -> PathBuf {
+        self.dir.join(format!("{}.md", teammate))
+    }
+
+    /// Create the session directory with a pending task per teammate.
+    pub fn write_tasks(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create team directory {}", self.dir.display()))?;
+        for (i, teammate) in self.definition.teammates.iter().enumerate() {
+            let task = TeammateTask {
+                id: (i + 1).to_string(),
+                subject: teammate.name.clone(),
+                description: teammate.role.clone(),
+                owner: teammate.name.clone(),
+                status: "pending".to_string(),
+                timeout_seconds: teammate.timeout_seconds,
+                output_file: self.output_file(&teammate.name),
+            };
+            let path = self.task_file(&teammate.name);
+            fs::write(&path, serde_json::to_string_pretty(&task)?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Instructions for the team lead: the job in `prompt`, who to spawn,
+    /// where their tasks are and where each must leave its result.
+    pub fn lead_prompt(&self, prompt: &str) -> String {
+        let mut out = format!(
+            "Create an agent team for: {}\n\nSpawn one teammate per task file in {}:\n",
+            prompt.trim(),
+            self.dir.display()
+        );
+        for teammate in &self.definition.teammates {
+            let _ = writeln!(
+                out,
+                "- {} (task {}): {}",
+                teammate.name,
+                self.task_file(&teammate.name).display(),
+                teammate.role.trim()
+            );
+        }
+        out.push_str(
+            "\nEach teammate sets its task's status to \"in_progress\" when it starts, \
+             writes its final result to the task's output_file, then sets the status \
+             to \"completed\". Wait for every teammate, then summarize what the team did.",
+        );
+        out
+    }
+
+    /// Read back every teammate's task status and output file. A teammate
+    /// with an output file counts as completed whatever its task file says.
+    pub fn collect(&self) -> Vec<TeammateOutput> {
+        self.definition
+            .teammates
+            .iter()
+            .map(|teammate| {
+                let status = fs::read_to_string(self.task_file(&teammate.name))
+                    .ok()
+                    .and_then(|s| serde_json::from_str::<TeammateTask>(&s).ok())
+                    .map(|t| t.status)
+                    .unwrap_or_else(|| "pending".to_string());
+                let output = fs::read_to_string(self.output_file(&teammate.name))
+                    .ok()
+                    .filter(|s| !s.trim().is_empty());
+                if output.is_none() {
+                    warn!("Teammate {} left no output ({})", teammate.name, status);
+                }
+                TeammateOutput {
+                    name: teammate.name.clone(),
+                    role: teammate.role.clone(),
+                    status: if output.is_some() {
+                        "completed".to_string()
+                    } else {
+                        status
+                    },
+                    output,
+                }
+            })
+            .collect()
+    }
+}
+
+/// `~/…` relative to the home directory; anything else as is.
+fn expand_home(path: &str) -> PathBuf {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TeammateDefinition;
+
+    fn session(dir: &Path) -> TeamSession {
+        let definition = TeamDefinition {
+            description: "Review a change".to_string(),
+            teammates: ["architect", "reviewer"]
+                .into_iter()
+                .map(|name| TeammateDefinition {
+                    name: name.to_string(),
+                    role: format!("You are the {}.", name),
+                    timeout_seconds: 60,
+                })
+                .collect(),
+        };
+        TeamSession::new("code-review", definition, &dir.to_string_lossy(), "run-1")
+    }
+
+    #[test]
+    fn test_write_tasks_and_collect() {
+        let dir = std::env::temp_dir().join(format!("orchestra-teams-{}", std::process::id()));
+        let session = session(&dir);
+        session.write_tasks().unwrap();
+        assert_eq!(session.dir, dir.join("run-1"));
+
+        let task: TeammateTask =
+            serde_json::from_str(&fs::read_to_string(session.task_file("reviewer")).unwrap())
+                .unwrap();
+        assert_eq!(task.status, "pending");
+        assert_eq!(task.output_file, session.dir.join("reviewer.md"));
+        let prompt = session.lead_prompt("Review a change");
+        assert!(prompt.starts_with("Create an agent team for: Review a change\n"));
+        assert!(prompt.contains("- architect (task "));
+
+        fs::write(session.output_file("architect"), "Plan: ship it\n").unwrap();
+        let outputs = session.collect();
+        assert_eq!(outputs[0].status, "completed");
+        assert_eq!(outputs[1].status, "pending");
+        assert_eq!(outputs[0].output.as_deref(), Some("Plan: ship it\n"));
+        assert_eq!(outputs[1].output, None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_home() {
+        assert_eq!(expand_home("/var/tasks"), PathBuf::from("/var/tasks"));
+        if let Some(home) = std::env::var_os("HOME") {
+            assert_eq!(
+                expand_home("~/.claude/tasks"),
+                Path::new(&home).join(".claude/tasks")
+            );
+        }
+    }
+}
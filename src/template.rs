@@ -0,0 +1,393 @@
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use tracing::warn;
+
+use crate::agents::AgentResult;
+
+/// Variables available to `{{ name }}` placeholders in prompts.
+///
+/// Built-ins are `now`, `hostname`, `mode` and `run_id`; `env.NAME` reads the
+/// environment at render time; anything passed with `--var key=value` is
+/// available under its key. `results.AGENT.output` and `results.AGENT.status`
+/// are filled in just before an agent runs, from agents that finished
+/// earlier in the run.
+///
+/// A template that uses more than plain placeholders ([`is_tera`]) is
+/// rendered by Tera, with the same variables as nested objects.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars {
+    vars: BTreeMap<String, String>,
+}
+
+impl TemplateVars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.vars.insert(key.into(), value.into());
+    }
+
+    pub fn extend(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        self.vars.extend(vars);
+    }
+
+    /// Every variable set so far; `env.*` aren't included.
+    pub fn all(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        match name.strip_prefix("env.") {
+            Some(var) => env::var(var).ok(),
+            None => self.vars.get(name).cloned(),
+        }
+    }
+
+    /// Substitute every `{{ name }}` placeholder. Placeholders that don't look
+    /// like a variable name (e.g. JSON examples in a prompt) are left alone, as
+    /// are unknown variables, whose names are returned so the caller can warn.
+    ///
+    /// A Tera template that reads `results` is left whole for
+    /// [`render_results`](Self::render_results), since Tera can't render part
+    /// of one.
+    pub fn render(&self, template: &str) -> (String, Vec<String>) {
+        if is_tera(template) {
+            if !result_refs(template).is_empty() {
+                return (template.to_string(), Vec::new());
+            }
+            if let Some(rendered) = self.render_tera(template) {
+                return (rendered, Vec::new());
+            }
+        }
+        self.render_where(template, |_| true)
+    }
+
+    /// Like [`render`](Self::render), but only `results.*` placeholders are
+    /// substituted; the rest, `env.*` included, are left as written. A Tera
+    /// template, which `render` left alone, is rendered whole.
+    pub fn render_results(&self, template: &str) -> (String, Vec<String>) {
+        if is_tera(template) {
+            if let Some(rendered) = self.render_tera(template) {
+                return (rendered, Vec::new());
+            }
+        }
+        self.render_where(template, |name| name.starts_with(RESULTS_PREFIX))
+    }
+
+    /// Render `template` with Tera, or `None`, with a warning, if it doesn't
+    /// parse or reads a variable that isn't set. The caller then falls back
+    /// to plain placeholders.
+    fn render_tera(&self, template: &str) -> Option<String> {
+        let context = tera::Context::from_value(self.context()).ok()?;
+        match tera::Tera::one_off(template, &context, false) {
+            Ok(rendered) => Some(rendered),
+            Err(e) => {
+                let mut reason = e.to_string();
+                let mut source = std::error::Error::source(&e);
+                while let Some(cause) = source {
+                    reason = format!("{}: {}", reason, cause);
+                    source = cause.source();
+                }
+                warn!(
+                    "Template not rendered by Tera, only its plain placeholders: {}",
+                    reason
+                );
+                None
+            }
+        }
+    }
+
+    /// The variables as Tera sees them: `results.monitor.output` becomes
+    /// `results` → `monitor` → `output`, and `env` holds the environment.
+    fn context(&self) -> Value {
+        let mut root = Map::new();
+        root.insert(
+            "env".to_string(),
+            Value::Object(env::vars().map(|(k, v)| (k, Value::String(v))).collect()),
+        );
+        'vars: for (name, value) in &self.vars {
+            let mut parts: Vec<&str> = name.split('.').collect();
+            let leaf = parts.pop().unwrap_or_default();
+            let mut node = &mut root;
+            for part in parts {
+                match node
+                    .entry(part)
+                    .or_insert_with(|| Value::Object(Map::new()))
+                {
+                    Value::Object(child) => node = child,
+                    // `a` is set and so is `a.b`; `a` wins
+                    _ => continue 'vars,
+                }
+            }
+            node.insert(leaf.to_string(), Value::String(value.clone()));
+        }
+        Value::Object(root)
+    }
+
+    fn render_where(&self, template: &str, wanted: impl Fn(&str) -> bool) -> (String, Vec<String>) {
+        let mut out = String::with_capacity(template.len());
+        let mut unknown = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + len].trim();
+            let end = start + 2 + len + 2;
+            out.push_str(&rest[..start]);
+
+            match self.lookup(name) {
+                Some(value) if is_var_name(name) && wanted(name) => out.push_str(&value),
+                _ => {
+                    if is_var_name(name) && wanted(name) {
+                        unknown.push(name.to_string());
+                    }
+                    out.push_str(&rest[start..end]);
+                }
+            }
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+        (out, unknown)
+    }
+}
+
+/// Variables every run sets, as opposed to those passed with `--var`.
+pub const BUILTINS: &[&str] = &["now", "hostname", "mode", "run_id"];
+
+/// Prefix of the placeholders that read an earlier agent's result.
+pub const RESULTS_PREFIX: &str = "results.";
+
+/// What's between each `open` and the next `close` in `text`.
+fn tags<'a>(text: &'a str, open: &str, close: &str) -> Vec<&'a str> {
+    let mut tags = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(open) {
+        let inner = &rest[start + open.len()..];
+        let Some(len) = inner.find(close) else {
+            break;
+        };
+        tags.push(&inner[..len]);
+        rest = &inner[len + close.len()..];
+    }
+    tags
+}
+
+/// Whether `template` uses more of Tera than plain `{{ name }}`
+/// placeholders: a `{% %}` tag, a `{# #}` comment, or an expression such
+/// as `{{ name | upper }}` or `{{ results["data-analyst"].output }}`.
+pub fn is_tera(template: &str) -> bool {
+    !tags(template, "{%", "%}").is_empty()
+        || !tags(template, "{#", "#}").is_empty()
+        || tags(template, "{{", "}}").iter().any(|inner| {
+            let inner = inner.trim();
+            inner.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && !is_var_name(inner)
+        })
+}
+
+/// Whether `text` reads variable `name`: in a `{{ name }}` placeholder, or
+/// anywhere in the tags of a Tera template.
+pub fn reads(text: &str, name: &str) -> bool {
+    if is_tera(text) {
+        let mut code = tags(text, "{{", "}}");
+        code.extend(tags(text, "{%", "%}"));
+        return code.iter().any(|tag| {
+            tag.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .any(|word| word == name)
+        });
+    }
+    tags(text, "{{", "}}").iter().any(|tag| tag.trim() == name)
+}
+
+/// The agents whose results `text` reads, in order of first use. In a Tera
+/// template that is every `results.NAME` or `results["NAME"]` in its tags.
+pub fn result_refs(text: &str) -> Vec<String> {
+    let mut agents: Vec<String> = Vec::new();
+    let mut add = |agent: &str| {
+        if !agent.is_empty() && !agents.iter().any(|known| known == agent) {
+            agents.push(agent.to_string());
+        }
+    };
+    if is_tera(text) {
+        let mut code = tags(text, "{{", "}}");
+        code.extend(tags(text, "{%", "%}"));
+        // In the order they appear
+        code.sort_by_key(|tag| tag.as_ptr() as usize);
+        for tag in code {
+            for (_, after) in tag
+                .match_indices("results")
+                .map(|(i, m)| tag.split_at(i + m.len()))
+            {
+                let agent = match after.strip_prefix('.') {
+                    Some(field) => field
+                        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .next(),
+                    None => after
+                        .strip_prefix("[\"")
+                        .or_else(|| after.strip_prefix("['"))
+                        .and_then(|quoted| quoted.split(['"', '\'']).next()),
+                };
+                add(agent.unwrap_or_default());
+            }
+        }
+        return agents;
+    }
+    for name in tags(text, "{{", "}}") {
+        let name = name.trim();
+        let agent = name
+            .strip_prefix(RESULTS_PREFIX)
+            .filter(|_| is_var_name(name))
+            .and_then(|field| field.rsplit_once('.'))
+            .map(|(agent, _)| agent);
+        add(agent.unwrap_or_default());
+    }
+    agents
+}
+
+/// `results.*` variables for one finished agent. A failed agent's output
+/// says why it has none, so the agent reading it isn't left guessing.
+pub fn result_vars(result: &AgentResult) -> [(String, String); 2] {
+    let output = match (&result.output, &result.error) {
+        (Some(output), _) if result.status == "success" => output.clone(),
+        (_, Some(error)) => format!("[{} {}: {}]", result.agent, result.status, error),
+        _ => format!("[{} {} without output]", result.agent, result.status),
+    };
+    let key = |field: &str| format!("{}{}.{}", RESULTS_PREFIX, result.agent, field);
+    [
+        (key("output"), output),
+        (key("status"), result.status.clone()),
+    ]
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Best-effort hostname: kernel, /etc/hostname, then $HOSTNAME.
+pub fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|p| fs::read_to_string(p).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> TemplateVars {
+        let mut vars = TemplateVars::new();
+        vars.insert("now", "2025-01-01T00:00:00Z");
+        vars.insert("service", "api");
+        vars
+    }
+
+    #[test]
+    fn test_render_substitutes_known_vars() {
+        let (out, unknown) = vars().render("At {{ now }} check {{service}}.");
+        assert_eq!(out, "At 2025-01-01T00:00:00Z check api.");
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn test_render_env_vars() {
+        env::set_var("ORCHESTRA_TEMPLATE_TEST", "staging");
+        let (out, _) = vars().render("env={{ env.ORCHESTRA_TEMPLATE_TEST }}");
+        assert_eq!(out, "env=staging");
+    }
+
+    #[test]
+    fn test_render_results_leaves_other_vars() {
+        env::set_var("ORCHESTRA_TEMPLATE_RESULTS_TEST", "secret");
+        let mut vars = vars();
+        vars.insert("results.monitor.output", "All green");
+        let (out, unknown) = vars.render_results(
+            "{{ results.monitor.output }} {{ env.ORCHESTRA_TEMPLATE_RESULTS_TEST }} {{ now }}",
+        );
+        assert_eq!(
+            out,
+            "All green {{ env.ORCHESTRA_TEMPLATE_RESULTS_TEST }} {{ now }}"
+        );
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn test_render_leaves_unknown_and_non_vars() {
+        let (out, unknown) = vars().render(r#"{{ missing }} and {{"a": 1}} and {{ oops"#);
+        assert_eq!(out, r#"{{ missing }} and {{"a": 1}} and {{ oops"#);
+        assert_eq!(unknown, vec!["missing"]);
+    }
+
+    #[test]
+    fn test_tera_templates() {
+        assert!(!is_tera("{{ now }} and {{\"a\": 1}}"));
+        assert!(is_tera("{{ service | upper }}"));
+        assert!(is_tera("{% if service %}x{% endif %}"));
+        assert!(reads("--q={{ prompt }}", "prompt"));
+        assert!(reads("{{ prompt | trim }}", "prompt"));
+        assert!(!reads("{{ system_prompt | trim }}", "prompt"));
+
+        let mut vars = vars();
+        vars.insert("results.monitor.status", "failed");
+        vars.insert("results.data-analyst.output", "Disk is slow");
+        let (out, unknown) = vars.render(
+            "{{ service | upper }}{% for s in [\"a\", \"b\"] %} {{ s }}{% endfor %} {{ now }}",
+        );
+        assert_eq!(out, "API a b 2025-01-01T00:00:00Z");
+        assert!(unknown.is_empty());
+
+        let (out, _) = vars.render(
+            r#"{{ env.ORCHESTRA_TEMPLATE_UNSET | default(value="staging") | upper }}{% if ticket is defined %}!{% endif %}"#,
+        );
+        assert_eq!(out, "STAGING");
+
+        // Left for the results pass, which renders it whole
+        let template = r#"{% if results.monitor.status == "failed" %}{{ results["data-analyst"].output }}{% endif %} on {{ service }}"#;
+        assert_eq!(result_refs(template), ["monitor", "data-analyst"]);
+        assert_eq!(vars.render(template).0, template);
+        assert_eq!(vars.render_results(template).0, "Disk is slow on api");
+
+        // Tera can't render it, so only plain placeholders are filled in
+        let (out, unknown) = vars.render("{{ missing | upper }} at {{ now }}");
+        assert_eq!(out, "{{ missing | upper }} at 2025-01-01T00:00:00Z");
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn test_result_refs_and_vars() {
+        let refs = result_refs(
+            "{{ results.monitor.output }} ({{results.monitor.status}}), {{ results.data-analyst.output }}, {{ run_id }}",
+        );
+        assert_eq!(refs, ["monitor", "data-analyst"]);
+
+        let mut vars = vars();
+        vars.extend(result_vars(&AgentResult::success(
+            "monitor".to_string(),
+            "All green".to_string(),
+            "mock".to_string(),
+        )));
+        vars.extend(result_vars(&AgentResult::failed(
+            "data-analyst".to_string(),
+            "Timed out after 60s".to_string(),
+            "mock".to_string(),
+        )));
+        let (out, unknown) = vars.render(
+            "{{ results.monitor.output }} / {{ results.data-analyst.output }} / {{ results.reporter.output }}",
+        );
+        assert_eq!(
+            out,
+            "All green / [data-analyst failed: Timed out after 60s] / {{ results.reporter.output }}"
+        );
+        assert_eq!(unknown, ["results.reporter.output"]);
+    }
+}
@@ -0,0 +1,619 @@
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{
+    Block, Borders, List, ListItem, ListState, Paragraph, Row, Table, TableState,
+};
+use ratatui::{DefaultTerminal, Frame};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use agent_orchestra::agent_log::AgentLogLayer;
+use agent_orchestra::agents::AgentResult;
+use agent_orchestra::config::Config;
+use agent_orchestra::encryption::Cipher;
+use agent_orchestra::progress::Progress;
+use agent_orchestra::shutdown::{AgentCancel, Shutdown};
+use agent_orchestra::ulid;
+use agent_orchestra::OrchestrationResult;
+
+use crate::cli::Args;
+use crate::{load_config, orchestrator_builder, output_cipher};
+
+/// Results files listed in the history pane, newest first.
+const HISTORY_LIMIT: usize = 50;
+/// Lines of an agent's log shown while it has no result yet.
+const LOG_TAIL: usize = 200;
+
+/// Where one agent of the run on screen has got to.
+#[derive(Debug, Clone)]
+enum AgentState {
+    Pending,
+    Running(Instant),
+    Done(Box<AgentResult>),
+}
+
+/// Agents of the run on screen, in run order. The orchestrator updates it
+/// through [`Progress`]; the draw loop reads it.
+#[derive(Default)]
+struct Board(Mutex<Vec<(String, AgentState)>>);
+
+impl Board {
+    /// Show the finished results of `run`, e.g. one picked from history.
+    fn load(&self, run: &OrchestrationResult) {
+        *self.lock() = run
+            .results
+            .iter()
+            .map(|r| (r.agent.clone(), AgentState::Done(Box::new(r.clone()))))
+            .collect();
+    }
+
+    fn set(&self, agent: &str, state: AgentState) {
+        let mut agents = self.lock();
+        match agents.iter_mut().find(|(name, _)| name == agent) {
+            Some((_, current)) => *current = state,
+            None => agents.push((agent.to_string(), state)),
+        }
+    }
+
+    fn snapshot(&self) -> Vec<(String, AgentState)> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(String, AgentState)>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Progress for Board {
+    fn queued(&self, agents: &[String]) {
+        for agent in agents {
+            self.set(agent, AgentState::Pending);
+        }
+    }
+
+    fn started(&self, agent: &str) {
+        self.set(agent, AgentState::Running(Instant::now()));
+    }
+
+    fn finished(&self, result: &AgentResult) {
+        self.set(&result.agent, AgentState::Done(Box::new(result.clone())));
+    }
+}
+
+/// A run executing in the background.
+struct Running {
+    task: JoinHandle<Result<OrchestrationResult>>,
+    cancel: AgentCancel,
+    stop: watch::Sender<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Focus {
+    Agents,
+    History,
+}
+
+struct App {
+    args: Args,
+    board: Arc<Board>,
+    /// Run whose agents are on screen; names its agent log directory.
+    run_id: String,
+    /// The finished run on screen, which failed agents are re-run from.
+    shown: Option<OrchestrationResult>,
+    running: Option<Running>,
+    history: Vec<OrchestrationResult>,
+    output_dir: PathBuf,
+    /// Opens results files written with `outputs.encryption`.
+    cipher: Option<Cipher>,
+    log_dir: Option<PathBuf>,
+    focus: Focus,
+    agents: TableState,
+    runs: ListState,
+    status: String,
+    quitting: bool,
+}
+
+/// Run the orchestration under a full-screen dashboard: the agent table,
+/// the selected agent's output (its log while it runs) and past runs from
+/// the output directory. Returns once the run is done and the user quits.
+pub async fn run(args: Args) -> Result<()> {
+    let config = load_config().await?;
+    let mut app = App {
+        args,
+        board: Arc::new(Board::default()),
+        run_id: String::new(),
+        shown: None,
+        running: None,
+        history: Vec::new(),
+        output_dir: PathBuf::from(&config.outputs.directory),
+        cipher: output_cipher(&config)?,
+        log_dir: config.logging.agent_logs.as_ref().map(PathBuf::from),
+        focus: Focus::Agents,
+        agents: TableState::default().with_selected(0),
+        runs: ListState::default().with_selected(Some(0)),
+        status: String::new(),
+        quitting: false,
+    };
+    app.history = load_history(&app.output_dir, app.cipher.as_ref());
+    app.start(config, None)?;
+
+    let mut terminal = ratatui::init();
+    let outcome = app.event_loop(&mut terminal).await;
+    ratatui::restore();
+    outcome
+}
+
+impl App {
+    /// Start a run of the configured mode, or re-run one agent of `previous`.
+    fn start(
+        &mut self,
+        config: Config,
+        rerun: Option<(OrchestrationResult, String)>,
+    ) -> Result<()> {
+        let args = match &rerun {
+            // A rerun has to resolve the mode the original run used
+            Some((previous, _)) => Args {
+                mode: Some(previous.mode.clone()),
+                resume: None,
+                ..self.args.clone()
+            },
+            None => self.args.clone(),
+        };
+        let (stop, shutdown) = Shutdown::channel();
+        let cancel = AgentCancel::new();
+        let orchestrator = orchestrator_builder(&args, config)?
+            .shutdown(shutdown)
+            .agent_cancel(cancel.clone())
+            .progress(Arc::clone(&self.board) as Arc<dyn Progress>)
+            .build()?;
+        self.run_id = orchestrator.run_id().to_string();
+        self.shown = None;
+        let task = match rerun {
+            Some((previous, agent)) => {
+                self.board.load(&previous);
+                self.status = format!("Rerunning {} from {}", agent, previous.run_id);
+                tokio::spawn(async move { orchestrator.rerun_agents(&previous, &[&agent]).await })
+            }
+            None => {
+                self.status = format!("Running {}", self.run_id);
+                tokio::spawn(async move { orchestrator.run().await })
+            }
+        };
+        self.running = Some(Running { task, cancel, stop });
+        Ok(())
+    }
+
+    async fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            if self.running.as_ref().is_some_and(|r| r.task.is_finished()) {
+                self.collect().await;
+                if self.quitting {
+                    return Ok(());
+                }
+            }
+            if Shutdown::on_signals().is_triggered() && !self.quitting {
+                if self.running.is_none() {
+                    return Ok(());
+                }
+                self.quit();
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let ready = tokio::task::block_in_place(|| event::poll(Duration::from_millis(100)))?;
+            if !ready {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                _ if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) => {
+                    // Asked twice, or nothing left to wait for
+                    if self.quitting || self.running.is_none() {
+                        return Ok(());
+                    }
+                    self.quit();
+                }
+                KeyCode::Up | KeyCode::Char('k') => self.step(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.step(1),
+                KeyCode::Tab => {
+                    self.focus = match self.focus {
+                        Focus::Agents => Focus::History,
+                        Focus::History => Focus::Agents,
+                    }
+                }
+                KeyCode::Enter if self.focus == Focus::History => self.open_history(),
+                KeyCode::Char('c') => self.cancel_selected(),
+                KeyCode::Char('r') => self.rerun_selected().await,
+                _ => {}
+            }
+        }
+    }
+
+    /// Pick up the result of the run that just finished.
+    async fn collect(&mut self) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        match running.task.await {
+            Ok(Ok(result)) => {
+                self.status = format!(
+                    "{} done: {}/{} succeeded",
+                    result.run_id,
+                    successful(&result),
+                    result.results.len()
+                );
+                self.board.load(&result);
+                self.shown = Some(result);
+            }
+            Ok(Err(e)) => self.status = format!("Run failed: {:#}", e),
+            Err(e) => self.status = format!("Run failed: {}", e),
+        }
+        self.history = load_history(&self.output_dir, self.cipher.as_ref());
+    }
+
+    /// Cancel whatever is still running and leave once results are written.
+    fn quit(&mut self) {
+        if let Some(running) = &self.running {
+            running.stop.send_replace(true);
+            self.status = "Stopping: cancelling running agents (q again to leave now)".into();
+        }
+        self.quitting = true;
+    }
+
+    fn step(&mut self, by: isize) {
+        let (state, len) = match self.focus {
+            Focus::Agents => (self.agents.selected_mut(), self.board.snapshot().len()),
+            Focus::History => (self.runs.selected_mut(), self.history.len()),
+        };
+        let at = state.unwrap_or(0) as isize + by;
+        *state = Some(at.clamp(0, len.saturating_sub(1) as isize) as usize);
+    }
+
+    fn selected_agent(&self) -> Option<(String, AgentState)> {
+        self.board
+            .snapshot()
+            .into_iter()
+            .nth(self.agents.selected()?)
+    }
+
+    fn cancel_selected(&mut self) {
+        let (Some(running), Some((agent, state))) = (&self.running, self.selected_agent()) else {
+            return;
+        };
+        if matches!(state, AgentState::Done(_)) {
+            self.status = format!("{} already finished", agent);
+            return;
+        }
+        running.cancel.cancel(&agent);
+        self.status = format!("Cancelling {}", agent);
+    }
+
+    async fn rerun_selected(&mut self) {
+        if self.running.is_some() {
+            self.status = "Wait for the current run to finish before re-running".into();
+            return;
+        }
+        let (Some(previous), Some((agent, AgentState::Done(result)))) =
+            (self.shown.clone(), self.selected_agent())
+        else {
+            return;
+        };
+        if result.status == "success" {
+            self.status = format!("{} succeeded; only failed agents are re-run", agent);
+            return;
+        }
+        if let Err(e) = load_config()
+            .await
+            .and_then(|config| self.start(config, Some((previous, agent))))
+        {
+            self.status = format!("Can't rerun: {:#}", e);
+        }
+    }
+
+    /// Show the run selected in the history pane.
+    fn open_history(&mut self) {
+        if self.running.is_some() {
+            self.status = "Wait for the current run to finish before opening another".into();
+            return;
+        }
+        let Some(run) = self
+            .runs
+            .selected()
+            .and_then(|i| self.history.get(i))
+            .cloned()
+        else {
+            return;
+        };
+        self.board.load(&run);
+        self.run_id = run.run_id.clone();
+        self.status = format!("Showing {}", run.run_id);
+        self.shown = Some(run);
+        self.agents.select(Some(0));
+        self.focus = Focus::Agents;
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [top, detail, footer] = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [table, history] =
+            Layout::horizontal([Constraint::Min(40), Constraint::Length(44)]).areas(top);
+
+        let agents = self.board.snapshot();
+        self.draw_agents(frame, table, &agents);
+        self.draw_history(frame, history);
+
+        let selected = self.agents.selected().and_then(|i| agents.get(i));
+        let (title, text) = match selected {
+            Some((agent, state)) => (agent.as_str(), self.detail(agent, state)),
+            None => ("", String::new()),
+        };
+        // Keep the end of the text in view, like `tail -f`
+        let height = detail.height.saturating_sub(2) as usize;
+        let lines: Vec<&str> = text.lines().collect();
+        let shown = lines[lines.len().saturating_sub(height)..].join("\n");
+        frame.render_widget(
+            Paragraph::new(shown).block(Block::default().borders(Borders::ALL).title(title)),
+            detail,
+        );
+
+        let keys = "↑↓ select  Tab pane  Enter open run  c cancel  r rerun failed  q quit";
+        frame.render_widget(
+            Paragraph::new(Line::from(format!("{}  {}", keys, self.status)))
+                .style(Style::default().fg(Color::DarkGray)),
+            footer,
+        );
+    }
+
+    fn draw_agents(&mut self, frame: &mut Frame, area: Rect, agents: &[(String, AgentState)]) {
+        let rows = agents.iter().map(|(agent, state)| {
+            let (status, time, attempts, model) = match state {
+                AgentState::Pending => (
+                    "pending".to_string(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ),
+                AgentState::Running(since) => (
+                    "running".to_string(),
+                    seconds(since.elapsed().as_millis() as u64),
+                    String::new(),
+                    String::new(),
+                ),
+                AgentState::Done(r) => (
+                    r.status.clone(),
+                    r.duration_ms.map(seconds).unwrap_or_default(),
+                    r.attempts.map(|n| n.to_string()).unwrap_or_default(),
+                    r.model.clone().unwrap_or_default(),
+                ),
+            };
+            let color = match status.as_str() {
+                "success" => Color::Green,
+                "running" => Color::Yellow,
+                "pending" | "cancelled" => Color::Gray,
+                _ => Color::Red,
+            };
+            Row::new([agent.clone(), status, time, attempts, model])
+                .style(Style::default().fg(color))
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(16),
+                Constraint::Length(10),
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Min(12),
+            ],
+        )
+        .header(
+            Row::new(["agent", "status", "time", "attempts", "model"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(pane(
+            format!("Agents — {}", self.run_id),
+            self.focus == Focus::Agents,
+        ))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.agents);
+    }
+
+    fn draw_history(&mut self, frame: &mut Frame, area: Rect) {
+        let items = self.history.iter().map(|run| {
+            ListItem::new(format!(
+                "{:<28} {}/{}",
+                run.run_id,
+                successful(run),
+                run.results.len()
+            ))
+        });
+        let list = List::new(items)
+            .block(pane("History".to_string(), self.focus == Focus::History))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.runs);
+    }
+
+    /// A finished agent's output, or its error and log; the log so far for
+    /// one that hasn't finished.
+    fn detail(&self, agent: &str, state: &AgentState) -> String {
+        let log = || {
+            self.log_dir
+                .as_deref()
+                .and_then(|dir| log_tail(&AgentLogLayer::path(dir, &self.run_id, agent)))
+                .unwrap_or_default()
+        };
+        match state {
+            AgentState::Done(r) if r.status == "success" => r.output.clone().unwrap_or_default(),
+            AgentState::Done(r) => {
+                format!("{}\n\n{}", r.error.as_deref().unwrap_or(&r.status), log())
+            }
+            AgentState::Pending => "Waiting to start".to_string(),
+            AgentState::Running(_) => log(),
+        }
+    }
+}
+
+fn pane(title: String, focused: bool) -> Block<'static> {
+    let style = if focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+fn successful(run: &OrchestrationResult) -> usize {
+    run.results.iter().filter(|r| r.status == "success").count()
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+/// The last lines of an agent log, if it exists yet.
+fn log_tail(path: &Path) -> Option<String> {
+    let log = fs::read_to_string(path).ok()?;
+    let lines: Vec<&str> = log.lines().collect();
+    Some(lines[lines.len().saturating_sub(LOG_TAIL)..].join("\n"))
+}
+
+/// `results-*.json` files in `dir`, newest first, encrypted ones too with
+/// `cipher`. Files that don't load are skipped.
+fn load_history(dir: &Path, cipher: Option<&Cipher>) -> Vec<OrchestrationResult> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.file_name()
+                        .and_then(|n| n.to_str())
+                        .map(|n| n.strip_suffix(".enc").unwrap_or(n))
+                        .is_some_and(|n| n.starts_with("results-") && n.ends_with(".json"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // Names end in the run's ID or timestamp, so this is newest first
+    files.sort_by_cached_key(|path| {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let stem = name
+            .strip_suffix(".enc")
+            .unwrap_or(name)
+            .trim_end_matches(".json");
+        std::cmp::Reverse(ulid::file_time(stem))
+    });
+    files
+        .iter()
+        .take(HISTORY_LIMIT)
+        .filter_map(|path| OrchestrationResult::load_with(path, cipher).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn app(board: Arc<Board>, history: Vec<OrchestrationResult>) -> App {
+        App {
+            args: Args::default(),
+            board,
+            run_id: "01KF5RZ4H2YB7PD0QG3SA6VEMT".to_string(),
+            shown: None,
+            running: None,
+            history,
+            output_dir: PathBuf::new(),
+            cipher: None,
+            log_dir: None,
+            focus: Focus::Agents,
+            agents: TableState::default().with_selected(0),
+            runs: ListState::default().with_selected(Some(0)),
+            status: String::new(),
+            quitting: false,
+        }
+    }
+
+    #[test]
+    fn test_board_follows_progress() {
+        let board = Board::default();
+        board.queued(&["monitor".to_string(), "analyzer".to_string()]);
+        board.started("monitor");
+        board.finished(&AgentResult::failed(
+            "analyzer".into(),
+            "boom".into(),
+            "api".into(),
+        ));
+        let agents = board.snapshot();
+        assert!(matches!(agents[0], (ref name, AgentState::Running(_)) if name == "monitor"));
+        assert!(matches!(&agents[1].1, AgentState::Done(r) if r.status == "failed"));
+
+        // A rerun queues only its agent; the rest keep their results
+        board.queued(&["analyzer".to_string()]);
+        assert_eq!(board.snapshot().len(), 2);
+        assert!(matches!(board.snapshot()[1].1, AgentState::Pending));
+    }
+
+    #[test]
+    fn test_draw_shows_agents_detail_and_history() {
+        let board = Arc::new(Board::default());
+        board.queued(&["monitor".to_string(), "analyzer".to_string()]);
+        board.finished(&AgentResult::success(
+            "monitor".into(),
+            "all clear".into(),
+            "api".into(),
+        ));
+        let past = OrchestrationResult {
+            run_id: "01KF3M2Q8V6W9XJ5T0R7C4N1BD".to_string(),
+            timestamp: chrono::Utc::now(),
+            mode: "auto".to_string(),
+            global_client_mode: "api".to_string(),
+            provider_outages: Vec::new(),
+            log_sampling: Vec::new(),
+            actions: Vec::new(),
+            rerun_of: None,
+            duration_ms: None,
+            consensus: None,
+            review: None,
+            results: Vec::new(),
+        };
+        let mut app = app(board, vec![past]);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("monitor"));
+        assert!(screen.contains("success"));
+        assert!(screen.contains("pending"));
+        assert!(screen.contains("all clear"));
+        assert!(screen.contains("01KF3M2Q8V6W9XJ5T0R7C4N1BD"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Run IDs: [ULIDs](https://github.com/ulid/spec), 26 characters that sort
+//! by the millisecond the run started and are random after that, so two runs
+//! never share an ID even when started together on different hosts.
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Crockford's base 32, as ULIDs use.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Length of a ULID.
+pub const LEN: usize = 26;
+
+static FALLBACK_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A new ULID for a run started `at`.
+pub fn new(at: DateTime<Utc>) -> String {
+    let millis = at.timestamp_millis().max(0) as u128 & ((1 << 48) - 1);
+    let mut random = [0u8; 16];
+    if SystemRandom::new().fill(&mut random[6..]).is_err() {
+        // Unique within the process, which is all a run needs
+        let seq = FALLBACK_SEQ.fetch_add(1, Ordering::Relaxed);
+        random[6..10].copy_from_slice(&std::process::id().to_be_bytes());
+        random[10..].copy_from_slice(&seq.to_be_bytes()[2..]);
+    }
+    let value = millis << 80 | u128::from_be_bytes(random);
+    (0..LEN)
+        .map(|i| ALPHABET[(value >> (125 - 5 * i) & 31) as usize] as char)
+        .collect()
+}
+
+/// When the run with ULID `id` started, if `id` is one.
+pub fn timestamp(id: &str) -> Option<DateTime<Utc>> {
+    if id.len() != LEN || id.as_bytes()[0] > b'7' {
+        return None;
+    }
+    let mut value: i64 = 0;
+    for (i, c) in id.bytes().enumerate() {
+        let digit = ALPHABET.iter().position(|&a| a == c.to_ascii_uppercase())? as i64;
+        if i < 10 {
+            value = value << 5 | digit;
+        }
+    }
+    Utc.timestamp_millis_opt(value).single()
+}
+
+/// When the run an output file belongs to started, from its name without
+/// the extension: `results-<ulid>`, or `results-<YYYYmmdd-HHMMSS>` as
+/// written before runs had ULIDs.
+pub fn file_time(stem: &str) -> Option<DateTime<Utc>> {
+    let tail = |n: usize| stem.get(stem.len().checked_sub(n)?..);
+    tail(LEN).and_then(timestamp).or_else(|| {
+        let naive = NaiveDateTime::parse_from_str(tail(15)?, "%Y%m%d-%H%M%S").ok()?;
+        Some(Utc.from_utc_datetime(&naive))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ulids_sort_by_time_and_decode() {
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 9, 0, 0).unwrap();
+        let (a, b) = (new(at), new(at));
+        assert_eq!(a.len(), LEN);
+        assert_ne!(a, b);
+        assert_eq!(timestamp(&a), Some(at));
+        let later = new(at + chrono::Duration::milliseconds(1));
+        assert!(later > a && later > b);
+        assert_eq!(timestamp("monitoring-20260101-090000"), None);
+    }
+
+    #[test]
+    fn test_file_time_reads_ulid_and_legacy_names() {
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 9, 0, 0).unwrap();
+        assert_eq!(file_time(&format!("results-{}", new(at))), Some(at));
+        assert_eq!(file_time("team-results-20260101-090000"), Some(at));
+        assert_eq!(file_time("history"), None);
+    }
+}
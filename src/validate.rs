@@ -0,0 +1,1254 @@
+use serde_yml::Value;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::client::ClientMode;
+use crate::config::{
+    self, AgentConfig, BudgetStrategy, Config, Expectation, ModelSpec, SessionMode,
+};
+use crate::remote;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem found in a config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// 1-based line in the config file, when it could be located.
+    pub line: Option<usize>,
+    /// Dotted key path, e.g. `agents.monitor.client_mode`.
+    pub path: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// `file:line: severity: path: message`, the way compilers report.
+    pub fn render(&self, file: &str) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}: {}", file, line, self),
+            None => format!("{}: {}", file, self),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.path.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.path, self.message)
+        }
+    }
+}
+
+/// Which keys a config section accepts.
+enum Shape {
+    Keys(&'static [&'static str]),
+    /// A map with user-chosen keys (agents, team definitions).
+    Map,
+    /// Not checked: free-form maps and sections read by the dashboard.
+    Open,
+}
+
+/// Modes accepted by `claude --permission-mode`.
+const PERMISSION_MODES: &[&str] = &["default", "acceptEdits", "plan", "bypassPermissions"];
+
+const AGENT_KEYS: &[&str] = &[
+    "enabled",
+    "timeout_seconds",
+    "client_mode",
+    "system_prompt",
+    "system_prompt_file",
+    "prompt",
+    "prompt_file",
+    "model",
+    "max_tokens",
+    "temperature",
+    "stop_sequences",
+    "logs",
+    "context",
+    "egress",
+    "working_dir",
+    "env",
+    "allowed_tools",
+    "disallowed_tools",
+    "permission_mode",
+    "mcp_servers",
+    "session",
+    "mock_response",
+    "exec",
+    "ssh",
+    "docker",
+    "requires_approval",
+    "priority",
+    "hooks",
+    "output_schema",
+    "budget",
+    "inherits",
+    "aliases",
+    "tests",
+];
+
+/// Keys accepted at a schema path. List items appear as `[]`, map entries as `*`.
+fn shape(pattern: &str) -> Shape {
+    match pattern {
+        "" => Shape::Keys(&[
+            "orchestra",
+            "client",
+            "agents",
+            "outputs",
+            "digitalocean",
+            "notifications",
+            "logging",
+            "features",
+            "teams",
+            "actions",
+            "approvals",
+            "history",
+            "events",
+            "hooks",
+            "distributed",
+            "health",
+            "secrets",
+            "consensus",
+            "review",
+            "map",
+            "bench",
+            "gm_projects",
+        ]),
+        "orchestra" => Shape::Keys(&[
+            "name",
+            "version",
+            "default_mode",
+            "schedule",
+            "max_failed_agents",
+            "lock",
+            "watch",
+        ]),
+        "orchestra.schedule" => {
+            Shape::Keys(&["interval_hours", "max_retries", "retry_delay_seconds"])
+        }
+        "orchestra.lock" => Shape::Keys(&["enabled", "wait_seconds"]),
+        "orchestra.watch" => Shape::Keys(&["debounce_seconds", "min_interval_seconds"]),
+        "client" => Shape::Keys(&[
+            "default_mode",
+            "default_model",
+            "status_check",
+            "circuit_breaker",
+            "api_keys",
+            "concurrency",
+        ]),
+        "client.concurrency" => Shape::Open,
+        "client.status_check" => Shape::Keys(&["enabled", "timeout_seconds", "providers"]),
+        "client.api_keys" => Shape::Keys(&["env", "strategy"]),
+        "client.circuit_breaker" => {
+            Shape::Keys(&["enabled", "failure_threshold", "cooldown_seconds"])
+        }
+        "agents" | "teams.definitions" | "consensus" | "review" | "map" | "secrets" => Shape::Map,
+        "secrets.*" => Shape::Keys(&["file", "command", "vault", "aws_secrets_manager"]),
+        "secrets.*.vault" => Shape::Keys(&["path", "field", "address"]),
+        "secrets.*.aws_secrets_manager" => Shape::Keys(&["secret_id", "field", "region"]),
+        "agents.*" => Shape::Keys(AGENT_KEYS),
+        "agents.*.logs[]" => Shape::Keys(&["path", "sampling"]),
+        "agents.*.logs[].sampling" => Shape::Keys(&["strategy", "lines"]),
+        "agents.*.context" => Shape::Keys(&[
+            "files",
+            "commands",
+            "urls",
+            "max_bytes_per_source",
+            "max_total_bytes",
+            "timeout_seconds",
+        ]),
+        "agents.*.egress" => Shape::Keys(&["allowed_domains", "allowed_cidrs", "proxy"]),
+        "agents.*.env" => Shape::Open,
+        "agents.*.exec" => Shape::Keys(&["command"]),
+        "agents.*.ssh" => Shape::Keys(&["host", "user", "port", "key", "options", "claude_path"]),
+        "agents.*.docker" => Shape::Keys(&[
+            "image",
+            "memory",
+            "cpus",
+            "network",
+            "read_only",
+            "args",
+            "claude_path",
+        ]),
+        "agents.*.mcp_servers" => Shape::Map,
+        "agents.*.mcp_servers.*" => Shape::Keys(&["command", "args", "env", "url", "token_env"]),
+        "agents.*.mcp_servers.*.env" => Shape::Open,
+        "agents.*.hooks" => Shape::Keys(&["pre", "post"]),
+        "agents.*.budget" => Shape::Keys(&["max_input_tokens", "strategy", "summarizer"]),
+        "agents.*.tests[]" => Shape::Keys(&["name", "prompt", "expect"]),
+        "agents.*.tests[].expect[]" => Shape::Keys(&["contains", "matches", "not_matches", "json"]),
+        "agents.*.tests[].expect[].json" => Shape::Keys(&["pointer", "equals"]),
+        "outputs" => Shape::Keys(&[
+            "directory",
+            "retention_days",
+            "formats",
+            "store",
+            "remote",
+            "encryption",
+            "git",
+        ]),
+        "outputs.encryption" => Shape::Keys(&["key_env"]),
+        "outputs.git" => Shape::Keys(&["repo", "branch", "path", "message", "push", "remote"]),
+        "outputs.remote" => Shape::Keys(&[
+            "bucket",
+            "endpoint",
+            "region",
+            "prefix",
+            "access_key_env",
+            "secret_key_env",
+            "signed_url_seconds",
+        ]),
+        "digitalocean" => Shape::Keys(&["region", "registry", "app", "workers"]),
+        "digitalocean.workers" => Shape::Keys(&[
+            "image",
+            "size",
+            "droplet_image",
+            "min_agents",
+            "agents_per_worker",
+            "max_workers",
+            "max_hourly_cost_usd",
+            "ssh_keys",
+            "ssh_key",
+            "token_env",
+            "boot_timeout_seconds",
+        ]),
+        "notifications" => Shape::Keys(&["enabled", "webhook", "channels"]),
+        "notifications.webhook" => Shape::Keys(&["url", "secret_env", "unsigned"]),
+        "logging" => Shape::Keys(&["level", "format", "agent_logs"]),
+        "features" => Shape::Keys(&[
+            "parallel_execution",
+            "auto_scaling",
+            "health_monitoring",
+            "fail_fast",
+        ]),
+        "teams" => Shape::Keys(&["enabled", "tasks_dir", "output_prefix", "definitions"]),
+        "teams.definitions.*" => Shape::Keys(&["description", "teammates"]),
+        "teams.definitions.*.teammates[]" => Shape::Keys(&["name", "role", "timeout_seconds"]),
+        "consensus.*" => Shape::Keys(&["prompt", "voters", "aggregator", "strategy"]),
+        "review.*" => Shape::Keys(&["prompt", "author", "critic", "criteria", "max_rounds"]),
+        "bench" => Shape::Keys(&["prompts", "targets", "runs", "timeout_seconds"]),
+        "bench.prompts[]" => Shape::Keys(&["name", "prompt", "system_prompt", "max_tokens"]),
+        "bench.targets[]" => Shape::Keys(&["mode", "model"]),
+        "map.*" => Shape::Keys(&[
+            "inputs",
+            "prompt",
+            "mapper",
+            "reducer",
+            "reduce_prompt",
+            "concurrency",
+            "max_bytes_per_file",
+            "batch",
+        ]),
+        "actions" => Shape::Keys(&["enabled", "queue_file"]),
+        "approvals" => Shape::Keys(&["dir", "poll_seconds"]),
+        "history" => Shape::Keys(&["enabled", "path", "sqlite_path"]),
+        "events" => Shape::Keys(&["enabled", "path"]),
+        "hooks" => Shape::Keys(&[
+            "pre_run",
+            "post_run",
+            "pre_agent",
+            "post_agent",
+            "timeout_seconds",
+        ]),
+        "distributed" => Shape::Keys(&[
+            "enabled",
+            "url",
+            "queue",
+            "claim_timeout_seconds",
+            "worker_concurrency",
+            "secret_env",
+        ]),
+        "health" => Shape::Keys(&["stall_seconds", "heartbeat_seconds"]),
+        _ => Shape::Open,
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Report keys the schema doesn't know, suggesting the nearest known key.
+fn check_keys(value: &Value, pattern: &str, path: &str, diags: &mut Vec<Diagnostic>) {
+    match value {
+        Value::Mapping(map) => {
+            let shape = shape(pattern);
+            for (key, child) in map {
+                let Some(key) = key.as_str() else { continue };
+                let child_path = join(path, key);
+                let child_pattern = match shape {
+                    Shape::Open => return,
+                    Shape::Map => join(pattern, "*"),
+                    Shape::Keys(known) => {
+                        if !known.contains(&key) {
+                            let max_distance = 2.max(key.len() / 4);
+                            let hint = known
+                                .iter()
+                                .filter(|k| edit_distance(k, key) <= max_distance)
+                                .min_by_key(|k| edit_distance(k, key))
+                                .map(|k| format!(" (did you mean '{}'?)", k))
+                                .unwrap_or_default();
+                            diags.push(Diagnostic {
+                                severity: Severity::Warning,
+                                line: None,
+                                path: child_path,
+                                message: format!("unknown key, ignored{}", hint),
+                            });
+                            continue;
+                        }
+                        join(pattern, key)
+                    }
+                };
+                check_keys(child, &child_pattern, &child_path, diags);
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter().enumerate() {
+                check_keys(
+                    item,
+                    &format!("{}[]", pattern),
+                    &format!("{}[{}]", path, i),
+                    diags,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Find the line of a dotted key path in block-style YAML. List indices are
+/// skipped, so the first matching key within the parent block is reported.
+fn locate(source: &str, path: &str) -> Option<usize> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut start = 0;
+    let mut parent_indent: isize = -1;
+    let mut found = None;
+    for segment in path.split('.') {
+        let key = segment.split('[').next().unwrap_or(segment);
+        let mut hit = None;
+        for (i, line) in lines.iter().enumerate().skip(start) {
+            let content = line.trim_start();
+            if content.is_empty() || content.starts_with('#') {
+                continue;
+            }
+            let indent = (line.len() - content.len()) as isize;
+            if indent <= parent_indent {
+                break;
+            }
+            let item = content.trim_start_matches("- ");
+            let item = item.trim_start_matches(['"', '\'']);
+            if item
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start_matches(['"', '\'']).starts_with(':'))
+            {
+                hit = Some((i, indent + (content.len() - item.len()) as isize));
+                break;
+            }
+        }
+        let (i, indent) = hit?;
+        start = i + 1;
+        parent_indent = indent;
+        found = Some(i + 1);
+    }
+    found
+}
+
+/// Semantic checks on the deserialized (but not yet file-resolved) config.
+fn check_config(config: &Config, base: &Path, diags: &mut Vec<Diagnostic>) {
+    let mut error = |path: String, message: String| {
+        diags.push(Diagnostic {
+            severity: Severity::Error,
+            line: None,
+            path,
+            message,
+        })
+    };
+
+    if let Err(e) = ClientMode::from_str(&config.client.default_mode) {
+        error("client.default_mode".to_string(), e.to_string());
+    }
+    if let Some(ModelSpec::Chain(chain)) = &config.client.default_model {
+        if chain.is_empty() {
+            error(
+                "client.default_model".to_string(),
+                "model list is empty".to_string(),
+            );
+        }
+    }
+    for (mode, &limit) in &config.client.concurrency {
+        let path = format!("client.concurrency.{}", mode);
+        if let Err(e) = ClientMode::from_str(mode) {
+            error(path, e.to_string());
+        } else if limit == 0 {
+            error(path, "must be greater than 0".to_string());
+        }
+    }
+    for (name, agent) in config.agents.all() {
+        check_agent(name, agent, base, &mut error);
+    }
+    for (i, target) in config.bench.targets.iter().enumerate() {
+        if let Err(e) = ClientMode::from_str(&target.mode) {
+            error(format!("bench.targets[{}].mode", i), e.to_string());
+        }
+    }
+    if config.bench.runs == 0 {
+        error(
+            "bench.runs".to_string(),
+            "must be greater than 0".to_string(),
+        );
+    }
+    if let Err(e) = config.logging.tracing_level() {
+        error("logging.level".to_string(), e.to_string());
+    }
+    if let Err(e) = config.logging.is_json() {
+        error("logging.format".to_string(), e.to_string());
+    }
+    if let Some(webhook) = &config.notifications.webhook {
+        if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+            error(
+                "notifications.webhook.url".to_string(),
+                format!("'{}' is not an http(s) URL", webhook.url),
+            );
+        }
+    }
+    for (name, team) in &config.teams.definitions {
+        if team.teammates.is_empty() {
+            error(
+                format!("teams.definitions.{}.teammates", name),
+                "a team needs at least one teammate".to_string(),
+            );
+        }
+    }
+    for (name, spec) in &config.consensus {
+        let at = |key: &str| format!("consensus.{}.{}", name, key);
+        if spec.voters.is_empty() {
+            error(
+                at("voters"),
+                "a consensus needs at least one voter".to_string(),
+            );
+        }
+        for (i, voter) in spec.voters.iter().enumerate() {
+            if spec.voters[..i].contains(voter) {
+                error(at("voters"), format!("'{}' is listed twice", voter));
+            }
+        }
+        if spec.voters.contains(&spec.aggregator) {
+            error(
+                at("aggregator"),
+                format!("'{}' is also a voter", spec.aggregator),
+            );
+        }
+    }
+    for (name, spec) in &config.review {
+        let at = |key: &str| format!("review.{}.{}", name, key);
+        if spec.max_rounds == 0 {
+            error(
+                at("max_rounds"),
+                "must be at least 1, or the draft is never reviewed".to_string(),
+            );
+        }
+        if spec.critic == spec.author {
+            error(
+                at("critic"),
+                format!("'{}' is also the author", spec.critic),
+            );
+        }
+    }
+    for (name, spec) in &config.map {
+        if spec.concurrency == 0 {
+            error(
+                format!("map.{}.concurrency", name),
+                "must be at least 1, or no file is ever mapped".to_string(),
+            );
+        }
+    }
+    if config.hooks.timeout_seconds == Some(0) {
+        error(
+            "hooks.timeout_seconds".to_string(),
+            "must be at least 1, or every hook times out".to_string(),
+        );
+    }
+    if config.outputs.encryption.is_some() && config.logging.agent_logs.is_some() {
+        error(
+            "logging.agent_logs".to_string(),
+            "agent logs keep prompts and outputs in plain text; set it to null with outputs.encryption"
+                .to_string(),
+        );
+    }
+    if let Some(ref remote) = config.outputs.remote {
+        if remote.endpoint.is_none()
+            && remote.region.is_none()
+            && config.digitalocean.region.is_empty()
+        {
+            error(
+                "outputs.remote".to_string(),
+                "needs an endpoint, or a region (here or in digitalocean) for Spaces".to_string(),
+            );
+        }
+        if let Some(seconds) = remote.signed_url_seconds {
+            if seconds == 0 || seconds > remote::MAX_SIGNED_URL_SECONDS {
+                error(
+                    "outputs.remote.signed_url_seconds".to_string(),
+                    format!(
+                        "must be between 1 and {} (7 days)",
+                        remote::MAX_SIGNED_URL_SECONDS
+                    ),
+                );
+            }
+        }
+    }
+    if let Some(ref workers) = config.digitalocean.workers {
+        if config.digitalocean.registry.is_empty() {
+            error(
+                "digitalocean.registry".to_string(),
+                format!("workers pull {} from it, but it is not set", workers.image),
+            );
+        }
+        for (key, value) in [
+            ("agents_per_worker", workers.agents_per_worker),
+            ("max_workers", workers.max_workers),
+        ] {
+            if value == 0 {
+                error(
+                    format!("digitalocean.workers.{}", key),
+                    "must be at least 1".to_string(),
+                );
+            }
+        }
+    }
+    if config.distributed.worker_concurrency == 0 {
+        error(
+            "distributed.worker_concurrency".to_string(),
+            "must be at least 1, or a worker never runs an agent".to_string(),
+        );
+    }
+    for (name, source) in &config.secrets {
+        if source.count() != 1 {
+            error(
+                format!("secrets.{}", name),
+                "set one of file, command, vault or aws_secrets_manager".to_string(),
+            );
+        }
+        if source.command.as_ref().is_some_and(|argv| argv.is_empty()) {
+            error(format!("secrets.{}.command", name), "is empty".to_string());
+        }
+    }
+    if config.client.circuit_breaker.failure_threshold == 0 {
+        error(
+            "client.circuit_breaker.failure_threshold".to_string(),
+            "must be at least 1".to_string(),
+        );
+    }
+    for (key, value) in [
+        ("stall_seconds", config.health.stall_seconds),
+        ("heartbeat_seconds", config.health.heartbeat_seconds),
+    ] {
+        if value == 0 {
+            error(format!("health.{}", key), "must be at least 1".to_string());
+        }
+    }
+    if let Err(e) = config.agents.check_aliases() {
+        error("agents".to_string(), e.to_string());
+    }
+
+    if config.notifications.enabled && config.notifications.webhook.is_none() {
+        diags.push(Diagnostic {
+            severity: Severity::Warning,
+            line: None,
+            path: "notifications".to_string(),
+            message: "enabled, but no webhook is configured so nothing will be sent".to_string(),
+        });
+    }
+    if config.features.auto_scaling && config.digitalocean.workers.is_none() {
+        diags.push(Diagnostic {
+            severity: Severity::Warning,
+            line: None,
+            path: "features.auto_scaling".to_string(),
+            message: "on, but digitalocean.workers is not configured so nothing scales".to_string(),
+        });
+    }
+    for (name, agent) in config.agents.all() {
+        let path = format!("agents.{}.budget.summarizer", name);
+        match &agent.budget.summarizer {
+            Some(summarizer) if config.agents.lookup(summarizer).is_none() => {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path,
+                    message: format!(
+                        "no agent named '{}'; it runs with default settings",
+                        summarizer
+                    ),
+                });
+            }
+            None if agent.budget.strategy == BudgetStrategy::Summarize => {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path,
+                    message:
+                        "strategy summarize needs a summarizer; prompts over budget are cut instead"
+                            .to_string(),
+                });
+            }
+            _ => {}
+        }
+    }
+    for (name, spec) in &config.consensus {
+        for agent in spec.voters.iter().chain([&spec.aggregator]) {
+            if config.agents.lookup(agent).is_none() {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path: format!("consensus.{}", name),
+                    message: format!("no agent named '{}'; it runs with default settings", agent),
+                });
+            }
+        }
+    }
+    for (name, spec) in &config.review {
+        for agent in [&spec.author, &spec.critic] {
+            if config.agents.lookup(agent).is_none() {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path: format!("review.{}", name),
+                    message: format!("no agent named '{}'; it runs with default settings", agent),
+                });
+            }
+        }
+    }
+    for (name, spec) in &config.map {
+        for agent in [&spec.mapper, &spec.reducer] {
+            if config.agents.lookup(agent).is_none() {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path: format!("map.{}", name),
+                    message: format!("no agent named '{}'; it runs with default settings", agent),
+                });
+            }
+        }
+        let mapper_mode = config
+            .agents
+            .lookup(&spec.mapper)
+            .and_then(|a| a.client_mode.as_deref())
+            .unwrap_or(&config.client.default_mode);
+        if spec.batch && mapper_mode != "api" {
+            diags.push(Diagnostic {
+                severity: Severity::Warning,
+                line: None,
+                path: format!("map.{}.batch", name),
+                message: format!(
+                    "only api agents can be batched; {} runs with {} as usual",
+                    spec.mapper, mapper_mode
+                ),
+            });
+        }
+    }
+    if let Some(ref git) = config.outputs.git {
+        let repo = base.join(&git.repo);
+        if !repo.join(".git").exists() {
+            diags.push(Diagnostic {
+                severity: Severity::Warning,
+                line: None,
+                path: "outputs.git.repo".to_string(),
+                message: format!("{} is not a git checkout on this machine", repo.display()),
+            });
+        }
+    }
+    for (name, agent) in config.agents.all() {
+        if let Some(ctx) = &agent.context {
+            if ctx.max_bytes_per_source > ctx.max_total_bytes {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path: format!("agents.{}.context.max_bytes_per_source", name),
+                    message: format!(
+                        "larger than max_total_bytes ({}), which caps it",
+                        ctx.max_total_bytes
+                    ),
+                });
+            }
+        }
+        if agent.session == SessionMode::Resume && !config.history.enabled {
+            diags.push(Diagnostic {
+                severity: Severity::Warning,
+                line: None,
+                path: format!("agents.{}.session", name),
+                message: "resume needs history enabled to find the last session".to_string(),
+            });
+        }
+        if let Some(dir) = agent.working_dir.as_ref().filter(|_| agent.ssh.is_none()) {
+            let dir = base.join(dir);
+            if !dir.is_dir() {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path: format!("agents.{}.working_dir", name),
+                    message: format!("{} is not a directory on this machine", dir.display()),
+                });
+            }
+        }
+        for (i, log) in agent.logs.iter().enumerate() {
+            if !Path::new(&log.path).exists() {
+                diags.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: None,
+                    path: format!("agents.{}.logs[{}].path", name, i),
+                    message: format!("{} does not exist on this machine", log.path),
+                });
+            }
+        }
+    }
+}
+
+fn check_agent(
+    name: &str,
+    agent: &AgentConfig,
+    base: &Path,
+    error: &mut impl FnMut(String, String),
+) {
+    let at = |key: &str| format!("agents.{}.{}", name, key);
+
+    if let Some(mode) = &agent.client_mode {
+        match ClientMode::from_str(mode) {
+            Ok(ClientMode::Exec) if agent.exec.as_ref().is_none_or(|e| e.command.is_empty()) => {
+                error(
+                    at("exec.command"),
+                    "client_mode exec needs a command to run".to_string(),
+                );
+            }
+            Ok(ClientMode::Ssh) if agent.ssh.is_none() => {
+                error(
+                    at("ssh.host"),
+                    "client_mode ssh needs a host to run on".to_string(),
+                );
+            }
+            Ok(ClientMode::Docker) if agent.docker.is_none() => {
+                error(
+                    at("docker.image"),
+                    "client_mode docker needs an image to run".to_string(),
+                );
+            }
+            Ok(_) => {}
+            Err(e) => error(at("client_mode"), e.to_string()),
+        }
+    }
+    for (inline, file, field) in [
+        (&agent.prompt, &agent.prompt_file, "prompt"),
+        (
+            &agent.system_prompt,
+            &agent.system_prompt_file,
+            "system_prompt",
+        ),
+    ] {
+        let Some(file) = file else { continue };
+        if inline.is_some() {
+            error(
+                at(field),
+                format!("set either {} or {}_file, not both", field, field),
+            );
+        }
+        let path = base.join(file);
+        if !path.is_file() {
+            error(
+                at(&format!("{}_file", field)),
+                format!("{} not found", path.display()),
+            );
+        }
+    }
+    if agent.timeout_seconds == 0 {
+        error(at("timeout_seconds"), "must be greater than 0".to_string());
+    }
+    if agent.max_tokens == Some(0) {
+        error(at("max_tokens"), "must be greater than 0".to_string());
+    }
+    if let Some(t) = agent.temperature {
+        if !(0.0..=1.0).contains(&t) {
+            error(at("temperature"), format!("{} is outside 0.0–1.0", t));
+        }
+    }
+    if let Some(ModelSpec::Chain(chain)) = &agent.model {
+        if chain.is_empty() {
+            error(at("model"), "model list is empty".to_string());
+        }
+    }
+    for (server, spec) in &agent.mcp_servers {
+        if spec.command.is_some() == spec.url.is_some() {
+            error(
+                at(&format!("mcp_servers.{}", server)),
+                "set either command or url".to_string(),
+            );
+        }
+    }
+    for key in agent.env.keys() {
+        if key.is_empty() || key.contains('=') || key.contains('\0') {
+            error(
+                at("env"),
+                format!("'{}' is not a valid environment variable name", key),
+            );
+        }
+    }
+    if let Some(mode) = &agent.permission_mode {
+        if !PERMISSION_MODES.contains(&mode.as_str()) {
+            error(
+                at("permission_mode"),
+                format!("'{}' is not one of {}", mode, PERMISSION_MODES.join(", ")),
+            );
+        }
+    }
+    if let Some(schema) = &agent.output_schema {
+        if let Err(e) = jsonschema::validator_for(schema) {
+            error(at("output_schema"), format!("invalid JSON Schema: {}", e));
+        }
+    }
+    if agent.budget.max_input_tokens == 0 {
+        error(
+            at("budget.max_input_tokens"),
+            "must be greater than 0".to_string(),
+        );
+    }
+    for (i, log) in agent.logs.iter().enumerate() {
+        if log.sampling.lines == 0 {
+            error(
+                format!("agents.{}.logs[{}].sampling.lines", name, i),
+                "must be greater than 0".to_string(),
+            );
+        }
+    }
+    for (i, test) in agent.tests.iter().enumerate() {
+        for (j, expect) in test.expect.iter().enumerate() {
+            if let Expectation::Matches(p) | Expectation::NotMatches(p) = expect {
+                if let Err(e) = regex_automata::meta::Regex::new(p) {
+                    error(
+                        format!("agents.{}.tests[{}].expect[{}]", name, i, j),
+                        format!("invalid regex /{}/: {}", p, e),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Check a config file, returning every problem found, errors first.
+pub fn validate_file(path: &Path) -> Vec<Diagnostic> {
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                line: None,
+                path: String::new(),
+                message: format!("cannot read {}: {}", path.display(), e),
+            }]
+        }
+    };
+    validate_str(&source, path.parent().unwrap_or(Path::new(".")))
+}
+
+/// Check config `source`, resolving prompt files relative to `base`.
+pub fn validate_str(source: &str, base: &Path) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    let mut raw: Value = match serde_yml::from_str(source) {
+        Ok(v) => v,
+        Err(e) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                line: e.location().map(|l| l.line()),
+                path: String::new(),
+                message: format!("invalid YAML: {}", e),
+            }]
+        }
+    };
+
+    check_keys(&raw, "", "", &mut diags);
+
+    if let Err(e) = config::resolve_inheritance(&mut raw) {
+        diags.push(Diagnostic {
+            severity: Severity::Error,
+            line: None,
+            path: "agents".to_string(),
+            message: format!("{:#}", e),
+        });
+    } else {
+        match serde_yml::from_value::<Config>(raw) {
+            Ok(config) => check_config(&config, base, &mut diags),
+            Err(e) => diags.push(Diagnostic {
+                severity: Severity::Error,
+                line: None,
+                path: String::new(),
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    for d in &mut diags {
+        if d.line.is_none() && !d.path.is_empty() {
+            d.line = locate(source, &d.path);
+        }
+    }
+    diags.sort_by_key(|d| (d.severity, d.line));
+    diags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "orchestra: { name: t, version: '1', default_mode: auto }
+outputs: { directory: outputs, retention_days: 1, formats: [json] }
+digitalocean: { region: x }
+notifications: { enabled: false }
+logging: { level: info, format: json }
+";
+
+    fn check(agents: &str) -> Vec<Diagnostic> {
+        validate_str(&format!("{}agents:\n{}", BASE, agents), Path::new("."))
+    }
+
+    const AGENTS: &str = "  monitor:
+    enabled: true
+    timeout_seconds: 120
+  analyzer:
+    enabled: true
+    timeout_seconds: 120
+  researcher:
+    enabled: true
+    timeout_seconds: 120
+  reporter:
+    enabled: true
+    timeout_seconds: 120
+";
+
+    #[test]
+    fn test_clean_config_has_no_diagnostics() {
+        assert_eq!(check(AGENTS), vec![]);
+    }
+
+    #[test]
+    fn test_unknown_key_with_suggestion_and_line() {
+        let diags = check(&AGENTS.replace(
+            "    timeout_seconds: 120\n  analyzer",
+            "    timeout_secs: 120\n  analyzer",
+        ));
+        let unknown = diags
+            .iter()
+            .find(|d| d.path == "agents.monitor.timeout_secs")
+            .unwrap();
+        assert_eq!(unknown.severity, Severity::Warning);
+        assert_eq!(unknown.line, Some(9));
+        assert!(unknown.message.contains("did you mean 'timeout_seconds'"));
+        // The missing required field is an error, reported first
+        assert_eq!(diags[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_semantic_errors() {
+        let agents = AGENTS.replace(
+            "  reporter:\n    enabled: true\n",
+            "  reporter:\n    enabled: true\n    client_mode: apii\n    temperature: 1.5\n    prompt: x\n    prompt_file: nope.md\n    env: { \"A=B\": x }\n    permission_mode: acceptedits\n",
+        );
+        let diags = check(&agents);
+        let paths: Vec<&str> = diags.iter().map(|d| d.path.as_str()).collect();
+        assert!(paths.contains(&"agents.reporter.client_mode"));
+        assert!(paths.contains(&"agents.reporter.temperature"));
+        assert!(paths.contains(&"agents.reporter.prompt"));
+        assert!(paths.contains(&"agents.reporter.prompt_file"));
+        assert!(paths.contains(&"agents.reporter.env"));
+        assert!(paths.contains(&"agents.reporter.permission_mode"));
+        assert!(diags.iter().all(|d| d.severity == Severity::Error));
+        let mode = diags
+            .iter()
+            .find(|d| d.path.ends_with("client_mode"))
+            .unwrap();
+        assert_eq!(mode.line, Some(18));
+
+        let diags = check(&AGENTS.replace(
+            "  researcher:\n    enabled: true\n",
+            "  researcher:\n    enabled: true\n    client_mode: exec\n",
+        ));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].path, "agents.researcher.exec.command");
+
+        let diags = check(&AGENTS.replace(
+            "  researcher:\n    enabled: true\n",
+            "  researcher:\n    enabled: true\n    client_mode: ssh\n",
+        ));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].path, "agents.researcher.ssh.host");
+
+        let diags = check(&AGENTS.replace(
+            "  researcher:\n    enabled: true\n",
+            "  researcher:\n    enabled: true\n    client_mode: docker\n",
+        ));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].path, "agents.researcher.docker.image");
+
+        let diags = check(&AGENTS.replace(
+            "  researcher:\n    enabled: true\n",
+            "  researcher:\n    enabled: true\n    mcp_servers:\n      db: { command: psql-mcp, url: http://localhost:9000 }\n",
+        ));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].path, "agents.researcher.mcp_servers.db");
+
+        let diags = check(&AGENTS.replace(
+            "  researcher:\n    enabled: true\n",
+            "  researcher:\n    enabled: true\n    output_schema:\n      type: objekt\n      properties: { status: { enum: [ok, down] } }\n",
+        ));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].path, "agents.researcher.output_schema");
+
+        let diags = check(&AGENTS.replace(
+            "  researcher:\n    enabled: true\n",
+            "  researcher:\n    enabled: true\n    budget: { max_input_tokens: 0, strategy: summarize }\n",
+        ));
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].path, "agents.researcher.budget.max_input_tokens");
+        assert_eq!(diags[1].path, "agents.researcher.budget.summarizer");
+        assert_eq!(diags[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_consensus_checks() {
+        let diags = check(&format!(
+            "{}consensus:\n  risk:\n    prompt: Ship it?\n    voters: [monitor, analyzer, monitor, ghost]\n    aggregator: analyzer\n",
+            AGENTS
+        ));
+        let messages: Vec<(&str, Severity)> = diags
+            .iter()
+            .map(|d| (d.message.as_str(), d.severity))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("'monitor' is listed twice", Severity::Error),
+                ("'analyzer' is also a voter", Severity::Error),
+                (
+                    "no agent named 'ghost'; it runs with default settings",
+                    Severity::Warning
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_review_checks() {
+        let diags = check(&format!(
+            "{}review:\n  draft:\n    prompt: Write it\n    author: monitor\n    critic: monitor\n    max_rounds: 0\n",
+            AGENTS
+        ));
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "'monitor' is also the author",
+                "must be at least 1, or the draft is never reviewed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_checks() {
+        let diags = check(&format!(
+            "{}map:\n  triage:\n    inputs: logs/*.log\n    prompt: Summarize {{{{ file }}}}\n    mapper: monitor\n    reducer: ghost\n    reduce_prompt: Combine them\n    concurrency: 0\n    batch: true\n",
+            AGENTS
+        ));
+        let messages: Vec<(&str, Severity)> = diags
+            .iter()
+            .map(|d| (d.message.as_str(), d.severity))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    "must be at least 1, or no file is ever mapped",
+                    Severity::Error
+                ),
+                (
+                    "no agent named 'ghost'; it runs with default settings",
+                    Severity::Warning
+                ),
+                (
+                    "only api agents can be batched; monitor runs with claude-code as usual",
+                    Severity::Warning
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bench_checks() {
+        let diags = check(&format!(
+            "{}bench:\n  runs: 0\n  targets:\n    - {{ mode: api, model: claude-haiku-4-5 }}\n    - {{ mode: apii }}\n  prompts:\n    - {{ name: triage, prompt: Which line matters?, tokens: 10 }}\n",
+            AGENTS
+        ));
+        let paths: Vec<&str> = diags.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "bench.targets[1].mode",
+                "bench.runs",
+                "bench.prompts[0].tokens"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_concurrency_checks() {
+        let diags = check(&format!(
+            "{}client:\n  concurrency: {{ api: 2, claude-code: 0, cli: 4 }}\n",
+            AGENTS
+        ));
+        let paths: Vec<&str> = diags.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["client.concurrency.claude-code", "client.concurrency.cli"]
+        );
+    }
+
+    #[test]
+    fn test_remote_output_checks() {
+        let base = BASE
+            .replace(
+                "formats: [json] }",
+                "formats: [json], remote: { bucket: reports, signed_url_seconds: 2592000 } }",
+            )
+            .replace(
+                "digitalocean: { region: x }",
+                "digitalocean: { region: '' }",
+            );
+        let diags = validate_str(&format!("{}agents:\n{}", base, AGENTS), Path::new("."));
+        let messages: Vec<(&str, &str)> = diags
+            .iter()
+            .map(|d| (d.path.as_str(), d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    "outputs.remote",
+                    "needs an endpoint, or a region (here or in digitalocean) for Spaces"
+                ),
+                (
+                    "outputs.remote.signed_url_seconds",
+                    "must be between 1 and 604800 (7 days)"
+                ),
+            ]
+        );
+
+        let base = BASE.replace(
+            "formats: [json] }",
+            "formats: [json], encryption: { key_env: OUTPUTS_KEY } }",
+        );
+        let diags = validate_str(&format!("{}agents:\n{}", base, AGENTS), Path::new("."));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].path, "logging.agent_logs");
+        let base = base.replace("format: json }", "format: json, agent_logs: null }");
+        let diags = validate_str(&format!("{}agents:\n{}", base, AGENTS), Path::new("."));
+        assert!(diags.is_empty());
+
+        let base = BASE.replace(
+            "formats: [json] }",
+            "formats: [json], git: { repo: no-such-checkout, push: true } }",
+        );
+        let diags = validate_str(&format!("{}agents:\n{}", base, AGENTS), Path::new("."));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].path, "outputs.git.repo");
+        assert_eq!(diags[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_worker_checks() {
+        let base = BASE.replace(
+            "digitalocean: { region: x }",
+            "digitalocean: { region: x, workers: { image: 'claude-agent:1', max_workers: 0 } }\nfeatures: { auto_scaling: true }",
+        );
+        let diags = validate_str(&format!("{}agents:\n{}", base, AGENTS), Path::new("."));
+        let messages: Vec<(&str, &str)> = diags
+            .iter()
+            .map(|d| (d.path.as_str(), d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    "digitalocean.registry",
+                    "workers pull claude-agent:1 from it, but it is not set"
+                ),
+                ("digitalocean.workers.max_workers", "must be at least 1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_health_checks() {
+        let base = format!(
+            "{}health: {{ stall_seconds: 0, heartbeat_secs: 5 }}\n",
+            BASE
+        );
+        let diags = validate_str(&format!("{}agents:\n{}", base, AGENTS), Path::new("."));
+        let paths: Vec<&str> = diags.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, ["health.stall_seconds", "health.heartbeat_secs"]);
+    }
+
+    #[test]
+    fn test_secret_checks() {
+        let base = format!(
+            "{}secrets:\n  ANTHROPIC_API_KEY: {{ command: [] }}\n  WEBHOOK_SECRET: {{ vault: {{ path: secret/data/x, field: s, token: t }} }}\n  DO_TOKEN: {{ file: a, command: [b] }}\n",
+            BASE
+        );
+        let diags = validate_str(&format!("{}agents:\n{}", base, AGENTS), Path::new("."));
+        let paths: Vec<&str> = diags.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "secrets.ANTHROPIC_API_KEY.command",
+                "secrets.DO_TOKEN",
+                "secrets.WEBHOOK_SECRET.vault.token"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_yaml_syntax_error_has_line() {
+        let diags = check("  monitor: [unclosed\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].line.is_some());
+    }
+
+    #[test]
+    fn test_shipped_config_is_valid() {
+        let diags = validate_file(Path::new("config/orchestra.yml"));
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+}
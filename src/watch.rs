@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use agent_orchestra::shutdown::Shutdown;
+
+use crate::cli::Args;
+use crate::{build_orchestrator, load_config, CONFIG_PATH};
+
+/// Template variable holding the paths that triggered a run, one per line.
+const CHANGED_VAR: &str = "changed_files";
+
+/// The watched paths `event` touches, if it is a change (not just a read)
+/// and isn't under one of `ignored`, where runs write their own outputs.
+fn changed_paths(event: &Event, watched: &[PathBuf], ignored: &[PathBuf]) -> Vec<PathBuf> {
+    if !matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) | EventKind::Any
+    ) {
+        return Vec::new();
+    }
+    event
+        .paths
+        .iter()
+        .filter(|p| {
+            !ignored.iter().any(|i| p.starts_with(i)) && watched.iter().any(|w| p.starts_with(w))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Gather changes from `rx` until none has come for `debounce`, and at
+/// least until `not_before`. `None` once the sender is gone.
+async fn settle(
+    rx: &mut UnboundedReceiver<Vec<PathBuf>>,
+    debounce: Duration,
+    not_before: Instant,
+) -> Option<BTreeSet<PathBuf>> {
+    let mut changed: BTreeSet<PathBuf> = BTreeSet::new();
+    while changed.is_empty() {
+        changed.extend(rx.recv().await?);
+    }
+    loop {
+        let deadline = (Instant::now() + debounce).max(not_before);
+        match tokio::time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some(paths)) => changed.extend(paths),
+            Ok(None) | Err(_) => return Some(changed),
+        }
+    }
+}
+
+/// Start a run whenever one of `args.watch` changes: files are watched
+/// themselves, directories with everything in them, and a path that doesn't
+/// exist yet (a deploy marker, say) through its parent directory. Changes
+/// are debounced and runs spaced by `orchestra.watch`; the config is re-read
+/// for every run, keeping the last one that loaded if it no longer does.
+pub async fn run(args: Args) -> Result<()> {
+    if args.stdin_var.is_some() {
+        anyhow::bail!("--stdin-var can't be used with --watch: stdin is read only once");
+    }
+    if args.resume.is_some() || args.dry_run {
+        anyhow::bail!("--watch starts new runs; it can't be used with --resume or --dry-run");
+    }
+    let mut config = load_config().await?;
+    let watched: Vec<PathBuf> = args
+        .watch
+        .iter()
+        .map(std::path::absolute)
+        .collect::<std::io::Result<_>>()
+        .context("Failed to resolve watched paths")?;
+    // A run writing its outputs mustn't trigger the next one
+    let ignored = vec![std::path::absolute(&config.outputs.directory)?];
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let filter = (watched.clone(), ignored);
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                let paths = changed_paths(&event, &filter.0, &filter.1);
+                if !paths.is_empty() {
+                    let _ = tx.send(paths);
+                }
+            }
+            Err(e) => warn!("Watch error: {}", e),
+        })
+        .context("Failed to start watching files")?;
+    for path in &watched {
+        let (target, mode) = if path.is_dir() {
+            (path.as_path(), RecursiveMode::Recursive)
+        } else if path.exists() {
+            (path.as_path(), RecursiveMode::NonRecursive)
+        } else {
+            let parent = path
+                .parent()
+                .filter(|p| p.is_dir())
+                .with_context(|| format!("Neither {} nor its directory exists", path.display()))?;
+            (parent, RecursiveMode::NonRecursive)
+        };
+        watcher
+            .watch(target, mode)
+            .with_context(|| format!("Failed to watch {}", target.display()))?;
+        info!("Watching {}", path.display());
+    }
+
+    let shutdown = Shutdown::on_signals();
+    let mut last_start: Option<Instant> = None;
+    loop {
+        let pacing = &config.orchestra.watch;
+        let not_before = last_start
+            .map(|t| t + Duration::from_secs(pacing.min_interval_seconds))
+            .unwrap_or_else(Instant::now);
+        let debounce = Duration::from_secs(pacing.debounce_seconds);
+        let changed = tokio::select! {
+            changed = settle(&mut rx, debounce, not_before) => changed,
+            _ = shutdown.wait() => None,
+        };
+        let Some(changed) = changed else {
+            info!("Stopped watching");
+            return Ok(());
+        };
+        info!(
+            "{} change(s), starting a run: {}",
+            changed.len(),
+            changed
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        match load_config().await {
+            Ok(new) => config = new,
+            Err(e) => warn!("Keeping previous {}: {:#}", CONFIG_PATH, e),
+        }
+        let mut run_args = args.clone();
+        let list: Vec<String> = changed.iter().map(|p| p.display().to_string()).collect();
+        run_args
+            .vars
+            .push((CHANGED_VAR.to_string(), list.join("\n")));
+        last_start = Some(Instant::now());
+        let result = match build_orchestrator(&run_args, config.clone(), None) {
+            Ok(orchestrator) => orchestrator.run().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Run failed: {:#}", e);
+        }
+        if shutdown.is_triggered() {
+            info!("Stopped watching");
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind};
+
+    #[test]
+    fn test_changed_paths_skip_reads_and_outputs() {
+        let watched = vec![
+            PathBuf::from("/srv/logs"),
+            PathBuf::from("/srv/deploy.done"),
+        ];
+        let ignored = &[PathBuf::from("/srv/logs/outputs")];
+        let event = |kind, path: &str| Event::new(kind).add_path(PathBuf::from(path));
+
+        let created = event(EventKind::Create(CreateKind::File), "/srv/logs/app.log");
+        assert_eq!(
+            changed_paths(&created, &watched, ignored),
+            vec![PathBuf::from("/srv/logs/app.log")]
+        );
+        let marker = event(EventKind::Create(CreateKind::File), "/srv/deploy.done");
+        assert_eq!(changed_paths(&marker, &watched, ignored).len(), 1);
+        // Something else in the marker's directory
+        let sibling = event(EventKind::Create(CreateKind::File), "/srv/other");
+        assert!(changed_paths(&sibling, &watched, ignored).is_empty());
+        let read = event(EventKind::Access(AccessKind::Any), "/srv/logs/app.log");
+        assert!(changed_paths(&read, &watched, ignored).is_empty());
+        let output = event(
+            EventKind::Create(CreateKind::File),
+            "/srv/logs/outputs/results.json",
+        );
+        assert!(changed_paths(&output, &watched, ignored).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_settle_debounces_and_waits_for_interval() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let debounce = Duration::from_millis(100);
+        let started = Instant::now();
+        tx.send(vec![PathBuf::from("a.log")]).unwrap();
+        let later = tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            later.send(vec![PathBuf::from("b.log")]).unwrap();
+        });
+        let changed = settle(&mut rx, debounce, started).await.unwrap();
+        assert_eq!(changed.len(), 2);
+        assert!(started.elapsed() >= Duration::from_millis(150));
+
+        // A run that started recently holds the next one back
+        let started = Instant::now();
+        tx.send(vec![PathBuf::from("a.log")]).unwrap();
+        let not_before = started + Duration::from_millis(300);
+        settle(&mut rx, debounce, not_before).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        drop(tx);
+        assert!(settle(&mut rx, debounce, Instant::now()).await.is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+use ring::hmac;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+use crate::agents::AgentResult;
+use crate::config::WebhookConfig;
+use crate::secrets;
+
+/// Bumped whenever the payload shape changes in a way receivers must handle.
+pub const WEBHOOK_SCHEMA_VERSION: u32 = 1;
+
+pub const SIGNATURE_HEADER: &str = "X-Orchestra-Signature";
+pub const RUN_ID_HEADER: &str = "X-Orchestra-Run-Id";
+pub const SCHEMA_VERSION_HEADER: &str = "X-Orchestra-Schema-Version";
+
+/// Body POSTed to the webhook URL when a run completes.
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload<'a> {
+    pub schema_version: u32,
+    pub event: &'static str,
+    pub run_id: &'a str,
+    pub mode: &'a str,
+    pub total: usize,
+    pub successful: usize,
+    pub failed: usize,
+    pub results: &'a [AgentResult],
+    /// Signed links to the run's uploaded outputs, by name (`results`,
+    /// `summary`), when `outputs.remote.signed_url_seconds` is set.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, String>,
+}
+
+impl<'a> WebhookPayload<'a> {
+    pub fn completed(run_id: &'a str, mode: &'a str, results: &'a [AgentResult]) -> Self {
+        let successful = results.iter().filter(|r| r.status == "success").count();
+        Self {
+            schema_version: WEBHOOK_SCHEMA_VERSION,
+            event: "run.completed",
+            run_id,
+            mode,
+            total: results.len(),
+            successful,
+            failed: results.len() - successful,
+            results,
+            links: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_links(mut self, links: BTreeMap<String, String>) -> Self {
+        self.links = links;
+        self
+    }
+}
+
+/// Compute the signature header value (`sha256=<hex>`) for a request body.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, body);
+    let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+/// The signature header value for `body`, or `None` if the secret is unset
+/// and the webhook opted into `unsigned` payloads.
+fn signature(config: &WebhookConfig, body: &[u8]) -> Result<Option<String>> {
+    match secrets::var(&config.secret_env) {
+        Some(secret) if !secret.is_empty() => Ok(Some(sign(secret.as_bytes(), body))),
+        _ if config.unsigned => {
+            warn!(
+                "Webhook secret ${} is not set; sending unsigned payload",
+                config.secret_env
+            );
+            Ok(None)
+        }
+        _ => anyhow::bail!(
+            "Webhook secret ${} is not set; set it, or `unsigned: true` to send unsigned payloads",
+            config.secret_env
+        ),
+    }
+}
+
+/// POST the payload to the configured webhook, signed with the shared secret
+/// read from `secret_env`. Fails if the secret is unset, unless the webhook
+/// is `unsigned`.
+pub async fn send(config: &WebhookConfig, payload: &WebhookPayload<'_>) -> Result<()> {
+    let body = serde_json::to_vec(payload).context("Failed to serialize webhook payload")?;
+    let signature = signature(config, &body)?;
+
+    let mut request = Client::new()
+        .post(&config.url)
+        .header("content-type", "application/json")
+        .header(RUN_ID_HEADER, payload.run_id)
+        .header(SCHEMA_VERSION_HEADER, payload.schema_version.to_string());
+
+    if let Some(signature) = signature {
+        request = request.header(SIGNATURE_HEADER, signature);
+    }
+
+    let response = request
+        .body(body)
+        .send()
+        .await
+        .context("Failed to send webhook")?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Webhook returned status {}", response.status());
+    }
+
+    info!("Webhook delivered to {}", config.url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_matches_known_vector() {
+        // RFC 4231 test case 2
+        let sig = sign(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            sig,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_missing_secret_fails_unless_unsigned() {
+        let mut config = WebhookConfig {
+            url: "https://hooks.example.com".to_string(),
+            secret_env: "ORCHESTRA_TEST_WEBHOOK_SECRET_UNSET".to_string(),
+            unsigned: false,
+        };
+        let err = signature(&config, b"{}").unwrap_err();
+        assert!(err.to_string().contains("unsigned: true"), "{}", err);
+        config.unsigned = true;
+        assert_eq!(signature(&config, b"{}").unwrap(), None);
+    }
+
+    #[test]
+    fn test_payload_counts_and_version() {
+        let results = vec![
+            AgentResult::success("a".into(), "ok".into(), "api".into()),
+            AgentResult::failed("b".into(), "boom".into(), "api".into()),
+        ];
+        let payload = WebhookPayload::completed("run-1", "auto", &results);
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["schema_version"], WEBHOOK_SCHEMA_VERSION);
+        assert_eq!(json["run_id"], "run-1");
+        assert_eq!(json["successful"], 1);
+        assert_eq!(json["failed"], 1);
+        assert!(json.get("links").is_none());
+
+        let links = BTreeMap::from([("summary".to_string(), "https://example".to_string())]);
+        let json = serde_json::to_value(payload.with_links(links)).unwrap();
+        assert_eq!(json["links"]["summary"], "https://example");
+    }
+}